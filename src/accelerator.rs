@@ -0,0 +1,126 @@
+//
+//	Acceleration Structures
+//
+
+// What a scene narrows down the objects a ray might hit with, built over their world bounds. The
+// hierarchy suits most scenes; the grid can do better when there are lots of objects of about the
+// same size spread evenly through the scene, the kd-tree when they're packed tight and overlapping
+
+use crate::bvh::Bvh;
+use crate::grid::Grid;
+use crate::kdtree::KdTree;
+use crate::structs::{Aabb, Ray};
+
+// How much looser than when it was built refitting can leave a hierarchy before it's built again
+const REBUILD_GROWTH: f64 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AcceleratorKind {
+	Bvh,
+	Grid,
+	KdTree,
+}
+
+impl AcceleratorKind {
+	pub fn from_name(name: &str) -> Option<AcceleratorKind> {
+		match name {
+			"bvh" => Some(AcceleratorKind::Bvh),
+			"grid" => Some(AcceleratorKind::Grid),
+			"kdtree" => Some(AcceleratorKind::KdTree),
+			_ => None,
+		}
+	}
+
+	pub fn build(&self, bounds: &[Aabb]) -> Box<dyn Accelerator> {
+		match self {
+			AcceleratorKind::Bvh => {
+				let bvh = Bvh::new(bounds);
+				Box::new(Hierarchy { built_cost: bvh.cost(), bvh })
+			}
+			AcceleratorKind::Grid => Box::new(Grid::new(bounds)),
+			AcceleratorKind::KdTree => Box::new(KdTree::new(bounds)),
+		}
+	}
+}
+
+pub trait Accelerator: Send + Sync {
+	fn kind(&self) -> AcceleratorKind;
+	// Every item whose box the ray passes through, in no particular order. There may be others
+	// along with them, and some may come up more than once
+	fn candidates<'a>(&'a self, ray: &'a Ray) -> Box<dyn Iterator<Item = usize> + 'a>;
+	// The same items, moved to bounds. False when it's come out loose enough that it's worth building again
+	fn refit(&mut self, bounds: &[Aabb]) -> bool;
+}
+
+struct Hierarchy {
+	bvh: Bvh,
+	built_cost: f64, // bvh.cost() when it was built
+}
+
+impl Accelerator for Hierarchy {
+	fn kind(&self) -> AcceleratorKind { AcceleratorKind::Bvh }
+
+	fn candidates<'a>(&'a self, ray: &'a Ray) -> Box<dyn Iterator<Item = usize> + 'a> {
+		Box::new(self.bvh.candidates(ray))
+	}
+
+	fn refit(&mut self, bounds: &[Aabb]) -> bool {
+		self.bvh.refit(bounds);
+		self.bvh.cost() <= self.built_cost * REBUILD_GROWTH
+	}
+}
+
+impl Accelerator for Grid {
+	fn kind(&self) -> AcceleratorKind { AcceleratorKind::Grid }
+
+	fn candidates<'a>(&'a self, ray: &'a Ray) -> Box<dyn Iterator<Item = usize> + 'a> {
+		Box::new(Grid::candidates(self, ray))
+	}
+
+	// Filling the cells again is about as quick as anything cleverer
+	fn refit(&mut self, bounds: &[Aabb]) -> bool {
+		*self = Grid::new(bounds);
+		true
+	}
+}
+
+impl Accelerator for KdTree {
+	fn kind(&self) -> AcceleratorKind { AcceleratorKind::KdTree }
+
+	fn candidates<'a>(&'a self, ray: &'a Ray) -> Box<dyn Iterator<Item = usize> + 'a> {
+		Box::new(KdTree::candidates(self, ray))
+	}
+
+	// Planes can't follow things moving, it's built again from scratch
+	fn refit(&mut self, bounds: &[Aabb]) -> bool {
+		*self = KdTree::new(bounds);
+		true
+	}
+}
+
+#[test]
+fn backends_find_the_same_hits() {
+	use crate::random::Rng;
+	use crate::structs::{Scene, Vec3};
+	let mut bvh = Scene::default_scene();
+	let mut grid = Scene::default_scene();
+	grid.render_settings.accelerator = AcceleratorKind::Grid;
+	let mut kd_tree = Scene::default_scene();
+	kd_tree.render_settings.accelerator = AcceleratorKind::KdTree;
+	let image = bvh.render(40, 20);
+	assert_eq!(grid.render(40, 20), image);
+	assert_eq!(kd_tree.render(40, 20), image);
+	let mut rng = Rng::new(2);
+	let mut point = || Vec3 { x: rng.next_f64() * 16.0 - 8.0, y: rng.next_f64() * 16.0 - 8.0, z: rng.next_f64() * 8.0 - 2.0 };
+	for _ in 0..300 {
+		let ray = Ray::from_to(&point(), &point());
+		let hit = |scene: &Scene| scene.trace_object(&ray).map(|(index, (point, _, _))| (index, point.x, point.y, point.z));
+		assert_eq!(hit(&grid), hit(&bvh));
+		assert_eq!(hit(&kd_tree), hit(&bvh));
+	}
+	// Switching builds the other kind
+	grid.render_settings.accelerator = AcceleratorKind::Bvh;
+	assert_eq!(grid.render(40, 20), image);
+	assert_eq!(AcceleratorKind::from_name("grid"), Some(AcceleratorKind::Grid));
+	assert_eq!(AcceleratorKind::from_name("kdtree"), Some(AcceleratorKind::KdTree));
+}
@@ -0,0 +1,110 @@
+//
+//	Exposure Analysis
+//
+
+// Tools for judging light intensities: a luminance histogram and a false color view where each
+// brightness band gets its own color and clipped pixels get zebra stripes
+
+use crate::structs::Color;
+
+pub const BINS: usize = 32;
+const HISTOGRAM_WIDTH: usize = BINS * 2;
+const HISTOGRAM_HEIGHT: usize = 24;
+
+// Rec. 709 weights on the displayed values, 0 to 1
+pub fn luminance(color: &Color) -> f64 {
+	(0.2126 * color.r as f64 + 0.7152 * color.g as f64 + 0.0722 * color.b as f64) / 255.0
+}
+
+// Shading clamps every channel at 255, so a maxed out channel is where the light got lost
+pub fn clipped(color: &Color) -> bool {
+	color.r == 255 || color.g == 255 || color.b == 255
+}
+
+pub struct Histogram {
+	pub bins: [u32; BINS],
+	pub clipped: u32, // pixels with a channel at 255, counted in the bins as well
+	pub total: u32,
+}
+
+impl Histogram {
+	pub fn new(colors: &[Color]) -> Self {
+		let mut histogram = Histogram { bins: [0; BINS], clipped: 0, total: colors.len() as u32 };
+		for color in colors {
+			histogram.bins[((luminance(color) * BINS as f64) as usize).min(BINS - 1)] += 1;
+			if clipped(color) {
+				histogram.clipped += 1;
+			}
+		}
+		histogram
+	}
+
+	// Luminance below which the given fraction of pixels fall, to the resolution of a bin
+	pub fn percentile(&self, fraction: f64) -> f64 {
+		let target = fraction * self.total as f64;
+		let mut seen = 0.0;
+		for (bin, count) in self.bins.iter().enumerate() {
+			seen += *count as f64;
+			if seen >= target && *count > 0 {
+				return (bin as f64 + 0.5) / BINS as f64;
+			}
+		}
+		1.0
+	}
+}
+
+// Crushed blacks purple, shadows blue, middle gray green, nearly blown highlights yellow and
+// clipped pixels red and white stripes, everything else stays gray at its own brightness
+pub fn false_color(color: &Color, x: usize, y: usize) -> Color {
+	let gray = |value: f64| {
+		let value = (value * 255.0).round() as u8;
+		Color { r: value, g: value, b: value }
+	};
+	if clipped(color) {
+		return if ((x + y) / 2).is_multiple_of(2) { Color { r: 255, g: 0, b: 0 } } else { Color { r: 255, g: 255, b: 255 } };
+	}
+	match luminance(color) {
+		l if l < 0.02 => Color { r: 110, g: 0, b: 150 },
+		l if l < 0.1 => Color { r: 0, g: 70, b: 230 },
+		l if (0.4..0.5).contains(&l) => Color { r: 40, g: 190, b: 40 },
+		l if l >= 0.9 => Color { r: 240, g: 220, b: 0 },
+		l => gray(l),
+	}
+}
+
+// The frame in false color with its histogram in the bottom left corner
+pub fn analysis_view(colors: &[Color], width: usize, height: usize) -> Vec<Color> {
+	let mut view: Vec<Color> = colors.iter().enumerate().map(|(index, color)| false_color(color, index % width, index / width)).collect();
+	let histogram = Histogram::new(colors);
+	let tallest = histogram.bins.iter().copied().max().unwrap_or(0).max(1) as f64;
+	let (left, top) = (1, height.saturating_sub(HISTOGRAM_HEIGHT + 1));
+	for x in 0..HISTOGRAM_WIDTH.min(width.saturating_sub(left)) {
+		let bin = x * BINS / HISTOGRAM_WIDTH;
+		let bar = (histogram.bins[bin] as f64 / tallest * HISTOGRAM_HEIGHT as f64).ceil() as usize;
+		for y in 0..HISTOGRAM_HEIGHT.min(height) {
+			let filled = HISTOGRAM_HEIGHT - y <= bar;
+			view[(top + y) * width + left + x] = match (filled, bin == BINS - 1 && histogram.clipped > 0) {
+				(false, _) => Color { r: 20, g: 20, b: 20 },
+				(true, false) => Color { r: 230, g: 230, b: 230 },
+				(true, true) => Color { r: 255, g: 0, b: 0 },
+			};
+		}
+	}
+	view
+}
+
+#[test]
+fn histogram_counts_every_pixel() {
+	let black = Color { r: 0, g: 0, b: 0 };
+	let gray = Color { r: 118, g: 118, b: 118 };
+	let blown = Color { r: 255, g: 40, b: 40 };
+	let colors = [black, black, gray, gray, gray, blown];
+	let histogram = Histogram::new(&colors);
+	assert_eq!(histogram.bins.iter().sum::<u32>(), 6);
+	assert_eq!((histogram.bins[0], histogram.clipped), (2, 1));
+	assert!((histogram.percentile(0.75) - luminance(&gray)).abs() < 1.0 / BINS as f64);
+	assert_eq!(false_color(&gray, 0, 0), Color { r: 40, g: 190, b: 40 });
+	// Stripes run diagonally two pixels wide
+	assert_ne!(false_color(&blown, 0, 0), false_color(&blown, 2, 0));
+	assert_eq!(false_color(&blown, 0, 0), false_color(&blown, 1, 0));
+}
@@ -0,0 +1,136 @@
+//
+//	Animation
+//
+
+// Parametric motion: an object's rest pose run through a list of modifiers, each a pure function
+// of time, so any frame can be evaluated without stepping through the ones before it
+
+use crate::structs::{Rot3, Scene, Vec3};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Modifier {
+	// Turns the object in place about an axis through its position
+	Spin { axis: Vec3, speed: f64 }, // radians per second
+	// Carries the object around center, about an axis through it, without turning it
+	Orbit { center: Vec3, axis: Vec3, speed: f64 },
+	// Moves it back and forth along offset, offset being the furthest it gets
+	Bob { offset: Vec3, frequency: f64, phase: f64 }, // cycles per second, radians
+}
+
+impl Modifier {
+	pub fn apply(&self, pos: Vec3, rot: Rot3, time: f64) -> (Vec3, Rot3) {
+		match self {
+			Modifier::Spin { axis, speed } => (pos, Rot3::from_axis_angle(axis, speed * time).compose(&rot)),
+			Modifier::Orbit { center, axis, speed } => (pos.sub(center).rotate_about(axis, speed * time).add(center), rot),
+			Modifier::Bob { offset, frequency, phase } => {
+				let swing = (std::f64::consts::TAU * frequency * time + phase).sin();
+				(pos.add(&offset.mul(swing)), rot)
+			}
+		}
+	}
+}
+
+// Weight of one of a mesh's morph targets over time, linear between keys and looping
+// once past the last one
+#[derive(Debug, Clone)]
+pub struct WeightTrack {
+	pub morph: usize, // index into the mesh's targets
+	pub keys: Vec<(f64, f64)>, // (seconds, weight), in order
+}
+
+impl WeightTrack {
+	pub fn value(&self, time: f64) -> f64 {
+		let (first, last) = match (self.keys.first(), self.keys.last()) {
+			(Some(first), Some(last)) => (*first, *last),
+			_ => return 0.0,
+		};
+		let time = if last.0 > 0.0 { time.rem_euclid(last.0) } else { time };
+		if time <= first.0 { return first.1 }
+		let after = self.keys.iter().position(|(key, _)| *key > time).unwrap_or(self.keys.len() - 1);
+		let (before, after) = (self.keys[after.saturating_sub(1)], self.keys[after]);
+		if after.0 <= before.0 { return after.1 }
+		before.1 + (after.1 - before.1) * (time - before.0) / (after.0 - before.0)
+	}
+}
+
+// Modifiers applied in order to the rest pose of the object with the given id,
+// along with the weights of any of its morph targets that change
+#[derive(Debug, Clone)]
+pub struct Animation {
+	pub target: String,
+	pub pos: Vec3,
+	pub rot: Rot3,
+	pub modifiers: Vec<Modifier>,
+	pub weights: Vec<WeightTrack>,
+}
+
+impl Animation {
+	// Starts from wherever the object is now, None when there's no such object
+	pub fn new(scene: &Scene, target: &str, modifiers: Vec<Modifier>) -> Option<Animation> {
+		let object = scene.objects.iter().find(|object| object.get_id() == target)?;
+		Some(Animation { target: target.to_string(), pos: *object.get_pos(), rot: *object.get_rot(), modifiers, weights: Vec::new() })
+	}
+
+	pub fn pose(&self, time: f64) -> (Vec3, Rot3) {
+		self.modifiers.iter().fold((self.pos, self.rot), |(pos, rot), modifier| modifier.apply(pos, rot, time))
+	}
+}
+
+#[test]
+fn modifiers() {
+	let origin = Vec3 { x: 0.0, y: 0.0, z: 0.0 };
+	let up = Vec3 { x: 0.0, y: 0.0, z: 1.0 };
+	let quarter = std::f64::consts::FRAC_PI_2;
+	let animation = Animation {
+		target: String::from("thing"),
+		pos: Vec3 { x: 2.0, y: 0.0, z: 0.0 },
+		rot: Rot3::new(),
+		modifiers: vec![
+			Modifier::Spin { axis: up, speed: quarter },
+			Modifier::Orbit { center: origin, axis: up, speed: quarter },
+			Modifier::Bob { offset: up, frequency: 0.25, phase: 0.0 },
+		],
+		weights: Vec::new(),
+	};
+	let (pos, rot) = animation.pose(0.0);
+	assert!(pos.dist(&animation.pos) < 1e-12 && rot.yaw.abs() < 1e-12);
+	// A second in: a quarter turn in place, a quarter of the way around, at the top of the bob
+	let (pos, rot) = animation.pose(1.0);
+	assert!(pos.dist(&Vec3 { x: 0.0, y: 2.0, z: 1.0 }) < 1e-12, "{:?}", pos);
+	assert!((rot.yaw - quarter).abs() < 1e-12 && rot.pitch.abs() < 1e-12 && rot.roll.abs() < 1e-12);
+	// Spinning about a tilted axis agrees with rotating points directly
+	let axis = Vec3 { x: 1.0, y: 1.0, z: 1.0 };
+	let point = Vec3 { x: 0.3, y: -1.0, z: 2.0 };
+	let turned = point.rotate(&Rot3::from_axis_angle(&axis, 1.3));
+	assert!(turned.dist(&point.rotate_about(&axis, 1.3)) < 1e-12);
+}
+
+#[test]
+fn morph_weights() {
+	use crate::structs::{Color, Material, Mesh, Scene, Tri};
+	let track = WeightTrack { morph: 0, keys: vec![(0.0, 0.0), (1.0, 1.0), (2.0, 0.0)] };
+	assert_eq!(track.value(0.5), 0.5);
+	assert_eq!(track.value(1.5), 0.5);
+	assert_eq!(track.value(3.25), 0.75);
+
+	// A triangle whose tip rises a unit at full weight
+	let material = Material { color: Color { r: 255, g: 255, b: 255 }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+	let origin = Vec3 { x: 0.0, y: 0.0, z: 0.0 };
+	let mut mesh = Mesh::new(origin, Rot3::new(), vec![Tri { a: origin, b: Vec3 { x: 1.0, y: 0.0, z: 0.0 }, c: Vec3 { x: 0.0, y: 1.0, z: 0.0 }, mat: material }]);
+	mesh.id = String::from("flap");
+	let tip = mesh.geometry.positions.iter().position(|p| p.y == 1.0).unwrap();
+	let mut offsets = vec![origin; 3];
+	offsets[tip] = Vec3 { x: 0.0, y: 0.0, z: 1.0 };
+	let bad = crate::structs::MorphTarget { name: String::from("bad"), positions: vec![origin], normals: Vec::new() };
+	assert!(mesh.set_morph_targets(vec![bad], Vec::new()).is_err());
+	mesh.set_morph_targets(vec![crate::structs::MorphTarget { name: String::from("up"), positions: offsets, normals: Vec::new() }], vec![0.0]).unwrap();
+
+	let mut scene = Scene::new(vec![Box::new(mesh)], crate::structs::Camera::new(origin, Rot3::new(), 40.0));
+	scene.animations.extend(Animation::new(&scene, "flap", Vec::new()));
+	scene.animations[0].weights.push(track);
+	scene.animate(0.5);
+	let tip_height = |scene: &mut Scene| scene.find_object("flap").unwrap().bounds().unwrap().max.z;
+	assert_eq!(tip_height(&mut scene), 0.5);
+	scene.animate(1.0);
+	assert_eq!(tip_height(&mut scene), 1.0);
+}
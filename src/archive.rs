@@ -0,0 +1,234 @@
+//
+//	Scene Archives
+//
+
+// A scene file along with everything it pulls in, references, textures, voxel chunks and point
+// clouds, as a single .rtscene file to hand to someone else. It's a zip of the files as they are,
+// uncompressed, the scene that gets loaded first and the rest laid out around it the way they were
+// on disk so the paths between them still work. Loading unpacks it somewhere temporary and loads
+// the scene from there
+
+use crate::scene_file::{load_scene, ObjectDesc, SceneFile};
+use crate::structs::Scene;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use uuid::Uuid;
+
+pub const EXTENSION: &str = "rtscene";
+
+const LOCAL_HEADER: u32 = 0x04034b50;
+const CENTRAL_HEADER: u32 = 0x02014b50;
+const END_OF_DIRECTORY: u32 = 0x06054b50;
+const VERSION: u16 = 20;
+const UTF8_NAMES: u16 = 1 << 11;
+const STORED: u16 = 0;
+const DOS_DATE: u16 = (1 << 5) | 1; // 1980-01-01, the earliest a zip can say
+
+pub fn is_archive(path: &Path) -> bool {
+	path.extension().is_some_and(|extension| extension == EXTENSION)
+}
+
+// The scene file and every file it needs, itself first, each once
+pub fn files(scene: &Path) -> Result<Vec<PathBuf>, String> {
+	fn visit(path: &Path, seen: &mut HashSet<PathBuf>, files: &mut Vec<PathBuf>, scene: bool) -> Result<(), String> {
+		let path = path.canonicalize().map_err(|e| format!("{}: {}", path.display(), e))?;
+		if !seen.insert(path.clone()) { return Ok(()) }
+		files.push(path.clone());
+		if !scene { return Ok(()) }
+		let file = SceneFile::load(&path)?;
+		let dir = path.parent().unwrap_or(Path::new(""));
+		if let Some(backplate) = &file.backplate {
+			visit(&dir.join(&backplate.path), seen, files, false)?;
+		}
+		if let Some(environment) = &file.environment {
+			visit(&dir.join(&environment.path), seen, files, false)?;
+		}
+		for object in file.objects.iter() {
+			match object {
+				ObjectDesc::Mesh { texture: Some(texture), .. } => visit(&dir.join(texture), seen, files, false)?,
+				ObjectDesc::Voxels { path, .. } | ObjectDesc::Points { path, .. } => visit(&dir.join(path), seen, files, false)?,
+				ObjectDesc::Light { spot: Some(spot), .. } => {
+					if let Some(gobo) = &spot.gobo {
+						visit(&dir.join(gobo), seen, files, false)?;
+					}
+				}
+				ObjectDesc::Reference { path, .. } => visit(&dir.join(path), seen, files, true)?,
+				_ => {}
+			}
+		}
+		Ok(())
+	}
+	let mut files = Vec::new();
+	visit(scene, &mut HashSet::new(), &mut files, true)?;
+	Ok(files)
+}
+
+// Packs the scene file at scene and everything it needs into an archive at path
+pub fn export(scene: &Path, path: &Path) -> Result<(), String> {
+	let files = files(scene)?;
+	// Named from the deepest folder they're all in
+	let mut root = files[0].parent().unwrap_or(Path::new("")).to_path_buf();
+	while !files.iter().all(|file| file.starts_with(&root)) {
+		root.pop();
+	}
+	let mut entries = Vec::new();
+	for file in files.iter() {
+		let name = file.strip_prefix(&root).unwrap_or(file).components().map(|part| part.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+		entries.push((name, fs::read(file).map_err(|e| format!("{}: {}", file.display(), e))?));
+	}
+	fs::write(path, encode(&entries)).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Loads the scene packed into the archive at path
+pub fn load(path: &Path) -> Result<Scene, String> {
+	let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+	let entries = decode(&bytes).map_err(|e| format!("{}: {}", path.display(), e))?;
+	let scene = entries.first().ok_or(format!("{}: empty archive", path.display()))?.0.clone();
+	let dir = std::env::temp_dir().join(format!("rtscene_{}", Uuid::new_v4()));
+	let unpacked = unpack(&entries, &dir).map_err(|e| format!("{}: {}", path.display(), e)).and_then(|()| load_scene(&dir.join(&scene)));
+	let _ = fs::remove_dir_all(&dir);
+	unpacked
+}
+
+fn unpack(entries: &[(String, Vec<u8>)], dir: &Path) -> Result<(), String> {
+	for (name, data) in entries.iter() {
+		// Nothing gets written outside dir
+		let relative = Path::new(name);
+		if name.is_empty() || !relative.components().all(|part| matches!(part, Component::Normal(_))) {
+			return Err(format!("entry `{}` points outside the archive", name));
+		}
+		let file = dir.join(relative);
+		if let Some(parent) = file.parent() {
+			fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+		}
+		fs::write(&file, data).map_err(|e| e.to_string())?;
+	}
+	Ok(())
+}
+
+// A zip of (name, contents) in order, nothing compressed
+pub fn encode(entries: &[(String, Vec<u8>)]) -> Vec<u8> {
+	let mut out = Vec::new();
+	let mut directory = Vec::new();
+	for (name, data) in entries.iter() {
+		let offset = out.len() as u32;
+		let crc = crc32(data);
+		header(&mut out, LOCAL_HEADER, None, name, crc, data.len() as u32);
+		out.extend_from_slice(data);
+		header(&mut directory, CENTRAL_HEADER, Some(offset), name, crc, data.len() as u32);
+	}
+	let directory_offset = out.len() as u32;
+	out.extend_from_slice(&directory);
+	out.extend_from_slice(&END_OF_DIRECTORY.to_le_bytes());
+	out.extend_from_slice(&[0; 4]); // this disk, the directory's disk
+	out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+	out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+	out.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+	out.extend_from_slice(&directory_offset.to_le_bytes());
+	out.extend_from_slice(&[0; 2]); // comment length
+	out
+}
+
+// Local headers come before the data, central ones, with where the local one is, in the directory at the end
+fn header(out: &mut Vec<u8>, signature: u32, offset: Option<u32>, name: &str, crc: u32, size: u32) {
+	out.extend_from_slice(&signature.to_le_bytes());
+	if offset.is_some() {
+		out.extend_from_slice(&VERSION.to_le_bytes()); // made by
+	}
+	for value in [VERSION, UTF8_NAMES, STORED, 0, DOS_DATE] {
+		out.extend_from_slice(&value.to_le_bytes());
+	}
+	for value in [crc, size, size] {
+		out.extend_from_slice(&value.to_le_bytes());
+	}
+	out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+	out.extend_from_slice(&[0; 2]); // extra field length
+	if let Some(offset) = offset {
+		out.extend_from_slice(&[0; 10]); // comment length, disk, internal and external attributes
+		out.extend_from_slice(&offset.to_le_bytes());
+	}
+	out.extend_from_slice(name.as_bytes());
+}
+
+// The entries of a zip, in the order its directory lists them. Only stored ones, as encode writes,
+// anything compressed is an error
+pub fn decode(bytes: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+	let u16_at = |at: usize| bytes.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as usize).ok_or("truncated archive");
+	let u32_at = |at: usize| bytes.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize).ok_or("truncated archive");
+	// The end of directory record is the last thing, before a comment of up to 64k
+	let end = (0..bytes.len().saturating_sub(21)).rev().take(65536 + 22)
+		.find(|at| u32_at(*at) == Ok(END_OF_DIRECTORY as usize))
+		.ok_or("not a zip archive")?;
+	let (count, mut at) = (u16_at(end + 10)?, u32_at(end + 16)?);
+	let mut entries = Vec::with_capacity(count);
+	for _ in 0..count {
+		if u32_at(at)? != CENTRAL_HEADER as usize { return Err(String::from("damaged directory")) }
+		let (method, crc, size) = (u16_at(at + 10)?, u32_at(at + 16)?, u32_at(at + 20)?);
+		let (name_length, skip) = (u16_at(at + 28)?, u16_at(at + 30)? + u16_at(at + 32)?);
+		let local = u32_at(at + 42)?;
+		let name = bytes.get(at + 46..at + 46 + name_length).ok_or("truncated archive")?;
+		let name = String::from_utf8_lossy(name).into_owned();
+		if method != STORED as usize {
+			return Err(format!("entry `{}` is compressed, only uncompressed archives can be loaded", name));
+		}
+		if u32_at(local)? != LOCAL_HEADER as usize { return Err(format!("entry `{}` is damaged", name)) }
+		let start = local + 30 + u16_at(local + 26)? + u16_at(local + 28)?;
+		let data = bytes.get(start..start + size).ok_or("truncated archive")?;
+		if crc32(data) as usize != crc { return Err(format!("entry `{}` is corrupt", name)) }
+		entries.push((name, data.to_vec()));
+		at += 46 + name_length + skip;
+	}
+	Ok(entries)
+}
+
+// The checksum zip uses, bit by bit, archives are small enough
+fn crc32(data: &[u8]) -> u32 {
+	let mut crc = !0u32;
+	for byte in data.iter() {
+		crc ^= *byte as u32;
+		for _ in 0..8 {
+			crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+		}
+	}
+	!crc
+}
+
+#[test]
+fn archives_round_trip() {
+	assert_eq!(crc32(b"123456789"), 0xcbf43926);
+	let entries = vec![(String::from("scene.ron"), b"()".to_vec()), (String::from("a/b.txt"), Vec::new())];
+	let bytes = encode(&entries);
+	assert_eq!(decode(&bytes).unwrap(), entries);
+	assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+	assert!(decode(&encode(&[(String::from("../escape"), Vec::new())])).is_ok_and(|entries| unpack(&entries, Path::new("unused")).is_err()));
+
+	// A scene reaching out of its folder for a reference, which reaches back for points
+	let dir = std::env::temp_dir().join(format!("archive_test_{}", std::process::id()));
+	fs::create_dir_all(dir.join("scenes")).unwrap();
+	fs::create_dir_all(dir.join("shared")).unwrap();
+	fs::write(dir.join("scenes/street.ron"), r#"(
+		camera: Some((pos: (0.0, -10.0, 2.0), look_at: Some((0.0, 0.0, 0.0)), fov: 40.0)),
+		materials: { "grey": () },
+		objects: [
+			Reference(id: Some("car"), path: "../shared/car.ron"),
+			Points(id: Some("dust"), path: "dust.txt", radius: 0.1),
+			Light(pos: (0.0, -5.0, 5.0), intensity: 50.0),
+		],
+	)"#).unwrap();
+	fs::write(dir.join("shared/car.ron"), r#"(
+		materials: { "paint": (color: (200, 20, 20)) },
+		objects: [ Cube(id: Some("body"), size: 2.0, material: "paint"), Points(path: "../scenes/dust.txt", radius: 0.2) ],
+	)"#).unwrap();
+	fs::write(dir.join("scenes/dust.txt"), "0 0 2\n1 0 2 255 0 0\n").unwrap();
+	let names: Vec<PathBuf> = files(&dir.join("scenes/street.ron")).unwrap().iter().map(|file| file.strip_prefix(dir.canonicalize().unwrap()).unwrap().to_path_buf()).collect();
+	assert_eq!(names, [Path::new("scenes/street.ron"), Path::new("shared/car.ron"), Path::new("scenes/dust.txt")]);
+
+	let archive = dir.join("street.rtscene");
+	export(&dir.join("scenes/street.ron"), &archive).unwrap();
+	let mut original = load_scene(&dir.join("scenes/street.ron")).unwrap();
+	let mut unpacked = load_scene(&archive).unwrap();
+	assert_eq!(original.objects.len(), unpacked.objects.len());
+	assert_eq!(original.render(32, 18), unpacked.render(32, 18));
+	fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,236 @@
+//
+//	Arena
+//
+
+// Slots for things that come and go, kept in one growing Vec rather than allocated one at a time, and
+// reached by a handle that stays good for as long as the thing is there. Removing something leaves a
+// hole the next insert fills, other handles don't move, and its own handle stops working instead of
+// reaching whatever took the slot: every slot counts how many times it's been refilled and a handle
+// remembers the count it was given out with. Scenes keep their distinct mesh geometry in one, see
+// Scene::geometry
+
+use crate::structs::{Mesh, MeshGeometry, Scene};
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+pub struct Handle<T> {
+	index: u32,
+	generation: u32,
+	kind: PhantomData<fn() -> T>,
+}
+
+// Derived, these would only hold for T that were Copy themselves
+impl<T> Clone for Handle<T> {
+	fn clone(&self) -> Self {
+		*self
+	}
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+	fn eq(&self, other: &Self) -> bool {
+		(self.index, self.generation) == (other.index, other.generation)
+	}
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> std::fmt::Debug for Handle<T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "Handle({}v{})", self.index, self.generation)
+	}
+}
+
+enum Slot<T> {
+	Full(T),
+	Empty(Option<u32>), // the hole filled after this one
+}
+
+pub struct Arena<T> {
+	slots: Vec<(u32, Slot<T>)>, // generation and contents
+	free: Option<u32>, // the hole filled next
+	len: usize,
+}
+
+impl<T> Default for Arena<T> {
+	fn default() -> Self {
+		Arena::new()
+	}
+}
+
+impl<T> Arena<T> {
+	pub fn new() -> Self {
+		Arena { slots: Vec::new(), free: None, len: 0 }
+	}
+
+	pub fn with_capacity(capacity: usize) -> Self {
+		Arena { slots: Vec::with_capacity(capacity), ..Arena::new() }
+	}
+
+	pub fn len(&self) -> usize {
+		self.len
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	pub fn insert(&mut self, value: T) -> Handle<T> {
+		self.len += 1;
+		let index = match self.free {
+			Some(index) => {
+				let (generation, slot) = &mut self.slots[index as usize];
+				if let Slot::Empty(next) = slot {
+					self.free = *next;
+				}
+				*generation += 1;
+				*slot = Slot::Full(value);
+				index
+			}
+			None => {
+				self.slots.push((0, Slot::Full(value)));
+				self.slots.len() as u32 - 1
+			}
+		};
+		Handle { index, generation: self.slots[index as usize].0, kind: PhantomData }
+	}
+
+	// What handle was given out for, None once it's been removed
+	pub fn remove(&mut self, handle: Handle<T>) -> Option<T> {
+		self.get(handle)?;
+		let slot = std::mem::replace(&mut self.slots[handle.index as usize].1, Slot::Empty(self.free));
+		self.free = Some(handle.index);
+		self.len -= 1;
+		match slot {
+			Slot::Full(value) => Some(value),
+			Slot::Empty(_) => None,
+		}
+	}
+
+	pub fn get(&self, handle: Handle<T>) -> Option<&T> {
+		match self.slots.get(handle.index as usize)? {
+			(generation, Slot::Full(value)) if *generation == handle.generation => Some(value),
+			_ => None,
+		}
+	}
+
+	pub fn get_mut(&mut self, handle: Handle<T>) -> Option<&mut T> {
+		match self.slots.get_mut(handle.index as usize)? {
+			(generation, Slot::Full(value)) if *generation == handle.generation => Some(value),
+			_ => None,
+		}
+	}
+
+	// Everything there, in the order of their slots
+	pub fn iter(&self) -> impl Iterator<Item = (Handle<T>, &T)> {
+		self.slots.iter().enumerate().filter_map(|(index, (generation, slot))| match slot {
+			Slot::Full(value) => Some((Handle { index: index as u32, generation: *generation, kind: PhantomData }, value)),
+			Slot::Empty(_) => None,
+		})
+	}
+}
+
+// How much geometry a scene holds, and how much sharing it between instances saved
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct GeometryStats {
+	pub meshes: usize,
+	pub distinct: usize, // geometries, each stored once
+	pub triangles: usize, // in those
+	pub instanced_triangles: usize, // every mesh's added up, as many as there would be without sharing
+	pub bytes: usize, // the distinct geometry takes up
+}
+
+impl Scene {
+	fn meshes(&self) -> impl Iterator<Item = &Mesh> {
+		self.objects.iter().filter_map(|object| object.as_any_immut().downcast_ref::<Mesh>())
+	}
+
+	// Brings geometry up to date with the meshes, for after they're added to or reshaped: what they
+	// still use keeps its handle, what none of them do any more is let go and new geometry added
+	pub fn intern_mesh_geometry(&mut self) {
+		let used: Vec<Arc<MeshGeometry>> = self.meshes().map(|mesh| mesh.geometry.clone()).collect();
+		let unused: Vec<_> = self.geometry.iter().filter(|(_, geometry)| !used.iter().any(|other| Arc::ptr_eq(geometry, other))).map(|(handle, _)| handle).collect();
+		for handle in unused {
+			self.geometry.remove(handle);
+		}
+		for geometry in used {
+			self.geometry_handle(&geometry).unwrap_or_else(|| self.geometry.insert(geometry));
+		}
+	}
+
+	// Where geometry is kept, None when it isn't
+	pub fn geometry_handle(&self, geometry: &Arc<MeshGeometry>) -> Option<Handle<Arc<MeshGeometry>>> {
+		self.geometry.iter().find(|(_, other)| Arc::ptr_eq(geometry, other)).map(|(handle, _)| handle)
+	}
+
+	pub fn geometry_stats(&self) -> GeometryStats {
+		GeometryStats {
+			meshes: self.meshes().count(),
+			distinct: self.geometry.len(),
+			triangles: self.geometry.iter().map(|(_, geometry)| geometry.indices.len()).sum(),
+			instanced_triangles: self.meshes().map(Mesh::triangle_count).sum(),
+			bytes: self.geometry.iter().map(|(_, geometry)| geometry.memory()).sum(),
+		}
+	}
+}
+
+#[test]
+fn handles_outlive_removals_around_them() {
+	let mut arena = Arena::new();
+	let (a, b, c) = (arena.insert("a"), arena.insert("b"), arena.insert("c"));
+	assert_eq!((arena.len(), arena.get(b)), (3, Some(&"b")));
+
+	// Taking one out leaves the rest where they were
+	assert_eq!(arena.remove(b), Some("b"));
+	assert_eq!((arena.get(a), arena.get(b), arena.get(c)), (Some(&"a"), None, Some(&"c")));
+	assert_eq!(arena.remove(b), None);
+
+	// Its slot is used again, without the old handle reaching the new contents
+	let d = arena.insert("d");
+	assert_ne!(d, b);
+	assert_eq!((arena.get(b), arena.get(d), arena.len()), (None, Some(&"d"), 3));
+	*arena.get_mut(d).unwrap() = "e";
+	assert_eq!(arena.iter().map(|(_, value)| *value).collect::<Vec<_>>(), ["a", "e", "c"]);
+
+	// Holes are filled most recent first, and only then does it grow
+	arena.remove(a);
+	arena.remove(c);
+	let (f, g, h) = (arena.insert("f"), arena.insert("g"), arena.insert("h"));
+	assert_eq!((arena.get(f), arena.get(g), arena.get(h)), (Some(&"f"), Some(&"g"), Some(&"h")));
+	assert_eq!(arena.slots.len(), 4);
+	assert!(arena.get(a).is_none() && arena.get(c).is_none());
+}
+
+#[test]
+fn scene_geometry_stored_once() {
+	use crate::structs::{Camera, Rot3, SceneObject, Vec3};
+	let scene = Scene::default_scene();
+	let stats = scene.geometry_stats();
+	assert_eq!((stats.meshes, stats.distinct), (2, 2));
+	assert!(stats.bytes > 0 && stats.triangles == stats.instanced_triangles);
+
+	// Instances of one geometry share its slot, reshaping one lets go of the old geometry once no mesh uses it
+	let cube = scene.meshes().find(|mesh| mesh.triangle_count() == 12).unwrap();
+	let (cube, materials) = (cube.geometry.clone(), cube.materials.clone());
+	let handle = scene.geometry_handle(&cube).unwrap();
+	let objects: Vec<Box<dyn SceneObject>> = (0..3).map(|x| Box::new(Mesh::instance(Vec3 { x: x as f64, y: 0.0, z: 0.0 }, Rot3::new(), cube.clone(), materials.clone())) as Box<dyn SceneObject>).collect();
+	let mut instanced = Scene::new(objects, Camera::new(Vec3 { x: 0.0, y: -5.0, z: 0.0 }, Rot3::new(), 40.0));
+	let stats = instanced.geometry_stats();
+	assert_eq!((stats.meshes, stats.distinct, stats.triangles, stats.instanced_triangles), (3, 1, 12, 36));
+	for mesh in instanced.get_all_meshes().into_iter().skip(1) {
+		mesh.set_vertex(0, Vec3 { x: 0.0, y: 0.0, z: 9.0 });
+		mesh.refit_bvh();
+	}
+	instanced.intern_mesh_geometry();
+	assert_eq!(instanced.geometry_stats().distinct, 3);
+	assert!(instanced.geometry_handle(&cube).is_some());
+	for mesh in instanced.get_all_meshes() {
+		mesh.set_vertex(0, Vec3 { x: 0.0, y: 0.0, z: 9.0 });
+		mesh.refit_bvh();
+	}
+	instanced.intern_mesh_geometry();
+	assert_eq!(instanced.geometry_stats().distinct, 3);
+	assert!(instanced.geometry_handle(&cube).is_none());
+	assert!(scene.geometry.get(handle).is_some());
+}
@@ -0,0 +1,95 @@
+//
+//	Backplates
+//
+
+// A photograph behind the scene, seen wherever a camera ray misses everything. It's pinned to the
+// frame rather than to any direction, so it stays put while the camera moves, and nothing is lit by
+// it or reflects it. Pixels showing it count as covered, a backplate is meant to end up in the
+// picture. In a scene file:
+//
+//	backplate: Some((path: "studio.png", fit: Fill))
+
+use crate::structs::{Color, Scene};
+use crate::texture::Texture;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+// How the image is sized to a frame of another shape
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum BackplateFit {
+	Fit, // all of it showing, bars either side left empty
+	Fill, // all of the frame covered, the image's edges cut off
+	Stretch, // pulled out of shape to match
+}
+
+impl BackplateFit {
+	pub fn from_name(name: &str) -> Option<BackplateFit> {
+		match name {
+			"fit" => Some(BackplateFit::Fit),
+			"fill" => Some(BackplateFit::Fill),
+			"stretch" => Some(BackplateFit::Stretch),
+			_ => None,
+		}
+	}
+}
+
+pub struct Backplate {
+	pub texture: Arc<Texture>,
+	pub fit: BackplateFit,
+}
+
+impl Backplate {
+	// The image at (x, y) across a frame of width x height pixels, None in the bars a fit leaves
+	pub fn color_at(&self, x: f64, y: f64, width: f64, height: f64) -> Option<Color> {
+		let (image, frame) = (self.texture.width as f64 / self.texture.height as f64, width / height);
+		// How much of the image one frame's width and height span
+		let (span_u, span_v) = match self.fit {
+			BackplateFit::Stretch => (1.0, 1.0),
+			BackplateFit::Fill if image > frame => (frame / image, 1.0),
+			BackplateFit::Fill => (1.0, image / frame),
+			BackplateFit::Fit if image > frame => (1.0, image / frame),
+			BackplateFit::Fit => (frame / image, 1.0),
+		};
+		let (u, v) = (0.5 + (x / width - 0.5) * span_u, 0.5 + (y / height - 0.5) * span_v);
+		if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) { return None }
+		Some(self.texture.sample_footprint(u, v, span_u / width, span_v / height))
+	}
+}
+
+impl Scene {
+	// What's behind pixel (x, y) offset into it, None without a backplate or outside it
+	pub fn backplate_color(&self, x: i32, y: i32, offset: (f64, f64), width: i32, height: i32) -> Option<Color> {
+		self.backplate.as_ref()?.color_at(x as f64 + offset.0, y as f64 + offset.1, width as f64, height as f64)
+	}
+}
+
+#[test]
+fn backplates_fit_and_fill() {
+	// Two texels wide and one high, black then white
+	let (black, white) = (Color { r: 0, g: 0, b: 0 }, Color { r: 255, g: 255, b: 255 });
+	let texture = Arc::new(Texture::new(2, 1, vec![black, white], String::new()));
+	let plate = |fit: BackplateFit| Backplate { texture: texture.clone(), fit };
+	// A square frame: fitting leaves bars above and below, filling cuts the sides off
+	let fit = plate(BackplateFit::Fit);
+	assert_eq!(fit.color_at(1.0, 0.5, 10.0, 10.0), None);
+	assert_eq!(fit.color_at(1.0, 5.0, 10.0, 10.0), Some(black));
+	assert_eq!(fit.color_at(9.0, 5.0, 10.0, 10.0), Some(white));
+	let fill = plate(BackplateFit::Fill);
+	assert_eq!(fill.color_at(0.5, 0.5, 10.0, 10.0), Some(black));
+	assert_eq!(fill.color_at(5.5, 9.5, 10.0, 10.0), Some(white));
+	// A frame the same shape shows the same either way
+	assert_eq!(fit.color_at(3.0, 0.5, 20.0, 10.0), plate(BackplateFit::Stretch).color_at(3.0, 0.5, 20.0, 10.0));
+	assert_eq!(BackplateFit::from_name("fill"), Some(BackplateFit::Fill));
+
+	// Behind the default scene, where its rays miss
+	let mut scene = Scene::default_scene();
+	let plain = scene.render_framebuffer(32, 18);
+	scene.backplate = Some(Backplate { texture: Arc::new(Texture::new(1, 1, vec![Color { r: 0, g: 0, b: 200 }], String::new())), fit: BackplateFit::Stretch });
+	let backed = scene.render_framebuffer(32, 18);
+	let (plain, backed) = (plain.to_rgba(), backed.to_rgba());
+	let missed: Vec<usize> = (0..32 * 18).filter(|pixel| plain[pixel * 4 + 3] == 0).collect();
+	assert!(!missed.is_empty());
+	for pixel in missed.iter() {
+		assert_eq!(backed[pixel * 4..pixel * 4 + 4], [0, 0, 200, 255]);
+	}
+}
@@ -0,0 +1,201 @@
+//
+//	Benchmark
+//
+
+// Fixed scenes rendered with fixed seeds, so timings from different builds of the same machine
+// can be compared and slowdowns caught before they ship. Besides a few looks at the default scene
+// there's a generated one of however many spheres, mesh triangles and lights, the same every time
+// for the same seed, for loads bigger than anything hand made
+
+use crate::accelerator::AcceleratorKind;
+use crate::render::Integrator;
+use crate::random::Rng;
+use crate::structs::{rays_traced, Camera, Color, LightSource, Material, Mesh, Rot3, Scene, SceneObject, Sphere, Tri, Vec3};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::Path;
+use std::time::Instant;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchResult {
+	pub name: String,
+	pub width: i32,
+	pub height: i32,
+	pub seconds: f64, // best of the repeats
+	pub rays: u64,
+	pub rays_per_second: f64,
+}
+
+// How much a generated stress scene has in it, see build
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StressScene {
+	pub spheres: usize,
+	pub triangles: usize, // of one bumpy ground mesh
+	pub lights: usize,
+	pub seed: u64,
+}
+
+// Ground, spheres and lights are spread over a square this many units across
+const STRESS_EXTENT: f64 = 20.0;
+
+impl StressScene {
+	// From `spheres,triangles,lights` with an optional `,seed` after, as on the command line
+	pub fn parse(text: &str) -> Option<StressScene> {
+		let numbers: Vec<u64> = text.split(',').map(|number| number.trim().parse().ok()).collect::<Option<_>>()?;
+		match numbers[..] {
+			[spheres, triangles, lights] => Some(StressScene { spheres: spheres as usize, triangles: triangles as usize, lights: lights as usize, seed: 0 }),
+			[spheres, triangles, lights, seed] => Some(StressScene { spheres: spheres as usize, triangles: triangles as usize, lights: lights as usize, seed }),
+			_ => None,
+		}
+	}
+
+	// Random spheres of random materials lying about a randomly bumpy ground, lights scattered above,
+	// seen from off one corner. However many lights there are share out the same brightness
+	pub fn build(&self) -> Scene {
+		let mut rng = Rng::new(self.seed);
+		let mut objects: Vec<Box<dyn SceneObject>> = Vec::new();
+		let random_material = |rng: &mut Rng| Material {
+			color: Color { r: rng.next_below(256) as u8, g: rng.next_below(256) as u8, b: rng.next_below(256) as u8 },
+			reflectivity: if rng.next_f64() < 0.2 { 0.5 } else { 0.0 },
+			transparency: 0.0,
+			roughness: 0.0,
+			ior: 1.5,
+		};
+		let at = |rng: &mut Rng, z: f64| Vec3 { x: (rng.next_f64() - 0.5) * STRESS_EXTENT, y: (rng.next_f64() - 0.5) * STRESS_EXTENT, z };
+
+		// Two triangles a grid cell, the last row cut short to come out at exactly triangles
+		let cells = ((self.triangles as f64 / 2.0).sqrt().ceil() as usize).max(1);
+		let heights: Vec<f64> = (0..(cells + 1) * (cells + 1)).map(|_| rng.next_f64() * 0.3).collect();
+		let corner = |x: usize, y: usize| Vec3 {
+			x: (x as f64 / cells as f64 - 0.5) * STRESS_EXTENT,
+			y: (y as f64 / cells as f64 - 0.5) * STRESS_EXTENT,
+			z: heights[y * (cells + 1) + x],
+		};
+		let ground = Material { color: Color { r: 200, g: 200, b: 200 }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+		let tris: Vec<Tri> = (0..cells * cells).flat_map(|cell| {
+			let (x, y) = (cell % cells, cell / cells);
+			[
+				Tri { a: corner(x, y), b: corner(x + 1, y), c: corner(x + 1, y + 1), mat: ground },
+				Tri { a: corner(x, y), b: corner(x + 1, y + 1), c: corner(x, y + 1), mat: ground },
+			]
+		}).take(self.triangles).collect();
+		if !tris.is_empty() {
+			objects.push(Box::new(Mesh::new(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, Rot3::new(), tris)));
+		}
+
+		for _ in 0..self.spheres {
+			let radius = 0.2 + rng.next_f64() * 0.6;
+			let pos = at(&mut rng, radius + 0.3);
+			let material = random_material(&mut rng);
+			objects.push(Box::new(Sphere::new(pos, radius as f32, material)));
+		}
+		for _ in 0..self.lights {
+			let height = 4.0 + rng.next_f64() * 4.0;
+			let pos = at(&mut rng, height);
+			objects.push(Box::new(LightSource::new(pos, Rot3::new(), 40.0 / self.lights as f32)));
+		}
+
+		let mut camera = Camera::new(Vec3 { x: STRESS_EXTENT * 0.6, y: -STRESS_EXTENT * 0.6, z: STRESS_EXTENT * 0.4 }, Rot3::new(), 50.0);
+		camera.look_at(&Vec3 { x: 0.0, y: 0.0, z: 0.0 }, &Vec3 { x: 0.0, y: 0.0, z: 1.0 });
+		Scene::new(objects, camera)
+	}
+}
+
+// Name, scene and image size of everything the benchmark renders
+pub fn reference_scenes() -> Vec<(&'static str, Scene, i32, i32)> {
+	let close_up = || {
+		let mut scene = Scene::default_scene();
+		scene.current_camera.pos = Vec3 { x: 6.0, y: 2.0, z: 3.0 };
+		scene.current_camera.look_at(&Vec3 { x: 0.0, y: 0.0, z: 1.0 }, &Vec3 { x: 0.0, y: 0.0, z: 1.0 });
+		scene
+	};
+
+	let mut soft_shadows = close_up();
+	for light in soft_shadows.get_all_light_sources() {
+		light.radius = 0.3;
+	}
+	soft_shadows.render_settings.samples_per_pixel = 4;
+	soft_shadows.render_settings.light_samples = 8;
+
+	let mut photon_mapping = close_up();
+	photon_mapping.render_settings.integrator = Integrator::Sppm;
+	photon_mapping.render_settings.samples_per_pixel = 2;
+	photon_mapping.render_settings.photons_per_pass = 20000;
+
+	vec![
+		("default", Scene::default_scene(), 160, 90),
+		("soft_shadows", soft_shadows, 160, 90),
+		("photon_mapping", photon_mapping, 80, 45),
+		("stress", StressScene { spheres: 200, triangles: 20000, lights: 16, seed: 1 }.build(), 80, 45),
+	]
+}
+
+// Renders every reference scene repeats times, keeping the fastest run of each
+pub fn run(repeats: u32, accelerator: AcceleratorKind) -> Vec<BenchResult> {
+	reference_scenes().into_iter().map(|(name, mut scene, width, height)| {
+		scene.render_settings.accelerator = accelerator;
+		let mut best: Option<(f64, u64)> = None;
+		for _ in 0..repeats.max(1) {
+			let rays_before = rays_traced();
+			let start = Instant::now();
+			scene.render_framebuffer(width, height);
+			let seconds = start.elapsed().as_secs_f64();
+			let rays = rays_traced() - rays_before;
+			if best.is_none_or(|(fastest, _)| seconds < fastest) {
+				best = Some((seconds, rays));
+			}
+		}
+		let (seconds, rays) = best.unwrap_or((0.0, 0));
+		BenchResult { name: name.to_string(), width, height, seconds, rays, rays_per_second: rays as f64 / seconds.max(1e-9) }
+	}).collect()
+}
+
+pub fn to_json(results: &[BenchResult]) -> String {
+	serde_json::to_string_pretty(results).unwrap_or_default()
+}
+
+pub fn load_baseline(path: &Path) -> io::Result<Vec<BenchResult>> {
+	let text = std::fs::read_to_string(path)?;
+	serde_json::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn save_baseline(path: &Path, results: &[BenchResult]) -> io::Result<()> {
+	std::fs::write(path, to_json(results) + "\n")
+}
+
+// Scenes whose throughput fell more than tolerance (0.1 being 10%) below the baseline,
+// as (name, baseline rays per second, current rays per second). Scenes missing from either side are skipped
+pub fn regressions(results: &[BenchResult], baseline: &[BenchResult], tolerance: f64) -> Vec<(String, f64, f64)> {
+	results.iter().filter_map(|result| {
+		let before = baseline.iter().find(|before| before.name == result.name)?;
+		if result.rays_per_second < before.rays_per_second * (1.0 - tolerance) {
+			Some((result.name.clone(), before.rays_per_second, result.rays_per_second))
+		} else {
+			None
+		}
+	}).collect()
+}
+
+#[test]
+fn flags_slower_scenes() {
+	let result = |name: &str, rays_per_second: f64| BenchResult { name: name.to_string(), width: 4, height: 4, seconds: 1.0, rays: rays_per_second as u64, rays_per_second };
+	let baseline = vec![result("default", 1000.0), result("soft_shadows", 1000.0)];
+	let parsed: Vec<BenchResult> = serde_json::from_str(&to_json(&baseline)).unwrap();
+	assert_eq!(parsed, baseline);
+
+	let current = vec![result("default", 950.0), result("soft_shadows", 700.0), result("new_scene", 1.0)];
+	let slower = regressions(&current, &baseline, 0.1);
+	assert_eq!(slower, vec![(String::from("soft_shadows"), 1000.0, 700.0)]);
+	assert!(reference_scenes().iter().all(|(_, scene, _, _)| !scene.objects.is_empty()));
+
+	// The same seed gives the same scene, with exactly what was asked for in it
+	let stress = StressScene::parse("30,101,3,7").unwrap();
+	assert_eq!(stress, StressScene { spheres: 30, triangles: 101, lights: 3, seed: 7 });
+	assert!(StressScene::parse("30,101").is_none() && StressScene::parse("a,b,c").is_none());
+	let (mut first, second) = (stress.build(), stress.build());
+	assert_eq!(first.objects.len(), 1 + 30 + 3);
+	assert_eq!(first.get_all_meshes()[0].triangle_count(), 101);
+	assert_eq!(first.get_all_light_sources_immut().len(), 3);
+	assert!(first.objects.iter().zip(second.objects.iter()).all(|(a, b)| a.get_pos().dist(b.get_pos()) == 0.0 && a.get_material() == b.get_material()));
+	assert!(StressScene { seed: 8, ..stress }.build().objects[1].get_pos().dist(first.objects[1].get_pos()) > 0.0);
+}
@@ -0,0 +1,384 @@
+//
+//	Bounding Volume Hierarchy
+//
+
+// Boxes around boxes, built over whatever a caller has bounds for: a mesh's triangles, a scene's
+// objects. A ray only looks at the items whose boxes it passes through, a handful instead of all of them.
+// A built tree can be written out as bytes and read back, for bvh_cache.rs
+
+use crate::structs::{Aabb, Ray, Vec3};
+use std::convert::TryInto;
+
+const LEAF_SIZE: usize = 4;
+// Halving the items every level, more than enough for anything that fits in memory
+const MAX_DEPTH: usize = 64;
+// Goes up whenever build would lay out a tree differently, so cached trees aren't mistaken for new ones
+pub const BUILD_VERSION: u32 = 1;
+const MAGIC: &[u8; 4] = b"BVH\0";
+// Magic, version, key, bound count, node count and item count
+const HEADER_SIZE: usize = 40;
+
+// Interior nodes have their first child right after them and the second at second,
+// leaves own count items from first
+#[derive(Clone)]
+struct Node {
+	bounds: Aabb,
+	first: u32,
+	second: u32,
+	count: u32,
+}
+
+#[derive(Clone)]
+pub struct Bvh {
+	nodes: Vec<Node>,
+	items: Vec<u32>, // indices of the bounds it was built from, in leaf order
+}
+
+impl Bvh {
+	pub fn new(bounds: &[Aabb]) -> Self {
+		let mut bvh = Bvh { nodes: Vec::new(), items: (0..bounds.len() as u32).collect() };
+		if !bounds.is_empty() {
+			let mut items = std::mem::take(&mut bvh.items);
+			bvh.build(bounds, &mut items, 0, 0);
+			bvh.items = items;
+		}
+		bvh
+	}
+
+	// Bytes its nodes and items take up
+	pub fn memory(&self) -> usize {
+		self.nodes.len() * std::mem::size_of::<Node>() + self.items.len() * std::mem::size_of::<u32>()
+	}
+
+	// Every item, in the order leaves hold them
+	pub fn items(&self) -> &[u32] {
+		&self.items
+	}
+
+	// Around everything, None when built over nothing
+	pub fn bounds(&self) -> Option<Aabb> {
+		self.nodes.first().map(|root| root.bounds)
+	}
+
+	// Area of every box added up, roughly how much work rays have getting through it
+	pub fn cost(&self) -> f64 {
+		self.nodes.iter().map(|node| node.bounds.area()).sum()
+	}
+
+	// Items moved but are still the same items: keeps the tree and regrows its boxes around
+	// the new bounds, much cheaper than building again though it gets looser the further things go
+	pub fn refit(&mut self, bounds: &[Aabb]) {
+		// Children come after their parent, so going backwards sees them first
+		for index in (0..self.nodes.len()).rev() {
+			let node = &self.nodes[index];
+			let mut fitted = Aabb::empty();
+			if node.count == 0 {
+				for child in [index + 1, node.second as usize] {
+					fitted.grow(&self.nodes[child].bounds.min);
+					fitted.grow(&self.nodes[child].bounds.max);
+				}
+			} else {
+				for item in &self.items[node.first as usize..(node.first + node.count) as usize] {
+					fitted.grow(&bounds[*item as usize].min);
+					fitted.grow(&bounds[*item as usize].max);
+				}
+				fitted = padded(fitted);
+			}
+			self.nodes[index].bounds = fitted;
+		}
+	}
+
+	// Calls hit with every item whose box the ray enters within reach, nearer boxes first, along with
+	// the distance to that box; hit hands back the reach from then on, how far the nearest hit so far is.
+	// Distances are in multiples of the ray's direction
+	pub fn traverse(&self, ray: &Ray, reach: f64, mut hit: impl FnMut(usize, f64) -> f64) {
+		self.traverse_leaves(ray, reach, |_, items, distance| items.iter().fold(reach, |_, item| hit(*item as usize, distance)));
+	}
+
+	// Same as traverse, a leaf at a time: leaf gets where its items start in items(), the items and
+	// the distance to its box
+	pub fn traverse_leaves(&self, ray: &Ray, mut reach: f64, mut leaf: impl FnMut(usize, &[u32], f64) -> f64) {
+		if self.nodes.is_empty() { return }
+		let mut stack = [(0u32, 0.0); MAX_DEPTH];
+		let mut depth = 0;
+		match self.nodes[0].bounds.ray_distance(ray) {
+			Some(distance) if distance <= reach => { stack[0] = (0, distance); depth = 1 }
+			_ => {}
+		}
+		while depth > 0 {
+			depth -= 1;
+			let (index, distance) = stack[depth];
+			if distance > reach { continue }
+			let node = &self.nodes[index as usize];
+			if node.count > 0 {
+				reach = leaf(node.first as usize, &self.items[node.first as usize..(node.first + node.count) as usize], distance);
+				continue;
+			}
+			let near = self.nodes[index as usize + 1].bounds.ray_distance(ray).map(|distance| (index + 1, distance));
+			let far = self.nodes[node.second as usize].bounds.ray_distance(ray).map(|distance| (node.second, distance));
+			let (near, far) = match (near, far) {
+				(Some(a), Some(b)) if b.1 < a.1 => (Some(b), Some(a)),
+				pair => pair,
+			};
+			// The nearer one goes on top
+			for child in [far, near].iter().flatten() {
+				if child.1 <= reach {
+					stack[depth] = *child;
+					depth += 1;
+				}
+			}
+		}
+	}
+
+	// traverse for a bundle of rays that mostly go the same way, walked together so each box is fetched once
+	// for all of them. leaf gets where its items start in items(), the items and which rays entered its box
+	// within their reach, and hands back every ray's reach from then on
+	pub fn traverse_packet<const N: usize>(&self, rays: &[Ray; N], mut reach: [f64; N], mut leaf: impl FnMut(usize, &[u32], [bool; N]) -> [f64; N]) {
+		if self.nodes.is_empty() { return }
+		let distances = |index: u32| rays.each_ref().map(|ray| self.nodes[index as usize].bounds.ray_distance(ray).unwrap_or(f64::INFINITY));
+		let entered = |distances: &[f64; N], reach: &[f64; N]| std::array::from_fn::<bool, N, _>(|lane| distances[lane] <= reach[lane]);
+		let mut stack = [(0u32, [f64::INFINITY; N]); MAX_DEPTH];
+		stack[0].1 = distances(0);
+		let mut depth = 1;
+		while depth > 0 {
+			depth -= 1;
+			let (index, distance) = stack[depth];
+			let active = entered(&distance, &reach);
+			if !active.contains(&true) { continue }
+			let node = &self.nodes[index as usize];
+			if node.count > 0 {
+				reach = leaf(node.first as usize, &self.items[node.first as usize..(node.first + node.count) as usize], active);
+				continue;
+			}
+			let (near, far) = ((index + 1, distances(index + 1)), (node.second, distances(node.second)));
+			// The one some ray reaches first goes on top
+			let nearest = |(_, distance): &(u32, [f64; N])| distance.iter().fold(f64::INFINITY, |nearest, distance| nearest.min(*distance));
+			let (near, far) = if nearest(&far) < nearest(&near) { (far, near) } else { (near, far) };
+			for child in [far, near] {
+				if entered(&child.1, &reach).contains(&true) {
+					stack[depth] = child;
+					depth += 1;
+				}
+			}
+		}
+	}
+
+	// Every item whose box the ray passes through at all, in no particular order
+	pub fn candidates<'a>(&'a self, ray: &'a Ray) -> impl Iterator<Item = usize> + 'a {
+		let mut stack = [0u32; MAX_DEPTH];
+		let mut depth = usize::from(self.nodes.first().is_some_and(|root| root.bounds.ray_distance(ray).is_some()));
+		let mut leaf: &[u32] = &[];
+		std::iter::from_fn(move || loop {
+			if let Some((item, rest)) = leaf.split_first() {
+				leaf = rest;
+				return Some(*item as usize);
+			}
+			if depth == 0 { return None }
+			depth -= 1;
+			let index = stack[depth] as usize;
+			let node = &self.nodes[index];
+			if node.count > 0 {
+				leaf = &self.items[node.first as usize..(node.first + node.count) as usize];
+				continue;
+			}
+			for child in [index as u32 + 1, node.second] {
+				if self.nodes[child as usize].bounds.ray_distance(ray).is_some() {
+					stack[depth] = child;
+					depth += 1;
+				}
+			}
+		})
+	}
+
+	// Calls near with every item whose box comes within reach of point, the closest boxes first,
+	// along with the distance to that box; near hands back the reach from then on
+	pub fn nearest(&self, point: &Vec3, mut reach: f64, mut near: impl FnMut(usize, f64) -> f64) {
+		let distance = |index: usize| self.nodes[index].bounds.closest_point(point).dist(point);
+		if self.nodes.is_empty() || distance(0) > reach { return }
+		let mut stack = [(0u32, distance(0)); MAX_DEPTH];
+		let mut depth = 1;
+		while depth > 0 {
+			depth -= 1;
+			let (index, to_box) = stack[depth];
+			if to_box > reach { continue }
+			let node = &self.nodes[index as usize];
+			if node.count > 0 {
+				for item in &self.items[node.first as usize..(node.first + node.count) as usize] {
+					reach = near(*item as usize, to_box);
+				}
+				continue;
+			}
+			let (first, second) = ((index + 1, distance(index as usize + 1)), (node.second, distance(node.second as usize)));
+			let (closer, further) = if second.1 < first.1 { (second, first) } else { (first, second) };
+			for child in [further, closer] {
+				if child.1 <= reach {
+					stack[depth] = child;
+					depth += 1;
+				}
+			}
+		}
+	}
+
+	// Settings the layout of a built tree depends on, BUILD_VERSION first
+	pub fn build_settings() -> [u64; 3] {
+		[BUILD_VERSION as u64, LEAF_SIZE as u64, MAX_DEPTH as u64]
+	}
+
+	// Little endian, tagged with key and the number of bounds it was built over
+	pub fn to_bytes(&self, key: u64, bound_count: usize) -> Vec<u8> {
+		let mut bytes = Vec::with_capacity(HEADER_SIZE + self.nodes.len() * 60 + self.items.len() * 4);
+		bytes.extend_from_slice(MAGIC);
+		bytes.extend_from_slice(&BUILD_VERSION.to_le_bytes());
+		for word in [key, bound_count as u64, self.nodes.len() as u64, self.items.len() as u64] {
+			bytes.extend_from_slice(&word.to_le_bytes());
+		}
+		for node in self.nodes.iter() {
+			let (min, max) = (node.bounds.min, node.bounds.max);
+			for value in [min.x, min.y, min.z, max.x, max.y, max.z] {
+				bytes.extend_from_slice(&value.to_le_bytes());
+			}
+			for value in [node.first, node.second, node.count] {
+				bytes.extend_from_slice(&value.to_le_bytes());
+			}
+		}
+		for item in self.items.iter() {
+			bytes.extend_from_slice(&item.to_le_bytes());
+		}
+		bytes
+	}
+
+	// What to_bytes wrote, None unless it has the same key and bound count and makes a whole tree
+	// that's safe to traverse, anything short, stale or scrambled
+	pub fn from_bytes(bytes: &[u8], key: u64, bound_count: usize) -> Option<Bvh> {
+		let mut at = 0;
+		let mut take = |count: usize| -> Option<&[u8]> {
+			let taken = bytes.get(at..at + count)?;
+			at += count;
+			Some(taken)
+		};
+		if take(4)? != MAGIC || take(4)? != BUILD_VERSION.to_le_bytes() { return None }
+		let mut word = || -> Option<u64> { Some(u64::from_le_bytes(take(8)?.try_into().ok()?)) };
+		let (stored_key, stored_count, node_count, item_count) = (word()?, word()?, word()? as usize, word()? as usize);
+		if node_count > bytes.len() / 60 || item_count > bytes.len() / 4 { return None }
+		if stored_key != key || stored_count != bound_count as u64 || bytes.len() != HEADER_SIZE + node_count * 60 + item_count * 4 { return None }
+		let f64_at = |offset: usize| f64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap_or_default());
+		let u32_at = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap_or_default());
+		let nodes: Vec<Node> = (0..node_count).map(|index| {
+			let offset = HEADER_SIZE + index * 60;
+			let value = |slot: usize| f64_at(offset + slot * 8);
+			Node {
+				bounds: Aabb { min: Vec3 { x: value(0), y: value(1), z: value(2) }, max: Vec3 { x: value(3), y: value(4), z: value(5) } },
+				first: u32_at(offset + 48),
+				second: u32_at(offset + 52),
+				count: u32_at(offset + 56),
+			}
+		}).collect();
+		let items: Vec<u32> = (0..item_count).map(|index| u32_at(HEADER_SIZE + node_count * 60 + index * 4)).collect();
+		if item_count != bound_count || items.iter().any(|item| *item as usize >= bound_count) { return None }
+
+		// Every node reached once from the root, children after their parent, no deeper than traversal allows
+		let mut stack = vec![(0usize, 0usize)];
+		let mut reached = 0;
+		while let Some((index, depth)) = stack.pop().filter(|_| !nodes.is_empty()) {
+			let node = &nodes[index];
+			reached += 1;
+			if reached > nodes.len() { return None }
+			if node.count > 0 {
+				if node.first as usize + node.count as usize > items.len() { return None }
+				continue;
+			}
+			let second = node.second as usize;
+			if depth + 2 >= MAX_DEPTH || index + 1 >= nodes.len() || second <= index + 1 || second >= nodes.len() { return None }
+			stack.push((index + 1, depth + 1));
+			stack.push((second, depth + 1));
+		}
+		if reached != nodes.len() || (nodes.is_empty() && bound_count > 0) { return None }
+		Some(Bvh { nodes, items })
+	}
+
+	// Splits items[..] in half along the longest side of their centers' bounds until the halves are
+	// small, items ending up in the order the leaves store them
+	fn build(&mut self, bounds: &[Aabb], items: &mut [u32], offset: usize, depth: usize) {
+		let mut around = Aabb::empty();
+		let mut centers = Aabb::empty();
+		for item in items.iter() {
+			let item = &bounds[*item as usize];
+			around.grow(&item.min);
+			around.grow(&item.max);
+			centers.grow(&item.center());
+		}
+		let node = self.nodes.len();
+		self.nodes.push(Node { bounds: padded(around), first: offset as u32, second: 0, count: items.len() as u32 });
+		if items.len() <= LEAF_SIZE || depth + 2 >= MAX_DEPTH { return }
+
+		let size = centers.max.sub(&centers.min);
+		let along = |item: &u32| {
+			let center = bounds[*item as usize].center();
+			if size.x >= size.y && size.x >= size.z { center.x } else if size.y >= size.z { center.y } else { center.z }
+		};
+		let half = items.len() / 2;
+		items.select_nth_unstable_by(half, |a, b| along(a).total_cmp(&along(b)));
+		let (left, right) = items.split_at_mut(half);
+		self.build(bounds, left, offset, depth + 1);
+		self.nodes[node].second = self.nodes.len() as u32;
+		self.nodes[node].count = 0;
+		self.build(bounds, right, offset + half, depth + 1);
+	}
+}
+
+// A hair bigger, so rounding never lets a ray slip past the box of something it hits
+pub fn padded(bounds: Aabb) -> Aabb {
+	let largest = [bounds.min.x, bounds.min.y, bounds.min.z, bounds.max.x, bounds.max.y, bounds.max.z].iter().fold(1.0f64, |largest, v| largest.max(v.abs()));
+	let pad = largest * 1e-9;
+	let pad = Vec3 { x: pad, y: pad, z: pad };
+	Aabb { min: bounds.min.sub(&pad), max: bounds.max.add(&pad) }
+}
+
+#[test]
+fn traversal_matches_testing_everything() {
+	use crate::random::Rng;
+	let mut rng = Rng::new(3);
+	let mut point = || Vec3 { x: rng.next_f64() * 20.0 - 10.0, y: rng.next_f64() * 20.0 - 10.0, z: rng.next_f64() * 20.0 - 10.0 };
+	let mut boxes: Vec<Aabb> = (0..300).map(|_| {
+		let mut bounds = Aabb::empty();
+		let corner = point();
+		bounds.grow(&corner);
+		bounds.grow(&corner.add(&Vec3 { x: 0.5, y: 0.3, z: 0.2 }));
+		bounds
+	}).collect();
+	let mut bvh = Bvh::new(&boxes);
+	let check = |bvh: &Bvh, boxes: &[Aabb], ray: &Ray, point: &Vec3| {
+		// Entry into the nearest box, found with and without the tree
+		let brute = boxes.iter().filter_map(|bounds| bounds.ray_distance(ray)).fold(f64::INFINITY, f64::min);
+		let mut nearest = f64::INFINITY;
+		bvh.traverse(ray, f64::INFINITY, |item, _| {
+			nearest = nearest.min(boxes[item].ray_distance(ray).unwrap_or(f64::INFINITY));
+			nearest
+		});
+		assert_eq!(nearest, brute);
+		let mut through: Vec<usize> = bvh.candidates(ray).filter(|item| boxes[*item].ray_distance(ray).is_some()).collect();
+		through.sort();
+		assert_eq!(through, (0..boxes.len()).filter(|item| boxes[*item].ray_distance(ray).is_some()).collect::<Vec<_>>());
+		let brute = boxes.iter().map(|bounds| bounds.closest_point(point).dist(point)).fold(f64::INFINITY, f64::min);
+		let mut closest = f64::INFINITY;
+		bvh.nearest(point, f64::INFINITY, |item, _| {
+			closest = closest.min(boxes[item].closest_point(point).dist(point));
+			closest
+		});
+		assert_eq!(closest, brute);
+	};
+	for _ in 0..100 {
+		let (from, to, at) = (point(), point(), point());
+		check(&bvh, &boxes, &Ray::from_to(&from.mul(3.0), &to), &at);
+	}
+	// Still right after everything moved
+	for bounds in boxes.iter_mut() {
+		let shift = point().mul(0.2);
+		*bounds = Aabb { min: bounds.min.add(&shift), max: bounds.max.add(&shift) };
+	}
+	bvh.refit(&boxes);
+	for _ in 0..100 {
+		let (from, to, at) = (point(), point(), point());
+		check(&bvh, &boxes, &Ray::from_to(&from.mul(3.0), &to), &at);
+	}
+}
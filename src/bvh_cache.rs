@@ -0,0 +1,113 @@
+//
+//	BVH Cache
+//
+
+// Building the hierarchy over a mesh of millions of triangles takes longer than loading the rest of
+// the scene, and it comes out the same every time for the same triangles. So big meshes keep theirs in
+// a directory of files named by a hash of every triangle's bounds and the settings the build goes by:
+// loading the scene again reads the tree back instead, and changing a mesh, or how trees are built,
+// gives another name and so a fresh build. Files that are unreadable, stale or damaged are built over
+
+use crate::bvh::Bvh;
+use crate::random::hash_u64;
+use crate::structs::Aabb;
+use std::fs;
+use std::path::PathBuf;
+
+// Fewer triangles than this are quicker built than read from disk
+pub const MIN_CACHED_ITEMS: usize = 10000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BvhCache {
+	pub dir: PathBuf,
+	pub min_items: usize, // smaller trees are just built
+}
+
+impl Default for BvhCache {
+	// In the system's temporary directory, shared by every scene
+	fn default() -> Self {
+		BvhCache { dir: std::env::temp_dir().join("raytracing_engine_bvh"), min_items: MIN_CACHED_ITEMS }
+	}
+}
+
+// Names the tree built over bounds, changing with any of their bits or the build settings
+pub fn cache_key(bounds: &[Aabb]) -> u64 {
+	let mut key = hash_u64(bounds.len() as u64);
+	for setting in Bvh::build_settings() {
+		key = hash_u64(key ^ setting);
+	}
+	for bounds in bounds.iter() {
+		for value in [bounds.min.x, bounds.min.y, bounds.min.z, bounds.max.x, bounds.max.y, bounds.max.z] {
+			key = hash_u64(key ^ value.to_bits());
+		}
+	}
+	key
+}
+
+impl BvhCache {
+	pub fn new(dir: PathBuf) -> Self {
+		BvhCache { dir, ..BvhCache::default() }
+	}
+
+	// The tree over bounds, read from the cache or built and written to it. Failing to write just
+	// means building again next time
+	pub fn bvh(&self, bounds: &[Aabb]) -> Bvh {
+		if bounds.len() < self.min_items { return Bvh::new(bounds) }
+		let key = cache_key(bounds);
+		let path = self.dir.join(format!("{:016x}.bvh", key));
+		if let Some(bvh) = fs::read(&path).ok().and_then(|bytes| Bvh::from_bytes(&bytes, key, bounds.len())) {
+			return bvh;
+		}
+		let bvh = Bvh::new(bounds);
+		// Written aside and moved into place, so another process never reads half a file
+		let partial = path.with_extension(format!("{}.partial", std::process::id()));
+		let written = fs::create_dir_all(&self.dir)
+			.and_then(|_| fs::write(&partial, bvh.to_bytes(key, bounds.len())))
+			.and_then(|_| fs::rename(&partial, &path));
+		if written.is_err() {
+			let _ = fs::remove_file(&partial);
+		}
+		bvh
+	}
+}
+
+#[test]
+fn cached_trees_read_back_until_the_mesh_changes() {
+	use crate::random::Rng;
+	use crate::structs::Vec3;
+	let mut rng = Rng::new(3);
+	let mut bounds: Vec<Aabb> = (0..200).map(|_| {
+		let min = Vec3 { x: rng.next_f64() * 10.0, y: rng.next_f64() * 10.0, z: rng.next_f64() * 10.0 };
+		Aabb { min, max: min.add(&Vec3 { x: 0.5, y: 0.5, z: 0.5 }) }
+	}).collect();
+	let dir = std::env::temp_dir().join(format!("bvh_cache_test_{}", std::process::id()));
+	let cache = BvhCache { dir: dir.clone(), min_items: 100 };
+	let files = || fs::read_dir(&dir).map_or(0, |entries| entries.count());
+
+	// Built and written the first time, the same tree read back the second
+	let built = cache.bvh(&bounds);
+	assert_eq!(files(), 1);
+	let path = dir.join(format!("{:016x}.bvh", cache_key(&bounds)));
+	let read = Bvh::from_bytes(&fs::read(&path).unwrap(), cache_key(&bounds), bounds.len()).unwrap();
+	assert_eq!((read.items(), read.cost()), (built.items(), built.cost()));
+	assert_eq!(cache.bvh(&bounds).items(), built.items());
+
+	// Damaged or cut short, it's built over; a moved triangle makes another entry
+	let bytes = fs::read(&path).unwrap();
+	assert!(Bvh::from_bytes(&bytes[..bytes.len() - 4], cache_key(&bounds), bounds.len()).is_none());
+	assert!(Bvh::from_bytes(&bytes, cache_key(&bounds) ^ 1, bounds.len()).is_none());
+	let mut scrambled = bytes.clone();
+	scrambled[40 + 52] = 0xff; // the root's second child
+	assert!(Bvh::from_bytes(&scrambled, cache_key(&bounds), bounds.len()).is_none());
+	fs::write(&path, &scrambled).unwrap();
+	assert_eq!(cache.bvh(&bounds).items(), built.items());
+	assert_eq!(fs::read(&path).unwrap(), bytes);
+	bounds[7].max.x += 1.0;
+	cache.bvh(&bounds);
+	assert_eq!(files(), 2);
+
+	// Small ones aren't worth it
+	cache.bvh(&bounds[..50]);
+	assert_eq!(files(), 2);
+	fs::remove_dir_all(&dir).unwrap();
+}
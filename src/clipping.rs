@@ -0,0 +1,130 @@
+//
+//	Clipping Planes
+//
+
+// Planes that cut away everything on one side of them, for cutaways of buildings and looking inside
+// models. Each cuts the objects it lists, or every object when it lists none, and every kind of ray
+// sees the cut, shadows included. Where a plane cuts through a closed object the hole can be capped
+// with a flat material, so the object looks solid rather than hollow. In a scene file:
+//
+//	clip_planes: [ (point: (0.0, 0.0, 1.5), normal: (0.0, 0.0, 1.0), cap: Some("concrete"), objects: ["house"]) ]
+
+use crate::media::crossings;
+use crate::structs::{Material, Ray, Scene, Sphere, Vec3};
+
+#[derive(Clone)]
+pub struct ClipPlane {
+	pub point: Vec3,
+	pub normal: Vec3, // towards the side that's cut away
+	pub cap: Option<Material>, // for the faces cut open, left open when None
+	pub objects: Vec<String>, // ids of what it cuts, everything when empty
+}
+
+impl ClipPlane {
+	pub fn cuts(&self, id: &str) -> bool {
+		self.objects.is_empty() || self.objects.iter().any(|object| object == id)
+	}
+}
+
+// The stretch of ray on the kept side of every plane, as distances along it, and the plane it comes
+// in through when that's ahead of the origin. None when it's all cut away
+fn kept_span<'a>(planes: &[&'a ClipPlane], ray: &Ray) -> Option<(f64, f64, Option<&'a ClipPlane>)> {
+	let (mut enter, mut exit, mut through) = (0.0f64, f64::INFINITY, None);
+	for plane in planes.iter() {
+		let (side, along) = (Vec3::dot(&ray.origin.sub(&plane.point), &plane.normal), Vec3::dot(&ray.direction, &plane.normal));
+		if along == 0.0 {
+			if side > 0.0 { return None }
+			continue;
+		}
+		let crossing = -side / along;
+		if along < 0.0 {
+			if crossing > enter {
+				enter = crossing;
+				through = Some(*plane);
+			}
+		} else {
+			exit = exit.min(crossing);
+		}
+	}
+	if enter < exit { Some((enter, exit, through)) } else { None }
+}
+
+impl Scene {
+	// objects[index]'s ray_hit with the clipping planes applied
+	pub fn clipped_hit(&self, index: usize, ray: &Ray) -> Option<(Vec3, Material, Vec3)> {
+		let object = &self.objects[index];
+		let planes: Vec<&ClipPlane> = self.clip_planes.iter().filter(|plane| plane.cuts(object.get_id())).collect();
+		if planes.is_empty() { return object.ray_hit(ray) }
+		let (enter, exit, through) = kept_span(&planes, ray)?;
+		// Traced from where the ray comes out of the cut away part, so whatever's behind the cut is found
+		let start = Ray { origin: ray.origin.add(&ray.direction.mul(enter)), direction: ray.direction, mask: ray.mask, differential: ray.differential };
+		// Coming in through a plane inside the object it hits the cap first
+		if let Some((plane, cap)) = through.and_then(|plane| plane.cap.map(|cap| (plane, cap))) {
+			if crossings(object.as_ref(), &start, f64::INFINITY).len() % 2 == 1 {
+				return Some((start.origin, cap, plane.normal.normalize()));
+			}
+		}
+		let hit = object.ray_hit(&start).or_else(|| {
+			// Opaque spheres don't see themselves from inside, and inside is where cut open ones get looked at
+			let sphere = object.as_any_immut().downcast_ref::<Sphere>()?;
+			let point = start.origin.add(&start.direction.mul(*crossings(object.as_ref(), &start, f64::INFINITY).first()?));
+			Some((point, sphere.material, point.sub(&sphere.center).div(sphere.radius as f64)))
+		});
+		let length_squared = Vec3::dot(&ray.direction, &ray.direction);
+		hit.filter(|(point, _, _)| Vec3::dot(&point.sub(&ray.origin), &ray.direction) / length_squared <= exit)
+	}
+
+	// Whether any clipping plane cuts objects[index]
+	pub fn is_clipped(&self, index: usize) -> bool {
+		let id = self.objects[index].get_id();
+		self.clip_planes.iter().any(|plane| plane.cuts(id))
+	}
+}
+
+#[test]
+fn cutaways() {
+	use crate::structs::{Camera, Color, Rot3};
+	let material = |r: u8| Material { color: Color { r, g: 0, b: 0 }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+	let origin = Vec3 { x: 0.0, y: 0.0, z: 0.0 };
+	let (mut outer, mut inner) = (Sphere::new(origin, 2.0, material(10)), Sphere::new(origin, 0.5, material(20)));
+	outer.id = String::from("shell");
+	inner.id = String::from("core");
+	let mut scene = Scene::new(vec![Box::new(outer), Box::new(inner)], Camera::new(Vec3 { x: 0.0, y: -10.0, z: 0.0 }, Rot3::new(), 40.0));
+	let towards = |x: f64| Ray::from_to(&Vec3 { x, y: -10.0, z: 0.0 }, &Vec3 { x, y: 0.0, z: 0.0 });
+	let seen = |scene: &Scene, x: f64| scene.trace(&towards(x)).map(|(point, material, normal)| (material.color.r, point.y, normal.y));
+	assert_eq!(seen(&scene, 0.0).map(|(r, _, _)| r), Some(10));
+	// Cutting the front off the shell shows the core inside
+	let front = ClipPlane { point: Vec3 { x: 0.0, y: -1.0, z: 0.0 }, normal: Vec3 { x: 0.0, y: -1.0, z: 0.0 }, cap: None, objects: vec![String::from("shell")] };
+	scene.clip_planes.push(front.clone());
+	assert!(seen(&scene, 0.0).is_some_and(|(r, y, _)| r == 20 && (y + 0.5).abs() < 1e-9));
+	// Beside the core, through the hole to the back of the shell
+	assert!(seen(&scene, 1.0).is_some_and(|(r, y, _)| r == 10 && y > 0.0));
+	// Capped, the cut face stands in front, flat and facing the camera
+	scene.clip_planes[0].cap = Some(material(30));
+	assert_eq!(seen(&scene, 1.0), Some((30, -1.0, -1.0)));
+	assert_eq!(seen(&scene, 1.9).map(|(r, _, _)| r), Some(10));
+	// A second plane leaves only a slab, cutting everything
+	scene.clip_planes[0].cap = None;
+	scene.clip_planes.push(ClipPlane { point: Vec3 { x: 0.0, y: 1.0, z: 0.0 }, normal: Vec3 { x: 0.0, y: 1.0, z: 0.0 }, cap: None, objects: Vec::new() });
+	assert!(seen(&scene, 1.0).is_none());
+	assert!(scene.is_clipped(1) && !ClipPlane { objects: vec![String::from("shell")], ..front }.cuts("core"));
+
+	// From a scene file, in its units
+	let dir = std::env::temp_dir().join(format!("clipping_test_{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	std::fs::write(dir.join("house.ron"), r#"(
+		units: Centimeters,
+		camera: Some((pos: (0.0, -500.0, 100.0), look_at: Some((0.0, 0.0, 0.0)), fov: 40.0)),
+		materials: { "brick": (color: (150, 60, 40)), "concrete": (color: (128, 128, 128)) },
+		objects: [ Cube(id: Some("house"), size: 200.0, material: "brick") ],
+		clip_planes: [ (point: (0.0, 0.0, 50.0), normal: (0.0, 0.0, 1.0), cap: Some("concrete")) ],
+	)"#).unwrap();
+	let house = crate::scene_file::load_scene(&dir.join("house.ron")).unwrap();
+	std::fs::remove_dir_all(&dir).unwrap();
+	assert!((house.clip_planes[0].point.z - 0.5).abs() < 1e-12 && house.clip_planes[0].cap.is_some_and(|cap| cap.color.r == 128));
+	// Looking straight down into the house, the cap is the floor of the cut
+	let down = Ray::from_to(&Vec3 { x: 0.2, y: 0.1, z: 3.0 }, &Vec3 { x: 0.2, y: 0.1, z: 0.0 });
+	assert!(house.trace(&down).is_some_and(|(point, material, _)| (point.z - 0.5).abs() < 1e-9 && material.color.r == 128));
+	let saved = crate::scene_file::SceneFile::from_scene(&house);
+	assert_eq!(saved.materials[saved.clip_planes[0].cap.as_ref().unwrap()].color, [128, 128, 128]);
+}
@@ -0,0 +1,77 @@
+//
+//	A/B Comparison
+//
+
+use crate::frontend::Presenter;
+use crate::input::ButtonState;
+use crate::render::RenderSettings;
+use crate::structs::Color;
+
+// Pixels either side of the divider that still grab it
+const GRAB_DISTANCE: i32 = 2;
+
+// Split screen between two sets of render settings. Left of the divider is A, frozen at whatever the
+// settings were when the comparison started, right of it is B, the live settings every control keeps
+// changing, so the two can be told apart side by side on the same frame
+pub struct Comparison {
+	pub settings: RenderSettings, // A
+	pub divider: i32, // first column rendered with B
+	pub dragging: bool,
+}
+
+impl Comparison {
+	pub fn new(settings: RenderSettings, width: i32) -> Self {
+		Comparison { settings, divider: width / 2, dragging: false }
+	}
+
+	pub fn is_a(&self, x: usize) -> bool {
+		(x as i32) < self.divider
+	}
+
+	// The left button picks the divider up near it and carries it along until let go
+	pub fn drag(&mut self, mouse_x: i32, button: ButtonState, width: i32) {
+		if button.pressed && (mouse_x - self.divider).abs() <= GRAB_DISTANCE {
+			self.dragging = true;
+		}
+		if !button.held {
+			self.dragging = false;
+		}
+		if self.dragging {
+			self.divider = mouse_x.clamp(0, width);
+		}
+	}
+
+	// The divider with A and B either side of its top
+	pub fn draw(&self, presenter: &mut dyn Presenter, height: i32) {
+		let white = Color { r: 255, g: 255, b: 255 };
+		for y in 0..height {
+			presenter.draw(self.divider, y, white);
+		}
+		presenter.draw_string(self.divider - 9, 1, "A", white);
+		presenter.draw_string(self.divider + 3, 1, "B", white);
+	}
+}
+
+#[test]
+fn divider_drags() {
+	let (press, hold, release) = (
+		ButtonState { pressed: true, held: true, released: false },
+		ButtonState { pressed: false, held: true, released: false },
+		ButtonState { pressed: false, held: false, released: true },
+	);
+	let mut comparison = Comparison::new(RenderSettings::new(), 160);
+	assert!(comparison.is_a(79) && !comparison.is_a(80));
+	// Pressing away from the divider leaves it be
+	comparison.drag(20, press, 160);
+	comparison.drag(30, hold, 160);
+	assert_eq!(comparison.divider, 80);
+	// Grabbed next to it, it follows the mouse and stays in the frame, until let go
+	comparison.drag(81, press, 160);
+	comparison.drag(120, hold, 160);
+	assert_eq!(comparison.divider, 120);
+	comparison.drag(400, hold, 160);
+	assert_eq!(comparison.divider, 160);
+	comparison.drag(150, release, 160);
+	comparison.drag(10, hold, 160);
+	assert!(!comparison.dragging && comparison.divider == 160);
+}
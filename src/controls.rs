@@ -0,0 +1,107 @@
+//
+//	Camera Controls
+//
+
+use crate::structs::{Camera, Rot3, Vec3};
+use crate::random::hash_u64;
+
+const WORLD_UP: Vec3 = Vec3 { x: 0.0, y: 0.0, z: 1.0 };
+
+// Keeps the camera on a sphere around a pivot, always looking at it
+pub struct OrbitController {
+	pub pivot: Vec3,
+	pub distance: f64,
+	pub azimuth: f64,
+	pub elevation: f64,
+}
+
+impl OrbitController {
+	// Starts orbiting from wherever the camera currently is
+	pub fn from_camera(camera: &Camera, pivot: Vec3) -> Self {
+		let offset = camera.pos.sub(&pivot);
+		let distance = camera.pos.dist(&pivot).max(0.1);
+		OrbitController {
+			pivot,
+			distance,
+			azimuth: offset.y.atan2(offset.x),
+			elevation: (offset.z / distance).clamp(-1.0, 1.0).asin(),
+		}
+	}
+
+	pub fn rotate(&mut self, d_azimuth: f64, d_elevation: f64) {
+		let limit = std::f64::consts::FRAC_PI_2 - 0.01;
+		self.azimuth += d_azimuth;
+		self.elevation = (self.elevation + d_elevation).clamp(-limit, limit);
+	}
+
+	// factor < 1 moves closer, > 1 moves away
+	pub fn zoom(&mut self, factor: f64) {
+		self.distance = (self.distance * factor).max(0.1);
+	}
+
+	pub fn apply(&self, camera: &mut Camera) {
+		let (sa, ca) = self.azimuth.sin_cos();
+		let (se, ce) = self.elevation.sin_cos();
+		let offset = Vec3 { x: ce * ca, y: ce * sa, z: se }.mul(self.distance);
+		camera.pos = self.pivot.add(&offset);
+		camera.look_at(&self.pivot, &WORLD_UP);
+	}
+}
+
+// Procedural handheld jitter, a pure function of time so it layers on top of any
+// camera motion: shake the pose for a frame, render, then put the pose back
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraShake {
+	pub amplitude: f64, // world units of positional wobble
+	pub rotation_amplitude: f64, // radians of angular wobble
+	pub frequency: f64, // wobbles per second, roughly
+	pub seed: u64,
+}
+
+impl CameraShake {
+	pub fn new(amplitude: f64, rotation_amplitude: f64, frequency: f64, seed: u64) -> Self {
+		CameraShake { amplitude, rotation_amplitude, frequency, seed }
+	}
+
+	// Offsets to add to the camera's position and rotation at time seconds
+	pub fn offset(&self, time: f64) -> (Vec3, Rot3) {
+		let t = time * self.frequency;
+		let channel = |index: u64| shake_noise(t, hash_u64(self.seed ^ index));
+		(
+			Vec3 { x: channel(0), y: channel(1), z: channel(2) }.mul(self.amplitude),
+			Rot3 { roll: channel(3) * self.rotation_amplitude, pitch: channel(4) * self.rotation_amplitude, yaw: channel(5) * self.rotation_amplitude },
+		)
+	}
+
+	pub fn apply(&self, camera: &mut Camera, time: f64) {
+		let (position, rotation) = self.offset(time);
+		camera.pos = camera.pos.add(&position);
+		camera.rot = Rot3 { roll: camera.rot.roll + rotation.roll, pitch: camera.rot.pitch + rotation.pitch, yaw: camera.rot.yaw + rotation.yaw };
+	}
+}
+
+// Smoothly interpolated value noise in [-1, 1], one octave on top of another for a less regular feel
+fn shake_noise(t: f64, seed: u64) -> f64 {
+	let lattice = |i: i64| (hash_u64(seed ^ i as u64) >> 11) as f64 / (1u64 << 52) as f64 - 1.0;
+	let octave = |t: f64| {
+		let (i, f) = (t.floor(), t - t.floor());
+		let smooth = f * f * (3.0 - 2.0 * f);
+		lattice(i as i64) * (1.0 - smooth) + lattice(i as i64 + 1) * smooth
+	};
+	(octave(t) + 0.5 * octave(t * 2.0 + 17.0)) / 1.5
+}
+
+#[test]
+fn camera_shake_is_bounded_and_repeatable() {
+	let shake = CameraShake::new(0.1, 0.02, 3.0, 7);
+	for step in 0..200 {
+		let time = step as f64 * 0.013;
+		let (position, rotation) = shake.offset(time);
+		assert!(position.x.abs() <= 0.1 && position.y.abs() <= 0.1 && position.z.abs() <= 0.1);
+		assert!(rotation.roll.abs() <= 0.02 && rotation.pitch.abs() <= 0.02 && rotation.yaw.abs() <= 0.02);
+		let (again, _) = shake.offset(time);
+		assert_eq!((position.x, position.y, position.z), (again.x, again.y, again.z));
+	}
+	let other = CameraShake { seed: 8, ..shake };
+	assert!(shake.offset(1.0).0.x != other.offset(1.0).0.x);
+}
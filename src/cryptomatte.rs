@@ -0,0 +1,177 @@
+//
+//	Cryptomatte
+//
+
+// ID mattes laid out the way compositing packages read them: every object and material name is
+// hashed to a float id and each pixel keeps its most covering ids along with how much they cover,
+// so anti-aliased edges can still be pulled apart in post
+
+use crate::exr;
+use crate::render::Scratch;
+use crate::structs::{Material, Scene};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+// Ids kept per pixel, two to a set of RGBA channels
+const RANKS: usize = 6;
+
+pub fn murmur3_32(data: &[u8], seed: u32) -> u32 {
+	const C1: u32 = 0xcc9e2d51;
+	const C2: u32 = 0x1b873593;
+	let mix = |k: u32| k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+	let mut hash = seed;
+	let mut chunks = data.chunks_exact(4);
+	for chunk in &mut chunks {
+		hash ^= mix(u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]));
+		hash = hash.rotate_left(13).wrapping_mul(5).wrapping_add(0xe6546b64);
+	}
+	let tail = chunks.remainder();
+	if !tail.is_empty() {
+		hash ^= mix(tail.iter().rev().fold(0, |k, byte| (k << 8) | *byte as u32));
+	}
+	hash ^= data.len() as u32;
+	hash ^= hash >> 16;
+	hash = hash.wrapping_mul(0x85ebca6b);
+	hash ^= hash >> 13;
+	hash = hash.wrapping_mul(0xc2b2ae35);
+	hash ^ (hash >> 16)
+}
+
+// The hash with its float exponent kept off denormals, infinities and NaNs
+fn name_hash(name: &str) -> u32 {
+	let hash = murmur3_32(name.as_bytes(), 0);
+	let exponent = (hash >> 23) & 0xff;
+	if exponent == 0 || exponent == 255 { hash ^ (1 << 23) } else { hash }
+}
+
+pub fn name_id(name: &str) -> f32 {
+	f32::from_bits(name_hash(name))
+}
+
+// Materials have no names of their own, so they go by what they look like
+pub fn material_name(material: &Material) -> String {
+	format!("#{:02x}{:02x}{:02x} reflectivity {} transparency {}", material.color.r, material.color.g, material.color.b, material.reflectivity, material.transparency)
+}
+
+pub struct Matte {
+	pub name: String, // layer name, channels are called name00.R and so on
+	pub width: usize,
+	pub height: usize,
+	pub coverage: Vec<Vec<(f32, f32)>>, // per pixel (id, coverage), most covering first
+	pub manifest: BTreeMap<String, u32>, // every name that appears, with its hash
+}
+
+impl Matte {
+	pub fn new(name: &str, width: usize, height: usize) -> Self {
+		Matte { name: name.to_string(), width, height, coverage: vec![Vec::new(); width * height], manifest: BTreeMap::new() }
+	}
+
+	pub fn add(&mut self, pixel: usize, name: &str, weight: f32) {
+		let id = name_id(name);
+		self.manifest.entry(name.to_string()).or_insert_with(|| id.to_bits());
+		let pixel = &mut self.coverage[pixel];
+		match pixel.iter_mut().find(|(existing, _)| existing.to_bits() == id.to_bits()) {
+			Some((_, coverage)) => *coverage += weight,
+			None => pixel.push((id, weight)),
+		}
+	}
+
+	// Ranks each pixel's ids, dropping whatever doesn't fit
+	pub fn finish(&mut self) {
+		for pixel in self.coverage.iter_mut() {
+			pixel.sort_by(|a, b| b.1.total_cmp(&a.1));
+			pixel.truncate(RANKS);
+		}
+	}
+
+	fn channels(&self) -> Vec<(String, Vec<f32>)> {
+		let mut channels = Vec::new();
+		for set in 0..RANKS / 2 {
+			for (suffix, rank, coverage) in [("R", set * 2, false), ("G", set * 2, true), ("B", set * 2 + 1, false), ("A", set * 2 + 1, true)].iter() {
+				let values = self.coverage.iter().map(|pixel| match pixel.get(*rank) {
+					Some((id, weight)) => if *coverage { *weight } else { *id },
+					None => 0.0,
+				}).collect();
+				channels.push((format!("{}{:02}.{}", self.name, set, suffix), values));
+			}
+		}
+		channels
+	}
+
+	// Header attributes tying the channels to their names, keyed by a hash of the layer name
+	fn metadata(&self) -> Vec<(String, String)> {
+		let key = &format!("{:08x}", murmur3_32(self.name.as_bytes(), 0))[..7];
+		let entries: Vec<String> = self.manifest.iter().map(|(name, hash)| format!("\"{}\":\"{:08x}\"", escape(name), hash)).collect();
+		vec![
+			(format!("cryptomatte/{}/name", key), self.name.clone()),
+			(format!("cryptomatte/{}/hash", key), String::from("MurmurHash3_32")),
+			(format!("cryptomatte/{}/conversion", key), String::from("uint32_to_float32")),
+			(format!("cryptomatte/{}/manifest", key), format!("{{{}}}", entries.join(","))),
+		]
+	}
+}
+
+fn escape(name: &str) -> String {
+	name.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Object and material mattes of a width x height frame, sampled like the beauty pass
+pub fn render(scene: &mut Scene, width: i32, height: i32) -> [Matte; 2] {
+	scene.update_culling(width, height);
+	let settings = scene.render_settings;
+	let samples = settings.samples_per_pixel.max(1);
+	let mut scratch = Scratch::new(&settings);
+	let mut objects = Matte::new("CryptoObject", width as usize, height as usize);
+	let mut materials = Matte::new("CryptoMaterial", width as usize, height as usize);
+	for index in 0..(width * height) as usize {
+		let (x, y) = (index as i32 % width, index as i32 / width);
+		for sample_index in 0..samples {
+			scratch.sampler.start_pixel_sample(x as u32, y as u32, sample_index);
+			let jitter = scratch.sampler.next_2d();
+			let offset = if samples == 1 { (0.5, 0.5) } else { jitter };
+			let ray = scene.primary_ray(x, y, offset, width, height);
+			if let Some((object, hit)) = scene.trace_primary_object(&ray) {
+				objects.add(index, scene.objects[object].get_id(), 1.0 / samples as f32);
+				materials.add(index, &material_name(&hit.1), 1.0 / samples as f32);
+			}
+		}
+	}
+	objects.finish();
+	materials.finish();
+	[objects, materials]
+}
+
+// All the mattes as layers of one exr
+pub fn write(path: &Path, mattes: &[Matte]) -> io::Result<()> {
+	let (width, height) = mattes.first().map(|matte| (matte.width, matte.height)).unwrap_or((0, 0));
+	let channels: Vec<(String, Vec<f32>)> = mattes.iter().flat_map(|matte| matte.channels()).collect();
+	let metadata: Vec<(String, String)> = mattes.iter().flat_map(|matte| matte.metadata()).collect();
+	let borrowed: Vec<(&str, &[f32])> = channels.iter().map(|(name, values)| (name.as_str(), values.as_slice())).collect();
+	exr::write_with_metadata(path, width, height, &borrowed, &metadata)
+}
+
+#[test]
+fn murmur_hash() {
+	assert_eq!(murmur3_32(b"", 0), 0);
+	assert_eq!(murmur3_32(b"hello", 0), 0x248bfa47);
+	assert_eq!(murmur3_32(b"hello, world", 0), 0x149bbb7f);
+	assert!(name_id("fuckin' cube").is_finite());
+}
+
+#[test]
+fn coverage_adds_up() {
+	let mut scene = Scene::default_scene();
+	scene.render_settings.samples_per_pixel = 4;
+	let [objects, materials] = render(&mut scene, 32, 18);
+	assert!(objects.manifest.contains_key("fuckin' cube"));
+	let cube = name_id("fuckin' cube");
+	// Somewhere along the cube's outline a pixel is only partly covered by it
+	assert!(objects.coverage.iter().flatten().any(|(id, coverage)| *id == cube && *coverage > 0.0 && *coverage < 1.0));
+	for matte in [&objects, &materials].iter() {
+		for pixel in matte.coverage.iter() {
+			assert!(pixel.iter().map(|(_, coverage)| coverage).sum::<f32>() <= 1.0 + 1e-6);
+			assert!(pixel.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+		}
+	}
+}
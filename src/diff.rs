@@ -0,0 +1,70 @@
+//
+//	Image Difference
+//
+
+// How far apart two images of the same size are, for checking a change didn't move any pixels or
+// finding the ones it did. Differences are in 0-255 channel steps, a pixel's being its largest channel's
+
+use crate::structs::Color;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiffStats {
+	pub rmse: f64, // over every channel of every pixel
+	pub psnr: f64, // decibels, infinite for identical images
+	pub max_diff: u8,
+	pub differing: usize, // pixels differing by more than the tolerance
+}
+
+fn pixel_diff(a: &Color, b: &Color) -> u8 {
+	a.r.abs_diff(b.r).max(a.g.abs_diff(b.g)).max(a.b.abs_diff(b.b))
+}
+
+// a and b are the same size, row by row
+pub fn compare(a: &[Color], b: &[Color], tolerance: u8) -> DiffStats {
+	let mut squared = 0.0;
+	let (mut max_diff, mut differing) = (0, 0);
+	for (a, b) in a.iter().zip(b.iter()) {
+		for (a, b) in [(a.r, b.r), (a.g, b.g), (a.b, b.b)] {
+			squared += (a as f64 - b as f64).powi(2);
+		}
+		let diff = pixel_diff(a, b);
+		max_diff = max_diff.max(diff);
+		if diff > tolerance {
+			differing += 1;
+		}
+	}
+	let rmse = (squared / (a.len().max(1) * 3) as f64).sqrt();
+	DiffStats { rmse, psnr: 20.0 * (255.0 / rmse).log10(), max_diff, differing }
+}
+
+// Black where the images agree, through red and yellow to white at the largest difference between them,
+// so even a single step stands out
+pub fn heatmap(a: &[Color], b: &[Color]) -> Vec<Color> {
+	let max_diff = a.iter().zip(b.iter()).map(|(a, b)| pixel_diff(a, b)).max().unwrap_or(0).max(1) as f64;
+	a.iter().zip(b.iter()).map(|(a, b)| {
+		let heat = pixel_diff(a, b) as f64 / max_diff * 3.0;
+		let channel = |from: f64| ((heat - from).clamp(0.0, 1.0) * 255.0).round() as u8;
+		Color { r: channel(0.0), g: channel(1.0), b: channel(2.0) }
+	}).collect()
+}
+
+#[test]
+fn differences_measured() {
+	let gray = |value: u8| Color { r: value, g: value, b: value };
+	let a = vec![gray(10), gray(20), gray(30), gray(40)];
+	let same = compare(&a, &a, 0);
+	assert_eq!((same.rmse, same.psnr, same.max_diff, same.differing), (0.0, f64::INFINITY, 0, 0));
+
+	// One pixel off by 6 in red only, another by 2 in every channel
+	let b = vec![Color { r: 16, g: 10, b: 10 }, gray(22), gray(30), gray(40)];
+	let stats = compare(&a, &b, 2);
+	assert!((stats.rmse - 2.0).abs() < 1e-12);
+	assert!((stats.psnr - 20.0 * (255.0 / 2.0f64).log10()).abs() < 1e-9);
+	assert_eq!((stats.max_diff, stats.differing), (6, 1));
+	assert_eq!(compare(&a, &b, 0).differing, 2);
+
+	let heat = heatmap(&a, &b);
+	assert_eq!(heat[0], gray(255));
+	assert_eq!(heat[1], Color { r: 255, g: 0, b: 0 });
+	assert_eq!((heat[2], heatmap(&a, &a)[0]), (gray(0), gray(0)));
+}
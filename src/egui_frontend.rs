@@ -0,0 +1,376 @@
+//
+//	egui Frontend
+//
+
+use eframe::egui;
+use raytracing_engine::structs::{Color, FovAxis, PhysicalLens};
+use raytracing_engine::controls::CameraShake;
+use raytracing_engine::quality::QualityController;
+use raytracing_engine::exposure::AutoExposure;
+use raytracing_engine::render::TraversalOrder;
+use raytracing_engine::sampler::SamplerKind;
+use raytracing_engine::input::{ButtonState, Key};
+use raytracing_engine::frontend::{InputSource, Presenter};
+use std::time::Instant;
+use crate::ExampleProgram;
+
+const TRAVERSAL_ORDERS: [TraversalOrder; 5] = [TraversalOrder::Scanline, TraversalOrder::Spiral, TraversalOrder::Hilbert, TraversalOrder::Random, TraversalOrder::BlueNoise];
+const SAMPLERS: [SamplerKind; 5] = [SamplerKind::Independent, SamplerKind::Halton, SamplerKind::Sobol, SamplerKind::BlueNoise, SamplerKind::Stratified];
+
+fn egui_key(key: Key) -> Option<egui::Key> {
+	Some(match key {
+		Key::A => egui::Key::A,
+		Key::B => egui::Key::B,
+		Key::C => egui::Key::C,
+		Key::D => egui::Key::D,
+		Key::E => egui::Key::E,
+		Key::F => egui::Key::F,
+		Key::G => egui::Key::G,
+		Key::H => egui::Key::H,
+		Key::I => egui::Key::I,
+		Key::J => egui::Key::J,
+		Key::K => egui::Key::K,
+		Key::L => egui::Key::L,
+		Key::M => egui::Key::M,
+		Key::N => egui::Key::N,
+		Key::O => egui::Key::O,
+		Key::P => egui::Key::P,
+		Key::Q => egui::Key::Q,
+		Key::R => egui::Key::R,
+		Key::S => egui::Key::S,
+		Key::T => egui::Key::T,
+		Key::U => egui::Key::U,
+		Key::V => egui::Key::V,
+		Key::W => egui::Key::W,
+		Key::X => egui::Key::X,
+		Key::Y => egui::Key::Y,
+		Key::Z => egui::Key::Z,
+		Key::K0 | Key::Numpad0 => egui::Key::Num0,
+		Key::K1 | Key::Numpad1 => egui::Key::Num1,
+		Key::K2 | Key::Numpad2 => egui::Key::Num2,
+		Key::K3 | Key::Numpad3 => egui::Key::Num3,
+		Key::K4 | Key::Numpad4 => egui::Key::Num4,
+		Key::K5 | Key::Numpad5 => egui::Key::Num5,
+		Key::K6 | Key::Numpad6 => egui::Key::Num6,
+		Key::K7 | Key::Numpad7 => egui::Key::Num7,
+		Key::K8 | Key::Numpad8 => egui::Key::Num8,
+		Key::K9 | Key::Numpad9 => egui::Key::Num9,
+		Key::F1 => egui::Key::F1,
+		Key::F2 => egui::Key::F2,
+		Key::F3 => egui::Key::F3,
+		Key::F4 => egui::Key::F4,
+		Key::F5 => egui::Key::F5,
+		Key::F6 => egui::Key::F6,
+		Key::F7 => egui::Key::F7,
+		Key::F8 => egui::Key::F8,
+		Key::F9 => egui::Key::F9,
+		Key::F10 => egui::Key::F10,
+		Key::F11 => egui::Key::F11,
+		Key::F12 => egui::Key::F12,
+		Key::Up => egui::Key::ArrowUp,
+		Key::Down => egui::Key::ArrowDown,
+		Key::Left => egui::Key::ArrowLeft,
+		Key::Right => egui::Key::ArrowRight,
+		Key::Space => egui::Key::Space,
+		Key::Tab => egui::Key::Tab,
+		Key::Insert => egui::Key::Insert,
+		Key::Delete => egui::Key::Delete,
+		Key::Home => egui::Key::Home,
+		Key::End => egui::Key::End,
+		Key::PageUp => egui::Key::PageUp,
+		Key::PageDown => egui::Key::PageDown,
+		Key::Backspace => egui::Key::Backspace,
+		Key::Escape => egui::Key::Escape,
+		Key::Return | Key::Enter => egui::Key::Enter,
+		Key::NumpadAdd => egui::Key::Plus,
+		Key::NumpadSub => egui::Key::Minus,
+		Key::NumpadDiv => egui::Key::Slash,
+		Key::Period | Key::NumpadDecimal => egui::Key::Period,
+		// Modifiers are not keys to egui, and the rest has no equivalent
+		Key::Shift | Key::Ctrl | Key::Pause | Key::ScrollLock | Key::NumpadMul => return None,
+	})
+}
+
+struct EguiInput<'a> {
+	input: &'a egui::InputState,
+	keyboard: bool, // false while a panel widget has keyboard focus
+	image_rect: egui::Rect,
+	image_size: (usize, usize),
+}
+
+impl EguiInput<'_> {
+	fn over_image(&self) -> bool {
+		self.input.pointer.hover_pos().is_some_and(|pos| self.image_rect.contains(pos))
+	}
+}
+
+impl InputSource for EguiInput<'_> {
+	fn key_state(&self, key: Key) -> ButtonState {
+		if !self.keyboard { return ButtonState::default() }
+		let modifiers = &self.input.modifiers;
+		match key {
+			Key::Shift => ButtonState { held: modifiers.shift, ..ButtonState::default() },
+			Key::Ctrl => ButtonState { held: modifiers.ctrl, ..ButtonState::default() },
+			_ => match egui_key(key) {
+				Some(key) => ButtonState {
+					pressed: self.input.key_pressed(key),
+					held: self.input.key_down(key),
+					released: self.input.key_released(key),
+				},
+				None => ButtonState::default(),
+			},
+		}
+	}
+
+	fn mouse_position(&self) -> (i32, i32) {
+		match self.input.pointer.latest_pos() {
+			Some(pos) => {
+				let local = (pos - self.image_rect.min) / self.image_rect.size();
+				((local.x * self.image_size.0 as f32) as i32, (local.y * self.image_size.1 as f32) as i32)
+			}
+			None => (0, 0),
+		}
+	}
+
+	fn mouse_button(&self, button: u32) -> ButtonState {
+		if !self.over_image() { return ButtonState::default() }
+		let button = match button {
+			0 => egui::PointerButton::Primary,
+			1 => egui::PointerButton::Secondary,
+			_ => egui::PointerButton::Middle,
+		};
+		let pointer = &self.input.pointer;
+		ButtonState {
+			pressed: pointer.button_pressed(button),
+			held: pointer.button_down(button),
+			released: pointer.button_released(button),
+		}
+	}
+
+	fn mouse_wheel(&self) -> i32 {
+		if !self.over_image() { return 0 }
+		let scroll = self.input.smooth_scroll_delta.y;
+		if scroll > 0.0 { 1 } else if scroll < 0.0 { -1 } else { 0 }
+	}
+}
+
+struct EguiPresenter {
+	image: egui::ColorImage,
+}
+
+impl Presenter for EguiPresenter {
+	fn draw(&mut self, x: i32, y: i32, color: Color) {
+		let [width, height] = self.image.size;
+		if x < 0 || y < 0 || x as usize >= width || y as usize >= height { return }
+		self.image.pixels[y as usize * width + x as usize] = egui::Color32::from_rgb(color.r, color.g, color.b);
+	}
+}
+
+struct EguiApp<'a> {
+	program: &'a mut ExampleProgram,
+	presenter: EguiPresenter,
+	texture: egui::TextureHandle,
+	image_rect: egui::Rect,
+	last_frame: Instant,
+}
+
+impl EguiApp<'_> {
+	fn controls(&mut self, ui: &mut egui::Ui) {
+		ui.heading("Camera");
+		if ui.button("frame selection").on_hover_text("or the whole scene when nothing is selected").clicked() {
+			self.program.frame_view();
+		}
+		let scene = &mut self.program.current_scene;
+		let [width, height] = self.presenter.image.size;
+
+		let camera = &mut scene.current_camera;
+		let mut fov = camera.fov;
+		if ui.add(egui::Slider::new(&mut fov, 1.0..=179.0).text("fov")).changed() {
+			camera.set_fov(fov);
+		}
+		let mut axis = camera.fov_axis;
+		ui.horizontal(|ui| {
+			ui.radio_value(&mut axis, FovAxis::Vertical, "vertical");
+			ui.radio_value(&mut axis, FovAxis::Horizontal, "horizontal");
+		});
+		camera.set_fov_axis(axis, width as f64 / height as f64);
+		let mut physical = camera.lens.is_some();
+		if ui.checkbox(&mut physical, "physical lens").changed() {
+			camera.set_lens(if physical { Some(PhysicalLens::full_frame(50.0)) } else { None });
+		}
+		if let Some(mut lens) = camera.lens {
+			ui.horizontal(|ui| {
+				ui.add(egui::DragValue::new(&mut lens.sensor_width).speed(0.1).range(1.0..=100.0).prefix("sensor ").suffix("mm"));
+				ui.add(egui::DragValue::new(&mut lens.sensor_height).speed(0.1).range(1.0..=100.0).prefix("x ").suffix("mm"));
+			});
+			ui.add(egui::DragValue::new(&mut lens.focal_length).speed(0.5).range(1.0..=1000.0).prefix("focal length ").suffix("mm"));
+			ui.horizontal(|ui| {
+				ui.add(egui::DragValue::new(&mut lens.shift_x).speed(0.1).prefix("shift x ").suffix("mm"));
+				ui.add(egui::DragValue::new(&mut lens.shift_y).speed(0.1).prefix("y ").suffix("mm"));
+			});
+			if Some(lens) != camera.lens {
+				camera.set_lens(Some(lens));
+			}
+		}
+
+		let mut shaking = self.program.shake.is_some();
+		if ui.checkbox(&mut shaking, "camera shake").changed() {
+			self.program.shake = if shaking { Some(CameraShake::new(0.03, 0.01, 1.5, scene.render_settings.seed)) } else { None };
+		}
+		if let Some(shake) = self.program.shake.as_mut() {
+			ui.add(egui::Slider::new(&mut shake.amplitude, 0.0..=0.5).text("amplitude"));
+			ui.add(egui::Slider::new(&mut shake.rotation_amplitude, 0.0..=0.1).text("rotation"));
+			ui.add(egui::Slider::new(&mut shake.frequency, 0.1..=10.0).text("frequency"));
+			ui.add(egui::DragValue::new(&mut shake.seed).prefix("shake seed "));
+		}
+
+		ui.separator();
+		ui.heading("Lights");
+		for light in scene.get_all_light_sources() {
+			ui.label(&light.id);
+			ui.horizontal(|ui| {
+				ui.add(egui::DragValue::new(&mut light.pos.x).speed(0.05).prefix("x "));
+				ui.add(egui::DragValue::new(&mut light.pos.y).speed(0.05).prefix("y "));
+				ui.add(egui::DragValue::new(&mut light.pos.z).speed(0.05).prefix("z "));
+			});
+			ui.add(egui::Slider::new(&mut light.intensity, 0.0..=10.0).text("intensity"));
+			ui.add(egui::Slider::new(&mut light.radius, 0.0..=2.0).text("radius"));
+			color_edit(ui, &mut light.color);
+		}
+
+		ui.separator();
+		ui.heading("Selected object");
+		let editable: Vec<String> = scene.objects.iter()
+			.filter(|object| object.get_material().is_some())
+			.map(|object| object.get_id().clone())
+			.collect();
+		let selected = &mut self.program.selected;
+		egui::ComboBox::from_id_salt("selected object")
+			.selected_text(selected.as_deref().unwrap_or("none (right click the image)"))
+			.show_ui(ui, |ui| {
+				for id in editable {
+					ui.selectable_value(selected, Some(id.clone()), id);
+				}
+			});
+		if let Some(object) = selected.as_deref().and_then(|id| scene.find_object(id)) {
+			if let Some(mut material) = object.get_material() {
+				let before = material;
+				color_edit(ui, &mut material.color);
+				ui.add(egui::Slider::new(&mut material.reflectivity, 0.0..=1.0).text("reflectivity"));
+				ui.add(egui::Slider::new(&mut material.transparency, 0.0..=1.0).text("transparency"));
+				ui.add(egui::Slider::new(&mut material.ior, 1.0..=2.5).text("ior"));
+				if material != before {
+					object.set_material(material);
+				}
+			}
+		}
+
+		ui.separator();
+		ui.heading("Render settings");
+		let settings = &mut scene.render_settings;
+		ui.add(egui::Slider::new(&mut settings.samples_per_pixel, 1..=64).text("samples per pixel"));
+		ui.add(egui::Slider::new(&mut settings.light_samples, 1..=64).text("light samples"));
+		egui::ComboBox::from_label("sampler")
+			.selected_text(format!("{:?}", settings.sampler))
+			.show_ui(ui, |ui| {
+				for kind in SAMPLERS.iter() {
+					ui.selectable_value(&mut settings.sampler, *kind, format!("{:?}", kind));
+				}
+			});
+		let (order, seed) = (settings.traversal_order, settings.seed);
+		egui::ComboBox::from_label("traversal order")
+			.selected_text(format!("{:?}", settings.traversal_order))
+			.show_ui(ui, |ui| {
+				for order in TRAVERSAL_ORDERS.iter() {
+					ui.selectable_value(&mut settings.traversal_order, *order, format!("{:?}", order));
+				}
+			});
+		ui.add(egui::DragValue::new(&mut settings.seed).prefix("seed "));
+		ui.checkbox(&mut settings.interleaved, "interleaved (half the pixels per frame)");
+		ui.add(egui::Slider::new(&mut settings.max_bounces, 0..=1).text("bounces"));
+		ui.add(egui::Slider::new(&mut settings.exposure, -8.0..=8.0).text("exposure (stops)"));
+		let mut auto = self.program.auto_exposure.is_some();
+		if ui.checkbox(&mut auto, "auto exposure").changed() {
+			self.program.auto_exposure = if auto { Some(AutoExposure::new()) } else { None };
+		}
+		if let Some(auto_exposure) = self.program.auto_exposure.as_mut() {
+			ui.add(egui::Slider::new(&mut auto_exposure.target, 0.05..=0.95).text("target brightness"));
+			ui.add(egui::Slider::new(&mut auto_exposure.adaptation_time, 0.05..=5.0).text("adaptation (s)"));
+		}
+		let mut adaptive = self.program.quality.is_some();
+		if ui.checkbox(&mut adaptive, "adaptive quality").changed() {
+			self.program.quality = if adaptive { Some(QualityController::new(0.033)) } else { None };
+		}
+		if let Some(quality) = self.program.quality.as_mut() {
+			let mut budget_ms = quality.budget * 1000.0;
+			if ui.add(egui::Slider::new(&mut budget_ms, 5.0..=200.0).text("frame budget (ms)")).changed() {
+				quality.budget = budget_ms / 1000.0;
+			}
+			ui.label(format!("quality level {}", quality.level));
+		}
+		if settings.traversal_order != order || settings.seed != seed {
+			self.program.refresh_render_order();
+		}
+	}
+}
+
+fn color_edit(ui: &mut egui::Ui, color: &mut Color) {
+	let mut rgb = [color.r, color.g, color.b];
+	ui.horizontal(|ui| {
+		ui.label("color");
+		ui.color_edit_button_srgb(&mut rgb);
+	});
+	*color = Color { r: rgb[0], g: rgb[1], b: rgb[2] };
+}
+
+impl eframe::App for EguiApp<'_> {
+	fn ui(&mut self, ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
+		egui::Panel::left("controls").show(ui, |ui| {
+			egui::ScrollArea::vertical().show(ui, |ui| self.controls(ui));
+		});
+
+		egui::CentralPanel::default().show(ui, |ui| {
+			let [width, height] = self.presenter.image.size;
+			let elapsed_time = self.last_frame.elapsed().as_secs_f32();
+			self.last_frame = Instant::now();
+			let keyboard = !ui.ctx().egui_wants_keyboard_input();
+			let image_rect = self.image_rect;
+			let program = &mut *self.program;
+			let presenter = &mut self.presenter;
+			ui.input(|input| {
+				let input = EguiInput { input, keyboard, image_rect, image_size: (width, height) };
+				program.update(presenter, &input, elapsed_time);
+			});
+			self.texture.set(self.presenter.image.clone(), egui::TextureOptions::NEAREST);
+
+			// Letterbox the image into whatever space the panel leaves
+			let available = ui.available_size();
+			let scale = (available.x / width as f32).min(available.y / height as f32);
+			let size = egui::vec2(width as f32, height as f32) * scale;
+			self.image_rect = ui.add(egui::Image::new(&self.texture).fit_to_exact_size(size).sense(egui::Sense::drag())).rect;
+		});
+
+		ui.ctx().request_repaint();
+	}
+}
+
+pub fn run(program: &mut ExampleProgram, width: usize, height: usize) {
+	let options = eframe::NativeOptions {
+		viewport: egui::ViewportBuilder::default().with_inner_size([width as f32 * 4.0 + 280.0, height as f32 * 4.0]),
+		..eframe::NativeOptions::default()
+	};
+	let result = eframe::run_native("Raytracing", options, Box::new(move |cc| {
+		let image = egui::ColorImage::new([width, height], vec![egui::Color32::BLACK; width * height]);
+		let texture = cc.egui_ctx.load_texture("render", image.clone(), egui::TextureOptions::NEAREST);
+		Ok(Box::new(EguiApp {
+			program,
+			presenter: EguiPresenter { image },
+			texture,
+			image_rect: egui::Rect::NOTHING,
+			last_frame: Instant::now(),
+		}))
+	}));
+	if let Err(e) = result {
+		eprintln!("Could not open window: {}", e);
+	}
+}
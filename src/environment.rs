@@ -0,0 +1,207 @@
+//
+//	Environment Lighting
+//
+
+// A lat-long panorama all round the scene, infinitely far away, with up at the top of the image.
+// It lights every surface and shows in their reflections without a ray ever being traced at it:
+// when it's set, the light it gives a diffuse surface facing any way is boiled down to nine spherical
+// harmonics, and what a glossy reflection sees of it is blurred ahead of time over the cone reflections
+// of a few roughnesses spread across, so shading only has to look both up. It isn't drawn behind
+// the scene, that's what a backplate is for. In a scene file:
+//
+//	environment: Some((path: "sky.png", intensity: 1.0))
+
+use crate::structs::{Color, Material, Scene, Vec3, GLOSSY_MAX_ANGLE};
+use crate::texture::Texture;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// Prefiltered for roughness 0, 0.25, 0.5, 0.75 and 1, the first being the image itself
+const SPECULAR_LEVELS: usize = 5;
+// Width of the prefiltered levels, each half as high
+const SPECULAR_WIDTH: usize = 64;
+
+type Rgb = [f64; 3];
+
+pub struct Environment {
+	pub texture: Arc<Texture>,
+	pub intensity: f64,
+	irradiance: [Rgb; 9], // spherical harmonics, bands 0 to 2
+	specular: Vec<Vec<Rgb>>, // levels after the first, SPECULAR_WIDTH wide, row by row
+}
+
+// Where direction lands on a lat-long image, u around from -x and v down from straight up
+fn direction_uv(direction: &Vec3) -> (f64, f64) {
+	let direction = direction.normalize();
+	(0.5 + direction.y.atan2(direction.x) / (2.0 * PI), direction.z.clamp(-1.0, 1.0).acos() / PI)
+}
+
+fn uv_direction(u: f64, v: f64) -> Vec3 {
+	let (phi, theta) = ((u - 0.5) * 2.0 * PI, v * PI);
+	Vec3 { x: theta.sin() * phi.cos(), y: theta.sin() * phi.sin(), z: theta.cos() }
+}
+
+fn rgb(color: &Color) -> Rgb {
+	[color.r as f64 / 255.0, color.g as f64 / 255.0, color.b as f64 / 255.0]
+}
+
+fn sh_basis(n: &Vec3) -> [f64; 9] {
+	[
+		0.282095,
+		0.488603 * n.y, 0.488603 * n.z, 0.488603 * n.x,
+		1.092548 * n.x * n.y, 1.092548 * n.y * n.z, 0.315392 * (3.0 * n.z * n.z - 1.0), 1.092548 * n.x * n.z, 0.546274 * (n.x * n.x - n.y * n.y),
+	]
+}
+
+// Every texel of a width x height lat-long image, its center's direction and the solid angle it covers
+fn texels(width: usize, height: usize) -> impl Iterator<Item = (usize, Vec3, f64)> {
+	(0..width * height).map(move |index| {
+		let (u, v) = (((index % width) as f64 + 0.5) / width as f64, ((index / width) as f64 + 0.5) / height as f64);
+		(index, uv_direction(u, v), (2.0 * PI / width as f64) * (PI / height as f64) * (v * PI).sin())
+	})
+}
+
+impl Environment {
+	pub fn new(texture: Arc<Texture>, intensity: f64) -> Self {
+		// Projected onto the harmonics, then convolved with the cosine lobe a diffuse surface gathers over
+		let mut irradiance = [[0.0; 3]; 9];
+		for (index, direction, solid_angle) in texels(texture.width, texture.height) {
+			let radiance = rgb(&texture.pixels[index]);
+			for (coefficient, basis) in irradiance.iter_mut().zip(sh_basis(&direction)) {
+				for channel in 0..3 {
+					coefficient[channel] += radiance[channel] * basis * solid_angle;
+				}
+			}
+		}
+		for (band, coefficient) in irradiance.iter_mut().enumerate() {
+			let lobe = match band { 0 => PI, 1..=3 => 2.0 * PI / 3.0, _ => PI / 4.0 };
+			coefficient.iter_mut().for_each(|channel| *channel *= lobe);
+		}
+
+		// Each level averages a small copy of the image over the cone its roughness reflects
+		let (width, height) = (SPECULAR_WIDTH, SPECULAR_WIDTH / 2);
+		let source: Vec<(Vec3, f64, Rgb)> = texels(width, height).map(|(index, direction, solid_angle)| {
+			let (u, v) = (((index % width) as f64 + 0.5) / width as f64, ((index / width) as f64 + 0.5) / height as f64);
+			(direction, solid_angle, rgb(&texture.sample_footprint(u, v, 1.0 / width as f64, 1.0 / height as f64)))
+		}).collect();
+		let specular = (1..SPECULAR_LEVELS).map(|level| {
+			let cos_max = (level as f64 / (SPECULAR_LEVELS - 1) as f64 * GLOSSY_MAX_ANGLE).cos();
+			source.iter().map(|(direction, _, own)| {
+				let (mut sum, mut weight) = ([0.0; 3], 0.0);
+				for (_, solid_angle, color) in source.iter().filter(|(other, _, _)| Vec3::dot(direction, other) >= cos_max) {
+					(0..3).for_each(|channel| sum[channel] += color[channel] * solid_angle);
+					weight += solid_angle;
+				}
+				if weight == 0.0 { return *own }
+				sum.map(|channel| channel / weight)
+			}).collect()
+		}).collect();
+		Environment { texture, intensity, irradiance, specular }
+	}
+
+	// Light reaching a diffuse surface facing normal, divided by pi so white paper under an even
+	// environment of 1 shows 1
+	pub fn diffuse(&self, normal: &Vec3) -> Rgb {
+		let basis = sh_basis(&normal.normalize());
+		let mut sum = [0.0; 3];
+		for (coefficient, basis) in self.irradiance.iter().zip(basis) {
+			(0..3).for_each(|channel| sum[channel] += coefficient[channel] * basis);
+		}
+		sum.map(|channel| (channel / PI).max(0.0))
+	}
+
+	// What a reflection of the given roughness sees looking along direction, blended between the
+	// two levels prefiltered either side of it
+	pub fn specular(&self, direction: &Vec3, roughness: f64) -> Rgb {
+		let (u, v) = direction_uv(direction);
+		let at = |level: usize| match level {
+			0 => rgb(&self.texture.sample(u, v)),
+			level => bilinear(&self.specular[level - 1], u, v),
+		};
+		let level = roughness.clamp(0.0, 1.0) * (SPECULAR_LEVELS - 1) as f64;
+		let (below, blend) = (level.floor() as usize, level.fract());
+		if blend == 0.0 { return at(below) }
+		let (below, above) = (at(below), at(below + 1));
+		[0, 1, 2].map(|channel| below[channel] + (above[channel] - below[channel]) * blend)
+	}
+}
+
+// Between the four texels of a prefiltered level around (u, v), wrapping round in u
+fn bilinear(pixels: &[Rgb], u: f64, v: f64) -> Rgb {
+	let (width, height) = (SPECULAR_WIDTH, SPECULAR_WIDTH / 2);
+	let (x, y) = (u * width as f64 - 0.5, (v * height as f64 - 0.5).clamp(0.0, (height - 1) as f64));
+	let (x0, y0, fx, fy) = (x.floor(), y.floor() as usize, x - x.floor(), y.fract());
+	let column = |x: f64| (x as i64).rem_euclid(width as i64) as usize;
+	let (x0, x1, y1) = (column(x0), column(x0 + 1.0), (y0 + 1).min(height - 1));
+	let texel = |x: usize, y: usize| pixels[y * width + x];
+	let mut out = [0.0; 3];
+	for (channel, out) in out.iter_mut().enumerate() {
+		let top = texel(x0, y0)[channel] * (1.0 - fx) + texel(x1, y0)[channel] * fx;
+		let bottom = texel(x0, y1)[channel] * (1.0 - fx) + texel(x1, y1)[channel] * fx;
+		*out = top * (1.0 - fy) + bottom * fy;
+	}
+	out
+}
+
+impl Scene {
+	// color with the environment's light added, off a surface of material facing normal seen along
+	// incoming: diffusely in its color and mirrored as much as it reflects, blurred by its roughness
+	pub fn environment_lit(&self, color: Color, incoming: &Vec3, material: &Material, normal: &Vec3) -> Color {
+		let environment = match &self.environment {
+			Some(environment) => environment,
+			None => return color,
+		};
+		let incoming = incoming.normalize();
+		let normal = if Vec3::dot(normal, &incoming) > 0.0 { normal.mul(-1.0) } else { *normal };
+		let mirrored = incoming.sub(&normal.mul(2.0 * Vec3::dot(&incoming, &normal)));
+		let (diffuse, specular) = (environment.diffuse(&normal), environment.specular(&mirrored, material.roughness as f64));
+		let scale = environment.intensity * 2f64.powf(self.render_settings.exposure);
+		let reflectivity = material.reflectivity as f64;
+		let channel = |base: u8, albedo: u8, channel: usize| {
+			let light = albedo as f64 * diffuse[channel] * (1.0 - reflectivity) + 255.0 * specular[channel] * reflectivity;
+			(base as f64 + light * scale).clamp(0.0, 255.0).round() as u8
+		};
+		Color { r: channel(color.r, material.color.r, 0), g: channel(color.g, material.color.g, 1), b: channel(color.b, material.color.b, 2) }
+	}
+
+	// What a reflection of the given roughness leaving the scene along direction sees, None without an
+	// environment
+	pub fn environment_seen(&self, direction: &Vec3, roughness: f64) -> Option<Color> {
+		let environment = self.environment.as_ref()?;
+		let scale = environment.intensity * 2f64.powf(self.render_settings.exposure);
+		let specular = environment.specular(direction, roughness);
+		let channel = |channel: usize| (255.0 * specular[channel] * scale).clamp(0.0, 255.0).round() as u8;
+		Some(Color { r: channel(0), g: channel(1), b: channel(2) })
+	}
+}
+
+#[test]
+fn environment_lights_from_above() {
+	use crate::structs::{Camera, Rot3, Sphere};
+	// White sky over black ground
+	let (white, black) = (Color { r: 255, g: 255, b: 255 }, Color { r: 0, g: 0, b: 0 });
+	let pixels = (0..32).map(|index| if index < 16 { white } else { black }).collect();
+	let environment = Environment::new(Arc::new(Texture::new(8, 4, pixels, String::new())), 1.0);
+	let (up, down, side) = (Vec3 { x: 0.0, y: 0.0, z: 1.0 }, Vec3 { x: 0.0, y: 0.0, z: -1.0 }, Vec3 { x: 1.0, y: 0.0, z: 0.0 });
+	// Only three bands of harmonics, so the edge between them is soft
+	assert!((environment.diffuse(&up)[0] - 1.0).abs() < 0.15);
+	assert!(environment.diffuse(&down)[0] < 0.15);
+	assert!((environment.diffuse(&side)[1] - 0.5).abs() < 0.05);
+	// Mirrors see either half sharply, rough reflections just over the horizon some of both
+	assert_eq!((environment.specular(&up, 0.0), environment.specular(&down, 0.0)), ([1.0; 3], [0.0; 3]));
+	let horizon = environment.specular(&Vec3 { x: 1.0, y: 0.0, z: 0.05 }, 1.0)[2];
+	assert!(horizon > 0.2 && horizon < 0.8, "{}", horizon);
+	assert!(environment.specular(&Vec3 { x: 1.0, y: 0.0, z: 0.05 }, 0.0)[2] == 1.0);
+
+	// A ball with no lights at all, lit on top and dark underneath
+	let gray = Material { color: Color { r: 200, g: 200, b: 200 }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+	let mut camera = Camera::new(Vec3 { x: 0.0, y: -4.0, z: 0.0 }, Rot3::new(), 40.0);
+	camera.look_at(&Vec3 { x: 0.0, y: 0.0, z: 0.0 }, &up);
+	let mut scene = Scene::new(vec![Box::new(Sphere::new(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, 1.0, gray))], camera);
+	let unlit = scene.render(16, 16);
+	scene.environment = Some(environment);
+	let lit = scene.render(16, 16);
+	let (top, bottom) = (16 * 3 + 8, 16 * 12 + 8);
+	assert_eq!((unlit[top], unlit[bottom]), (black, black));
+	assert!(lit[top].r > 150 && lit[bottom].r < 40, "{:?} {:?}", lit[top], lit[bottom]);
+}
@@ -0,0 +1,86 @@
+//
+//	Auto Exposure
+//
+
+use crate::analysis::luminance;
+use crate::structs::Color;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Metering {
+	Average, // log average, the way eyes and most cameras meter
+	Percentile(f64), // brightness of the pixel this far up the sorted frame, 0.5 being the median
+}
+
+// Eye adaptation: meters the last frame and eases the exposure towards whatever brings the
+// metered brightness to the target, quicker when it has further to go
+pub struct AutoExposure {
+	pub metering: Metering,
+	pub target: f64, // displayed luminance the metered value is steered to
+	pub adaptation_time: f64, // seconds to get about two thirds of the way there
+	pub min: f64, // stops
+	pub max: f64,
+}
+
+impl AutoExposure {
+	pub fn new() -> Self {
+		AutoExposure { metering: Metering::Average, target: 0.45, adaptation_time: 0.5, min: -8.0, max: 8.0 }
+	}
+
+	// Brightness of the frame as displayed, None when there's nothing to meter.
+	// Pure black is left out, it's where nothing was hit and no exposure would change it
+	pub fn meter(&self, colors: &[Color]) -> Option<f64> {
+		let mut lit: Vec<f64> = colors.iter().map(luminance).filter(|l| *l > 0.0).collect();
+		if lit.is_empty() { return None }
+		Some(match self.metering {
+			Metering::Average => (lit.iter().map(|l| l.ln()).sum::<f64>() / lit.len() as f64).exp(),
+			Metering::Percentile(fraction) => {
+				lit.sort_by(|a, b| a.total_cmp(b));
+				lit[((fraction.clamp(0.0, 1.0) * lit.len() as f64) as usize).min(lit.len() - 1)]
+			}
+		})
+	}
+
+	// Exposure in stops for the next frame, given the frame rendered at the current one
+	pub fn update(&self, colors: &[Color], exposure: f64, elapsed_time: f64) -> f64 {
+		let metered = match self.meter(colors) {
+			Some(metered) => metered,
+			None => return exposure,
+		};
+		// Shading is linear in the light until it clamps, so each stop doubles the metered value
+		let wanted = (exposure + (self.target / metered).log2()).clamp(self.min, self.max);
+		let blend = 1.0 - (-elapsed_time / self.adaptation_time.max(1e-3)).exp();
+		exposure + (wanted - exposure) * blend
+	}
+}
+
+impl Default for AutoExposure {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+#[test]
+fn adapts_towards_target() {
+	use crate::structs::{Scene, Vec3};
+	let mut scene = Scene::default_scene();
+	scene.current_camera.pos = Vec3 { x: 6.0, y: 2.0, z: 3.0 };
+	scene.current_camera.look_at(&Vec3 { x: 0.0, y: 0.0, z: 1.0 }, &Vec3 { x: 0.0, y: 0.0, z: 1.0 });
+	let auto = AutoExposure { metering: Metering::Percentile(0.5), ..AutoExposure::new() };
+	let gray = |value: u8| Color { r: value, g: value, b: value };
+	// Dark frame brightens, bright frame darkens, a step at a time
+	let dark = vec![gray(20); 16];
+	let step = auto.update(&dark, 0.0, 0.1);
+	assert!(step > 0.0 && step < (0.45 / luminance(&gray(20))).log2());
+	assert!(auto.update(&[gray(250); 16], 0.0, 0.1) < 0.0);
+	assert_eq!(auto.update(&[gray(0); 16], 1.5, 0.1), 1.5);
+
+	// Settles on a real render too
+	let mut exposure = 0.0;
+	for _ in 0..40 {
+		scene.render_settings.exposure = exposure;
+		let colors = scene.render(32, 18);
+		exposure = auto.update(&colors, exposure, 0.5);
+	}
+	let metered = auto.meter(&scene.render(32, 18)).unwrap();
+	assert!((metered - 0.45).abs() < 0.1, "{} at {} stops", metered, exposure);
+}
@@ -0,0 +1,110 @@
+//
+//	OpenEXR Writer
+//
+
+// Just enough of the format for float AOVs: single part, scanline, uncompressed, 32-bit float channels
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+const VERSION: u32 = 2;
+const PIXEL_TYPE_FLOAT: i32 = 2;
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+	header.extend_from_slice(name.as_bytes());
+	header.push(0);
+	header.extend_from_slice(kind.as_bytes());
+	header.push(0);
+	header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+	header.extend_from_slice(value);
+}
+
+fn box2i(width: usize, height: usize) -> Vec<u8> {
+	[0, 0, width as i32 - 1, height as i32 - 1].iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+// Channels are (name, width * height values in row order), readers expect them sorted by name
+pub fn encode(width: usize, height: usize, channels: &[(&str, &[f32])]) -> Vec<u8> {
+	encode_with_metadata(width, height, channels, &[])
+}
+
+// Same, with extra string attributes in the header
+pub fn encode_with_metadata(width: usize, height: usize, channels: &[(&str, &[f32])], metadata: &[(String, String)]) -> Vec<u8> {
+	let mut channels = channels.to_vec();
+	channels.sort_by(|a, b| a.0.cmp(b.0));
+
+	let mut channel_list = Vec::new();
+	for (name, _) in channels.iter() {
+		channel_list.extend_from_slice(name.as_bytes());
+		channel_list.push(0);
+		channel_list.extend_from_slice(&PIXEL_TYPE_FLOAT.to_le_bytes());
+		channel_list.extend_from_slice(&[0, 0, 0, 0]); // pLinear + reserved
+		channel_list.extend_from_slice(&1_i32.to_le_bytes()); // x sampling
+		channel_list.extend_from_slice(&1_i32.to_le_bytes()); // y sampling
+	}
+	channel_list.push(0);
+
+	let mut out = Vec::new();
+	out.extend_from_slice(&MAGIC);
+	out.extend_from_slice(&VERSION.to_le_bytes());
+	attribute(&mut out, "channels", "chlist", &channel_list);
+	attribute(&mut out, "compression", "compression", &[0]);
+	attribute(&mut out, "dataWindow", "box2i", &box2i(width, height));
+	attribute(&mut out, "displayWindow", "box2i", &box2i(width, height));
+	attribute(&mut out, "lineOrder", "lineOrder", &[0]);
+	attribute(&mut out, "pixelAspectRatio", "float", &1.0_f32.to_le_bytes());
+	attribute(&mut out, "screenWindowCenter", "v2f", &[0; 8]);
+	attribute(&mut out, "screenWindowWidth", "float", &1.0_f32.to_le_bytes());
+	for (name, value) in metadata.iter() {
+		attribute(&mut out, name, "string", value.as_bytes());
+	}
+	out.push(0);
+
+	// Offset table, one uncompressed scanline per block
+	let line_size = width * channels.len() * 4;
+	let first_line = out.len() + height * 8;
+	for y in 0..height {
+		out.extend_from_slice(&((first_line + y * (line_size + 8)) as u64).to_le_bytes());
+	}
+
+	for y in 0..height {
+		out.extend_from_slice(&(y as i32).to_le_bytes());
+		out.extend_from_slice(&(line_size as i32).to_le_bytes());
+		for (_, values) in channels.iter() {
+			for value in &values[y * width..(y + 1) * width] {
+				out.extend_from_slice(&value.to_le_bytes());
+			}
+		}
+	}
+	out
+}
+
+pub fn write(path: &Path, width: usize, height: usize, channels: &[(&str, &[f32])]) -> io::Result<()> {
+	write_with_metadata(path, width, height, channels, &[])
+}
+
+pub fn write_with_metadata(path: &Path, width: usize, height: usize, channels: &[(&str, &[f32])], metadata: &[(String, String)]) -> io::Result<()> {
+	let mut file = BufWriter::new(File::create(path)?);
+	file.write_all(&encode_with_metadata(width, height, channels, metadata))?;
+	file.flush()
+}
+
+#[test]
+fn exr_layout() {
+	use std::convert::TryInto;
+	let (width, height) = (3, 2);
+	let z: Vec<f32> = (0..6).map(|v| v as f32).collect();
+	let bytes = encode(width, height, &[("Z", &z)]);
+	assert_eq!(&bytes[..4], &MAGIC);
+
+	// The offset table points at each scanline, which holds y, size and the row
+	let table = bytes.len() - height * (8 + width * 4) - height * 8;
+	for y in 0..height {
+		let offset = u64::from_le_bytes(bytes[table + y * 8..table + y * 8 + 8].try_into().unwrap()) as usize;
+		assert_eq!(i32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()), y as i32);
+		let first = f32::from_le_bytes(bytes[offset + 8..offset + 12].try_into().unwrap());
+		assert_eq!(first, z[y * width]);
+	}
+}
@@ -0,0 +1,180 @@
+//
+//	Framebuffer
+//
+
+use crate::structs::{Color, Vec3};
+use crate::analysis;
+use crate::exr;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+// A rendered frame, colors premultiplied over black with alpha 0 wherever nothing was hit
+pub struct Framebuffer {
+	pub width: usize,
+	pub height: usize,
+	pub color: Vec<Color>,
+	pub alpha: Vec<u8>,
+	// AOVs from the pixel center ray: camera space depth (infinite on a miss) and world normal
+	pub depth: Vec<f32>,
+	pub normal: Vec<Vec3>,
+	// Only filled in when rendering with ao_samples: how much of the sky each point sees (0 on a miss)
+	// and the average unoccluded direction, both from the pixel center ray too
+	pub sky_visibility: Vec<f32>,
+	pub bent_normal: Vec<Vec3>,
+	// Pixels the surface under each pixel center moved since the scene's previous frame, x right and y down
+	pub motion: Vec<(f32, f32)>,
+}
+
+impl Framebuffer {
+	pub fn new(width: usize, height: usize) -> Self {
+		Self {
+			width,
+			height,
+			color: vec![Color { r: 0, g: 0, b: 0 }; width * height],
+			alpha: vec![0; width * height],
+			depth: vec![f32::INFINITY; width * height],
+			normal: vec![Vec3 { x: 0.0, y: 0.0, z: 0.0 }; width * height],
+			sky_visibility: vec![0.0; width * height],
+			bent_normal: vec![Vec3 { x: 0.0, y: 0.0, z: 0.0 }; width * height],
+			motion: vec![(0.0, 0.0); width * height],
+		}
+	}
+
+	pub fn set(&mut self, x: usize, y: usize, color: Color, alpha: u8) {
+		let index = y * self.width + x;
+		self.color[index] = color;
+		self.alpha[index] = alpha;
+	}
+
+	pub fn set_aovs(&mut self, x: usize, y: usize, depth: f32, normal: Vec3) {
+		let index = y * self.width + x;
+		self.depth[index] = depth;
+		self.normal[index] = normal;
+	}
+
+	pub fn set_sky(&mut self, x: usize, y: usize, visibility: f32, bent_normal: Vec3) {
+		let index = y * self.width + x;
+		self.sky_visibility[index] = visibility;
+		self.bent_normal[index] = bent_normal;
+	}
+
+	pub fn set_motion(&mut self, x: usize, y: usize, motion_x: f32, motion_y: f32) {
+		self.motion[y * self.width + x] = (motion_x, motion_y);
+	}
+
+	// Straight (non premultiplied) RGBA, the way image formats expect it
+	pub fn to_rgba(&self) -> Vec<u8> {
+		let mut rgba = Vec::with_capacity(self.width * self.height * 4);
+		for (color, alpha) in self.color.iter().zip(self.alpha.iter()) {
+			let scale = if *alpha == 0 { 0.0 } else { 255.0 / *alpha as f64 };
+			for channel in [color.r, color.g, color.b].iter() {
+				rgba.push((*channel as f64 * scale).round().min(255.0) as u8);
+			}
+			rgba.push(*alpha);
+		}
+		rgba
+	}
+
+	// Depth scaled so the nearest hit is black and the farthest white, misses are white too
+	pub fn depth_to_gray16(&self) -> Vec<u16> {
+		let finite = self.depth.iter().filter(|depth| depth.is_finite());
+		let near = finite.clone().fold(f32::INFINITY, |a, b| a.min(*b));
+		let far = finite.fold(0.0, |a: f32, b| a.max(*b));
+		let range = (far - near).max(f32::EPSILON);
+		self.depth.iter().map(|depth| {
+			if depth.is_finite() { ((depth - near) / range * 65535.0).round() as u16 } else { u16::MAX }
+		}).collect()
+	}
+
+	// World space normals mapped from -1..1 to 0..255, misses stay black
+	pub fn normal_to_rgb(&self) -> Vec<u8> {
+		let mut rgb = Vec::with_capacity(self.width * self.height * 3);
+		for (normal, depth) in self.normal.iter().zip(self.depth.iter()) {
+			for component in [normal.x, normal.y, normal.z].iter() {
+				rgb.push(if !depth.is_finite() { 0 } else { ((component * 0.5 + 0.5) * 255.0).round() as u8 });
+			}
+		}
+		rgb
+	}
+
+	pub fn write_png(&self, path: &Path) -> io::Result<()> {
+		write_png(path, self.width, self.height, png::ColorType::Rgba, png::BitDepth::Eight, &self.to_rgba())
+	}
+
+	// 16-bit grayscale png, or raw float depth when the path ends in .exr
+	pub fn write_depth(&self, path: &Path) -> io::Result<()> {
+		if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("exr")) {
+			return exr::write(path, self.width, self.height, &[("Z", &self.depth)]);
+		}
+		// png wants 16-bit samples big endian
+		let data: Vec<u8> = self.depth_to_gray16().iter().flat_map(|v| v.to_be_bytes()).collect();
+		write_png(path, self.width, self.height, png::ColorType::Grayscale, png::BitDepth::Sixteen, &data)
+	}
+
+	pub fn write_normals(&self, path: &Path) -> io::Result<()> {
+		write_png(path, self.width, self.height, png::ColorType::Rgb, png::BitDepth::Eight, &self.normal_to_rgb())
+	}
+
+	// Visibility as 8-bit grayscale png, or visibility and bent normal as floats when the path ends in .exr
+	pub fn write_sky(&self, path: &Path) -> io::Result<()> {
+		if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("exr")) {
+			let component = |f: fn(&Vec3) -> f64| -> Vec<f32> { self.bent_normal.iter().map(|n| f(n) as f32).collect() };
+			let (x, y, z) = (component(|n| n.x), component(|n| n.y), component(|n| n.z));
+			let channels = [("sky.Y", &self.sky_visibility[..]), ("bent.X", &x[..]), ("bent.Y", &y[..]), ("bent.Z", &z[..])];
+			return exr::write(path, self.width, self.height, &channels);
+		}
+		let data: Vec<u8> = self.sky_visibility.iter().map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8).collect();
+		write_png(path, self.width, self.height, png::ColorType::Grayscale, png::BitDepth::Eight, &data)
+	}
+
+	// Motion as floats when the path ends in .exr, otherwise 8-bit red and green centered on 128,
+	// scaled so the longest vector reaches the edge
+	pub fn write_motion(&self, path: &Path) -> io::Result<()> {
+		if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("exr")) {
+			let x: Vec<f32> = self.motion.iter().map(|motion| motion.0).collect();
+			let y: Vec<f32> = self.motion.iter().map(|motion| motion.1).collect();
+			return exr::write(path, self.width, self.height, &[("motion.X", &x), ("motion.Y", &y)]);
+		}
+		let longest = self.motion.iter().fold(f32::EPSILON, |a, motion| a.max(motion.0.abs()).max(motion.1.abs()));
+		let channel = |v: f32| (128.0 + v / longest * 127.0).round() as u8;
+		let data: Vec<u8> = self.motion.iter().flat_map(|motion| [channel(motion.0), channel(motion.1), 128]).collect();
+		write_png(path, self.width, self.height, png::ColorType::Rgb, png::BitDepth::Eight, &data)
+	}
+
+	// The false color exposure view with its histogram, see analysis
+	pub fn write_analysis(&self, path: &Path) -> io::Result<()> {
+		let data: Vec<u8> = analysis::analysis_view(&self.color, self.width, self.height).iter().flat_map(|color| [color.r, color.g, color.b]).collect();
+		write_png(path, self.width, self.height, png::ColorType::Rgb, png::BitDepth::Eight, &data)
+	}
+}
+
+fn write_png(path: &Path, width: usize, height: usize, color: png::ColorType, depth: png::BitDepth, data: &[u8]) -> io::Result<()> {
+	let file = BufWriter::new(File::create(path)?);
+	let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+	encoder.set_color(color);
+	encoder.set_depth(depth);
+	let mut writer = encoder.write_header().map_err(io::Error::other)?;
+	writer.write_image_data(data).map_err(io::Error::other)
+}
+
+#[test]
+fn background_is_transparent() {
+	use crate::structs::{Scene, Vec3};
+	let mut scene = Scene::default_scene();
+	// Level with the cube, so the upper half of the frame is empty sky
+	scene.current_camera.pos = Vec3 { x: 8.0, y: 0.0, z: 1.5 };
+	scene.current_camera.look_at(&Vec3 { x: 0.0, y: 0.0, z: 1.5 }, &Vec3 { x: 0.0, y: 0.0, z: 1.0 });
+	let framebuffer = scene.render_framebuffer(32, 18);
+	assert!(framebuffer.alpha.contains(&0));
+	assert!(framebuffer.alpha.contains(&255));
+	let rgba = framebuffer.to_rgba();
+	for (index, alpha) in framebuffer.alpha.iter().enumerate() {
+		assert_eq!(rgba[index * 4 + 3], *alpha);
+		if *alpha == 0 {
+			assert_eq!(&rgba[index * 4..index * 4 + 3], &[0, 0, 0]);
+		}
+		// At 1 spp coverage and the depth AOV come from the same center ray
+		assert_eq!(*alpha == 0, framebuffer.depth[index].is_infinite());
+	}
+}
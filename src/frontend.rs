@@ -0,0 +1,24 @@
+//
+//	Frontend Interface
+//
+
+use crate::structs::Color;
+use crate::input::{ButtonState, Key};
+
+// Where rendered pixels end up: a window, a terminal, an image...
+pub trait Presenter {
+	fn draw(&mut self, x: i32, y: i32, color: Color);
+
+	// Text overlay, frontends without a font can ignore it
+	fn draw_string(&mut self, _x: i32, _y: i32, _text: &str, _color: Color) {}
+}
+
+// Keyboard and mouse state for the current frame
+pub trait InputSource {
+	fn key_state(&self, key: Key) -> ButtonState;
+	fn mouse_position(&self) -> (i32, i32);
+	// 0 = left, 1 = right, 2 = middle
+	fn mouse_button(&self, button: u32) -> ButtonState;
+	// Positive when scrolled up/away since the last frame
+	fn mouse_wheel(&self) -> i32;
+}
@@ -0,0 +1,67 @@
+//
+//	HUD Graphs
+//
+
+use crate::frontend::Presenter;
+use crate::structs::Color;
+use std::collections::VecDeque;
+
+// The last few values of something measured once a frame, drawn as a strip of bars
+pub struct RollingGraph {
+	pub label: &'static str,
+	pub values: VecDeque<f64>,
+	pub capacity: usize,
+	pub color: Color,
+}
+
+impl RollingGraph {
+	pub fn new(label: &'static str, capacity: usize, color: Color) -> Self {
+		RollingGraph { label, values: VecDeque::with_capacity(capacity), capacity, color }
+	}
+
+	pub fn push(&mut self, value: f64) {
+		if self.values.len() == self.capacity {
+			self.values.pop_front();
+		}
+		self.values.push_back(value);
+	}
+
+	pub fn latest(&self) -> f64 {
+		self.values.back().copied().unwrap_or(0.0)
+	}
+
+	// Bar heights in pixels, oldest first, scaled so the largest value fills the height
+	pub fn bars(&self, height: usize) -> Vec<usize> {
+		let largest = self.values.iter().copied().fold(0.0, f64::max);
+		self.values.iter().map(|value| {
+			if largest <= 0.0 { 0 } else { (value / largest * height as f64).round() as usize }
+		}).collect()
+	}
+
+	// One column per value, newest on the right, over a dark background
+	pub fn draw(&self, presenter: &mut dyn Presenter, x: i32, y: i32, height: usize) {
+		let background = Color { r: 16, g: 16, b: 16 };
+		let bars = self.bars(height);
+		let empty = self.capacity - bars.len();
+		for column in 0..self.capacity {
+			let bar = if column < empty { 0 } else { bars[column - empty] };
+			for row in 0..height {
+				let color = if height - row <= bar { self.color } else { background };
+				presenter.draw(x + column as i32, y + row as i32, color);
+			}
+		}
+		presenter.draw_string(x, y, &format!("{} {:.3}", self.label, self.latest()), Color { r: 255, g: 255, b: 255 });
+	}
+}
+
+#[test]
+fn keeps_the_latest_values() {
+	let mut graph = RollingGraph::new("ms", 3, Color { r: 255, g: 0, b: 0 });
+	assert_eq!(graph.bars(10), Vec::<usize>::new());
+	for value in [1.0, 2.0, 4.0, 8.0].iter() {
+		graph.push(*value);
+	}
+	assert_eq!(graph.values, [2.0, 4.0, 8.0]);
+	assert_eq!(graph.latest(), 8.0);
+	assert_eq!(graph.bars(8), vec![2, 4, 8]);
+}
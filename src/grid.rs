@@ -0,0 +1,152 @@
+//
+//	Uniform Grid
+//
+
+// Space around a set of boxes cut into equal cells, each listing the boxes overlapping it. A ray
+// steps from cell to cell along its length (Amanatides and Woo's walk, as in voxel.rs) and only looks
+// at what's listed in the cells it passes. Cheap to build and hard to beat when the boxes are many,
+// about the same size and spread evenly; lopsided scenes leave most cells empty and a few crowded
+
+use crate::bvh::padded;
+use crate::structs::{Aabb, Ray, Vec3};
+
+const CELLS_PER_ITEM: f64 = 3.0;
+const MAX_RESOLUTION: usize = 64; // cells along any axis
+
+pub struct Grid {
+	bounds: Aabb, // around everything, a little larger
+	resolution: [usize; 3],
+	cell: [f64; 3], // size along each axis
+	starts: Vec<u32>, // cell i lists items[starts[i]..starts[i + 1]], cells x fastest then y then z
+	items: Vec<u32>, // indices of the bounds it was built from
+}
+
+impl Grid {
+	pub fn new(bounds: &[Aabb]) -> Self {
+		let mut around = Aabb::empty();
+		for item in bounds.iter() {
+			around.grow(&item.min);
+			around.grow(&item.max);
+		}
+		if bounds.is_empty() {
+			return Grid { bounds: around, resolution: [0; 3], cell: [0.0; 3], starts: vec![0], items: Vec::new() };
+		}
+		let around = padded(around);
+		let extent = axes(&around.max.sub(&around.min));
+		// Cells about as wide along every axis, CELLS_PER_ITEM of them per item, flat scenes get flat grids
+		let largest = extent.iter().fold(0.0f64, |largest, size| largest.max(*size));
+		let volume: f64 = extent.iter().map(|size| size.max(largest * 1e-3)).product();
+		let per_unit = (CELLS_PER_ITEM * bounds.len() as f64 / volume).cbrt();
+		let resolution = extent.map(|size| ((size * per_unit).ceil() as usize).clamp(1, MAX_RESOLUTION));
+		let cell = [0, 1, 2].map(|axis| extent[axis] / resolution[axis] as f64);
+		let mut grid = Grid { bounds: around, resolution, cell, starts: Vec::new(), items: Vec::new() };
+
+		// Counted first so every cell's list can sit in one array
+		let mut counts = vec![0u32; resolution.iter().product::<usize>() + 1];
+		for item in bounds.iter() {
+			grid.each_cell(item, |cell| counts[cell + 1] += 1);
+		}
+		for cell in 1..counts.len() {
+			counts[cell] += counts[cell - 1];
+		}
+		let mut items = vec![0; counts[counts.len() - 1] as usize];
+		let mut next = counts.clone();
+		for (index, item) in bounds.iter().enumerate() {
+			grid.each_cell(item, |cell| {
+				items[next[cell] as usize] = index as u32;
+				next[cell] += 1;
+			});
+		}
+		grid.starts = counts;
+		grid.items = items;
+		grid
+	}
+
+	// Calls visit with every cell item overlaps
+	fn each_cell(&self, item: &Aabb, mut visit: impl FnMut(usize)) {
+		let (low, high) = (self.cell_of(&axes(&item.min)), self.cell_of(&axes(&item.max)));
+		for z in low[2]..=high[2] {
+			for y in low[1]..=high[1] {
+				for x in low[0]..=high[0] {
+					visit(self.index([x, y, z]));
+				}
+			}
+		}
+	}
+
+	// Cell point is in, the nearest one for points outside
+	fn cell_of(&self, point: &[f64; 3]) -> [usize; 3] {
+		let min = axes(&self.bounds.min);
+		[0, 1, 2].map(|axis| (((point[axis] - min[axis]) / self.cell[axis]).max(0.0) as usize).min(self.resolution[axis] - 1))
+	}
+
+	fn index(&self, [x, y, z]: [usize; 3]) -> usize {
+		(z * self.resolution[1] + y) * self.resolution[0] + x
+	}
+
+	// Items listed in every cell the ray passes through, from where it enters the grid on. Items
+	// spanning several cells come up once for each
+	pub fn candidates<'a>(&'a self, ray: &'a Ray) -> impl Iterator<Item = usize> + 'a {
+		let entry = if self.items.is_empty() { None } else { self.bounds.ray_distance(ray) };
+		let (origin, direction) = (axes(&ray.origin), axes(&ray.direction));
+		let start = entry.map_or([0; 3], |entry| self.cell_of(&[0, 1, 2].map(|axis| origin[axis] + direction[axis] * entry)));
+		let mut cell = start.map(|index| index as i64);
+		let min = axes(&self.bounds.min);
+		let step = direction.map(|d| if d > 0.0 { 1 } else if d < 0.0 { -1 } else { 0 });
+		// Distance along the ray to the next boundary on each axis, and between boundaries
+		let mut next: [f64; 3] = [0, 1, 2].map(|axis| match step[axis] {
+			0 => f64::INFINITY,
+			s => (min[axis] + (cell[axis] + i64::from(s > 0)) as f64 * self.cell[axis] - origin[axis]) / direction[axis],
+		});
+		let delta = [0, 1, 2].map(|axis| if step[axis] == 0 { f64::INFINITY } else { self.cell[axis] / direction[axis].abs() });
+		let mut inside = entry.is_some();
+		let mut listed: &[u32] = &[];
+		std::iter::from_fn(move || loop {
+			if let Some((item, rest)) = listed.split_first() {
+				listed = rest;
+				return Some(*item as usize);
+			}
+			if !inside { return None }
+			let index = self.index(cell.map(|index| index as usize));
+			listed = &self.items[self.starts[index] as usize..self.starts[index + 1] as usize];
+			let axis = if next[0] < next[1] { if next[0] < next[2] { 0 } else { 2 } } else if next[1] < next[2] { 1 } else { 2 };
+			cell[axis] += step[axis];
+			next[axis] += delta[axis];
+			inside = step[axis] != 0 && cell[axis] >= 0 && (cell[axis] as usize) < self.resolution[axis];
+		})
+	}
+}
+
+fn axes(v: &Vec3) -> [f64; 3] {
+	[v.x, v.y, v.z]
+}
+
+#[test]
+fn grid_finds_what_the_rays_pass_through() {
+	use crate::random::Rng;
+	let mut rng = Rng::new(8);
+	let mut point = || Vec3 { x: rng.next_f64() * 20.0 - 10.0, y: rng.next_f64() * 20.0 - 10.0, z: rng.next_f64() * 4.0 - 2.0 };
+	let mut boxes: Vec<Aabb> = (0..300).map(|_| {
+		let mut bounds = Aabb::empty();
+		let corner = point();
+		bounds.grow(&corner);
+		bounds.grow(&corner.add(&Vec3 { x: 1.5, y: 0.3, z: 0.2 }));
+		bounds
+	}).collect();
+	// A flat one, like a floor
+	boxes.push(Aabb { min: Vec3 { x: -20.0, y: -20.0, z: 0.0 }, max: Vec3 { x: 20.0, y: 20.0, z: 0.0 } });
+	let grid = Grid::new(&boxes);
+	assert!(grid.resolution.iter().all(|cells| *cells > 1));
+	for _ in 0..200 {
+		let (from, to) = (point().mul(3.0), point());
+		let ray = Ray::from_to(&from, &to);
+		let mut found: Vec<usize> = grid.candidates(&ray).filter(|item| boxes[*item].ray_distance(&ray).is_some()).collect();
+		found.sort();
+		found.dedup();
+		assert_eq!(found, (0..boxes.len()).filter(|item| boxes[*item].ray_distance(&ray).is_some()).collect::<Vec<_>>());
+	}
+	// Straight down, along no axis but one
+	let ray = Ray::from_to(&Vec3 { x: 0.5, y: 0.5, z: 10.0 }, &Vec3 { x: 0.5, y: 0.5, z: 0.0 });
+	assert!(grid.candidates(&ray).any(|item| item == boxes.len() - 1));
+	assert_eq!(Grid::new(&[]).candidates(&ray).count(), 0);
+}
@@ -0,0 +1,447 @@
+//
+//	Input Bindings
+//
+
+use std::collections::HashMap;
+use std::fs;
+use crate::frontend::InputSource;
+
+// Keyboard keys, named after their olc counterparts so config files read naturally
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+	A,
+	B,
+	C,
+	D,
+	E,
+	F,
+	G,
+	H,
+	I,
+	J,
+	K,
+	L,
+	M,
+	N,
+	O,
+	P,
+	Q,
+	R,
+	S,
+	T,
+	U,
+	V,
+	W,
+	X,
+	Y,
+	Z,
+	K0,
+	K1,
+	K2,
+	K3,
+	K4,
+	K5,
+	K6,
+	K7,
+	K8,
+	K9,
+	F1,
+	F2,
+	F3,
+	F4,
+	F5,
+	F6,
+	F7,
+	F8,
+	F9,
+	F10,
+	F11,
+	F12,
+	Up,
+	Down,
+	Left,
+	Right,
+	Space,
+	Tab,
+	Shift,
+	Ctrl,
+	Insert,
+	Delete,
+	Home,
+	End,
+	PageUp,
+	PageDown,
+	Backspace,
+	Escape,
+	Return,
+	Enter,
+	Pause,
+	ScrollLock,
+	Numpad0,
+	Numpad1,
+	Numpad2,
+	Numpad3,
+	Numpad4,
+	Numpad5,
+	Numpad6,
+	Numpad7,
+	Numpad8,
+	Numpad9,
+	NumpadMul,
+	NumpadDiv,
+	NumpadAdd,
+	NumpadSub,
+	NumpadDecimal,
+	Period,
+}
+
+const KEY_NAMES: [(Key, &str); 84] = [
+	(Key::A, "A"),
+	(Key::B, "B"),
+	(Key::C, "C"),
+	(Key::D, "D"),
+	(Key::E, "E"),
+	(Key::F, "F"),
+	(Key::G, "G"),
+	(Key::H, "H"),
+	(Key::I, "I"),
+	(Key::J, "J"),
+	(Key::K, "K"),
+	(Key::L, "L"),
+	(Key::M, "M"),
+	(Key::N, "N"),
+	(Key::O, "O"),
+	(Key::P, "P"),
+	(Key::Q, "Q"),
+	(Key::R, "R"),
+	(Key::S, "S"),
+	(Key::T, "T"),
+	(Key::U, "U"),
+	(Key::V, "V"),
+	(Key::W, "W"),
+	(Key::X, "X"),
+	(Key::Y, "Y"),
+	(Key::Z, "Z"),
+	(Key::K0, "K0"),
+	(Key::K1, "K1"),
+	(Key::K2, "K2"),
+	(Key::K3, "K3"),
+	(Key::K4, "K4"),
+	(Key::K5, "K5"),
+	(Key::K6, "K6"),
+	(Key::K7, "K7"),
+	(Key::K8, "K8"),
+	(Key::K9, "K9"),
+	(Key::F1, "F1"),
+	(Key::F2, "F2"),
+	(Key::F3, "F3"),
+	(Key::F4, "F4"),
+	(Key::F5, "F5"),
+	(Key::F6, "F6"),
+	(Key::F7, "F7"),
+	(Key::F8, "F8"),
+	(Key::F9, "F9"),
+	(Key::F10, "F10"),
+	(Key::F11, "F11"),
+	(Key::F12, "F12"),
+	(Key::Up, "UP"),
+	(Key::Down, "DOWN"),
+	(Key::Left, "LEFT"),
+	(Key::Right, "RIGHT"),
+	(Key::Space, "SPACE"),
+	(Key::Tab, "TAB"),
+	(Key::Shift, "SHIFT"),
+	(Key::Ctrl, "CTRL"),
+	(Key::Insert, "INS"),
+	(Key::Delete, "DEL"),
+	(Key::Home, "HOME"),
+	(Key::End, "END"),
+	(Key::PageUp, "PGUP"),
+	(Key::PageDown, "PGDN"),
+	(Key::Backspace, "BACK"),
+	(Key::Escape, "ESCAPE"),
+	(Key::Return, "RETURN"),
+	(Key::Enter, "ENTER"),
+	(Key::Pause, "PAUSE"),
+	(Key::ScrollLock, "SCROLL"),
+	(Key::Numpad0, "NP0"),
+	(Key::Numpad1, "NP1"),
+	(Key::Numpad2, "NP2"),
+	(Key::Numpad3, "NP3"),
+	(Key::Numpad4, "NP4"),
+	(Key::Numpad5, "NP5"),
+	(Key::Numpad6, "NP6"),
+	(Key::Numpad7, "NP7"),
+	(Key::Numpad8, "NP8"),
+	(Key::Numpad9, "NP9"),
+	(Key::NumpadMul, "NP_MUL"),
+	(Key::NumpadDiv, "NP_DIV"),
+	(Key::NumpadAdd, "NP_ADD"),
+	(Key::NumpadSub, "NP_SUB"),
+	(Key::NumpadDecimal, "NP_DECIMAL"),
+	(Key::Period, "PERIOD"),
+];
+
+impl Key {
+	pub fn all() -> impl Iterator<Item = Key> {
+		KEY_NAMES.iter().map(|(key, _)| *key)
+	}
+
+	pub fn name(&self) -> &'static str {
+		KEY_NAMES.iter().find(|(key, _)| key == self).map(|(_, name)| *name).unwrap_or("")
+	}
+
+	pub fn from_name(name: &str) -> Option<Key> {
+		KEY_NAMES.iter().find(|(_, n)| n.eq_ignore_ascii_case(name)).map(|(key, _)| *key)
+	}
+}
+
+// Everything the interactive frontend can be asked to do from the keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+	CameraYawLeft,
+	CameraYawRight,
+	CameraRollUp,
+	CameraRollDown,
+	FovIncrease,
+	FovDecrease,
+	FovAxisToggle,
+	OrbitToggle,
+	OrbitZoomIn,
+	OrbitZoomOut,
+	LightMoveXNeg,
+	LightMoveXPos,
+	LightMoveYNeg,
+	LightMoveYPos,
+	LightMoveZNeg,
+	LightMoveZPos,
+	CycleTraversalOrder,
+	CycleSampler,
+	SamplesIncrease,
+	SamplesDecrease,
+	CameraShakeToggle,
+	InterleavedToggle,
+	AdaptiveQualityToggle,
+	MaterialParamNext,
+	MaterialIncrease,
+	MaterialDecrease,
+	SceneSave,
+	AnalysisToggle,
+	AutoExposureToggle,
+	GraphsToggle,
+	MagnifierToggle,
+	FrameView,
+	PhysicsReset,
+	OutlinerToggle,
+	OutlinerUp,
+	OutlinerDown,
+	ProgressiveToggle,
+	HideSelected,
+	IsolateSelected,
+	UnhideAll,
+	MeasureToggle,
+	MacroRecordToggle,
+	CompareToggle,
+	ProfilerToggle,
+	PresetNext,
+	LightPlaceToggle,
+}
+
+const ACTION_NAMES: [(Action, &str); 46] = [
+	(Action::CameraYawLeft, "camera_yaw_left"),
+	(Action::CameraYawRight, "camera_yaw_right"),
+	(Action::CameraRollUp, "camera_roll_up"),
+	(Action::CameraRollDown, "camera_roll_down"),
+	(Action::FovIncrease, "fov_increase"),
+	(Action::FovDecrease, "fov_decrease"),
+	(Action::FovAxisToggle, "fov_axis_toggle"),
+	(Action::OrbitToggle, "orbit_toggle"),
+	(Action::OrbitZoomIn, "orbit_zoom_in"),
+	(Action::OrbitZoomOut, "orbit_zoom_out"),
+	(Action::LightMoveXNeg, "light_move_x_neg"),
+	(Action::LightMoveXPos, "light_move_x_pos"),
+	(Action::LightMoveYNeg, "light_move_y_neg"),
+	(Action::LightMoveYPos, "light_move_y_pos"),
+	(Action::LightMoveZNeg, "light_move_z_neg"),
+	(Action::LightMoveZPos, "light_move_z_pos"),
+	(Action::CycleTraversalOrder, "cycle_traversal_order"),
+	(Action::CycleSampler, "cycle_sampler"),
+	(Action::SamplesIncrease, "samples_increase"),
+	(Action::SamplesDecrease, "samples_decrease"),
+	(Action::CameraShakeToggle, "camera_shake_toggle"),
+	(Action::InterleavedToggle, "interleaved_toggle"),
+	(Action::AdaptiveQualityToggle, "adaptive_quality_toggle"),
+	(Action::MaterialParamNext, "material_param_next"),
+	(Action::MaterialIncrease, "material_increase"),
+	(Action::MaterialDecrease, "material_decrease"),
+	(Action::SceneSave, "scene_save"),
+	(Action::AnalysisToggle, "analysis_toggle"),
+	(Action::AutoExposureToggle, "auto_exposure_toggle"),
+	(Action::GraphsToggle, "graphs_toggle"),
+	(Action::MagnifierToggle, "magnifier_toggle"),
+	(Action::FrameView, "frame_view"),
+	(Action::PhysicsReset, "physics_reset"),
+	(Action::OutlinerToggle, "outliner_toggle"),
+	(Action::OutlinerUp, "outliner_up"),
+	(Action::OutlinerDown, "outliner_down"),
+	(Action::ProgressiveToggle, "progressive_toggle"),
+	(Action::HideSelected, "hide_selected"),
+	(Action::IsolateSelected, "isolate_selected"),
+	(Action::UnhideAll, "unhide_all"),
+	(Action::MeasureToggle, "measure_toggle"),
+	(Action::MacroRecordToggle, "macro_record_toggle"),
+	(Action::CompareToggle, "compare_toggle"),
+	(Action::ProfilerToggle, "profiler_toggle"),
+	(Action::PresetNext, "preset_next"),
+	(Action::LightPlaceToggle, "light_place_toggle"),
+];
+
+impl Action {
+	pub fn all() -> impl Iterator<Item = Action> {
+		ACTION_NAMES.iter().map(|(action, _)| *action)
+	}
+
+	pub fn name(&self) -> &'static str {
+		ACTION_NAMES.iter().find(|(action, _)| action == self).map(|(_, name)| *name).unwrap_or("")
+	}
+
+	pub fn from_name(name: &str) -> Option<Action> {
+		ACTION_NAMES.iter().find(|(_, n)| *n == name).map(|(action, _)| *action)
+	}
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ButtonState {
+	pub pressed: bool,
+	pub held: bool,
+	pub released: bool,
+}
+
+pub struct Bindings {
+	keys: HashMap<Action, Vec<Key>>,
+}
+
+impl Default for Bindings {
+	fn default() -> Self {
+		let mut bindings = Bindings { keys: HashMap::new() };
+		bindings.bind(Action::CameraYawLeft, &[Key::Left]);
+		bindings.bind(Action::CameraYawRight, &[Key::Right]);
+		bindings.bind(Action::CameraRollUp, &[Key::Up]);
+		bindings.bind(Action::CameraRollDown, &[Key::Down]);
+		bindings.bind(Action::FovIncrease, &[Key::R]);
+		bindings.bind(Action::FovDecrease, &[Key::F]);
+		bindings.bind(Action::FovAxisToggle, &[Key::V]);
+		bindings.bind(Action::OrbitToggle, &[Key::O]);
+		bindings.bind(Action::OrbitZoomIn, &[Key::PageUp]);
+		bindings.bind(Action::OrbitZoomOut, &[Key::PageDown]);
+		bindings.bind(Action::LightMoveXNeg, &[Key::G]);
+		bindings.bind(Action::LightMoveXPos, &[Key::J]);
+		bindings.bind(Action::LightMoveYNeg, &[Key::H]);
+		bindings.bind(Action::LightMoveYPos, &[Key::Y]);
+		bindings.bind(Action::LightMoveZNeg, &[Key::U]);
+		bindings.bind(Action::LightMoveZPos, &[Key::T]);
+		bindings.bind(Action::CycleTraversalOrder, &[Key::P]);
+		bindings.bind(Action::CycleSampler, &[Key::K]);
+		bindings.bind(Action::SamplesIncrease, &[Key::NumpadAdd]);
+		bindings.bind(Action::SamplesDecrease, &[Key::NumpadSub]);
+		bindings.bind(Action::CameraShakeToggle, &[Key::X]);
+		bindings.bind(Action::InterleavedToggle, &[Key::I]);
+		bindings.bind(Action::AdaptiveQualityToggle, &[Key::B]);
+		bindings.bind(Action::MaterialParamNext, &[Key::M]);
+		bindings.bind(Action::MaterialIncrease, &[Key::E]);
+		bindings.bind(Action::MaterialDecrease, &[Key::Q]);
+		bindings.bind(Action::SceneSave, &[Key::F5]);
+		bindings.bind(Action::AnalysisToggle, &[Key::Z]);
+		bindings.bind(Action::AutoExposureToggle, &[Key::N]);
+		bindings.bind(Action::GraphsToggle, &[Key::F3]);
+		bindings.bind(Action::MagnifierToggle, &[Key::F4]);
+		bindings.bind(Action::FrameView, &[Key::Home]);
+		bindings.bind(Action::PhysicsReset, &[Key::Backspace]);
+		bindings.bind(Action::OutlinerToggle, &[Key::F6]);
+		bindings.bind(Action::OutlinerUp, &[Key::W]);
+		bindings.bind(Action::OutlinerDown, &[Key::S]);
+		bindings.bind(Action::ProgressiveToggle, &[Key::L]);
+		bindings.bind(Action::HideSelected, &[Key::Delete]);
+		bindings.bind(Action::IsolateSelected, &[Key::NumpadDiv]);
+		bindings.bind(Action::UnhideAll, &[Key::Insert]);
+		bindings.bind(Action::MeasureToggle, &[Key::F7]);
+		bindings.bind(Action::MacroRecordToggle, &[Key::F8]);
+		bindings.bind(Action::CompareToggle, &[Key::C]);
+		bindings.bind(Action::ProfilerToggle, &[Key::F9]);
+		bindings.bind(Action::PresetNext, &[Key::F10]);
+		bindings.bind(Action::LightPlaceToggle, &[Key::F11]);
+		bindings
+	}
+}
+
+impl Bindings {
+	pub fn bind(&mut self, action: Action, keys: &[Key]) {
+		self.keys.insert(action, keys.to_vec());
+	}
+
+	pub fn keys(&self, action: Action) -> &[Key] {
+		self.keys.get(&action).map(|keys| keys.as_slice()).unwrap_or(&[])
+	}
+
+	// Parses `action = KEY, KEY` lines on top of the defaults; `#` starts a comment
+	pub fn parse(text: &str) -> Result<Bindings, String> {
+		let mut bindings = Bindings::default();
+		for (line_no, line) in text.lines().enumerate() {
+			let line = line.split('#').next().unwrap_or("").trim();
+			if line.is_empty() { continue }
+			let (name, keys) = line.split_once('=').ok_or(format!("line {}: expected `action = KEY`", line_no + 1))?;
+			let action = Action::from_name(name.trim()).ok_or(format!("line {}: unknown action `{}`", line_no + 1, name.trim()))?;
+			let mut parsed = Vec::new();
+			for key in keys.split(',').map(|k| k.trim()).filter(|k| !k.is_empty()) {
+				parsed.push(Key::from_name(key).ok_or(format!("line {}: unknown key `{}`", line_no + 1, key))?);
+			}
+			bindings.bind(action, &parsed);
+		}
+		Ok(bindings)
+	}
+
+	pub fn load(path: &str) -> Result<Bindings, String> {
+		let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+		Bindings::parse(&text)
+	}
+
+	pub fn to_config(&self) -> String {
+		let mut text = String::new();
+		for action in Action::all() {
+			let keys: Vec<&str> = self.keys(action).iter().map(|k| k.name()).collect();
+			text += &format!("{} = {}\n", action.name(), keys.join(", "));
+		}
+		text
+	}
+
+	// Combined state of every key bound to action
+	pub fn state(&self, input: &dyn InputSource, action: Action) -> ButtonState {
+		let mut state = ButtonState::default();
+		for key in self.keys(action) {
+			let key_state = input.key_state(*key);
+			state.pressed |= key_state.pressed;
+			state.held |= key_state.held;
+			state.released |= key_state.released;
+		}
+		state
+	}
+
+	pub fn pressed(&self, input: &dyn InputSource, action: Action) -> bool {
+		self.state(input, action).pressed
+	}
+
+	pub fn held(&self, input: &dyn InputSource, action: Action) -> bool {
+		self.state(input, action).held
+	}
+}
+
+#[test]
+fn bindings_round_trip() {
+	let mut bindings = Bindings::default();
+	bindings.bind(Action::OrbitToggle, &[Key::Tab, Key::F2]);
+	let parsed = Bindings::parse(&bindings.to_config()).unwrap();
+	for action in Action::all() {
+		assert_eq!(parsed.keys(action), bindings.keys(action));
+	}
+	assert!(Bindings::parse("orbit_toggle = NOPE").is_err());
+}
@@ -0,0 +1,224 @@
+//
+//	kd-tree
+//
+
+// Space split in two by axis aligned planes, over and over, each plane placed where the surface area
+// heuristic says rays will do least work: the chance of a ray passing through each side, going by
+// its area, times what's in it. Unlike the hierarchy's boxes the two sides never overlap, so a ray
+// walks them strictly front to back, but a box straddling a plane is listed on both sides
+
+use crate::structs::{Aabb, Ray, Vec3};
+
+const TRAVERSAL_COST: f64 = 1.0;
+const INTERSECT_COST: f64 = 80.0; // per item, relative to stepping down a node
+const EMPTY_BONUS: f64 = 0.5; // how much cheaper a split leaving one side empty counts as
+const MAX_STACK: usize = 64;
+
+// Interior nodes have the side below split right after them and the one above at above,
+// leaves own count items from first
+#[derive(Clone, Copy)]
+enum Node {
+	Interior { axis: usize, split: f64, above: u32 },
+	Leaf { first: u32, count: u32 },
+}
+
+pub struct KdTree {
+	bounds: Aabb,
+	nodes: Vec<Node>,
+	items: Vec<u32>, // indices of the bounds it was built from, leaf by leaf
+}
+
+impl KdTree {
+	pub fn new(bounds: &[Aabb]) -> Self {
+		let mut around = Aabb::empty();
+		for item in bounds.iter() {
+			around.grow(&item.min);
+			around.grow(&item.max);
+		}
+		let mut tree = KdTree { bounds: around, nodes: Vec::new(), items: Vec::new() };
+		if !bounds.is_empty() {
+			let max_depth = (8.0 + 1.3 * (bounds.len() as f64).log2()).round() as usize;
+			tree.build(bounds, (0..bounds.len() as u32).collect(), around, max_depth.min(MAX_STACK - 1));
+		}
+		tree
+	}
+
+	fn build(&mut self, bounds: &[Aabb], items: Vec<u32>, node_bounds: Aabb, depth: usize) {
+		let split = if depth == 0 || items.len() <= 1 { None } else { best_split(bounds, &items, &node_bounds) };
+		let (axis, split) = match split {
+			Some(split) => split,
+			None => {
+				self.nodes.push(Node::Leaf { first: self.items.len() as u32, count: items.len() as u32 });
+				self.items.extend(items);
+				return;
+			}
+		};
+		// Whatever touches the plane goes both ways
+		let below: Vec<u32> = items.iter().copied().filter(|item| axes(&bounds[*item as usize].min)[axis] <= split).collect();
+		let above: Vec<u32> = items.iter().copied().filter(|item| axes(&bounds[*item as usize].max)[axis] >= split).collect();
+		let (mut below_bounds, mut above_bounds) = (node_bounds, node_bounds);
+		set_axis(&mut below_bounds.max, axis, split);
+		set_axis(&mut above_bounds.min, axis, split);
+		let index = self.nodes.len();
+		self.nodes.push(Node::Interior { axis, split, above: 0 });
+		self.build(bounds, below, below_bounds, depth - 1);
+		let above_index = self.nodes.len() as u32;
+		if let Node::Interior { above, .. } = &mut self.nodes[index] {
+			*above = above_index;
+		}
+		self.build(bounds, above, above_bounds, depth - 1);
+	}
+
+	// Items in every leaf the ray passes through, front to back. Items in several leaves come up once for each
+	pub fn candidates<'a>(&'a self, ray: &'a Ray) -> impl Iterator<Item = usize> + 'a {
+		let (origin, direction) = (axes(&ray.origin), axes(&ray.direction));
+		let mut stack = [(0u32, 0.0, 0.0); MAX_STACK];
+		let mut depth = 0;
+		if let Some((near, far)) = self.bounds_span(ray).filter(|_| !self.nodes.is_empty()) {
+			stack[0] = (0, near, far);
+			depth = 1;
+		}
+		let mut leaf: &[u32] = &[];
+		std::iter::from_fn(move || loop {
+			if let Some((item, rest)) = leaf.split_first() {
+				leaf = rest;
+				return Some(*item as usize);
+			}
+			if depth == 0 { return None }
+			depth -= 1;
+			let (index, near, far) = stack[depth];
+			let (axis, split, above) = match self.nodes[index as usize] {
+				Node::Leaf { first, count } => {
+					leaf = &self.items[first as usize..(first + count) as usize];
+					continue;
+				}
+				Node::Interior { axis, split, above } => (axis, split, above),
+			};
+			let below = index + 1;
+			// Along the plane only the side the ray runs down matters, both when it's right on it
+			if direction[axis] == 0.0 {
+				for (child, side) in [(below, origin[axis] <= split), (above, origin[axis] >= split)] {
+					if side {
+						stack[depth] = (child, near, far);
+						depth += 1;
+					}
+				}
+				continue;
+			}
+			let crossing = (split - origin[axis]) / direction[axis];
+			let (first, second) = if origin[axis] < split || (origin[axis] == split && direction[axis] < 0.0) { (below, above) } else { (above, below) };
+			// The far side goes on the stack first so the near one comes off next
+			if crossing > far || crossing <= 0.0 {
+				stack[depth] = (first, near, far);
+				depth += 1;
+			} else if crossing < near {
+				stack[depth] = (second, near, far);
+				depth += 1;
+			} else {
+				stack[depth] = (second, crossing, far);
+				stack[depth + 1] = (first, near, crossing);
+				depth += 2;
+			}
+		})
+	}
+
+	// Distances along the ray to where it enters and leaves the tree's bounds
+	fn bounds_span(&self, ray: &Ray) -> Option<(f64, f64)> {
+		let (origin, direction) = (axes(&ray.origin), axes(&ray.direction));
+		let (min, max) = (axes(&self.bounds.min), axes(&self.bounds.max));
+		let (mut near, mut far) = (0.0f64, f64::INFINITY);
+		for axis in 0..3 {
+			if direction[axis] == 0.0 {
+				if origin[axis] < min[axis] || origin[axis] > max[axis] { return None }
+				continue;
+			}
+			let (t0, t1) = ((min[axis] - origin[axis]) / direction[axis], (max[axis] - origin[axis]) / direction[axis]);
+			near = near.max(t0.min(t1));
+			far = far.min(t0.max(t1));
+		}
+		if near <= far { Some((near, far)) } else { None }
+	}
+}
+
+// Plane with the lowest expected cost, as (axis, position), None when not splitting is cheaper.
+// Candidates are the edges of the items' boxes, swept along each axis in order
+fn best_split(bounds: &[Aabb], items: &[u32], node_bounds: &Aabb) -> Option<(usize, f64)> {
+	let (min, max) = (axes(&node_bounds.min), axes(&node_bounds.max));
+	let size = [0, 1, 2].map(|axis| max[axis] - min[axis]);
+	let area = node_bounds.area();
+	if area <= 0.0 { return None }
+	let leaf_cost = INTERSECT_COST * items.len() as f64;
+	let mut best: Option<(f64, usize, f64)> = None;
+	let mut edges: Vec<(f64, bool)> = Vec::with_capacity(items.len() * 2); // position, whether a box starts there
+	for axis in 0..3 {
+		edges.clear();
+		for item in items.iter() {
+			let item = &bounds[*item as usize];
+			edges.push((axes(&item.min)[axis], true));
+			edges.push((axes(&item.max)[axis], false));
+		}
+		// Ends before starts at the same place, so a box is counted out before the next is in
+		edges.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+		let (other1, other2) = ((axis + 1) % 3, (axis + 2) % 3);
+		let (mut below, mut above) = (0, items.len());
+		for (position, starts) in edges.iter().copied() {
+			if !starts { above -= 1 }
+			if position > min[axis] && position < max[axis] {
+				let side_area = |length: f64| 2.0 * (size[other1] * size[other2] + length * (size[other1] + size[other2]));
+				let (below_area, above_area) = (side_area(position - min[axis]), side_area(max[axis] - position));
+				let bonus = if below == 0 || above == 0 { EMPTY_BONUS } else { 0.0 };
+				let cost = TRAVERSAL_COST + INTERSECT_COST * (1.0 - bonus) * (below_area / area * below as f64 + above_area / area * above as f64);
+				if best.is_none_or(|(best, _, _)| cost < best) {
+					best = Some((cost, axis, position));
+				}
+			}
+			if starts { below += 1 }
+		}
+	}
+	best.filter(|(cost, _, _)| *cost < leaf_cost).map(|(_, axis, position)| (axis, position))
+}
+
+fn axes(v: &Vec3) -> [f64; 3] {
+	[v.x, v.y, v.z]
+}
+
+fn set_axis(v: &mut Vec3, axis: usize, value: f64) {
+	match axis {
+		0 => v.x = value,
+		1 => v.y = value,
+		_ => v.z = value,
+	}
+}
+
+#[test]
+fn kd_tree_finds_what_the_rays_pass_through() {
+	use crate::random::Rng;
+	let mut rng = Rng::new(9);
+	let mut point = || Vec3 { x: rng.next_f64() * 20.0 - 10.0, y: rng.next_f64() * 20.0 - 10.0, z: rng.next_f64() * 20.0 - 10.0 };
+	let mut boxes: Vec<Aabb> = (0..300).map(|_| {
+		let mut bounds = Aabb::empty();
+		let corner = point();
+		bounds.grow(&corner);
+		bounds.grow(&corner.add(&Vec3 { x: 0.5, y: 0.3, z: 0.2 }));
+		bounds
+	}).collect();
+	// A flat one, and a few stacked right on top of each other
+	boxes.push(Aabb { min: Vec3 { x: -20.0, y: -20.0, z: 0.0 }, max: Vec3 { x: 20.0, y: 20.0, z: 0.0 } });
+	boxes.extend([boxes[0]; 4]);
+	let tree = KdTree::new(&boxes);
+	assert!(tree.nodes.len() > 100);
+	let check = |ray: &Ray| {
+		let mut found: Vec<usize> = tree.candidates(ray).filter(|item| boxes[*item].ray_distance(ray).is_some()).collect();
+		found.sort();
+		found.dedup();
+		assert_eq!(found, (0..boxes.len()).filter(|item| boxes[*item].ray_distance(ray).is_some()).collect::<Vec<_>>());
+	};
+	for _ in 0..200 {
+		let (from, to) = (point().mul(3.0), point());
+		check(&Ray::from_to(&from, &to));
+	}
+	// Straight down onto the flat box, and along it
+	check(&Ray::from_to(&Vec3 { x: 0.5, y: 0.5, z: 10.0 }, &Vec3 { x: 0.5, y: 0.5, z: 0.0 }));
+	check(&Ray::from_to(&Vec3 { x: -30.0, y: 0.5, z: 0.0 }, &Vec3 { x: 30.0, y: 0.5, z: 0.0 }));
+	assert_eq!(KdTree::new(&[]).candidates(&Ray::from_to(&Vec3 { x: 0.0, y: 0.0, z: 1.0 }, &Vec3 { x: 0.0, y: 0.0, z: 0.0 })).count(), 0);
+}
@@ -0,0 +1,139 @@
+//
+//	Text Labels
+//
+
+// Text as geometry, for labels and titles in the scene itself. Glyphs come from a small bundled
+// 5x7 pixel font, each lit pixel extruded into a block, with the sides between neighbors left out
+
+use crate::structs::{Material, Mesh, Rot3, Tri, Vec3};
+
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+const ADVANCE: usize = GLYPH_WIDTH + 1; // pixels from one glyph to the next
+const LINE_HEIGHT: usize = GLYPH_HEIGHT + 2;
+
+// Rows top to bottom, the leftmost pixel in the highest bit. Lowercase is drawn as uppercase
+const GLYPHS: [(char, [u8; GLYPH_HEIGHT]); 49] = [
+	('A', [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+	('B', [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110]),
+	('C', [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110]),
+	('D', [0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110]),
+	('E', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111]),
+	('F', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000]),
+	('G', [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111]),
+	('H', [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+	('I', [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+	('J', [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100]),
+	('K', [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001]),
+	('L', [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111]),
+	('M', [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001]),
+	('N', [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001]),
+	('O', [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+	('P', [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000]),
+	('Q', [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101]),
+	('R', [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001]),
+	('S', [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110]),
+	('T', [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100]),
+	('U', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+	('V', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100]),
+	('W', [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010]),
+	('X', [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001]),
+	('Y', [0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100, 0b00100]),
+	('Z', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111]),
+	('0', [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110]),
+	('1', [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+	('2', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111]),
+	('3', [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110]),
+	('4', [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010]),
+	('5', [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110]),
+	('6', [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110]),
+	('7', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000]),
+	('8', [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110]),
+	('9', [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100]),
+	('.', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100]),
+	(',', [0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000]),
+	('!', [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00100]),
+	('?', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100]),
+	('-', [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000]),
+	('+', [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000]),
+	(':', [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000]),
+	('\'', [0b00100, 0b00100, 0b01000, 0b00000, 0b00000, 0b00000, 0b00000]),
+	('/', [0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000]),
+	('(', [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010]),
+	(')', [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000]),
+	('#', [0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010]),
+	('_', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111]),
+];
+
+fn glyph(c: char) -> Option<[u8; GLYPH_HEIGHT]> {
+	if c == ' ' { return None }
+	let c = c.to_ascii_uppercase();
+	let find = |c: char| GLYPHS.iter().find(|(glyph, _)| *glyph == c).map(|(_, rows)| *rows);
+	find(c).or_else(|| find('?'))
+}
+
+// Two triangles spanning corner to corner + u + v
+fn quad(tris: &mut Vec<Tri>, corner: Vec3, u: Vec3, v: Vec3, mat: Material) {
+	let (a, b, c, d) = (corner, corner.add(&u), corner.add(&u).add(&v), corner.add(&v));
+	tris.push(Tri { a, b, c, mat });
+	tris.push(Tri { a, b: c, c: d, mat });
+}
+
+// Lines centered on x = 0 one under the other, reading along +x and up +z, the bottom of the last one
+// at z = 0. height is the height of a capital, the letters are depth thick, centered on y = 0
+pub fn text_tris(text: &str, height: f64, depth: f64, material: Material) -> Vec<Tri> {
+	let pixel = height / GLYPH_HEIGHT as f64;
+	let lines: Vec<&str> = text.lines().collect();
+	let (front, back) = (-depth * 0.5, depth * 0.5);
+	let mut tris = Vec::new();
+	for (line_index, line) in lines.iter().enumerate() {
+		let width = (line.chars().count() * ADVANCE).saturating_sub(1) as f64 * pixel;
+		let top = ((lines.len() - line_index - 1) * LINE_HEIGHT + GLYPH_HEIGHT) as f64 * pixel;
+		for (column, c) in line.chars().enumerate() {
+			let rows = match glyph(c) {
+				Some(rows) => rows,
+				None => continue,
+			};
+			let lit = |x: i32, y: i32| x >= 0 && y >= 0 && x < GLYPH_WIDTH as i32 && y < GLYPH_HEIGHT as i32
+				&& rows[y as usize] & (1 << (GLYPH_WIDTH as i32 - 1 - x)) != 0;
+			for y in 0..GLYPH_HEIGHT as i32 {
+				for x in 0..GLYPH_WIDTH as i32 {
+					if !lit(x, y) { continue }
+					let left = (column * ADVANCE) as f64 * pixel + x as f64 * pixel - width * 0.5;
+					let bottom = top - (y + 1) as f64 * pixel;
+					let (right, upper) = (left + pixel, bottom + pixel);
+					let corner = |x: f64, y: f64, z: f64| Vec3 { x, y, z };
+					let (across, up, through) = (corner(pixel, 0.0, 0.0), corner(0.0, 0.0, pixel), corner(0.0, depth, 0.0));
+					quad(&mut tris, corner(left, front, bottom), across, up, material);
+					quad(&mut tris, corner(left, back, bottom), up, across, material);
+					if !lit(x - 1, y) { quad(&mut tris, corner(left, front, bottom), up, through, material) }
+					if !lit(x + 1, y) { quad(&mut tris, corner(right, front, bottom), through, up, material) }
+					if !lit(x, y - 1) { quad(&mut tris, corner(left, front, upper), across, through, material) }
+					if !lit(x, y + 1) { quad(&mut tris, corner(left, front, bottom), through, across, material) }
+				}
+			}
+		}
+	}
+	tris
+}
+
+pub fn text_mesh(anchor: Vec3, rot: Rot3, text: &str, height: f64, depth: f64, material: Material) -> Mesh {
+	Mesh::new(anchor, rot, text_tris(text, height, depth, material))
+}
+
+#[test]
+fn letters_become_blocks() {
+	use crate::structs::{Color, SceneObject};
+	let material = Material { color: Color { r: 255, g: 255, b: 255 }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+	// A dash is a single row of five pixels: front and back, two ends, top and bottom
+	assert_eq!(text_tris("-", 7.0, 1.0, material).len(), 5 * 4 + 2 * 2 + 5 * 2 * 2);
+	assert!(text_tris(" ", 7.0, 1.0, material).is_empty());
+	assert_eq!(text_tris("a", 1.0, 0.1, material).len(), text_tris("A", 1.0, 0.1, material).len());
+	assert_eq!(text_tris("~", 1.0, 0.1, material).len(), text_tris("?", 1.0, 0.1, material).len());
+
+	// Two lines, two full width glyphs and the gap between them across, standing on z = 0
+	let bounds = text_mesh(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, Rot3::new(), "HH\nT", 0.7, 0.2, material).bounds().unwrap();
+	assert!((bounds.min.x + 0.55).abs() < 1e-9 && (bounds.max.x - 0.55).abs() < 1e-9, "{:?}", bounds);
+	assert!(bounds.min.z.abs() < 1e-9 && (bounds.max.z - 1.6).abs() < 1e-9);
+	assert!((bounds.min.y + 0.1).abs() < 1e-9 && (bounds.max.y - 0.1).abs() < 1e-9);
+}
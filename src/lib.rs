@@ -0,0 +1,58 @@
+pub mod structs;
+pub mod render;
+pub mod random;
+pub mod sampler;
+pub mod controls;
+pub mod input;
+pub mod frontend;
+pub mod framebuffer;
+pub mod exr;
+pub mod quality;
+pub mod scene_file;
+pub mod texture;
+pub mod cryptomatte;
+pub mod photon;
+pub mod sppm;
+pub mod path;
+pub mod transport;
+pub mod analysis;
+pub mod exposure;
+pub mod bench;
+pub mod graphs;
+pub mod magnifier;
+pub mod animation;
+pub mod physics;
+pub mod voxel;
+pub mod point_cloud;
+pub mod label;
+pub mod bvh;
+pub mod bvh_cache;
+pub mod arena;
+pub mod outline;
+pub mod tiles;
+pub mod outliner;
+pub mod progressive;
+pub mod measure;
+pub mod simd;
+pub mod archive;
+pub mod macros;
+pub mod grid;
+pub mod accelerator;
+pub mod kdtree;
+pub mod media;
+pub mod clipping;
+pub mod backplate;
+pub mod environment;
+pub mod light_tree;
+pub mod light_placement;
+pub mod material_preview;
+pub mod stats;
+pub mod resolution;
+pub mod stereo;
+pub mod compare;
+pub mod diff;
+pub mod regions;
+pub mod lint;
+pub mod profiler;
+pub mod presets;
+pub mod sequence;
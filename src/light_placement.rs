@@ -0,0 +1,75 @@
+//
+//	Light Placement
+//
+
+// Putting lights where they're wanted by pointing at the surface they should light, rather than
+// typing in coordinates. Each pick adds a point light hovering offset in front of the surface under
+// the cursor, on the side the camera sees, and hands back its id so it can be selected and moved on
+// from there
+
+use crate::frontend::Presenter;
+use crate::structs::{Color, LightSource, Rot3, Scene, Vec3};
+
+pub const DEFAULT_LIGHT_OFFSET: f64 = 0.5;
+pub const PLACED_INTENSITY: f32 = 1.0;
+
+pub struct LightPlacement {
+	pub offset: f64, // along the surface normal
+	pub intensity: f32, // of the lights placed
+	pub placed: usize, // so far, for the readout
+}
+
+impl LightPlacement {
+	pub fn new(offset: f64) -> Self {
+		LightPlacement { offset, intensity: PLACED_INTENSITY, placed: 0 }
+	}
+
+	// Adds a light in front of the surface under pixel (x, y) of a width x height view, returning its
+	// id, None when there's nothing there
+	pub fn place(&mut self, scene: &mut Scene, x: i32, y: i32, width: i32, height: i32) -> Option<String> {
+		let ray = scene.primary_ray(x, y, (0.5, 0.5), width, height);
+		let hit = scene.raycast(&ray, f64::INFINITY)?;
+		let normal = if Vec3::dot(&hit.normal, &ray.direction) > 0.0 { hit.normal.mul(-1.0) } else { hit.normal };
+		let light = LightSource::new(hit.point.add(&normal.mul(self.offset)), Rot3::new(), self.intensity);
+		let id = light.id.clone();
+		scene.objects.push(Box::new(light));
+		self.placed += 1;
+		Some(id)
+	}
+
+	pub fn draw(&self, presenter: &mut dyn Presenter, height: i32) {
+		let readout = format!("place light {:.2} off, {} placed", self.offset, self.placed);
+		presenter.draw_string(1, height - 9, &readout, Color { r: 255, g: 255, b: 255 });
+	}
+}
+
+impl Default for LightPlacement {
+	fn default() -> Self {
+		Self::new(DEFAULT_LIGHT_OFFSET)
+	}
+}
+
+#[test]
+fn lights_placed_in_front_of_picked_surfaces() {
+	let mut scene = Scene::default_scene();
+	scene.current_camera.look_at(&Vec3 { x: 0.0, y: 0.0, z: 1.5 }, &Vec3 { x: 0.0, y: 0.0, z: 1.0 });
+	let (width, height) = (32, 18);
+	let objects = scene.objects.len();
+	let mut placement = LightPlacement::new(0.25);
+
+	// The cube in the middle of the view, the light between it and the camera
+	let ray = scene.primary_ray(16, 9, (0.5, 0.5), width, height);
+	let surface = scene.raycast(&ray, f64::INFINITY).unwrap();
+	let id = placement.place(&mut scene, 16, 9, width, height).unwrap();
+	assert_eq!((scene.objects.len(), placement.placed), (objects + 1, 1));
+	let light = scene.objects.last().unwrap();
+	assert_eq!(light.get_id(), &id);
+	assert!((light.get_pos().dist(&surface.point) - 0.25).abs() < 1e-9);
+	assert!(light.get_pos().dist(&scene.current_camera.pos) < surface.point.dist(&scene.current_camera.pos));
+	assert_eq!(scene.pick(16, 9, width, height), Some(scene.objects[surface.object].get_id().clone()));
+
+	// Nothing to put it in front of
+	scene.current_camera.look_at(&Vec3 { x: 0.0, y: 0.0, z: 100.0 }, &Vec3 { x: 0.0, y: 0.0, z: 1.0 });
+	assert_eq!(placement.place(&mut scene, 16, 0, width, height), None);
+	assert_eq!(scene.objects.len(), objects + 1);
+}
@@ -0,0 +1,135 @@
+//
+//	Light Tree
+//
+
+// For scenes lit by hundreds of lights, emissive particles or a city at night, where sending a shadow
+// ray to every one of them from every point costs more than the rest of the frame together. The lights
+// are gathered into a hierarchy by where they are, each node knowing its bounds and how bright
+// everything under it is, and a shading point picks a few by walking down from the top, going at each
+// node to one child or the other in proportion to how much light each looks to give from there. A
+// pick costs the depth of the tree rather than the number of lights, nearby bright ones come up
+// most, and each is weighted by how unlikely it was so the few together stand in for them all
+
+use crate::sampler::Sampler;
+use crate::structs::{Aabb, LightSource, Scene, Vec3};
+
+struct LightNode {
+	bounds: Aabb, // of the lights under it, grown by their radii
+	power: f64, // their intensities added up
+	children: Option<(usize, usize)>,
+	light: usize, // leaves only, index into the scene's objects
+}
+
+pub struct LightTree {
+	nodes: Vec<LightNode>, // root first
+}
+
+impl LightTree {
+	pub fn new(scene: &Scene) -> Self {
+		let mut lights: Vec<(usize, &LightSource)> = scene.objects.iter().enumerate()
+			.filter_map(|(index, object)| object.as_any_immut().downcast_ref::<LightSource>().map(|light| (index, light)))
+			.collect();
+		let mut tree = LightTree { nodes: Vec::with_capacity(lights.len() * 2) };
+		if !lights.is_empty() {
+			tree.build(&mut lights);
+		}
+		tree
+	}
+
+	// Split in half along the widest spread of positions, until there's one light a node
+	fn build(&mut self, lights: &mut [(usize, &LightSource)]) -> usize {
+		let index = self.nodes.len();
+		let mut bounds = Aabb::empty();
+		for (_, light) in lights.iter() {
+			let radius = Vec3 { x: light.radius as f64, y: light.radius as f64, z: light.radius as f64 };
+			bounds.grow(&light.pos.sub(&radius));
+			bounds.grow(&light.pos.add(&radius));
+		}
+		let power = lights.iter().map(|(_, light)| (light.intensity as f64).max(0.0)).sum();
+		self.nodes.push(LightNode { bounds, power, children: None, light: lights[0].0 });
+		if lights.len() > 1 {
+			let extent = bounds.max.sub(&bounds.min);
+			let axis = |position: &Vec3| if extent.x >= extent.y && extent.x >= extent.z { position.x } else if extent.y >= extent.z { position.y } else { position.z };
+			lights.sort_by(|a, b| axis(&a.1.pos).total_cmp(&axis(&b.1.pos)));
+			let (below, above) = lights.split_at_mut(lights.len() / 2);
+			let children = (self.build(below), self.build(above));
+			self.nodes[index].children = Some(children);
+		}
+		index
+	}
+
+	// Roughly how much of node's light reaches point, its power over the squared distance to its
+	// middle, or to its edge when that's nearer than its size
+	fn importance(&self, node: usize, point: &Vec3) -> f64 {
+		let node = &self.nodes[node];
+		let center = node.bounds.center();
+		let size = node.bounds.max.sub(&center);
+		let distance = point.sub(&center);
+		node.power / Vec3::dot(&distance, &distance).max(Vec3::dot(&size, &size)).max(1e-12)
+	}
+
+	// One light for point by the random number u in [0, 1), as its index in the scene's objects and
+	// the probability of it being the one picked
+	pub fn pick(&self, point: &Vec3, mut u: f64) -> Option<(usize, f64)> {
+		let mut node = 0;
+		let mut probability = 1.0;
+		while let Some((left, right)) = self.nodes.get(node)?.children {
+			let (left_importance, right_importance) = (self.importance(left, point), self.importance(right, point));
+			let total = left_importance + right_importance;
+			let chance = if total > 0.0 { left_importance / total } else { 0.5 };
+			if u < chance {
+				u /= chance;
+				node = left;
+				probability *= chance;
+			} else {
+				u = ((u - chance) / (1.0 - chance)).min(1.0 - f64::EPSILON);
+				node = right;
+				probability *= 1.0 - chance;
+			}
+		}
+		Some((self.nodes[node].light, probability))
+	}
+
+	// count lights for point into picks, each with the weight it's shaded with so that on average
+	// they add up to every light in the tree. The same light can come up more than once
+	pub fn pick_lights(&self, point: &Vec3, count: u32, sampler: &mut dyn Sampler, picks: &mut Vec<(usize, f64)>) {
+		picks.clear();
+		for _ in 0..count {
+			if let Some((light, probability)) = self.pick(point, sampler.next_1d()) {
+				picks.push((light, 1.0 / (probability * count as f64)));
+			}
+		}
+	}
+}
+
+#[test]
+fn nearby_bright_lights_picked_most() {
+	use crate::structs::{Camera, Rot3, SceneObject};
+	// A row of a hundred equal lights along x, and one much brighter at the far end
+	let mut objects: Vec<Box<dyn SceneObject>> = (0..100).map(|x| Box::new(LightSource::new(Vec3 { x: x as f64, y: 0.0, z: 2.0 }, Rot3::new(), 1.0)) as Box<dyn SceneObject>).collect();
+	objects.push(Box::new(LightSource::new(Vec3 { x: 120.0, y: 0.0, z: 2.0 }, Rot3::new(), 1000.0)));
+	let scene = Scene::new(objects, Camera::new(Vec3 { x: 0.0, y: -10.0, z: 0.0 }, Rot3::new(), 40.0));
+	let tree = LightTree::new(&scene);
+	assert_eq!(tree.nodes.len(), 201);
+
+	// Every light can come up, each about as often as its probability says
+	let point = Vec3 { x: 10.0, y: 0.0, z: 0.0 };
+	let draws = 20000;
+	let mut counts = vec![0; 101];
+	let mut probabilities = vec![0.0; 101];
+	for draw in 0..draws {
+		let (light, probability) = tree.pick(&point, (draw as f64 + 0.5) / draws as f64).unwrap();
+		counts[light] += 1;
+		probabilities[light] = probability;
+	}
+	assert!(counts.iter().all(|count| *count > 0));
+	assert!((probabilities.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+	for (count, probability) in counts.iter().zip(probabilities.iter()) {
+		assert!((*count as f64 / draws as f64 - probability).abs() < 0.002);
+	}
+	// The light right over the point beats one at the far end of the row, and the bright one past
+	// the end beats that too
+	assert!(probabilities[10] > probabilities[90] * 10.0);
+	assert!(probabilities[100] > probabilities[90] * 10.0);
+	assert!(LightTree::new(&Scene::new(Vec::new(), Camera::new(point, Rot3::new(), 40.0))).pick(&point, 0.5).is_none());
+}
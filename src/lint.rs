@@ -0,0 +1,158 @@
+//
+//	Scene Linting
+//
+
+// Authoring mistakes that still load but make for a wrong or wasted render, found before spending
+// hours on one. Each file, referenced ones included, is checked as written: materials out of range
+// or never used, things sized zero, triangles with no area and textures a mesh has no uvs for. Then
+// the loaded scene for lights shut inside solid objects, where they light nothing
+
+use crate::media::crossings;
+use crate::scene_file::{load_scene, ObjectDesc, SceneFile};
+use crate::structs::{LightSource, Ray, Scene, Vec3};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+// Cross product lengths below this make a triangle degenerate, in the file's units squared
+const DEGENERATE_AREA: f64 = 1e-12;
+
+// Problems with the scene file at path, one line each, or why it doesn't load at all
+pub fn lint(path: &Path) -> Result<Vec<String>, String> {
+	let scene = load_scene(path)?;
+	let mut problems = Vec::new();
+	let mut linted = HashSet::new();
+	lint_file(path, &mut linted, &mut problems)?;
+	problems.extend(enclosed_lights(&scene));
+	Ok(problems)
+}
+
+fn lint_file(path: &Path, linted: &mut HashSet<PathBuf>, problems: &mut Vec<String>) -> Result<(), String> {
+	if !linted.insert(path.canonicalize().map_err(|e| format!("{}: {}", path.display(), e))?) { return Ok(()) }
+	let file = SceneFile::load(path)?;
+	let mut problem = |message: String| problems.push(format!("{}: {}", path.display(), message));
+	if file.scale <= 0.0 {
+		problem(format!("scale {} leaves nothing to see", file.scale));
+	}
+
+	let mut used: HashSet<&String> = file.clip_planes.iter().filter_map(|plane| plane.cap.as_ref()).collect();
+	let mut references = Vec::new();
+	for (index, object) in file.objects.iter().enumerate() {
+		let (id, size, materials): (&Option<String>, Option<f64>, Vec<&String>) = match object {
+			ObjectDesc::Mesh { id, materials, .. } => (id, None, materials.iter().collect()),
+			ObjectDesc::Cube { id, size, material, .. } | ObjectDesc::Plane { id, size, material, .. } => (id, Some(*size), vec![material]),
+			ObjectDesc::Text { id, height, material, .. } => (id, Some(*height), vec![material]),
+			ObjectDesc::Sphere { id, radius, material, .. } => (id, Some(*radius as f64), vec![material]),
+			ObjectDesc::Voxels { id, size, .. } => (id, Some(*size), Vec::new()),
+			ObjectDesc::Points { id, radius, .. } => (id, Some(*radius), Vec::new()),
+			ObjectDesc::Light { .. } => continue,
+			ObjectDesc::Reference { path: reference, overrides, .. } => {
+				used.extend(overrides.values());
+				references.push(path.parent().unwrap_or(Path::new("")).join(reference));
+				continue;
+			}
+		};
+		used.extend(materials);
+		let name = match id {
+			Some(id) => format!("object {} (`{}`)", index, id),
+			None => format!("object {}", index),
+		};
+		if size.is_some_and(|size| size <= 0.0) {
+			problem(format!("{} has zero size", name));
+		}
+		if let ObjectDesc::Mesh { positions, indices, uvs, texture, .. } = object {
+			let degenerate = indices.iter().filter(|triangle| {
+				let [a, b, c] = triangle.map(|index| positions.get(index as usize).map_or(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, |p| Vec3 { x: p[0], y: p[1], z: p[2] }));
+				Vec3::cross(&b.sub(&a), &c.sub(&a)).dist(&Vec3 { x: 0.0, y: 0.0, z: 0.0 }) < DEGENERATE_AREA
+			}).count();
+			if degenerate > 0 {
+				problem(format!("{} has {} degenerate triangle{}", name, degenerate, if degenerate == 1 { "" } else { "s" }));
+			}
+			if let (Some(texture), true) = (texture, uvs.is_empty()) {
+				problem(format!("{} has no uvs, so its texture `{}` is never used", name, texture));
+			}
+		}
+	}
+
+	for (name, material) in file.materials.iter() {
+		for (parameter, value) in [("reflectivity", material.reflectivity), ("transparency", material.transparency), ("roughness", material.roughness)] {
+			if !(0.0..=1.0).contains(&value) {
+				problem(format!("material `{}` has {} {}, outside 0 to 1", name, parameter, value));
+			}
+		}
+		if material.reflectivity + material.transparency > 1.0 {
+			problem(format!("material `{}` reflects and transmits more light than reaches it", name));
+		}
+		if material.ior <= 0.0 {
+			problem(format!("material `{}` has ior {}, which has to be above 0", name, material.ior));
+		}
+		if !used.contains(name) {
+			problem(format!("material `{}` is never used", name));
+		}
+	}
+
+	for reference in references {
+		lint_file(&reference, linted, problems)?;
+	}
+	Ok(())
+}
+
+// Lights inside a solid object, seen from crossing its surface an odd number of times both ways along
+// a line, which a light merely beside an open surface doesn't. Volumes are meant to be inside of
+fn enclosed_lights(scene: &Scene) -> Vec<String> {
+	let mut problems = Vec::new();
+	for light in scene.objects.iter().filter_map(|object| object.as_any_immut().downcast_ref::<LightSource>()) {
+		// Skewed so it's unlikely to run along an edge
+		let direction = Vec3 { x: 0.267, y: 0.535, z: 0.802 };
+		let ray = |direction: Vec3| Ray::from_to(&light.pos, &light.pos.add(&direction));
+		for object in scene.objects.iter() {
+			if object.as_any_immut().is::<LightSource>() || scene.volumes.iter().any(|volume| volume.target == *object.get_id()) { continue }
+			if !object.bounds().is_some_and(|bounds| bounds.closest_point(&light.pos).dist(&light.pos) == 0.0) { continue }
+			let odd = |direction: Vec3| crossings(object.as_ref(), &ray(direction), f64::INFINITY).len() % 2 == 1;
+			if odd(direction) && odd(direction.mul(-1.0)) {
+				problems.push(format!("light `{}` is inside `{}`", light.id, object.get_id()));
+			}
+		}
+	}
+	problems
+}
+
+#[test]
+fn lint_finds_authoring_mistakes() {
+	let dir = std::env::temp_dir().join(format!("lint_test_{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	std::fs::write(dir.join("prop.ron"), r#"(
+		materials: { "chrome": (reflectivity: 0.8, transparency: 0.5) },
+		objects: [ Sphere(pos: (0.0, 0.0, 0.0), radius: 0.0, material: "chrome") ],
+	)"#).unwrap();
+	std::fs::write(dir.join("room.ron"), r#"(
+		camera: Some((pos: (0.0, -10.0, 2.0), look_at: Some((0.0, 0.0, 1.0)), fov: 40.0)),
+		materials: { "wall": (roughness: 1.5), "spare": (ior: 0.0) },
+		objects: [
+			Cube(id: Some("box"), pos: (0.0, 0.0, 1.0), size: 2.0, material: "wall"),
+			Light(id: Some("bulb"), pos: (0.1, 0.2, 1.1), intensity: 5.0),
+			Light(id: Some("sun"), pos: (0.0, 0.0, 9.0), intensity: 5.0),
+			Plane(id: Some("floor"), size: 20.0, material: "wall"),
+			Mesh(id: Some("sliver"), positions: [(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (2.0, 0.0, 0.0)], indices: [(0, 1, 2)],
+				materials: ["wall"], texture: Some("wood.png")),
+			Reference(path: "prop.ron", pos: (3.0, 0.0, 0.0)),
+		],
+	)"#).unwrap();
+	// Loading needs the texture to be there
+	crate::framebuffer::Framebuffer::new(1, 1).write_png(&dir.join("wood.png")).unwrap();
+	let problems = lint(&dir.join("room.ron")).unwrap();
+	let found = |text: &str| problems.iter().filter(|problem| problem.contains(text)).count();
+	assert_eq!(found("roughness 1.5"), 1);
+	assert_eq!(found("`spare` is never used"), 1);
+	assert_eq!(found("`spare` has ior 0"), 1);
+	assert_eq!(found("object 4 (`sliver`) has 1 degenerate triangle"), 1);
+	assert_eq!(found("texture `wood.png` is never used"), 1);
+	assert_eq!(found("prop.ron: object 0 has zero size"), 1);
+	assert_eq!(found("`chrome` reflects and transmits more"), 1);
+	// The bulb is shut in the box, the sun above the floor plane is fine
+	assert_eq!(found("light `bulb` is inside `box`"), 1);
+	assert_eq!(found("sun"), 0);
+	assert_eq!(problems.len(), 8, "{:?}", problems);
+	std::fs::remove_dir_all(&dir).unwrap();
+
+	assert!(lint(Path::new("no such scene.ron")).is_err());
+}
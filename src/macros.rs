@@ -0,0 +1,140 @@
+//
+//	Input Macros
+//
+
+// Everything the frontend was told, frame by frame, written down as it happens so it can be played
+// back later in place of the keyboard and mouse. Frames replay with the time they took when they
+// were recorded, so a macro ends up in the same place however fast the machine playing it is.
+// A line per frame, `#` starting a comment:
+//
+//	0.033 120 45 0 MOUSE1:ph LEFT:h
+//
+// seconds the frame took, mouse x and y, wheel, then every mouse button and key that wasn't idle
+// with p for pressed, h held and r released
+
+use crate::frontend::InputSource;
+use crate::input::{ButtonState, Key};
+use std::fs::{self, File};
+use std::io::{self, Write};
+
+const BUTTONS: [&str; 3] = ["MOUSE0", "MOUSE1", "MOUSE2"];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrameInput {
+	pub elapsed: f32, // seconds
+	pub mouse: (i32, i32),
+	pub wheel: i32,
+	pub buttons: [ButtonState; 3],
+	pub keys: Vec<(Key, ButtonState)>, // only the ones that weren't idle
+}
+
+impl FrameInput {
+	pub fn capture(input: &dyn InputSource, elapsed: f32) -> Self {
+		FrameInput {
+			elapsed,
+			mouse: input.mouse_position(),
+			wheel: input.mouse_wheel(),
+			buttons: [0, 1, 2].map(|button| input.mouse_button(button)),
+			keys: Key::all().map(|key| (key, input.key_state(key))).filter(|(_, state)| *state != ButtonState::default()).collect(),
+		}
+	}
+
+	pub fn to_line(&self) -> String {
+		let mut line = format!("{} {} {} {}", self.elapsed, self.mouse.0, self.mouse.1, self.wheel);
+		let buttons = BUTTONS.iter().zip(self.buttons.iter()).filter(|(_, state)| **state != ButtonState::default()).map(|(name, state)| (*name, state));
+		for (name, state) in buttons.chain(self.keys.iter().map(|(key, state)| (key.name(), state))) {
+			line += &format!(" {}:{}{}{}", name, if state.pressed { "p" } else { "" }, if state.held { "h" } else { "" }, if state.released { "r" } else { "" });
+		}
+		line
+	}
+
+	pub fn parse_line(line: &str) -> Result<FrameInput, String> {
+		let mut words = line.split_whitespace();
+		let mut number = |what: &str| words.next().ok_or(format!("missing {}", what));
+		let (elapsed, x, y, wheel) = (number("seconds")?, number("mouse x")?, number("mouse y")?, number("wheel")?);
+		let mut frame = FrameInput {
+			elapsed: elapsed.parse().map_err(|_| format!("bad seconds `{}`", elapsed))?,
+			mouse: (x.parse().map_err(|_| format!("bad mouse x `{}`", x))?, y.parse().map_err(|_| format!("bad mouse y `{}`", y))?),
+			wheel: wheel.parse().map_err(|_| format!("bad wheel `{}`", wheel))?,
+			..FrameInput::default()
+		};
+		for word in words {
+			let (name, flags) = word.split_once(':').ok_or(format!("expected `KEY:flags`, got `{}`", word))?;
+			if flags.chars().any(|flag| !"phr".contains(flag)) {
+				return Err(format!("bad flags `{}`, expected p, h or r", flags));
+			}
+			let state = ButtonState { pressed: flags.contains('p'), held: flags.contains('h'), released: flags.contains('r') };
+			match BUTTONS.iter().position(|button| *button == name) {
+				Some(button) => frame.buttons[button] = state,
+				None => frame.keys.push((Key::from_name(name).ok_or(format!("unknown key `{}`", name))?, state)),
+			}
+		}
+		Ok(frame)
+	}
+}
+
+impl InputSource for FrameInput {
+	fn key_state(&self, key: Key) -> ButtonState {
+		self.keys.iter().find(|(recorded, _)| *recorded == key).map_or(ButtonState::default(), |(_, state)| *state)
+	}
+
+	fn mouse_position(&self) -> (i32, i32) { self.mouse }
+	fn mouse_button(&self, button: u32) -> ButtonState { self.buttons.get(button as usize).copied().unwrap_or_default() }
+	fn mouse_wheel(&self) -> i32 { self.wheel }
+}
+
+pub fn parse(text: &str) -> Result<Vec<FrameInput>, String> {
+	text.lines().enumerate()
+		.map(|(line_no, line)| (line_no, line.split('#').next().unwrap_or("").trim()))
+		.filter(|(_, line)| !line.is_empty())
+		.map(|(line_no, line)| FrameInput::parse_line(line).map_err(|e| format!("line {}: {}", line_no + 1, e)))
+		.collect()
+}
+
+pub fn load(path: &str) -> Result<Vec<FrameInput>, String> {
+	let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+	parse(&text).map_err(|e| format!("{}: {}", path, e))
+}
+
+// Writes frames out as they come, so a recording survives the program being closed mid-way
+pub struct Recorder {
+	pub path: String,
+	file: File,
+}
+
+impl Recorder {
+	pub fn start(path: &str) -> io::Result<Recorder> {
+		let mut file = File::create(path)?;
+		writeln!(file, "# seconds, mouse x y, wheel, then buttons and keys as NAME:flags")?;
+		Ok(Recorder { path: path.to_string(), file })
+	}
+
+	pub fn record(&mut self, frame: &FrameInput) -> io::Result<()> {
+		writeln!(self.file, "{}", frame.to_line())
+	}
+}
+
+#[test]
+fn frames_read_back() {
+	use crate::input::{Action, Bindings};
+	let held = ButtonState { pressed: false, held: true, released: false };
+	let frame = FrameInput {
+		elapsed: 1.0 / 30.0,
+		mouse: (120, -3),
+		wheel: 2,
+		buttons: [ButtonState::default(), ButtonState { pressed: true, held: true, released: false }, ButtonState::default()],
+		keys: vec![(Key::Left, held), (Key::NumpadAdd, ButtonState { pressed: false, held: false, released: true })],
+	};
+	assert_eq!(frame.to_line(), "0.033333335 120 -3 2 MOUSE1:ph LEFT:h NP_ADD:r");
+	assert_eq!(FrameInput::parse_line(&frame.to_line()).unwrap(), frame);
+	// Captured, it looks the same to everything reading input
+	let captured = FrameInput::capture(&frame, frame.elapsed);
+	assert_eq!(captured, frame);
+	assert!(Bindings::default().held(&captured, Action::CameraYawLeft));
+
+	let frames = parse("# a comment\n0.5 0 0 0\n\n0.25 1 1 0 SPACE:ph # trailing\n").unwrap();
+	assert_eq!(frames.len(), 2);
+	assert_eq!(frames[1].key_state(Key::Space), ButtonState { pressed: true, held: true, released: false });
+	assert!(parse("0.5 0 0 0 NOPE:p").unwrap_err().starts_with("line 1:"));
+	assert!(parse("0.5 0 0").is_err() && parse("0.5 0 0 0 LEFT:x").is_err());
+}
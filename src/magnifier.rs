@@ -0,0 +1,74 @@
+//
+//	Magnifier
+//
+
+use crate::frontend::Presenter;
+use crate::structs::Color;
+
+// Blows up the pixels around the cursor from what's already on screen, nothing gets re-rendered
+pub struct Magnifier {
+	pub radius: usize, // pixels shown either side of the one under the cursor
+	pub zoom: usize, // screen pixels per magnified pixel
+}
+
+impl Magnifier {
+	pub fn new() -> Self {
+		Magnifier { radius: 5, zoom: 3 }
+	}
+
+	// Side of the magnified square in screen pixels
+	pub fn size(&self) -> usize {
+		(self.radius * 2 + 1) * self.zoom
+	}
+
+	// The magnified square row by row, pixels outside the image come out black
+	pub fn crop(&self, colors: &[Color], width: usize, height: usize, center: (i32, i32)) -> Vec<Color> {
+		let size = self.size();
+		(0..size * size).map(|index| {
+			let x = center.0 + (index % size / self.zoom) as i32 - self.radius as i32;
+			let y = center.1 + (index / size / self.zoom) as i32 - self.radius as i32;
+			if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 { Color { r: 0, g: 0, b: 0 } } else { colors[y as usize * width + x as usize] }
+		}).collect()
+	}
+
+	// Draws the crop in whichever top corner is further from the cursor, the center pixel outlined,
+	// and the cursor position and color underneath
+	pub fn draw(&self, presenter: &mut dyn Presenter, colors: &[Color], width: usize, height: usize, cursor: (i32, i32)) {
+		let size = self.size() as i32;
+		let left = if cursor.0 < width as i32 / 2 { width as i32 - size - 1 } else { 1 };
+		let crop = self.crop(colors, width, height, cursor);
+		let (inner_start, inner_end) = ((self.radius * self.zoom) as i32 - 1, ((self.radius + 1) * self.zoom) as i32);
+		for (index, color) in crop.into_iter().enumerate() {
+			let (x, y) = (index as i32 % size, index as i32 / size);
+			// Outline just outside the center pixel
+			let outline = (x == inner_start || x == inner_end) && (inner_start..=inner_end).contains(&y)
+				|| (y == inner_start || y == inner_end) && (inner_start..=inner_end).contains(&x);
+			presenter.draw(left + x, 1 + y, if outline { Color { r: 255, g: 0, b: 255 } } else { color });
+		}
+		if cursor.0 >= 0 && cursor.1 >= 0 && (cursor.0 as usize) < width && (cursor.1 as usize) < height {
+			let color = colors[cursor.1 as usize * width + cursor.0 as usize];
+			let readout = format!("{},{} {} {} {}", cursor.0, cursor.1, color.r, color.g, color.b);
+			presenter.draw_string(left, size + 2, &readout, Color { r: 255, g: 255, b: 255 });
+		}
+	}
+}
+
+impl Default for Magnifier {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+#[test]
+fn crop_repeats_each_pixel() {
+	let colors: Vec<Color> = (0..16).map(|value| Color { r: value, g: 0, b: 0 }).collect();
+	let magnifier = Magnifier { radius: 1, zoom: 2 };
+	let crop = magnifier.crop(&colors, 4, 4, (0, 1));
+	assert_eq!(crop.len(), 36);
+	let red: Vec<u8> = crop.iter().step_by(2).map(|color| color.r).collect();
+	// Every other column of every row, the leftmost column being off the image
+	assert_eq!(&red[..3], &[0, 0, 1]);
+	assert_eq!(&red[6..9], &[0, 4, 5]);
+	assert_eq!(&red[12..15], &[0, 8, 9]);
+	assert_eq!(crop[0], crop[7]);
+}
@@ -1,137 +1,1140 @@
-use olc_pixel_game_engine as olc;
-
 struct ExampleProgram {
 	pub current_scene: Scene,
-	pub render_index: u64,
-	pub complete: bool
+	pub render_order: Vec<u64>,
+	pub orbit: Option<OrbitController>,
+	pub last_mouse: (i32, i32),
+	pub bindings: Bindings,
+	pub selected: Option<String>,
+	pub shake: Option<CameraShake>,
+	pub time: f64,
+	pub frame: u64,
+	pub quality: Option<QualityController>,
+	pub resolution: DynamicResolution, // the fraction of the viewport traced when not progressive
+	pub stereo: Option<Stereo>, // both eyes every frame, taking over from progressive and scaled rendering
+	pub comparison: Option<Comparison>, // split screen of two render settings, also traced in full every frame
+	pub material_param: MaterialParam, // what the material keys change on the selected object
+	pub scene_path: String, // where scene_save writes to
+	pub preset: Option<String>, // the render preset last applied, preset_next goes on from it
+	pub analysis: bool, // false color and histogram instead of the image
+	pub auto_exposure: Option<AutoExposure>,
+	pub frame_colors: Vec<Color>, // last traced color of every viewport pixel
+	pub show_graphs: bool,
+	pub magnifier: Option<Magnifier>,
+	pub measure: Option<Measure>,
+	pub light_placement: Option<LightPlacement>, // right click adds a light in front of the surface rather than selecting
+	pub light_offset: f64, // how far in front light placement puts them
+	pub progressive: Option<Progressive>, // None traces every frame in full
+	pub progressive_budget: usize,
+	pub accumulation: Option<Accumulation>, // full frames averaged together while the view holds still, None traces each afresh
+	pub outliner: Option<Outliner>,
+	pub recorder: Option<Recorder>, // input being written to a macro
+	pub macro_path: String, // where recording writes to
+	pub playback: Option<std::vec::IntoIter<FrameInput>>, // macro frames left to play, in place of the real input
+	pub graphs: [RollingGraph; 3] // frame time, samples per pixel traced, estimated variance
 }
 
-pub mod structs;
-use crate::structs::*;
+#[cfg(feature = "olc")]
+mod olc_frontend;
+#[cfg(feature = "minifb")]
+mod minifb_frontend;
+#[cfg(feature = "egui")]
+mod egui_frontend;
+mod terminal_frontend;
 
-const VIEWPORT_HEIGHT: u64 = 90*1;
-const VIEWPORT_WIDTH: u64 = 160*1;
+use raytracing_engine::structs::*;
+use raytracing_engine::controls::{CameraShake, OrbitController};
+use raytracing_engine::input::{Action, Bindings};
+use raytracing_engine::frontend::{InputSource, Presenter};
+use raytracing_engine::framebuffer::Framebuffer;
+use raytracing_engine::graphs::RollingGraph;
+use raytracing_engine::magnifier::Magnifier;
+use raytracing_engine::measure::Measure;
+use raytracing_engine::light_placement::{LightPlacement, DEFAULT_LIGHT_OFFSET};
+use raytracing_engine::outliner::Outliner;
+use raytracing_engine::progressive::{focus_area, screen_bounds, Accumulation, Progressive, ViewChange};
+use raytracing_engine::analysis;
+use raytracing_engine::archive;
+use raytracing_engine::bench::{self, StressScene};
+use raytracing_engine::label;
+use raytracing_engine::lint;
+use raytracing_engine::accelerator::AcceleratorKind;
+use raytracing_engine::backplate::{Backplate, BackplateFit};
+use raytracing_engine::bvh_cache::BvhCache;
+use raytracing_engine::environment::Environment;
+use raytracing_engine::macros::{self, FrameInput, Recorder};
+use raytracing_engine::material_preview::{self, PREVIEW_SIZE};
+use raytracing_engine::cryptomatte;
+use raytracing_engine::outline::OutlineFormat;
+use raytracing_engine::exposure::AutoExposure;
+use raytracing_engine::render::{frame_pixels, Integrator};
+use raytracing_engine::texture::Texture;
+use raytracing_engine::tiles::{render_tiles, Priority};
+use raytracing_engine::presets::Aov;
+use raytracing_engine::sequence::Sequence;
+use raytracing_engine::quality::QualityController;
+use raytracing_engine::resolution::{self, DynamicResolution};
+use raytracing_engine::compare::Comparison;
+use raytracing_engine::diff;
+use raytracing_engine::stereo::{Stereo, StereoMode, DEFAULT_SEPARATION};
+use raytracing_engine::scene_file::{self, SceneFile};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, PoisonError};
 
+const VIEWPORT_HEIGHT: u64 = 90;
+const VIEWPORT_WIDTH: u64 = 160;
+const BINDINGS_FILE: &str = "bindings.cfg";
+const SCENE_FILE: &str = "scene.ron";
+const MACRO_FILE: &str = "macro.txt";
+const DEFAULT_FRAME_BUDGET: f32 = 0.033;
+const DEFAULT_AO_SAMPLES: u32 = 32;
+const FINAL_GLOSSY_SAMPLES: u32 = 16; // headless renders trade the interactive cone for this many rays
+const GRAPH_LENGTH: usize = 48; // frames shown
+const GRAPH_HEIGHT: usize = 12;
+const DEFAULT_BENCH_REPEATS: u32 = 3;
+const RANDOM_PALETTE: [(u8, u8, u8); 6] = [(230, 60, 50), (245, 170, 40), (240, 230, 210), (70, 180, 90), (60, 120, 230), (150, 80, 200)];
+const DEFAULT_PROGRESSIVE_BUDGET: usize = 2000; // pixels per update
+const PROGRESSIVE_PASSES: u32 = 64;
+const DEFAULT_BENCH_TOLERANCE: f64 = 0.1;
+const SLOWEST_TILES: usize = 5; // listed by --render-stats
+const SEQUENCE_FRAMES: u32 = 24; // --sequence exports a second at
+const SEQUENCE_FPS: f64 = 24.0;
 
-impl olc::Application for ExampleProgram {
-	fn on_user_create(&mut self) -> Result<(), olc::Error> {
-		olc::clear(olc::BLACK);
-		Ok(())
-	}
+type ImageWriter = fn(&Framebuffer, &Path) -> std::io::Result<()>;
 
-	fn on_user_update(&mut self, _elapsed_time: f32) -> Result<(), olc::Error> {
-		//let render_pos_x = self.render_index % VIEWPORT_WIDTH;
-		//let render_pos_y = self.render_index / VIEWPORT_WIDTH;
-		if self.complete != true {
-			for _ in 0..(VIEWPORT_WIDTH * VIEWPORT_HEIGHT) {
-				let cast_ray_final_color: Color = self.current_scene.cast_ray(self.render_index, VIEWPORT_WIDTH as i32, VIEWPORT_HEIGHT as i32); 
-				olc::draw((self.render_index % VIEWPORT_WIDTH) as i32, (self.render_index / VIEWPORT_WIDTH) as i32, 
-				olc::Pixel { r: cast_ray_final_color.r, g: cast_ray_final_color.g, b: cast_ray_final_color.b, a:255 });
-				self.render_index += 1; 
+#[cfg(feature = "olc")]
+const DEFAULT_FRONTEND: &str = "olc";
+#[cfg(all(not(feature = "olc"), feature = "minifb"))]
+const DEFAULT_FRONTEND: &str = "minifb";
+#[cfg(all(not(any(feature = "olc", feature = "minifb")), feature = "egui"))]
+const DEFAULT_FRONTEND: &str = "egui";
+#[cfg(not(any(feature = "olc", feature = "minifb", feature = "egui")))]
+const DEFAULT_FRONTEND: &str = "terminal";
+
+impl ExampleProgram {
+	fn move_light(&mut self, offset: Vec3) {
+		for light in self.current_scene.get_all_light_sources().iter_mut() {
+			if light.id == "fuckin' light" {
+				light.pos = light.pos.add(&offset);
 			}
-			//if self.render_index >= VIEWPORT_HEIGHT * VIEWPORT_WIDTH { self.complete = true }
-			self.render_index = 0;
 		}
+	}
 
-		if olc::get_key(olc::Key::RIGHT).held {
-			self.current_scene.current_camera.rot.yaw -= 0.01;
+	// Points the camera at the selected object, or the whole scene when nothing is selected
+	pub fn frame_view(&mut self) {
+		let aspect_ratio = VIEWPORT_WIDTH as f64 / VIEWPORT_HEIGHT as f64;
+		let framed = match &self.selected {
+			Some(id) => self.current_scene.frame_object(id, aspect_ratio),
+			None => self.current_scene.frame_all(aspect_ratio),
+		};
+		// An orbit would snap the camera straight back, so it carries on around the new center
+		if let (Some(center), Some(_)) = (framed, &self.orbit) {
+			self.orbit = Some(OrbitController::from_camera(&self.current_scene.current_camera, center));
 		}
+	}
 
-		if olc::get_key(olc::Key::LEFT).held {
-			self.current_scene.current_camera.rot.yaw += 0.01;
+	// Applies the scene's render preset called name, false when it has none by that name
+	fn use_preset(&mut self, name: &str) -> bool {
+		let scale = match self.current_scene.apply_preset(name) {
+			Some(preset) => preset.render_scale,
+			None => return false,
+		};
+		if let Some(scale) = scale {
+			self.resolution = DynamicResolution::new(scale, self.resolution.target);
 		}
+		self.preset = Some(name.to_string());
+		true
+	}
 
-		if olc::get_key(olc::Key::UP).held {
-			self.current_scene.current_camera.rot.roll += 0.01;
+	// Restarts progressive rendering with the current traversal order and seed
+	fn refresh_render_order(&mut self) {
+		let settings = &self.current_scene.render_settings;
+		self.render_order = settings.traversal_order.pixel_order(VIEWPORT_WIDTH, VIEWPORT_HEIGHT, settings.seed);
+		if let Some(progressive) = self.progressive.as_mut() {
+			progressive.restart();
 		}
+	}
 
-		if olc::get_key(olc::Key::DOWN).held {
-			self.current_scene.current_camera.rot.roll -= 0.01;
+	// Every pixel of a width x height frame, stretched over the viewport
+	fn trace_scaled(&mut self, presenter: &mut dyn Presenter, width: usize, height: usize) {
+		let colors = trace_frame(&mut self.current_scene, width, height);
+		let upscaled = resolution::upscale(&colors, width, height, VIEWPORT_WIDTH as usize, VIEWPORT_HEIGHT as usize);
+		self.present_frame(presenter, upscaled, width * height);
+	}
+
+	// Both eyes in full, put together the way stereo asks
+	fn trace_stereo(&mut self, presenter: &mut dyn Presenter, stereo: Stereo) {
+		let (width, height) = (VIEWPORT_WIDTH as usize, VIEWPORT_HEIGHT as usize);
+		let eye_width = stereo.mode.eye_width(width);
+		let (left, right) = self.current_scene.for_each_eye(stereo.separation, |scene| trace_frame(scene, eye_width, height));
+		let composed = stereo.mode.compose(&left, &right, width, height);
+		self.present_frame(presenter, composed, 2 * eye_width * height);
+	}
+
+	// A left of the divider and B right of it, every pixel of both
+	fn trace_compared(&mut self, presenter: &mut dyn Presenter) {
+		let (divider, a) = match &self.comparison {
+			Some(comparison) => (comparison.divider.max(0) as usize, comparison.settings),
+			None => return,
+		};
+		let (width, height) = (VIEWPORT_WIDTH as usize, VIEWPORT_HEIGHT as usize);
+		let b = self.current_scene.render_settings;
+		let mut colors = vec![None; width * height];
+		for (settings, left) in [(a, true), (b, false)] {
+			self.current_scene.render_settings = settings;
+			self.current_scene.update_culling(width as i32, height as i32);
+			let scene = &self.current_scene;
+			let side = render_tiles(scene, width, height, |x, _| (x < divider) == left,
+				|x, y, scratch| scene.cast_ray_samples((y * width + x) as u64, width as i32, height as i32, 0, scratch).0);
+			for (color, traced) in colors.iter_mut().zip(side) {
+				if traced.is_some() { *color = traced }
+			}
 		}
+		self.current_scene.render_settings = b;
+		self.present_frame(presenter, colors.into_iter().flatten().collect(), width * height);
+	}
 
-		if olc::get_key(olc::Key::R).held {
-			self.current_scene.current_camera.fov += 1;
+	// Shows a whole frame of viewport colors, traced pixels of them actually cast
+	fn present_frame(&mut self, presenter: &mut dyn Presenter, colors: Vec<Color>, traced: usize) {
+		let mut squared_change = 0.0;
+		for (index, color) in colors.iter().enumerate() {
+			let change = analysis::luminance(color) - analysis::luminance(&self.frame_colors[index]);
+			squared_change += change * change;
+			if !self.analysis {
+				presenter.draw((index as u64 % VIEWPORT_WIDTH) as i32, (index as u64 / VIEWPORT_WIDTH) as i32, *color);
+			}
 		}
+		self.frame_colors = colors;
+		self.frame += 1;
+		self.current_scene.remember_frame();
+		let viewport_pixels = (VIEWPORT_WIDTH * VIEWPORT_HEIGHT) as f64;
+		self.graphs[1].push(traced as f64 * self.current_scene.render_settings.samples_per_pixel.max(1) as f64 / viewport_pixels);
+		self.graphs[2].push(squared_change / (2.0 * viewport_pixels));
+	}
 
-		if olc::get_key(olc::Key::F).held {
-			self.current_scene.current_camera.fov -= 1;
+	fn update(&mut self, presenter: &mut dyn Presenter, input: &dyn InputSource, elapsed_time: f32) {
+		// Recording starts and stops from the real keyboard, the frame that does it isn't part of the macro
+		if self.bindings.pressed(input, Action::MacroRecordToggle) {
+			self.recorder = match self.recorder.take() {
+				Some(recorder) => {
+					println!("Recorded {}", recorder.path);
+					None
+				}
+				None => Recorder::start(&self.macro_path).map_err(|e| eprintln!("Could not record {}: {}", self.macro_path, e)).ok(),
+			};
+		} else if let Some(recorder) = self.recorder.as_mut() {
+			if let Err(e) = recorder.record(&FrameInput::capture(input, elapsed_time)) {
+				eprintln!("Could not record {}: {}", recorder.path, e);
+				self.recorder = None;
+			}
+		}
+		// A macro being played stands in for the real input and clock, a recorded frame per update
+		let played = self.playback.as_mut().and_then(|frames| frames.next());
+		if played.is_none() {
+			self.playback = None;
+		}
+		let (input, elapsed_time) = match &played {
+			Some(frame) => (frame as &dyn InputSource, frame.elapsed),
+			None => (input, elapsed_time),
+		};
+		self.current_scene.profiler.begin_frame();
+		// Shake only the rendered pose, so it never feeds back into the controls
+		self.time += elapsed_time as f64;
+		self.current_scene.animate(self.time);
+		self.current_scene.simulate(elapsed_time as f64);
+		let steady = (self.current_scene.current_camera.pos, self.current_scene.current_camera.rot);
+		if let Some(shake) = &self.shake {
+			shake.apply(&mut self.current_scene.current_camera, self.time);
 		}
 
+		// Same for quality scaling, the requested settings are what the controls see
+		let requested = self.current_scene.render_settings;
+		let bindings = &self.bindings;
+		let idle = !Action::all().any(|action| bindings.held(input, action))
+			&& !(0..3).any(|button| input.mouse_button(button).held) && input.mouse_wheel() == 0;
+		// Progressive rendering already keeps frames cheap, and would start over at every quality change
+		if let (Some(quality), None) = (self.quality.as_mut(), &self.progressive) {
+			quality.update(elapsed_time, idle);
+			self.current_scene.render_settings = quality.apply(&requested);
+		}
+		self.resolution.update(elapsed_time, idle);
+		let scaled = self.resolution.size(VIEWPORT_WIDTH as usize, VIEWPORT_HEIGHT as usize);
 
-		if olc::get_key(olc::Key::H).held {
-			for light in self.current_scene.get_all_light_sources().iter_mut() {
-				if light.id == String::from("fuckin' light") {
-					light.pos.y -= 0.05;
+		// When only a few objects changed, only the pixels they could have reached are traced again
+		let mut retrace = None;
+		if let Some(progressive) = self.progressive.as_mut() {
+			progressive.update_view(&self.current_scene);
+			// Refined first where the selection is on screen, or else around the cursor
+			let (width, height) = (VIEWPORT_WIDTH as usize, VIEWPORT_HEIGHT as usize);
+			let mouse = input.mouse_position();
+			let cursor = ((mouse.0 as f64, mouse.1 as f64), (mouse.0 as f64, mouse.1 as f64));
+			let scene = &self.current_scene;
+			let focus = self.selected.as_ref().and_then(|id| screen_bounds(scene, id, width as i32, height as i32)).unwrap_or(cursor);
+			progressive.focus_on(focus_area(focus.0, focus.1, width, height), width);
+		} else if let Some(accumulation) = self.accumulation.as_mut() {
+			self.current_scene.update_culling(VIEWPORT_WIDTH as i32, VIEWPORT_HEIGHT as i32);
+			if let ViewChange::Pixels(pixels) = accumulation.update_regions(&self.current_scene, VIEWPORT_WIDTH as i32, VIEWPORT_HEIGHT as i32) {
+				retrace = Some(pixels);
+			}
+		}
+		if let Some(stereo) = self.stereo {
+			self.trace_stereo(presenter, stereo);
+		} else if self.comparison.is_some() {
+			self.trace_compared(presenter);
+		} else if self.progressive.is_none() && scaled != (VIEWPORT_WIDTH as usize, VIEWPORT_HEIGHT as usize) {
+			self.trace_scaled(presenter, scaled.0, scaled.1);
+		} else if !self.progressive.as_ref().is_some_and(|progressive| progressive.complete) {
+			self.current_scene.update_culling(VIEWPORT_WIDTH as i32, VIEWPORT_HEIGHT as i32);
+			let settings = self.current_scene.render_settings;
+			// Progressive frames are full resolution, just not all at once
+			let (mut pixels, scale): (Vec<u64>, i32) = match self.progressive.as_mut() {
+				Some(progressive) => (progressive.next_pixels(&self.render_order), 1),
+				None => (frame_pixels(&self.render_order, VIEWPORT_WIDTH, self.frame, &settings).collect(), settings.pixel_scale.max(1) as i32),
+			};
+			if let Some(retrace) = &retrace {
+				pixels.retain(|pixel_index| (0..scale * scale).any(|block| {
+					let (x, y) = (pixel_index % VIEWPORT_WIDTH + (block % scale) as u64, pixel_index / VIEWPORT_WIDTH + (block / scale) as u64);
+					x < VIEWPORT_WIDTH && y < VIEWPORT_HEIGHT && retrace[(y * VIEWPORT_WIDTH + x) as usize]
+				}));
+			}
+			let mut wanted = vec![false; (VIEWPORT_WIDTH * VIEWPORT_HEIGHT) as usize];
+			for pixel_index in pixels.iter() {
+				wanted[*pixel_index as usize] = true;
+			}
+			let (mut traced, mut squared_change) = (0, 0.0);
+			// Traced in parallel up front, then shown in the traversal order
+			let (scene, progressive, accumulation) = (&self.current_scene, &self.progressive, &self.accumulation);
+			let colors = render_tiles(scene, VIEWPORT_WIDTH as usize, VIEWPORT_HEIGHT as usize,
+				|x, y| wanted[y * VIEWPORT_WIDTH as usize + x],
+				|x, y, scratch| {
+					let index = y as u64 * VIEWPORT_WIDTH + x as u64;
+					let first_sample = match (progressive, accumulation) {
+						(Some(progressive), _) => progressive.first_sample(index, &settings),
+						(None, Some(accumulation)) => accumulation.first_sample(index, &settings),
+						(None, None) => 0,
+					};
+					scene.cast_ray_samples(index, VIEWPORT_WIDTH as i32, VIEWPORT_HEIGHT as i32, first_sample, scratch).0
+				});
+			for pixel_index in pixels {
+				let mut cast_ray_final_color = match colors[pixel_index as usize] {
+					Some(color) => color,
+					None => continue,
+				};
+				if let Some(progressive) = self.progressive.as_mut() {
+					cast_ray_final_color = progressive.accumulate(pixel_index, cast_ray_final_color, settings.exposure);
+				} else if let Some(accumulation) = self.accumulation.as_mut() {
+					cast_ray_final_color = accumulation.accumulate(pixel_index, cast_ray_final_color, settings.exposure);
+				}
+				let (x, y) = ((pixel_index % VIEWPORT_WIDTH) as i32, (pixel_index / VIEWPORT_WIDTH) as i32);
+				let change = analysis::luminance(&cast_ray_final_color) - analysis::luminance(&self.frame_colors[pixel_index as usize]);
+				squared_change += change * change;
+				traced += 1;
+				for block in 0..scale * scale {
+					let (block_x, block_y) = (x + block % scale, y + block / scale);
+					if block_x < VIEWPORT_WIDTH as i32 && block_y < VIEWPORT_HEIGHT as i32 {
+						self.frame_colors[(block_y as u64 * VIEWPORT_WIDTH + block_x as u64) as usize] = cast_ray_final_color;
+					}
+					if !self.analysis {
+						presenter.draw(block_x, block_y, cast_ray_final_color);
+					}
 				}
 			}
+			self.frame += 1;
+			self.current_scene.remember_frame();
+			// Two independent estimates of a pixel differ by twice its variance on average
+			let viewport_pixels = (VIEWPORT_WIDTH * VIEWPORT_HEIGHT) as f64;
+			self.graphs[1].push(traced as f64 * settings.samples_per_pixel.max(1) as f64 / viewport_pixels);
+			self.graphs[2].push(if traced > 0 { squared_change / (2.0 * traced as f64) } else { 0.0 });
 		}
+		if self.analysis {
+			let view = analysis::analysis_view(&self.frame_colors, VIEWPORT_WIDTH as usize, VIEWPORT_HEIGHT as usize);
+			for (index, color) in view.into_iter().enumerate() {
+				presenter.draw((index as u64 % VIEWPORT_WIDTH) as i32, (index as u64 / VIEWPORT_WIDTH) as i32, color);
+			}
+		} else if let Some(progressive) = &self.progressive {
+			// Most pixels weren't traced this update, redrawing them keeps overlays from smearing.
+			// At the exposure now, which auto exposure may have moved since they were
+			let exposure = self.current_scene.render_settings.exposure;
+			for (index, color) in self.frame_colors.iter_mut().enumerate() {
+				*color = progressive.resolve(index as u64, exposure).unwrap_or(*color);
+				presenter.draw((index as u64 % VIEWPORT_WIDTH) as i32, (index as u64 / VIEWPORT_WIDTH) as i32, *color);
+			}
+		}
+		self.current_scene.render_settings = requested;
+		if let Some(auto_exposure) = &self.auto_exposure {
+			let settings = &mut self.current_scene.render_settings;
+			settings.exposure = auto_exposure.update(&self.frame_colors, settings.exposure, elapsed_time as f64);
+		}
+		self.current_scene.current_camera.pos = steady.0;
+		self.current_scene.current_camera.rot = steady.1;
 
-		if olc::get_key(olc::Key::Y).held {
-			for light in self.current_scene.get_all_light_sources().iter_mut() {
-				if light.id == String::from("fuckin' light") {
-					light.pos.y += 0.05;
-				}
+		self.graphs[0].push(elapsed_time as f64 * 1000.0);
+		if self.show_graphs {
+			let left = VIEWPORT_WIDTH as i32 - GRAPH_LENGTH as i32 - 1;
+			for (index, graph) in self.graphs.iter().enumerate() {
+				graph.draw(presenter, left, 1 + index as i32 * (GRAPH_HEIGHT as i32 + 2), GRAPH_HEIGHT);
 			}
 		}
+		self.current_scene.profiler.end_frame();
+		if let Some(profile) = self.current_scene.profiler.last_frame() {
+			profile.draw(presenter, 1, VIEWPORT_HEIGHT as i32 - 70);
+		}
+		if let Some(magnifier) = &self.magnifier {
+			magnifier.draw(presenter, &self.frame_colors, VIEWPORT_WIDTH as usize, VIEWPORT_HEIGHT as usize, input.mouse_position());
+		}
+		if let Some(comparison) = self.comparison.as_mut() {
+			comparison.drag(input.mouse_position().0, input.mouse_button(0), VIEWPORT_WIDTH as i32);
+			comparison.draw(presenter, VIEWPORT_HEIGHT as i32);
+		}
 
-		if olc::get_key(olc::Key::U).held {
-			for light in self.current_scene.get_all_light_sources().iter_mut() {
-				if light.id == String::from("fuckin' light") {
-					light.pos.z -= 0.05;
+		// Right click selects the object under the cursor for editing, or places a point while measuring
+		if input.mouse_button(1).pressed {
+			let (x, y) = input.mouse_position();
+			match (self.measure.as_mut(), self.light_placement.as_mut()) {
+				(Some(measure), _) => { measure.pick(&self.current_scene, x, y, VIEWPORT_WIDTH as i32, VIEWPORT_HEIGHT as i32); }
+				// The new light selected, ready to be moved
+				(None, Some(placement)) => {
+					if let Some(id) = placement.place(&mut self.current_scene, x, y, VIEWPORT_WIDTH as i32, VIEWPORT_HEIGHT as i32) {
+						self.selected = Some(id);
+					}
 				}
+				(None, None) => self.selected = self.current_scene.pick(x, y, VIEWPORT_WIDTH as i32, VIEWPORT_HEIGHT as i32),
 			}
 		}
-
-		if olc::get_key(olc::Key::T).held {
-			for light in self.current_scene.get_all_light_sources().iter_mut() {
-				if light.id == String::from("fuckin' light") {
-					light.pos.z += 0.05;
+		if let Some(measure) = &self.measure {
+			measure.draw(presenter, &self.current_scene, VIEWPORT_WIDTH as i32, VIEWPORT_HEIGHT as i32);
+		} else if let Some(placement) = &self.light_placement {
+			placement.draw(presenter, VIEWPORT_HEIGHT as i32);
+		}
+		// Stepping through the outliner selects as it goes, drawn under the selection's name and material
+		if let Some(outliner) = &self.outliner {
+			let steps = if self.bindings.pressed(input, Action::OutlinerDown) { 1 }
+				else if self.bindings.pressed(input, Action::OutlinerUp) { -1 }
+				else { 0 };
+			if steps != 0 {
+				self.selected = Outliner::step(&Outliner::rows(&self.current_scene), self.selected.as_deref(), steps);
+			}
+			outliner.draw(presenter, &self.current_scene, self.selected.as_deref(), 21);
+		}
+		if let Some(id) = self.selected.clone() {
+			let white = Color { r: 255, g: 255, b: 255 };
+			presenter.draw_string(1, 1, &id, white);
+			let param = self.material_param;
+			let steps = if self.bindings.pressed(input, Action::MaterialIncrease) { 1.0 }
+				else if self.bindings.pressed(input, Action::MaterialDecrease) { -1.0 }
+				else { 0.0 };
+			if let Some(object) = self.current_scene.find_object(&id) {
+				if let Some(mut material) = object.get_material() {
+					if steps != 0.0 {
+						material = param.adjust(&material, steps);
+						object.set_material(material);
+					}
+					presenter.draw_string(1, 11, &format!("{} {:.2}", param.name(), param.value(&material)), white);
 				}
 			}
+			if self.bindings.pressed(input, Action::MaterialParamNext) {
+				self.material_param = param.next();
+			}
+			if self.bindings.pressed(input, Action::HideSelected) {
+				self.current_scene.hide(&id);
+			}
+			if self.bindings.pressed(input, Action::IsolateSelected) {
+				self.current_scene.isolate(&id);
+			}
+		}
+		if self.bindings.pressed(input, Action::UnhideAll) {
+			self.current_scene.unhide_all();
 		}
 
-		if olc::get_key(olc::Key::G).held {
-			for light in self.current_scene.get_all_light_sources().iter_mut() {
-				if light.id == String::from("fuckin' light") {
-					light.pos.x -= 0.05;
-				}
+		if self.bindings.pressed(input, Action::SceneSave) {
+			let path = Path::new(&self.scene_path);
+			// Written over, an archive would lose the files packed in with the scene
+			let saved = if archive::is_archive(path) {
+				Err(format!("{} is a scene archive", self.scene_path))
+			} else {
+				SceneFile::from_scene(&self.current_scene).save(path)
+			};
+			match saved {
+				Ok(()) => println!("Saved {}", self.scene_path),
+				Err(e) => eprintln!("Could not save scene: {}", e),
 			}
 		}
 
-		if olc::get_key(olc::Key::J).held {
-			for light in self.current_scene.get_all_light_sources().iter_mut() {
-				if light.id == String::from("fuckin' light") {
-					light.pos.x += 0.05;
+		if self.bindings.pressed(input, Action::FovAxisToggle) {
+			let camera = &mut self.current_scene.current_camera;
+			let axis = match camera.fov_axis {
+				FovAxis::Vertical => FovAxis::Horizontal,
+				FovAxis::Horizontal => FovAxis::Vertical,
+			};
+			camera.set_fov_axis(axis, VIEWPORT_WIDTH as f64 / VIEWPORT_HEIGHT as f64);
+		}
+
+		if self.bindings.pressed(input, Action::CycleTraversalOrder) {
+			let settings = &mut self.current_scene.render_settings;
+			settings.traversal_order = settings.traversal_order.next();
+			self.refresh_render_order();
+		}
+
+		if self.bindings.pressed(input, Action::AnalysisToggle) {
+			self.analysis = !self.analysis;
+		}
+
+		if self.bindings.pressed(input, Action::AutoExposureToggle) {
+			self.auto_exposure = match self.auto_exposure {
+				Some(_) => None,
+				None => Some(AutoExposure::new()),
+			};
+		}
+
+		if self.bindings.pressed(input, Action::PhysicsReset) {
+			let scene = &mut self.current_scene;
+			if let Some(physics) = scene.physics.as_mut() {
+				physics.reset(&mut scene.objects);
+			}
+		}
+
+		if self.bindings.pressed(input, Action::FrameView) {
+			self.frame_view();
+		}
+
+		if self.bindings.pressed(input, Action::MagnifierToggle) {
+			self.magnifier = match self.magnifier {
+				Some(_) => None,
+				None => Some(Magnifier::new()),
+			};
+		}
+
+		if self.bindings.pressed(input, Action::MeasureToggle) {
+			self.measure = match self.measure {
+				Some(_) => None,
+				None => Some(Measure::new()),
+			};
+		}
+
+		if self.bindings.pressed(input, Action::LightPlaceToggle) {
+			self.light_placement = match self.light_placement {
+				Some(_) => None,
+				None => Some(LightPlacement::new(self.light_offset)),
+			};
+		}
+
+		if self.bindings.pressed(input, Action::OutlinerToggle) {
+			self.outliner = match self.outliner {
+				Some(_) => None,
+				None => Some(Outliner::new()),
+			};
+		}
+
+		if self.bindings.pressed(input, Action::ProgressiveToggle) {
+			self.progressive = match self.progressive {
+				Some(_) => None,
+				None => Some(Progressive::new(self.progressive_budget, PROGRESSIVE_PASSES, (VIEWPORT_WIDTH * VIEWPORT_HEIGHT) as usize)),
+			};
+		}
+
+		// What the settings are now stays on the left, changes from here on show on the right
+		if self.bindings.pressed(input, Action::CompareToggle) {
+			self.comparison = match self.comparison {
+				Some(_) => None,
+				None => Some(Comparison::new(self.current_scene.render_settings, VIEWPORT_WIDTH as i32)),
+			};
+		}
+
+		if self.bindings.pressed(input, Action::GraphsToggle) {
+			self.show_graphs = !self.show_graphs;
+		}
+
+		if self.bindings.pressed(input, Action::PresetNext) {
+			if let Some(name) = self.current_scene.next_preset(self.preset.as_deref()).map(String::from) {
+				self.use_preset(&name);
+				println!("Preset {}", name);
+			}
+		}
+
+		if self.bindings.pressed(input, Action::ProfilerToggle) {
+			self.current_scene.profiler.enabled = !self.current_scene.profiler.enabled;
+		}
+
+		if self.bindings.pressed(input, Action::InterleavedToggle) {
+			let settings = &mut self.current_scene.render_settings;
+			settings.interleaved = !settings.interleaved;
+		}
+
+		if self.bindings.pressed(input, Action::AdaptiveQualityToggle) {
+			self.quality = match self.quality {
+				Some(_) => None,
+				None => Some(QualityController::new(DEFAULT_FRAME_BUDGET)),
+			};
+		}
+
+		if self.bindings.pressed(input, Action::CycleSampler) {
+			let settings = &mut self.current_scene.render_settings;
+			settings.sampler = settings.sampler.next();
+		}
+
+		if self.bindings.pressed(input, Action::SamplesIncrease) {
+			self.current_scene.render_settings.samples_per_pixel += 1;
+		}
+
+		if self.bindings.pressed(input, Action::SamplesDecrease) && self.current_scene.render_settings.samples_per_pixel > 1 {
+			self.current_scene.render_settings.samples_per_pixel -= 1;
+		}
+
+		if self.bindings.pressed(input, Action::CameraShakeToggle) {
+			self.shake = match self.shake {
+				Some(_) => None,
+				None => Some(CameraShake::new(0.03, 0.01, 1.5, self.current_scene.render_settings.seed)),
+			};
+		}
+
+		if self.bindings.pressed(input, Action::OrbitToggle) {
+			self.orbit = match self.orbit {
+				Some(_) => None,
+				None => {
+					// Orbit around whatever is under the screen center, or the origin
+					let center_ray = self.current_scene.primary_ray(VIEWPORT_WIDTH as i32 / 2, VIEWPORT_HEIGHT as i32 / 2, (0.5, 0.5), VIEWPORT_WIDTH as i32, VIEWPORT_HEIGHT as i32);
+					let pivot = match self.current_scene.trace(&center_ray) {
+						Some(hit) => hit.0,
+						None => Vec3 { x: 0.0, y: 0.0, z: 0.0 },
+					};
+					Some(OrbitController::from_camera(&self.current_scene.current_camera, pivot))
 				}
+			};
+		}
+
+		let mouse = input.mouse_position();
+		if let Some(orbit) = self.orbit.as_mut() {
+			if self.bindings.held(input, Action::CameraYawRight) { orbit.rotate(0.02, 0.0); }
+			if self.bindings.held(input, Action::CameraYawLeft) { orbit.rotate(-0.02, 0.0); }
+			if self.bindings.held(input, Action::CameraRollUp) { orbit.rotate(0.0, 0.02); }
+			if self.bindings.held(input, Action::CameraRollDown) { orbit.rotate(0.0, -0.02); }
+			// Unless it's the comparison's divider being dragged
+			if input.mouse_button(0).held && !self.comparison.as_ref().is_some_and(|comparison| comparison.dragging) {
+				orbit.rotate(-(mouse.0 - self.last_mouse.0) as f64 * 0.02, (mouse.1 - self.last_mouse.1) as f64 * 0.02);
+			}
+			let wheel = input.mouse_wheel();
+			if wheel > 0 || self.bindings.held(input, Action::OrbitZoomIn) { orbit.zoom(0.97); }
+			if wheel < 0 || self.bindings.held(input, Action::OrbitZoomOut) { orbit.zoom(1.03); }
+			orbit.apply(&mut self.current_scene.current_camera);
+		} else {
+			if self.bindings.held(input, Action::CameraYawRight) {
+				self.current_scene.current_camera.rot.yaw -= 0.01;
+			}
+
+			if self.bindings.held(input, Action::CameraYawLeft) {
+				self.current_scene.current_camera.rot.yaw += 0.01;
+			}
+
+			if self.bindings.held(input, Action::CameraRollUp) {
+				self.current_scene.current_camera.rot.roll += 0.01;
 			}
+
+			if self.bindings.held(input, Action::CameraRollDown) {
+				self.current_scene.current_camera.rot.roll -= 0.01;
+			}
+		}
+		self.last_mouse = mouse;
+
+		if self.bindings.held(input, Action::FovIncrease) {
+			let fov = self.current_scene.current_camera.fov;
+			self.current_scene.current_camera.set_fov(fov + 30.0 * elapsed_time);
+		}
+
+		if self.bindings.held(input, Action::FovDecrease) {
+			let fov = self.current_scene.current_camera.fov;
+			self.current_scene.current_camera.set_fov(fov - 30.0 * elapsed_time);
 		}
 
-		// Rotate the fuckin' cube
-		for mesh in self.current_scene.get_all_meshes().iter_mut() {
-			if mesh.id == String::from("fuckin' cube") {
-				mesh.rot.pitch += 0.01;
-				mesh.rot.roll += 0.01;
-				mesh.rot.yaw += 0.01;
+
+		let light_moves = [
+			(Action::LightMoveYNeg, Vec3 { x: 0.0, y: -0.05, z: 0.0 }),
+			(Action::LightMoveYPos, Vec3 { x: 0.0, y: 0.05, z: 0.0 }),
+			(Action::LightMoveZNeg, Vec3 { x: 0.0, y: 0.0, z: -0.05 }),
+			(Action::LightMoveZPos, Vec3 { x: 0.0, y: 0.0, z: 0.05 }),
+			(Action::LightMoveXNeg, Vec3 { x: -0.05, y: 0.0, z: 0.0 }),
+			(Action::LightMoveXPos, Vec3 { x: 0.05, y: 0.0, z: 0.0 }),
+		];
+		for (action, offset) in light_moves.iter() {
+			if self.bindings.held(input, *action) {
+				self.move_light(*offset);
 			}
 		}
 
-		//let _ = olc::draw_string(0, 0, &(String::from("roll ") + &self.current_scene.current_camera.rot.roll.to_string()), olc::WHITE);
-		//let _ = olc::draw_string(0, 10, &(String::from("yaw ") + &self.current_scene.current_camera.rot.yaw.to_string()), olc::WHITE);
-		//let _ = olc::draw_string(0, 20, &(String::from("pitch ") + &self.current_scene.current_camera.rot.pitch.to_string()), olc::WHITE);
+		//presenter.draw_string(0, 0, &(String::from("roll ") + &self.current_scene.current_camera.rot.roll.to_string()), WHITE);
+		//presenter.draw_string(0, 10, &(String::from("yaw ") + &self.current_scene.current_camera.rot.yaw.to_string()), WHITE);
+		//presenter.draw_string(0, 20, &(String::from("pitch ") + &self.current_scene.current_camera.rot.pitch.to_string()), WHITE);
+	}
+}
+
+// The demo's name standing behind the cube
+fn title() -> Mesh {
+	let material = Material { color: Color { r: 255, g: 200, b: 60 }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+	let facing_camera = Rot3::from_axis_angle(&Vec3 { x: 0.0, y: 0.0, z: 1.0 }, 135f64.to_radians());
+	let mut title = label::text_mesh(Vec3 { x: -2.5, y: -2.5, z: 0.0 }, facing_camera, "RAYTRACING\nENGINE", 0.6, 0.15, material);
+	title.id = String::from("title");
+	title
+}
+
+fn arg_value<'a>(args: &'a [String], prefix: &str) -> Option<&'a str> {
+	args.iter().find_map(|arg| arg.strip_prefix(prefix))
+}
 
-		Ok(())
+// Where headless macro playback draws to, nowhere
+struct Offscreen;
+
+impl Presenter for Offscreen {
+	fn draw(&mut self, _x: i32, _y: i32, _color: Color) {}
+}
+
+// Prints the reference scene timings as JSON, returning the exit code: 1 when slower than the baseline
+fn run_bench(args: &[String]) -> i32 {
+	let repeats = arg_value(args, "--bench-repeats=").and_then(|repeats| repeats.parse().ok()).unwrap_or(DEFAULT_BENCH_REPEATS);
+	// Against the same baseline, to see which accelerator suits the reference scenes
+	let accelerator = arg_value(args, "--accelerator=").and_then(AcceleratorKind::from_name).unwrap_or(AcceleratorKind::Bvh);
+	let results = bench::run(repeats, accelerator);
+	println!("{}", bench::to_json(&results));
+	if let Some(path) = arg_value(args, "--bench-save=") {
+		if let Err(e) = bench::save_baseline(Path::new(path), &results) {
+			eprintln!("Could not write {}: {}", path, e);
+			return 2;
+		}
+	}
+	let path = match arg_value(args, "--bench-baseline=") {
+		Some(path) => path,
+		None => return 0,
+	};
+	let baseline = match bench::load_baseline(Path::new(path)) {
+		Ok(baseline) => baseline,
+		Err(e) => {
+			eprintln!("Could not read baseline {}: {}", path, e);
+			return 2;
+		}
+	};
+	let tolerance = arg_value(args, "--bench-tolerance=").and_then(|tolerance| tolerance.parse().ok()).unwrap_or(DEFAULT_BENCH_TOLERANCE);
+	let slower = bench::regressions(&results, &baseline, tolerance);
+	for (name, before, now) in slower.iter() {
+		eprintln!("Regression in {}: {:.0} rays/s, baseline {:.0} ({:+.1}%)", name, now, before, (now / before - 1.0) * 100.0);
+	}
+	if slower.is_empty() { 0 } else { 1 }
+}
+
+// What the scene's rays did, where the frame's time went, how the tiles of its last frame were shared out and the ones that took longest
+fn print_render_stats(scene: &Scene) {
+	let traced = scene.stats();
+	println!("{} rays ({} camera, {} shadow, {} reflection), {:.1}% hit, {:.1} triangle tests a ray, {:.2} Mrays/s",
+		traced.rays, traced.camera_rays, traced.shadow_rays, traced.reflection_rays, traced.hit_rate() * 100.0, traced.triangle_tests_per_ray(), traced.rays_per_second() / 1e6);
+	let stats = scene.render_stats.read().unwrap_or_else(PoisonError::into_inner);
+	let stats = match stats.as_ref() {
+		Some(stats) => stats,
+		None => return,
+	};
+	let geometry = scene.geometry_stats();
+	println!("{} meshes sharing {} geometries, {} of {} triangles stored, {:.1} MB",
+		geometry.meshes, geometry.distinct, geometry.triangles, geometry.instanced_triangles, geometry.bytes as f64 / 1e6);
+	if let Some(profile) = scene.profiler.last_frame() {
+		println!("{}", profile.lines().join(", "));
+	}
+	println!("{} tiles on {} threads, {} stolen, {} rays in {:.1} ms", stats.tiles.len(), stats.threads, stats.stolen(), stats.rays(), stats.seconds * 1000.0);
+	for tile in stats.slowest(SLOWEST_TILES) {
+		println!("  {}x{} at ({}, {}): {:.2} ms, {} rays, thread {}", tile.tile.width, tile.tile.height, tile.tile.x, tile.tile.y, tile.seconds * 1000.0, tile.rays, tile.thread);
+	}
+}
+
+// Prints how far apart two width x height images are and writes their heatmap if asked,
+// exiting with 1 when any pixel differs by more than the tolerance
+fn report_diff(args: &[String], a: &[Color], b: &[Color], width: usize, height: usize) -> i32 {
+	let tolerance = arg_value(args, "--diff-tolerance=").and_then(|tolerance| tolerance.parse().ok()).unwrap_or(0);
+	let stats = diff::compare(a, b, tolerance);
+	println!("rmse {:.4} psnr {:.2} dB max {} differing {}/{}", stats.rmse, stats.psnr, stats.max_diff, stats.differing, width * height);
+	if let Some(path) = arg_value(args, "--diff-heatmap=") {
+		let mut heatmap = Framebuffer::new(width, height);
+		for (index, color) in diff::heatmap(a, b).into_iter().enumerate() {
+			heatmap.set(index % width, index / width, color, 255);
+		}
+		if let Err(e) = heatmap.write_png(Path::new(path)) {
+			eprintln!("Could not write {}: {}", path, e);
+			return 2;
+		}
 	}
+	if stats.differing > 0 { 1 } else { 0 }
+}
 
-	fn on_user_destroy(&mut self) -> Result<(), olc::Error> {
-		Ok(())
+// --diff=a.png,b.png
+fn run_diff_images(args: &[String], paths: &str) -> i32 {
+	let (a, b) = match paths.split_once(',') {
+		Some(paths) => paths,
+		None => {
+			eprintln!("Expected two images, --diff=a.png,b.png");
+			return 2;
+		}
+	};
+	let (a, b) = match (Texture::load_png(Path::new(a)), Texture::load_png(Path::new(b))) {
+		(Ok(a), Ok(b)) => (a, b),
+		(Err(e), _) | (_, Err(e)) => {
+			eprintln!("Could not read image: {}", e);
+			return 2;
+		}
+	};
+	if (a.width, a.height) != (b.width, b.height) {
+		eprintln!("Images differ in size, {}x{} and {}x{}", a.width, a.height, b.width, b.height);
+		return 2;
 	}
+	report_diff(args, &a.pixels, &b.pixels, a.width, a.height)
+}
+
+// Every pixel of a width x height frame, traced in full
+fn trace_frame(scene: &mut Scene, width: usize, height: usize) -> Vec<Color> {
+	scene.update_culling(width as i32, height as i32);
+	let scene = &*scene;
+	render_tiles(scene, width, height, |_, _| true,
+		|x, y, scratch| scene.cast_ray_samples((y * width + x) as u64, width as i32, height as i32, 0, scratch).0)
+		.into_iter().flatten().collect()
 }
 
 fn main() {
+	let bindings = if Path::new(BINDINGS_FILE).exists() {
+		Bindings::load(BINDINGS_FILE).unwrap_or_else(|e| {
+			eprintln!("Ignoring key bindings: {}", e);
+			Bindings::default()
+		})
+	} else {
+		Bindings::default()
+	};
+	let args: Vec<String> = std::env::args().collect();
+	if args.iter().any(|arg| arg == "--bench") {
+		std::process::exit(run_bench(&args));
+	}
+	if let Some(paths) = arg_value(&args, "--diff=") {
+		std::process::exit(run_diff_images(&args, paths));
+	}
+	// Authoring mistakes in a scene file, one a line, exiting with 1 when there are any
+	if let Some(path) = arg_value(&args, "--lint=") {
+		std::process::exit(match lint::lint(Path::new(path)) {
+			Ok(problems) => {
+				for problem in problems.iter() {
+					println!("{}", problem);
+				}
+				if problems.is_empty() { 0 } else { 1 }
+			}
+			Err(e) => {
+				eprintln!("Could not load scene: {}", e);
+				2
+			}
+		});
+	}
+	let scene_path = arg_value(&args, "--scene=").unwrap_or(SCENE_FILE).to_string();
+	// The scene file and everything it uses packed into one file to pass around
+	if let Some(path) = arg_value(&args, "--export-archive=") {
+		match archive::export(Path::new(&scene_path), Path::new(path)) {
+			Ok(()) => println!("Wrote {}", path),
+			Err(e) => eprintln!("Could not export scene: {}", e),
+		}
+		return;
+	}
+	// A --preview-size thumbnail of every material in the scene file, for a material library
+	if let Some(dir) = arg_value(&args, "--material-previews=") {
+		let size = arg_value(&args, "--preview-size=").and_then(|size| size.parse().ok()).unwrap_or(PREVIEW_SIZE);
+		match material_preview::write_library(Path::new(&scene_path), Path::new(dir), size) {
+			Ok(paths) => paths.iter().for_each(|path| println!("Wrote {}", path.display())),
+			Err(e) => eprintln!("Could not preview materials: {}", e),
+		}
+		return;
+	}
+	// Where big meshes' hierarchies are kept between runs, or off to always build them
+	let bvh_cache = match arg_value(&args, "--bvh-cache=") {
+		Some("off") => None,
+		Some(dir) => Some(BvhCache::new(PathBuf::from(dir))),
+		None => Some(BvhCache::default()),
+	};
+	let mut scene = match arg_value(&args, "--scene=") {
+		Some(path) => match scene_file::load_scene_with_cache(Path::new(path), bvh_cache.as_ref()) {
+			Ok(scene) => scene,
+			Err(e) => {
+				eprintln!("Could not load scene: {}", e);
+				return;
+			}
+		},
+		None => {
+			let mut scene = Scene::default_scene();
+			scene.objects.push(Box::new(title()));
+			scene
+		}
+	};
+	// A generated stress scene instead, from spheres,triangles,lights and optionally a seed
+	if let Some(spec) = arg_value(&args, "--stress=") {
+		match StressScene::parse(spec) {
+			Some(stress) => scene = stress.build(),
+			None => eprintln!("Expected --stress=spheres,triangles,lights[,seed], not `{}`", spec),
+		}
+	}
+	// Repaints the scene from a few contrasting colors, for quick test scenes
+	if let Some(seed) = arg_value(&args, "--randomize-materials=").and_then(|seed| seed.parse().ok()) {
+		let palette: Vec<Material> = RANDOM_PALETTE.iter()
+			.map(|(r, g, b)| Material { color: Color { r: *r, g: *g, b: *b }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 })
+			.collect();
+		scene.randomize_materials(seed, &palette);
+	}
+	// Meshes added or recolored since the scene was made
+	scene.intern_mesh_geometry();
+	// The loaded scene's objects as a tree on stdout, text, json or dot
+	if let Some(name) = arg_value(&args, "--hierarchy=") {
+		match OutlineFormat::from_name(name) {
+			Some(format) => print!("{}", scene.dump_hierarchy(format)),
+			None => eprintln!("Unknown hierarchy format `{}`, expected text, json or dot", name),
+		}
+		return;
+	}
+	let render_order = scene.render_settings.traversal_order.pixel_order(VIEWPORT_WIDTH, VIEWPORT_HEIGHT, scene.render_settings.seed);
 	let mut example = ExampleProgram {
-		current_scene: Scene::default_scene(),
-		render_index: 0,
-		complete: false
+		current_scene: scene,
+		render_order,
+		progressive: None,
+		progressive_budget: DEFAULT_PROGRESSIVE_BUDGET,
+		accumulation: Some(Accumulation::new((VIEWPORT_WIDTH * VIEWPORT_HEIGHT) as usize)),
+		orbit: None,
+		last_mouse: (0, 0),
+		bindings,
+		selected: None,
+		shake: None,
+		time: 0.0,
+		frame: 0,
+		quality: None,
+		resolution: DynamicResolution::new(1.0, None),
+		stereo: None,
+		comparison: None,
+		material_param: MaterialParam::Hue,
+		scene_path,
+		preset: None,
+		analysis: false,
+		auto_exposure: None,
+		frame_colors: vec![Color { r: 0, g: 0, b: 0 }; (VIEWPORT_WIDTH * VIEWPORT_HEIGHT) as usize],
+		show_graphs: false,
+		magnifier: None,
+		measure: None,
+		light_placement: None,
+		light_offset: arg_value(&args, "--light-offset=").and_then(|offset| offset.parse().ok()).unwrap_or(DEFAULT_LIGHT_OFFSET),
+		outliner: None,
+		recorder: None,
+		macro_path: arg_value(&args, "--record-macro=").unwrap_or(MACRO_FILE).to_string(),
+		playback: None,
+		graphs: [
+			RollingGraph::new("ms", GRAPH_LENGTH, Color { r: 230, g: 170, b: 40 }),
+			RollingGraph::new("spp", GRAPH_LENGTH, Color { r: 60, g: 200, b: 90 }),
+			RollingGraph::new("var", GRAPH_LENGTH, Color { r: 90, g: 140, b: 255 }),
+		]
 	};
-	olc::start("Raytracing", &mut example, VIEWPORT_WIDTH as i32, VIEWPORT_HEIGHT as i32, 1, 1).unwrap();
+
+	// A bundle of settings by name, draft, preview, final or one of the scene's own, that the flags after can still change
+	if let Some(name) = arg_value(&args, "--preset=") {
+		if !example.use_preset(name) {
+			eprintln!("Unknown preset `{}`, the scene has {:?}", name, example.current_scene.presets.keys().collect::<Vec<_>>());
+		}
+	}
+	if let Some(budget) = arg_value(&args, "--frame-budget=").and_then(|ms| ms.parse::<f32>().ok()) {
+		example.quality = Some(QualityController::new(budget / 1000.0));
+	}
+	// Traces a fraction of the viewport, 0.25 to 1, and optionally drops lower while moving to keep frames under so many ms
+	if let Some(scale) = arg_value(&args, "--render-scale=").and_then(|scale| scale.parse().ok()) {
+		example.resolution = DynamicResolution::new(scale, example.resolution.target);
+	}
+	if let Some(target) = arg_value(&args, "--scale-budget=").and_then(|ms| ms.parse::<f32>().ok()) {
+		example.resolution = DynamicResolution::new(example.resolution.scale, Some(target / 1000.0));
+	}
+	// Both eyes side by side or as a red/cyan anaglyph, this many meters apart
+	if let Some(name) = arg_value(&args, "--stereo=") {
+		match StereoMode::from_name(name) {
+			Some(mode) => {
+				let separation = arg_value(&args, "--eye-separation=").and_then(|meters| meters.parse().ok()).unwrap_or(DEFAULT_SEPARATION);
+				example.stereo = Some(Stereo { mode, separation });
+			}
+			None => eprintln!("Unknown stereo mode `{}`, expected side-by-side or anaglyph", name),
+		}
+	}
+	// Every frame as traced, even when nothing has moved since the last
+	if args.iter().any(|arg| arg == "--no-accumulation") {
+		example.accumulation = None;
+	}
+	// Starts out progressive, tracing this many pixels an update
+	if let Some(budget) = arg_value(&args, "--progressive=").and_then(|budget| budget.parse().ok()) {
+		example.progressive_budget = budget;
+		example.progressive = Some(Progressive::new(budget, PROGRESSIVE_PASSES, (VIEWPORT_WIDTH * VIEWPORT_HEIGHT) as usize));
+	}
+	// Records from the start, otherwise the record key starts it
+	if arg_value(&args, "--record-macro=").is_some() {
+		example.recorder = Recorder::start(&example.macro_path).map_err(|e| eprintln!("Could not record {}: {}", example.macro_path, e)).ok();
+	}
+	// Plays a recorded macro back before taking any input, headless renders show where it ends up
+	if let Some(path) = arg_value(&args, "--play-macro=") {
+		match macros::load(path) {
+			Ok(frames) => example.playback = Some(frames.into_iter()),
+			Err(e) => eprintln!("Could not load macro: {}", e),
+		}
+	}
+	// Render threads, all cores unless told otherwise, the size of the tiles they share out, and
+	// whether to leave room for other work
+	let renderer = &mut example.current_scene.renderer;
+	if let Some(threads) = arg_value(&args, "--threads=").and_then(|threads| threads.parse().ok()) {
+		renderer.threads = threads;
+	}
+	if let Some(size) = arg_value(&args, "--tile-size=").and_then(|size| size.parse().ok()) {
+		renderer.tile_size = size;
+	}
+	if let Some(name) = arg_value(&args, "--priority=") {
+		match Priority::from_name(name) {
+			Some(priority) => renderer.priority = priority,
+			None => eprintln!("Unknown priority `{}`, using {:?}", name, renderer.priority),
+		}
+	}
+	// Scenes with many lights shade each point with this many picked from a light tree instead of all of them
+	if let Some(lights) = arg_value(&args, "--sampled-lights=").and_then(|lights| lights.parse().ok()) {
+		example.current_scene.render_settings.sampled_lights = lights;
+	}
+	// What rays find objects with, bvh, grid or kdtree
+	if let Some(name) = arg_value(&args, "--accelerator=") {
+		match AcceleratorKind::from_name(name) {
+			Some(accelerator) => example.current_scene.render_settings.accelerator = accelerator,
+			None => eprintln!("Unknown accelerator `{}`, using {:?}", name, example.current_scene.render_settings.accelerator),
+		}
+	}
+	// How light is gathered, direct, sppm or path, in the viewer as much as headless
+	if let Some(name) = arg_value(&args, "--integrator=") {
+		match Integrator::from_name(name) {
+			Some(integrator) => example.current_scene.render_settings.integrator = integrator,
+			None => eprintln!("Unknown integrator `{}`, using {:?}", name, example.current_scene.render_settings.integrator),
+		}
+	}
+	// A png behind the scene, over whatever the scene file set, fitted or filling the frame
+	if let Some(path) = arg_value(&args, "--backplate=") {
+		let fit = match arg_value(&args, "--backplate-fit=") {
+			Some(name) => BackplateFit::from_name(name).unwrap_or_else(|| {
+				eprintln!("Unknown backplate fit `{}`, using fill", name);
+				BackplateFit::Fill
+			}),
+			None => BackplateFit::Fill,
+		};
+		match Texture::load_png(Path::new(path)) {
+			Ok(texture) => example.current_scene.backplate = Some(Backplate { texture: Arc::new(texture), fit }),
+			Err(e) => eprintln!("Could not load backplate {}: {}", path, e),
+		}
+	}
+	// A lat-long png lighting the scene from all round, brightened or dimmed by --environment-intensity
+	if let Some(path) = arg_value(&args, "--environment=") {
+		let intensity = arg_value(&args, "--environment-intensity=").and_then(|value| value.parse().ok()).unwrap_or(1.0);
+		match Texture::load_png(Path::new(path)) {
+			Ok(texture) => example.current_scene.environment = Some(Environment::new(Arc::new(texture), intensity)),
+			Err(e) => eprintln!("Could not load environment {}: {}", path, e),
+		}
+	}
+	// Headless: render a single frame, beauty as an RGBA png with a transparent background, depth, sky and motion as png or exr,
+	// analysis as the false color view with its histogram.
+	// A single frame has nothing before it, so its motion vectors are all zero.
+	// --diff renders it a second time and reports any pixel that came out differently,
+	// --render-stats prints where the time went. The preset's AOVs are written beside --output, as name.depth.png and so on
+	let outputs: [(&str, ImageWriter); 6] = [
+		("--output=", Framebuffer::write_png),
+		("--depth=", Framebuffer::write_depth),
+		("--normals=", Framebuffer::write_normals),
+		("--sky=", Framebuffer::write_sky),
+		("--motion=", Framebuffer::write_motion),
+		("--analysis=", Framebuffer::write_analysis),
+	];
+	let render_twice = args.iter().any(|arg| arg == "--diff");
+	if render_twice || outputs.iter().any(|(flag, _)| arg_value(&args, flag).is_some()) {
+		// Over whatever the preset said
+		let settings = &mut example.current_scene.render_settings;
+		if let Some(samples) = arg_value(&args, "--spp=").and_then(|samples| samples.parse().ok()) {
+			settings.samples_per_pixel = samples;
+		}
+		if let Some(bounces) = arg_value(&args, "--max-bounces=").and_then(|bounces| bounces.parse().ok()) {
+			settings.max_bounces = bounces;
+		}
+		// Animations and physics played this many seconds in
+		if let Some(seconds) = arg_value(&args, "--time=").and_then(|seconds| seconds.parse().ok()) {
+			example.time = seconds;
+			example.current_scene.animate(seconds);
+			example.current_scene.simulate(seconds);
+		}
+		while example.playback.as_ref().is_some_and(|frames| frames.len() > 0) {
+			example.update(&mut Offscreen, &FrameInput::default(), 0.0);
+		}
+		if args.iter().any(|arg| arg == "--frame-all") {
+			example.frame_view();
+		}
+		if let Some(stops) = arg_value(&args, "--exposure=").and_then(|stops| stops.parse().ok()) {
+			example.current_scene.render_settings.exposure = stops;
+		}
+		let preset = example.preset.as_ref().and_then(|name| example.current_scene.presets.get(name)).cloned().unwrap_or_default();
+		// Rough reflections sampled properly rather than with one blurred ray
+		let glossy_samples = arg_value(&args, "--glossy-samples=").and_then(|samples| samples.parse().ok()).or(preset.glossy_samples);
+		example.current_scene.render_settings.glossy_samples = glossy_samples.unwrap_or(FINAL_GLOSSY_SAMPLES);
+		if (arg_value(&args, "--sky=").is_some() || preset.aovs.contains(&Aov::Sky)) && example.current_scene.render_settings.ao_samples == 0 {
+			example.current_scene.render_settings.ao_samples = DEFAULT_AO_SAMPLES;
+		}
+		let render = |example: &mut ExampleProgram| match example.stereo {
+			Some(stereo) => example.current_scene.render_stereo(VIEWPORT_WIDTH as i32, VIEWPORT_HEIGHT as i32, &stereo),
+			None => example.current_scene.render_framebuffer(VIEWPORT_WIDTH as i32, VIEWPORT_HEIGHT as i32),
+		};
+		let profile = args.iter().any(|arg| arg == "--render-stats");
+		example.current_scene.profiler.enabled = profile;
+		example.current_scene.profiler.begin_frame();
+		let framebuffer = render(&mut example);
+		example.current_scene.profiler.end_frame();
+		if profile {
+			print_render_stats(&example.current_scene);
+		}
+		let output = arg_value(&args, "--output=");
+		for (flag, write) in outputs.iter() {
+			let aov = preset.aovs.iter().find(|aov| format!("--{}=", aov.name()) == *flag);
+			let beside = aov.zip(output).map(|(aov, output)| Path::new(output).with_extension(format!("{}.png", aov.name())).to_string_lossy().into_owned());
+			if let Some(path) = arg_value(&args, flag).map(String::from).or(beside) {
+				if let Err(e) = write(&framebuffer, Path::new(&path)) {
+					eprintln!("Could not write {}: {}", path, e);
+				}
+			}
+		}
+		if render_twice {
+			let again = render(&mut example);
+			std::process::exit(report_diff(&args, &framebuffer.color, &again.color, framebuffer.width, framebuffer.height));
+		}
+		return;
+	}
+	// ID mattes for compositing, object and material layers in one multi-layer exr
+	if let Some(path) = arg_value(&args, "--cryptomatte=") {
+		let mattes = cryptomatte::render(&mut example.current_scene, VIEWPORT_WIDTH as i32, VIEWPORT_HEIGHT as i32);
+		if let Err(e) = cryptomatte::write(Path::new(path), &mattes) {
+			eprintln!("Could not write {}: {}", path, e);
+		}
+		return;
+	}
+
+	// The animation as numbered frames with their cameras and motion, --sequence-frames of them at --fps from --time
+	if let Some(path) = arg_value(&args, "--sequence=") {
+		let sequence = Sequence {
+			frames: arg_value(&args, "--sequence-frames=").and_then(|frames| frames.parse().ok()).unwrap_or(SEQUENCE_FRAMES),
+			fps: arg_value(&args, "--fps=").and_then(|fps| fps.parse().ok()).filter(|fps: &f64| *fps > 0.0).unwrap_or(SEQUENCE_FPS),
+			start: arg_value(&args, "--time=").and_then(|seconds| seconds.parse().ok()).unwrap_or(0.0),
+		};
+		match sequence.export(&mut example.current_scene, VIEWPORT_WIDTH as usize, VIEWPORT_HEIGHT as usize, Path::new(path)) {
+			Ok(metadata) => println!("Wrote {} frames to {}", metadata.frames.len(), path),
+			Err(e) => eprintln!("Could not write {}: {}", path, e),
+		}
+		return;
+	}
+	let frontend = arg_value(&args, "--frontend=").unwrap_or(DEFAULT_FRONTEND);
+	match frontend {
+		#[cfg(feature = "olc")]
+		"olc" => olc_frontend::run(&mut example, VIEWPORT_WIDTH as i32, VIEWPORT_HEIGHT as i32),
+		#[cfg(feature = "minifb")]
+		"minifb" => minifb_frontend::run(&mut example, VIEWPORT_WIDTH as usize, VIEWPORT_HEIGHT as usize),
+		#[cfg(feature = "egui")]
+		"egui" => egui_frontend::run(&mut example, VIEWPORT_WIDTH as usize, VIEWPORT_HEIGHT as usize),
+		"terminal" => {
+			let mode = match arg_value(&args, "--terminal-mode=") {
+				Some("ascii") => terminal_frontend::TerminalMode::Ascii,
+				_ => terminal_frontend::TerminalMode::HalfBlock,
+			};
+			let frames = arg_value(&args, "--frames=").and_then(|frames| frames.parse().ok());
+			terminal_frontend::run(&mut example, VIEWPORT_WIDTH as usize, VIEWPORT_HEIGHT as usize, mode, frames)
+		}
+		other => eprintln!("Frontend `{}` is not available in this build", other),
+	}
 }
\ No newline at end of file
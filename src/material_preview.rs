@@ -0,0 +1,109 @@
+//
+//	Material Previews
+//
+
+// Any material on a ball in the same little studio every time, so a library of them can be told apart
+// from thumbnails at a glance. The ball sits on a gray floor under one large soft light, in front of a
+// gradient from a pale sky down to a dark ground that also lights it and shows in its reflections.
+// Everything is made here rather than loaded from files, so previews come out the same wherever
+// they're rendered
+
+use crate::backplate::{Backplate, BackplateFit};
+use crate::environment::Environment;
+use crate::framebuffer::Framebuffer;
+use crate::scene_file::{self, SceneFile};
+use crate::structs::{Camera, Color, LightSource, Material, Mesh, Rot3, Scene, SceneObject, Sphere, Tri, Vec3};
+use crate::texture::Texture;
+use std::io;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub const PREVIEW_SIZE: usize = 128;
+
+// Top to bottom of the gradient, the horizon halfway
+const SKY: Color = Color { r: 190, g: 200, b: 215 };
+const HORIZON: Color = Color { r: 110, g: 110, b: 115 };
+const GROUND: Color = Color { r: 25, g: 25, b: 28 };
+const GRADIENT_ROWS: usize = 32;
+const ENVIRONMENT_INTENSITY: f64 = 1.0;
+
+const FLOOR: Material = Material { color: Color { r: 120, g: 120, b: 120 }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+const FLOOR_EXTENT: f64 = 20.0;
+const BALL_RADIUS: f32 = 1.0;
+
+// A lat-long image, so the same one does for the backplate and the environment
+fn gradient() -> Texture {
+	let pixels = (0..GRADIENT_ROWS).flat_map(|row| {
+		let t = row as f64 / (GRADIENT_ROWS - 1) as f64 * 2.0;
+		let color = if t < 1.0 { SKY.lerp(&HORIZON, t) } else { HORIZON.lerp(&GROUND, t - 1.0) };
+		vec![color, color]
+	}).collect();
+	Texture::new(2, GRADIENT_ROWS, pixels, String::from("material preview gradient"))
+}
+
+// The studio with material on the ball, looked at from a little above
+pub fn preview_scene(material: &Material) -> Scene {
+	let ball = Sphere::new(Vec3 { x: 0.0, y: 0.0, z: BALL_RADIUS as f64 }, BALL_RADIUS, *material);
+	let corner = |x: f64, y: f64| Vec3 { x: x * FLOOR_EXTENT / 2.0, y: y * FLOOR_EXTENT / 2.0, z: 0.0 };
+	let (a, b, c, d) = (corner(-1.0, -1.0), corner(1.0, -1.0), corner(1.0, 1.0), corner(-1.0, 1.0));
+	let floor = Mesh::new(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, Rot3::new(), vec![Tri { a, b, c, mat: FLOOR }, Tri { a, b: c, c: d, mat: FLOOR }]);
+	// Up, left and in front, big enough to soften the shadow the ball casts
+	let mut light = LightSource::new(Vec3 { x: -3.0, y: -3.0, z: 5.0 }, Rot3::new(), 8.0);
+	light.radius = 1.5;
+	let objects: Vec<Box<dyn SceneObject>> = vec![Box::new(floor), Box::new(ball), Box::new(light)];
+
+	let mut camera = Camera::new(Vec3 { x: 0.0, y: -5.5, z: 2.2 }, Rot3::new(), 35.0);
+	camera.look_at(&Vec3 { x: 0.0, y: 0.0, z: BALL_RADIUS as f64 * 0.9 }, &Vec3 { x: 0.0, y: 0.0, z: 1.0 });
+	let mut scene = Scene::new(objects, camera);
+	let texture = Arc::new(gradient());
+	scene.backplate = Some(Backplate { texture: texture.clone(), fit: BackplateFit::Stretch });
+	scene.environment = Some(Environment::new(texture, ENVIRONMENT_INTENSITY));
+	// Enough to get through glass and back out, and to smooth the soft shadow
+	scene.render_settings.samples_per_pixel = 4;
+	scene.render_settings.light_samples = 16;
+	scene.render_settings.max_bounces = 4;
+	scene.render_settings.glossy_samples = 8;
+	scene
+}
+
+// A size x size thumbnail of material
+pub fn render_preview(material: &Material, size: usize) -> Framebuffer {
+	preview_scene(material).render_framebuffer(size as i32, size as i32)
+}
+
+pub fn write_preview(material: &Material, size: usize, path: &Path) -> io::Result<()> {
+	render_preview(material, size).write_png(path)
+}
+
+// A preview of every material in a scene file's table into dir, made if it isn't there, each named
+// after its material. Returns where they went
+pub fn write_library(scene: &Path, dir: &Path, size: usize) -> Result<Vec<PathBuf>, String> {
+	let file = SceneFile::load(scene)?;
+	fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+	file.materials.iter().map(|(name, desc)| {
+		let path = dir.join(format!("{}.png", name));
+		write_preview(&scene_file::material(desc), size, &path).map_err(|e| format!("{}: {}", path.display(), e))?;
+		Ok(path)
+	}).collect()
+}
+
+#[test]
+fn previews_tell_materials_apart() {
+	let red = Material { color: Color { r: 220, g: 30, b: 30 }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+	let size = 24;
+	let preview = render_preview(&red, size);
+	let at = |image: &Framebuffer, x: usize, y: usize| image.color[y * size + x];
+	// The ball in the middle, the gradient behind it over the top, every pixel covered
+	let middle = at(&preview, size / 2, size / 2);
+	assert!(middle.r > middle.g + 50, "{:?}", middle);
+	let top = at(&preview, 0, 0);
+	assert!(top.b > top.r && top.b > 150, "{:?}", top);
+	assert!(preview.alpha.iter().all(|alpha| *alpha == 255));
+
+	// The same every time, and different for another material
+	assert_eq!(render_preview(&red, size).color, preview.color);
+	let mirror = Material { color: Color { r: 0, g: 0, b: 0 }, reflectivity: 1.0, ..red };
+	let reflected = at(&render_preview(&mirror, size), size / 2, size / 2);
+	assert!(reflected.r < middle.r / 2, "{:?}", reflected);
+}
@@ -0,0 +1,113 @@
+//
+//	Measuring
+//
+
+// Distance between two points picked on surfaces, for checking a scene is to scale or how big an
+// imported model came in. The points stay put in the world, the segment between them is redrawn
+// over the image wherever the camera goes
+
+use crate::frontend::Presenter;
+use crate::structs::{Color, Scene, Vec3};
+
+pub struct Measure {
+	pub points: Vec<Vec3>, // none, one, or the two being measured between
+}
+
+impl Measure {
+	pub fn new() -> Self {
+		Measure { points: Vec::new() }
+	}
+
+	// Adds the surface point under pixel (x, y) of a width x height view, starting over after two,
+	// false when there's nothing there
+	pub fn pick(&mut self, scene: &Scene, x: i32, y: i32, width: i32, height: i32) -> bool {
+		let ray = scene.primary_ray(x, y, (0.5, 0.5), width, height);
+		let hit = match scene.raycast(&ray, f64::INFINITY) {
+			Some(hit) => hit,
+			None => return false,
+		};
+		if self.points.len() == 2 {
+			self.points.clear();
+		}
+		self.points.push(hit.point);
+		true
+	}
+
+	pub fn distance(&self) -> Option<f64> {
+		match self.points.as_slice() {
+			[from, to] => Some(from.dist(to)),
+			_ => None,
+		}
+	}
+
+	// Marks the points and the segment between them as the scene's camera sees them, with the
+	// distance along the bottom edge
+	pub fn draw(&self, presenter: &mut dyn Presenter, scene: &Scene, width: i32, height: i32) {
+		let color = Color { r: 255, g: 220, b: 80 };
+		let projected: Vec<Option<(i32, i32)>> = self.points.iter()
+			.map(|point| scene.current_camera.project(point, width, height).map(|(x, y)| (x as i32, y as i32)))
+			.collect();
+		let mut plot = |x: i32, y: i32| {
+			if x >= 0 && y >= 0 && x < width && y < height {
+				presenter.draw(x, y, color);
+			}
+		};
+		if let [Some(from), Some(to)] = projected.as_slice() {
+			for (x, y) in line(*from, *to) {
+				plot(x, y);
+			}
+		}
+		for (x, y) in projected.iter().flatten() {
+			for offset in -2..=2 {
+				plot(x + offset, *y);
+				plot(*x, y + offset);
+			}
+		}
+		let readout = match self.distance() {
+			Some(distance) => format!("dist {:.3}", distance),
+			None => format!("pick point {}", self.points.len() + 1),
+		};
+		presenter.draw_string(1, height - 9, &readout, Color { r: 255, g: 255, b: 255 });
+	}
+}
+
+impl Default for Measure {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+// Pixels from one end to the other, both included
+fn line(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+	let (dx, dy) = ((to.0 - from.0).abs(), -(to.1 - from.1).abs());
+	let (step_x, step_y) = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+	let (mut x, mut y, mut error) = (from.0, from.1, dx + dy);
+	let mut pixels = vec![(x, y)];
+	while (x, y) != to {
+		let twice = 2 * error;
+		if twice >= dy { error += dy; x += step_x }
+		if twice <= dx { error += dx; y += step_y }
+		pixels.push((x, y));
+	}
+	pixels
+}
+
+#[test]
+fn measures_between_surfaces() {
+	assert_eq!(line((0, 0), (3, 1)), [(0, 0), (1, 0), (2, 1), (3, 1)]);
+	assert_eq!(line((2, 5), (2, 3)), [(2, 5), (2, 4), (2, 3)]);
+
+	// Looking straight down on a ball
+	use crate::structs::{Camera, Material, Rot3, Sphere};
+	let material = Material { color: Color { r: 255, g: 255, b: 255 }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+	let scene = Scene::new(vec![Box::new(Sphere::new(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, 1.0, material))], Camera::new(Vec3 { x: 0.0, y: 0.0, z: 5.0 }, Rot3::new(), 40.0));
+	let mut measure = Measure::new();
+	assert!(!measure.pick(&scene, 0, 0, 160, 90) && measure.distance().is_none());
+	assert!(measure.pick(&scene, 80, 45, 160, 90) && measure.pick(&scene, 95, 45, 160, 90));
+	assert!(measure.points[0].dist(&Vec3 { x: 0.0, y: 0.0, z: 1.0 }) < 0.05);
+	let distance = measure.distance().unwrap();
+	assert!(distance > 0.1 && distance == measure.points[0].dist(&measure.points[1]));
+	// A third point starts a new measurement
+	assert!(measure.pick(&scene, 80, 45, 160, 90));
+	assert_eq!(measure.points.len(), 1);
+}
@@ -0,0 +1,241 @@
+//
+//	Participating Media
+//
+
+// Spheres and closed meshes can be filled with something light travels through rather than bounces
+// off, water or fog, which dims what's seen through it and glows with the light it scatters towards
+// the eye. Their surface is only where the medium starts, no ray sees it. Camera rays keep an
+// interior list, the volumes they're inside, starting from the ones the camera is in; where volumes
+// overlap the one with the highest priority fills the overlap, the one entered last on a tie. Only
+// camera rays go through media, shadow and reflection rays pass straight through. In a scene file:
+//
+//	volumes: [ (target: "sea", absorption: (0.45, 0.09, 0.06), scatter: (0.02, 0.05, 0.06), priority: 1) ]
+//
+// with both coefficients per unit of the file's length, for red, green and blue
+
+use crate::structs::{Color, Ray, Scene, SceneObject, Sphere, Vec3};
+
+const MAX_CROSSINGS: usize = 64; // per volume along one ray, in case a mesh isn't as closed as it says
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Medium {
+	pub absorption: [f64; 3], // per meter, red green blue
+	pub scatter: [f64; 3],
+}
+
+impl Medium {
+	// Fraction of each channel that makes it through distance meters of it
+	pub fn transmittance(&self, distance: f64) -> [f64; 3] {
+		[0, 1, 2].map(|c| (-(self.absorption[c] + self.scatter[c]) * distance).exp())
+	}
+
+	// What leaves distance meters of it towards the eye with behind going in at the far end, lit
+	// evenly by light along the way
+	pub fn through(&self, behind: [f64; 3], distance: f64, light: [f64; 3]) -> [f64; 3] {
+		let transmittance = self.transmittance(distance);
+		[0, 1, 2].map(|c| {
+			let extinction = self.absorption[c] + self.scatter[c];
+			let scattered = if extinction > 0.0 { light[c] * self.scatter[c] / extinction * (1.0 - transmittance[c]) } else { 0.0 };
+			behind[c] * transmittance[c] + scattered
+		})
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Volume {
+	pub target: String, // id of the sphere or closed mesh it fills
+	pub medium: Medium,
+	pub priority: i32,
+}
+
+// Volumes a ray is inside, by index into the scene's, in the order it went into them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Interior {
+	inside: Vec<usize>,
+}
+
+impl Interior {
+	// In if it was out, out if it was in
+	pub fn cross(&mut self, volume: usize) {
+		match self.inside.iter().position(|inside| *inside == volume) {
+			Some(at) => { self.inside.remove(at); }
+			None => self.inside.push(volume),
+		}
+	}
+
+	// The medium filling where the ray is now
+	pub fn medium<'a>(&self, volumes: &'a [Volume]) -> Option<&'a Medium> {
+		self.inside.iter().max_by_key(|volume| volumes[**volume].priority).map(|volume| &volumes[*volume].medium)
+	}
+}
+
+// Distances along ray to where it crosses object's surface, nearest first, out to reach. Spheres are
+// worked out directly since their ray_hit doesn't see them from inside
+pub fn crossings(object: &dyn SceneObject, ray: &Ray, reach: f64) -> Vec<f64> {
+	let length_squared = Vec3::dot(&ray.direction, &ray.direction);
+	if let Some(sphere) = object.as_any_immut().downcast_ref::<Sphere>() {
+		let oc = ray.origin.sub(&sphere.center);
+		let half_b = Vec3::dot(&oc, &ray.direction);
+		let discriminant = half_b * half_b - length_squared * (Vec3::dot(&oc, &oc) - (sphere.radius as f64).powi(2));
+		if discriminant <= 0.0 { return Vec::new() }
+		let root = discriminant.sqrt();
+		return [(-half_b - root) / length_squared, (-half_b + root) / length_squared].iter().copied().filter(|t| *t > 0.0 && *t < reach).collect();
+	}
+	// Anything else one surface at a time, each ray_hit starting from the last one
+	let mut found = Vec::new();
+	let mut from = ray.origin;
+	while found.len() < MAX_CROSSINGS {
+		let next = Ray { origin: from, direction: ray.direction, mask: ray.mask, differential: None };
+		let (point, _, _) = match object.ray_hit(&next) {
+			Some(hit) => hit,
+			None => break,
+		};
+		let t = Vec3::dot(&point.sub(&ray.origin), &ray.direction) / length_squared;
+		if t >= reach { break }
+		found.push(t);
+		from = point;
+	}
+	found
+}
+
+impl Scene {
+	// Works out which volumes the camera is in once for all of its rays, see update_culling
+	pub fn update_media(&mut self) {
+		let pos = self.current_camera.pos;
+		self.camera_interior = if self.volumes.is_empty() { None } else { Some((pos, self.interior_at(&pos))) };
+	}
+
+	// Volumes point is in, going by whether a ray from it crosses their surface an odd number of times
+	pub fn interior_at(&self, point: &Vec3) -> Interior {
+		// Skewed so it's unlikely to run along an edge
+		let ray = Ray::from_to(point, &point.add(&Vec3 { x: 0.267, y: 0.535, z: 0.802 }));
+		let mut interior = Interior::default();
+		for (index, volume) in self.volumes.iter().enumerate() {
+			if let Some(object) = self.objects.iter().find(|object| *object.get_id() == volume.target) {
+				if crossings(object.as_ref(), &ray, f64::INFINITY).len() % 2 == 1 {
+					interior.cross(index);
+				}
+			}
+		}
+		interior
+	}
+
+	// color, what a camera ray hit at distance along it or None when it escaped, as seen through the
+	// media on the way there. Escaping rays that went through any come back with what they scattered
+	pub fn through_media(&self, ray: &Ray, distance: Option<f64>, color: Option<Color>) -> Option<Color> {
+		if self.volumes.is_empty() { return color }
+		let reach = distance.unwrap_or(f64::INFINITY);
+		let mut events: Vec<(f64, usize)> = Vec::new();
+		for (index, volume) in self.volumes.iter().enumerate() {
+			if let Some(object) = self.objects.iter().find(|object| *object.get_id() == volume.target) {
+				events.extend(crossings(object.as_ref(), ray, reach).into_iter().map(|t| (t, index)));
+			}
+		}
+		events.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+		let mut interior = match &self.camera_interior {
+			Some((at, interior)) if at.dist(&ray.origin) == 0.0 => interior.clone(),
+			_ => self.interior_at(&ray.origin),
+		};
+		// Stretches of the ray and what fills them, near to far
+		let mut segments = Vec::new();
+		let mut start = 0.0;
+		for (t, volume) in events.into_iter().chain(std::iter::once((reach, usize::MAX))) {
+			if let Some(medium) = interior.medium(&self.volumes) {
+				segments.push((start, t, *medium));
+			}
+			if volume != usize::MAX {
+				interior.cross(volume);
+			}
+			start = t;
+		}
+		if segments.is_empty() { return color }
+		// Then from the far end back to the eye. Past the last surface there's nothing to light
+		let mut seen = color.map_or([0.0; 3], |color| [color.r as f64, color.g as f64, color.b as f64]);
+		let length = Vec3::dot(&ray.direction, &ray.direction).sqrt();
+		for (start, end, medium) in segments.iter().rev().filter(|(_, end, _)| end.is_finite()) {
+			let middle = ray.origin.add(&ray.direction.mul((start + end) * 0.5));
+			seen = medium.through(seen, (end - start) * length, self.light_at(&middle));
+		}
+		let channel = |value: f64| value.round().clamp(0.0, 255.0) as u8;
+		Some(Color { r: channel(seen[0]), g: channel(seen[1]), b: channel(seen[2]) })
+	}
+
+	// Light reaching point from every light source, shadows left out
+	fn light_at(&self, point: &Vec3) -> [f64; 3] {
+		let exposure = 2f64.powf(self.render_settings.exposure);
+		let mut light = [0.0; 3];
+		for ls in self.light_sources() {
+			let (strength, color) = ls.emission(point);
+			let luminosity = strength * ls.intensity as f64 * exposure / (point.dist(&ls.pos) * point.dist(&ls.pos));
+			light = [light[0] + color.r as f64 * luminosity, light[1] + color.g as f64 * luminosity, light[2] + color.b as f64 * luminosity];
+		}
+		light
+	}
+}
+
+#[test]
+fn cameras_under_water_see_through_it() {
+	use crate::structs::{Camera, LightSource, Material, Rot3};
+	let white = Material { color: Color { r: 255, g: 255, b: 255 }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+	let water = Medium { absorption: [0.6, 0.1, 0.05], scatter: [0.0, 0.02, 0.03] };
+	let origin = Vec3 { x: 0.0, y: 0.0, z: 0.0 };
+	let scene = |volumes: Vec<Volume>| {
+		let mut camera = Camera::new(Vec3 { x: 0.0, y: -5.0, z: 0.0 }, Rot3::new(), 40.0);
+		camera.look_at(&origin, &Vec3 { x: 0.0, y: 0.0, z: 1.0 });
+		let mut sea = Sphere::new(origin, 20.0, white);
+		sea.id = String::from("sea");
+		let mut bubble = Sphere::new(camera.pos, 0.5, white);
+		bubble.id = String::from("bubble");
+		let ball = Sphere::new(origin, 1.5, white);
+		let light = LightSource::new(Vec3 { x: 0.0, y: -4.0, z: 3.0 }, Rot3::new(), 20.0);
+		let mut scene = Scene::new(vec![Box::new(light), Box::new(ball), Box::new(sea), Box::new(bubble)], camera);
+		for volume in volumes.iter() {
+			let object = scene.find_object(&volume.target).unwrap();
+			object.set_mask(object.get_mask().with_kinds(0));
+		}
+		scene.volumes = volumes;
+		scene
+	};
+	let sea = Volume { target: String::from("sea"), medium: water, priority: 0 };
+	// Air inside the bubble takes over from the water around it
+	let air = Volume { target: String::from("bubble"), medium: Medium { absorption: [0.0; 3], scatter: [0.0; 3] }, priority: 1 };
+	let mut dry = scene(Vec::new());
+	let mut wet = scene(vec![sea.clone()]);
+	let mut bubble = scene(vec![sea, air]);
+	// The camera sits in the middle of the bubble, both spheres around it
+	assert_eq!(wet.interior_at(&wet.current_camera.pos), Interior { inside: vec![0] });
+	assert_eq!(bubble.interior_at(&bubble.current_camera.pos), Interior { inside: vec![0, 1] });
+	assert_eq!(bubble.interior_at(&Vec3 { x: 0.0, y: 0.0, z: 30.0 }), Interior::default());
+	let (dry, wet, bubble) = (dry.render(24, 16), wet.render(24, 16), bubble.render(24, 16));
+	let total = |image: &[Color], channel: fn(&Color) -> u8| image.iter().map(|color| channel(color) as u64).sum::<u64>();
+	// Red goes first, and nothing under water is as bright as in the air
+	assert!(total(&wet, |color| color.r) < total(&dry, |color| color.r) / 2);
+	assert!(total(&wet, |color| color.b) > total(&wet, |color| color.r));
+	// Looking out of the bubble the ball is still further away than the air in it
+	assert!(total(&bubble, |color| color.r) < total(&dry, |color| color.r));
+	assert!(total(&bubble, |color| color.r) > total(&wet, |color| color.r));
+	// Nearer things keep more of their color
+	let medium = Medium { absorption: [0.5; 3], scatter: [0.0; 3] };
+	assert!(medium.through([200.0; 3], 1.0, [0.0; 3])[0] > medium.through([200.0; 3], 2.0, [0.0; 3])[0]);
+	assert_eq!(Medium { absorption: [0.0; 3], scatter: [1.0; 3] }.through([0.0; 3], f64::MAX, [50.0; 3]), [50.0; 3]);
+
+	// From a scene file in centimeters, coefficients come out per meter
+	let dir = std::env::temp_dir().join(format!("media_test_{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	std::fs::write(dir.join("pool.ron"), r#"(
+		units: Centimeters,
+		camera: Some((pos: (0.0, -500.0, 0.0), look_at: Some((0.0, 0.0, 0.0)), fov: 40.0)),
+		materials: { "white": () },
+		objects: [ Sphere(id: Some("pool"), pos: (0.0, 0.0, 0.0), radius: 2000.0, material: "white"), Light(pos: (0.0, -400.0, 300.0), intensity: 20.0) ],
+		volumes: [ (target: "pool", absorption: (0.006, 0.001, 0.0005)) ],
+	)"#).unwrap();
+	let mut pool = crate::scene_file::load_scene(&dir.join("pool.ron")).unwrap();
+	std::fs::write(dir.join("bad.ron"), r#"(camera: Some((pos: (0.0, 0.0, 0.0), fov: 40.0)), volumes: [ (target: "nothing", absorption: (1.0, 1.0, 1.0)) ])"#).unwrap();
+	assert!(crate::scene_file::load_scene(&dir.join("bad.ron")).is_err());
+	std::fs::remove_dir_all(&dir).unwrap();
+	assert!((pool.volumes[0].medium.absorption[0] - 0.6).abs() < 1e-12 && pool.volumes[0].medium.scatter == [0.0; 3]);
+	assert_eq!(pool.find_object("pool").unwrap().get_mask().kinds, 0);
+	assert_eq!(crate::scene_file::SceneFile::from_scene(&pool).volumes[0].target, "pool");
+	pool.update_media();
+	assert_eq!(pool.camera_interior.as_ref().map(|(_, interior)| interior.clone()), Some(Interior { inside: vec![0] }));
+}
@@ -0,0 +1,172 @@
+//
+//	minifb Frontend
+//
+
+use minifb::{KeyRepeat, MouseButton, MouseMode, Scale, Window, WindowOptions};
+use raytracing_engine::structs::Color;
+use raytracing_engine::input::{ButtonState, Key};
+use raytracing_engine::frontend::{InputSource, Presenter};
+use std::time::Instant;
+use crate::ExampleProgram;
+
+fn minifb_key(key: Key) -> minifb::Key {
+	match key {
+		Key::A => minifb::Key::A,
+		Key::B => minifb::Key::B,
+		Key::C => minifb::Key::C,
+		Key::D => minifb::Key::D,
+		Key::E => minifb::Key::E,
+		Key::F => minifb::Key::F,
+		Key::G => minifb::Key::G,
+		Key::H => minifb::Key::H,
+		Key::I => minifb::Key::I,
+		Key::J => minifb::Key::J,
+		Key::K => minifb::Key::K,
+		Key::L => minifb::Key::L,
+		Key::M => minifb::Key::M,
+		Key::N => minifb::Key::N,
+		Key::O => minifb::Key::O,
+		Key::P => minifb::Key::P,
+		Key::Q => minifb::Key::Q,
+		Key::R => minifb::Key::R,
+		Key::S => minifb::Key::S,
+		Key::T => minifb::Key::T,
+		Key::U => minifb::Key::U,
+		Key::V => minifb::Key::V,
+		Key::W => minifb::Key::W,
+		Key::X => minifb::Key::X,
+		Key::Y => minifb::Key::Y,
+		Key::Z => minifb::Key::Z,
+		Key::K0 => minifb::Key::Key0,
+		Key::K1 => minifb::Key::Key1,
+		Key::K2 => minifb::Key::Key2,
+		Key::K3 => minifb::Key::Key3,
+		Key::K4 => minifb::Key::Key4,
+		Key::K5 => minifb::Key::Key5,
+		Key::K6 => minifb::Key::Key6,
+		Key::K7 => minifb::Key::Key7,
+		Key::K8 => minifb::Key::Key8,
+		Key::K9 => minifb::Key::Key9,
+		Key::F1 => minifb::Key::F1,
+		Key::F2 => minifb::Key::F2,
+		Key::F3 => minifb::Key::F3,
+		Key::F4 => minifb::Key::F4,
+		Key::F5 => minifb::Key::F5,
+		Key::F6 => minifb::Key::F6,
+		Key::F7 => minifb::Key::F7,
+		Key::F8 => minifb::Key::F8,
+		Key::F9 => minifb::Key::F9,
+		Key::F10 => minifb::Key::F10,
+		Key::F11 => minifb::Key::F11,
+		Key::F12 => minifb::Key::F12,
+		Key::Up => minifb::Key::Up,
+		Key::Down => minifb::Key::Down,
+		Key::Left => minifb::Key::Left,
+		Key::Right => minifb::Key::Right,
+		Key::Space => minifb::Key::Space,
+		Key::Tab => minifb::Key::Tab,
+		Key::Shift => minifb::Key::LeftShift,
+		Key::Ctrl => minifb::Key::LeftCtrl,
+		Key::Insert => minifb::Key::Insert,
+		Key::Delete => minifb::Key::Delete,
+		Key::Home => minifb::Key::Home,
+		Key::End => minifb::Key::End,
+		Key::PageUp => minifb::Key::PageUp,
+		Key::PageDown => minifb::Key::PageDown,
+		Key::Backspace => minifb::Key::Backspace,
+		Key::Escape => minifb::Key::Escape,
+		Key::Return => minifb::Key::Enter,
+		Key::Enter => minifb::Key::NumPadEnter,
+		Key::Pause => minifb::Key::Pause,
+		Key::ScrollLock => minifb::Key::ScrollLock,
+		Key::Numpad0 => minifb::Key::NumPad0,
+		Key::Numpad1 => minifb::Key::NumPad1,
+		Key::Numpad2 => minifb::Key::NumPad2,
+		Key::Numpad3 => minifb::Key::NumPad3,
+		Key::Numpad4 => minifb::Key::NumPad4,
+		Key::Numpad5 => minifb::Key::NumPad5,
+		Key::Numpad6 => minifb::Key::NumPad6,
+		Key::Numpad7 => minifb::Key::NumPad7,
+		Key::Numpad8 => minifb::Key::NumPad8,
+		Key::Numpad9 => minifb::Key::NumPad9,
+		Key::NumpadMul => minifb::Key::NumPadAsterisk,
+		Key::NumpadDiv => minifb::Key::NumPadSlash,
+		Key::NumpadAdd => minifb::Key::NumPadPlus,
+		Key::NumpadSub => minifb::Key::NumPadMinus,
+		Key::NumpadDecimal => minifb::Key::NumPadDot,
+		Key::Period => minifb::Key::Period,
+	}
+}
+
+struct MinifbInput<'a> {
+	window: &'a Window,
+	mouse_down: [bool; 3],
+	last_mouse_down: [bool; 3],
+}
+
+impl InputSource for MinifbInput<'_> {
+	fn key_state(&self, key: Key) -> ButtonState {
+		let key = minifb_key(key);
+		ButtonState {
+			pressed: self.window.is_key_pressed(key, KeyRepeat::No),
+			held: self.window.is_key_down(key),
+			released: self.window.is_key_released(key),
+		}
+	}
+
+	fn mouse_position(&self) -> (i32, i32) {
+		self.window.get_mouse_pos(MouseMode::Clamp).map(|(x, y)| (x as i32, y as i32)).unwrap_or((0, 0))
+	}
+
+	fn mouse_button(&self, button: u32) -> ButtonState {
+		let index = (button as usize).min(2);
+		let (down, was_down) = (self.mouse_down[index], self.last_mouse_down[index]);
+		ButtonState { pressed: down && !was_down, held: down, released: !down && was_down }
+	}
+
+	fn mouse_wheel(&self) -> i32 {
+		self.window.get_scroll_wheel().map(|(_, y)| y.signum() as i32).unwrap_or(0)
+	}
+}
+
+struct MinifbPresenter {
+	buffer: Vec<u32>,
+	width: usize,
+	height: usize,
+}
+
+impl Presenter for MinifbPresenter {
+	fn draw(&mut self, x: i32, y: i32, color: Color) {
+		if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height { return }
+		self.buffer[y as usize * self.width + x as usize] = ((color.r as u32) << 16) | ((color.g as u32) << 8) | color.b as u32;
+	}
+}
+
+pub fn run(program: &mut ExampleProgram, width: usize, height: usize) {
+	let options = WindowOptions { scale: Scale::X4, ..WindowOptions::default() };
+	let mut window = match Window::new("Raytracing", width, height, options) {
+		Ok(window) => window,
+		Err(e) => { eprintln!("Could not open window: {}", e); return }
+	};
+	window.set_target_fps(60);
+
+	let mut presenter = MinifbPresenter { buffer: vec![0; width * height], width, height };
+	let mut last_mouse_down = [false; 3];
+	let mut last_frame = Instant::now();
+	while window.is_open() && !window.is_key_down(minifb::Key::Escape) {
+		let mouse_down = [
+			window.get_mouse_down(MouseButton::Left),
+			window.get_mouse_down(MouseButton::Right),
+			window.get_mouse_down(MouseButton::Middle),
+		];
+		let elapsed_time = last_frame.elapsed().as_secs_f32();
+		last_frame = Instant::now();
+		let input = MinifbInput { window: &window, mouse_down, last_mouse_down };
+		program.update(&mut presenter, &input, elapsed_time);
+		last_mouse_down = mouse_down;
+		if let Err(e) = window.update_with_buffer(&presenter.buffer, width, height) {
+			eprintln!("Could not present frame: {}", e);
+			return;
+		}
+	}
+}
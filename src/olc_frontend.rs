@@ -0,0 +1,159 @@
+//
+//	olc::PixelGameEngine Frontend
+//
+
+use olc_pixel_game_engine as olc;
+use raytracing_engine::structs::Color;
+use raytracing_engine::input::{ButtonState, Key};
+use raytracing_engine::frontend::{InputSource, Presenter};
+use crate::ExampleProgram;
+
+fn olc_key(key: Key) -> olc::Key {
+	match key {
+		Key::A => olc::Key::A,
+		Key::B => olc::Key::B,
+		Key::C => olc::Key::C,
+		Key::D => olc::Key::D,
+		Key::E => olc::Key::E,
+		Key::F => olc::Key::F,
+		Key::G => olc::Key::G,
+		Key::H => olc::Key::H,
+		Key::I => olc::Key::I,
+		Key::J => olc::Key::J,
+		Key::K => olc::Key::K,
+		Key::L => olc::Key::L,
+		Key::M => olc::Key::M,
+		Key::N => olc::Key::N,
+		Key::O => olc::Key::O,
+		Key::P => olc::Key::P,
+		Key::Q => olc::Key::Q,
+		Key::R => olc::Key::R,
+		Key::S => olc::Key::S,
+		Key::T => olc::Key::T,
+		Key::U => olc::Key::U,
+		Key::V => olc::Key::V,
+		Key::W => olc::Key::W,
+		Key::X => olc::Key::X,
+		Key::Y => olc::Key::Y,
+		Key::Z => olc::Key::Z,
+		Key::K0 => olc::Key::K0,
+		Key::K1 => olc::Key::K1,
+		Key::K2 => olc::Key::K2,
+		Key::K3 => olc::Key::K3,
+		Key::K4 => olc::Key::K4,
+		Key::K5 => olc::Key::K5,
+		Key::K6 => olc::Key::K6,
+		Key::K7 => olc::Key::K7,
+		Key::K8 => olc::Key::K8,
+		Key::K9 => olc::Key::K9,
+		Key::F1 => olc::Key::F1,
+		Key::F2 => olc::Key::F2,
+		Key::F3 => olc::Key::F3,
+		Key::F4 => olc::Key::F4,
+		Key::F5 => olc::Key::F5,
+		Key::F6 => olc::Key::F6,
+		Key::F7 => olc::Key::F7,
+		Key::F8 => olc::Key::F8,
+		Key::F9 => olc::Key::F9,
+		Key::F10 => olc::Key::F10,
+		Key::F11 => olc::Key::F11,
+		Key::F12 => olc::Key::F12,
+		Key::Up => olc::Key::UP,
+		Key::Down => olc::Key::DOWN,
+		Key::Left => olc::Key::LEFT,
+		Key::Right => olc::Key::RIGHT,
+		Key::Space => olc::Key::SPACE,
+		Key::Tab => olc::Key::TAB,
+		Key::Shift => olc::Key::SHIFT,
+		Key::Ctrl => olc::Key::CTRL,
+		Key::Insert => olc::Key::INS,
+		Key::Delete => olc::Key::DEL,
+		Key::Home => olc::Key::HOME,
+		Key::End => olc::Key::END,
+		Key::PageUp => olc::Key::PGUP,
+		Key::PageDown => olc::Key::PGDN,
+		Key::Backspace => olc::Key::BACK,
+		Key::Escape => olc::Key::ESCAPE,
+		Key::Return => olc::Key::RETURN,
+		Key::Enter => olc::Key::ENTER,
+		Key::Pause => olc::Key::PAUSE,
+		Key::ScrollLock => olc::Key::SCROLL,
+		Key::Numpad0 => olc::Key::NP0,
+		Key::Numpad1 => olc::Key::NP1,
+		Key::Numpad2 => olc::Key::NP2,
+		Key::Numpad3 => olc::Key::NP3,
+		Key::Numpad4 => olc::Key::NP4,
+		Key::Numpad5 => olc::Key::NP5,
+		Key::Numpad6 => olc::Key::NP6,
+		Key::Numpad7 => olc::Key::NP7,
+		Key::Numpad8 => olc::Key::NP8,
+		Key::Numpad9 => olc::Key::NP9,
+		Key::NumpadMul => olc::Key::NP_MUL,
+		Key::NumpadDiv => olc::Key::NP_DIV,
+		Key::NumpadAdd => olc::Key::NP_ADD,
+		Key::NumpadSub => olc::Key::NP_SUB,
+		Key::NumpadDecimal => olc::Key::NP_DECIMAL,
+		Key::Period => olc::Key::PERIOD,
+	}
+}
+
+fn button_state(button: olc::HWButton) -> ButtonState {
+	ButtonState { pressed: button.pressed, held: button.held, released: button.released }
+}
+
+struct OlcInput;
+
+impl InputSource for OlcInput {
+	fn key_state(&self, key: Key) -> ButtonState {
+		button_state(olc::get_key(olc_key(key)))
+	}
+
+	fn mouse_position(&self) -> (i32, i32) {
+		(olc::get_mouse_x(), olc::get_mouse_y())
+	}
+
+	fn mouse_button(&self, button: u32) -> ButtonState {
+		button_state(olc::get_mouse(button))
+	}
+
+	fn mouse_wheel(&self) -> i32 {
+		olc::get_mouse_wheel()
+	}
+}
+
+struct OlcPresenter;
+
+impl Presenter for OlcPresenter {
+	fn draw(&mut self, x: i32, y: i32, color: Color) {
+		olc::draw(x, y, olc::Pixel { r: color.r, g: color.g, b: color.b, a: 255 });
+	}
+
+	fn draw_string(&mut self, x: i32, y: i32, text: &str, color: Color) {
+		let _ = olc::draw_string(x, y, text, olc::Pixel { r: color.r, g: color.g, b: color.b, a: 255 });
+	}
+}
+
+struct OlcApp<'a> {
+	program: &'a mut ExampleProgram,
+}
+
+impl olc::Application for OlcApp<'_> {
+	fn on_user_create(&mut self) -> Result<(), olc::Error> {
+		olc::clear(olc::BLACK);
+		Ok(())
+	}
+
+	fn on_user_update(&mut self, elapsed_time: f32) -> Result<(), olc::Error> {
+		self.program.update(&mut OlcPresenter, &OlcInput, elapsed_time);
+		Ok(())
+	}
+
+	fn on_user_destroy(&mut self) -> Result<(), olc::Error> {
+		Ok(())
+	}
+}
+
+pub fn run(program: &mut ExampleProgram, width: i32, height: i32) {
+	let mut app = OlcApp { program };
+	olc::start("Raytracing", &mut app, width, height, 1, 1).unwrap();
+}
@@ -0,0 +1,238 @@
+//
+//	Scene Outline
+//
+
+// The objects of a scene as a tree with their transforms and materials, for finding your way around
+// an imported scene without a GUI. Ids scoped by references, like "house/door/handle", nest under
+// the references they came from
+
+use crate::point_cloud::PointCloud;
+use crate::structs::{LightSource, Material, Mesh, Rot3, Scene, SceneObject, Sphere, Vec3};
+use crate::voxel::VoxelGrid;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutlineFormat {
+	Text, // indented, a line per node
+	Json,
+	Dot, // GraphViz
+}
+
+impl OutlineFormat {
+	pub fn from_name(name: &str) -> Option<OutlineFormat> {
+		match name {
+			"text" => Some(OutlineFormat::Text),
+			"json" => Some(OutlineFormat::Json),
+			"dot" => Some(OutlineFormat::Dot),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutlineNode {
+	pub name: String, // last part of the id
+	pub id: String, // empty for the root and for references that only hold other objects
+	pub kind: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub pos: Option<[f64; 3]>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub rot: Option<[f64; 3]>, // yaw, pitch, roll in degrees
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub materials: Vec<String>,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub notes: Vec<String>, // anything else worth knowing, "animated", "12 triangles"
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub children: Vec<OutlineNode>,
+}
+
+impl OutlineNode {
+	fn group(name: &str, kind: &str) -> Self {
+		OutlineNode { name: name.to_string(), id: String::new(), kind: kind.to_string(), pos: None, rot: None, materials: Vec::new(), notes: Vec::new(), children: Vec::new() }
+	}
+
+	fn child(&mut self, name: &str) -> &mut OutlineNode {
+		let index = match self.children.iter().position(|child| child.name == name) {
+			Some(index) => index,
+			None => {
+				self.children.push(OutlineNode::group(name, "Reference"));
+				self.children.len() - 1
+			}
+		};
+		&mut self.children[index]
+	}
+
+	fn text(&self, depth: usize, out: &mut String) {
+		out.push_str(&"  ".repeat(depth));
+		out.push_str(&self.name);
+		out.push_str(&format!(" [{}]", self.kind));
+		if let (Some(pos), Some(rot)) = (self.pos, self.rot) {
+			out.push_str(&format!(" at ({:.3}, {:.3}, {:.3}) rot ({:.1}, {:.1}, {:.1})", pos[0], pos[1], pos[2], rot[0], rot[1], rot[2]));
+		}
+		if !self.materials.is_empty() {
+			out.push_str(&format!(" materials {}", self.materials.join(" ")));
+		}
+		if !self.notes.is_empty() {
+			out.push_str(&format!(" ({})", self.notes.join(", ")));
+		}
+		out.push('\n');
+		for child in self.children.iter() {
+			child.text(depth + 1, out);
+		}
+	}
+
+	// Nodes are named by their path from the root, so same named objects under different references stay apart
+	fn dot(&self, path: &str, out: &mut String) {
+		let mut label = format!("{}\\n{}", escape(&self.name), self.kind);
+		if let Some(pos) = self.pos {
+			label.push_str(&format!("\\n({:.2}, {:.2}, {:.2})", pos[0], pos[1], pos[2]));
+		}
+		for line in self.materials.iter().chain(self.notes.iter()) {
+			label.push_str(&format!("\\n{}", escape(line)));
+		}
+		out.push_str(&format!("\t\"{}\" [label=\"{}\"];\n", escape(path), label));
+		for child in self.children.iter() {
+			let child_path = format!("{}/{}", path, child.name);
+			out.push_str(&format!("\t\"{}\" -> \"{}\";\n", escape(path), escape(&child_path)));
+			child.dot(&child_path, out);
+		}
+	}
+}
+
+impl Scene {
+	// The scene as a tree: the camera, then every object under the references it came from, in scene order
+	pub fn outline(&self) -> OutlineNode {
+		let mut root = OutlineNode::group("scene", "Scene");
+		let camera = &self.current_camera;
+		root.children.push(OutlineNode {
+			notes: vec![format!("fov {}", camera.fov)],
+			..placed("camera", &camera.id, "Camera", &camera.pos, &camera.rot)
+		});
+		for object in self.objects.iter() {
+			let id = object.get_id();
+			let mut parts: Vec<&str> = id.split('/').collect();
+			let name = parts.pop().unwrap_or("");
+			let parent = parts.iter().fold(&mut root, |node, part| node.child(part));
+			let mut node = describe(object.as_ref(), name);
+			if self.animations.iter().any(|animation| animation.target == *id) {
+				node.notes.push(String::from("animated"));
+			}
+			if self.physics.as_ref().is_some_and(|physics| physics.bodies.iter().any(|body| body.target == *id)) {
+				node.notes.push(String::from("rigid body"));
+			}
+			// An object whose id a reference also uses takes over the reference's node
+			match parent.children.iter_mut().find(|child| child.name == name && child.id.is_empty()) {
+				Some(existing) => {
+					node.children = std::mem::take(&mut existing.children);
+					*existing = node;
+				}
+				None => parent.children.push(node),
+			}
+		}
+		root
+	}
+
+	pub fn dump_hierarchy(&self, format: OutlineFormat) -> String {
+		let root = self.outline();
+		match format {
+			OutlineFormat::Text => {
+				let mut out = String::new();
+				root.text(0, &mut out);
+				out
+			}
+			OutlineFormat::Json => serde_json::to_string_pretty(&root).unwrap_or_default() + "\n",
+			OutlineFormat::Dot => {
+				let mut out = String::from("digraph scene {\n\tnode [shape=box];\n");
+				root.dot(&root.name, &mut out);
+				out.push_str("}\n");
+				out
+			}
+		}
+	}
+}
+
+fn placed(name: &str, id: &str, kind: &str, pos: &Vec3, rot: &Rot3) -> OutlineNode {
+	OutlineNode {
+		id: id.to_string(),
+		pos: Some([pos.x, pos.y, pos.z]),
+		rot: Some([rot.yaw.to_degrees(), rot.pitch.to_degrees(), rot.roll.to_degrees()]),
+		..OutlineNode::group(name, kind)
+	}
+}
+
+fn describe(object: &dyn SceneObject, name: &str) -> OutlineNode {
+	let any = object.as_any_immut();
+	let mut node = placed(name, object.get_id(), "Object", object.get_pos(), object.get_rot());
+	let (kind, materials, notes): (&str, Vec<Material>, Vec<String>) = if let Some(mesh) = any.downcast_ref::<Mesh>() {
+		let mut notes = vec![format!("{} triangles", mesh.triangle_count())];
+		if let Some(morph) = &mesh.morph {
+			notes.push(format!("{} morph targets", morph.targets.len()));
+		}
+		("Mesh", mesh.materials.clone(), notes)
+	} else if let Some(sphere) = any.downcast_ref::<Sphere>() {
+		("Sphere", vec![sphere.material], vec![format!("radius {}", sphere.radius)])
+	} else if let Some(light) = any.downcast_ref::<LightSource>() {
+		let mut notes = vec![format!("intensity {}", light.intensity), format!("color {}", hex(&light.color))];
+		if light.spot.is_some() { notes.push(String::from("spot")) }
+		("Light", Vec::new(), notes)
+	} else if let Some(grid) = any.downcast_ref::<VoxelGrid>() {
+		("Voxels", grid.palette.clone(), vec![format!("{}x{}x{} cells of {}", grid.dims[0], grid.dims[1], grid.dims[2], grid.voxel_size)])
+	} else if let Some(cloud) = any.downcast_ref::<PointCloud>() {
+		("Points", Vec::new(), vec![format!("{} points of radius {}", cloud.len(), cloud.radius)])
+	} else {
+		("Object", Vec::new(), Vec::new())
+	};
+	node.kind = kind.to_string();
+	node.notes = notes;
+	let mut seen: Vec<Material> = Vec::new();
+	for material in materials {
+		if !seen.contains(&material) {
+			seen.push(material);
+		}
+	}
+	node.materials = seen.iter().map(|material| {
+		let mut text = hex(&material.color);
+		if material.reflectivity != 0.0 { text.push_str(&format!(" reflective {}", material.reflectivity)) }
+		if material.transparency != 0.0 { text.push_str(&format!(" transparent {}", material.transparency)) }
+		text
+	}).collect();
+	node
+}
+
+fn hex(color: &crate::structs::Color) -> String {
+	format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+fn escape(text: &str) -> String {
+	text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[test]
+fn references_nest_their_objects() {
+	use crate::structs::{Camera, Color};
+	let material = Material { color: Color { r: 255, g: 0, b: 0 }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+	let mut wheel = Sphere::new(Vec3 { x: 1.0, y: 0.0, z: 0.0 }, 0.5, material);
+	wheel.id = String::from("garage/car/wheel");
+	let mut car = Sphere::new(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, 2.0, material);
+	car.id = String::from("garage/car");
+	let mut light = LightSource::new(Vec3 { x: 0.0, y: 0.0, z: 3.0 }, Rot3::new(), 1.0);
+	light.id = String::from("sun");
+	let scene = Scene::new(vec![Box::new(wheel), Box::new(car), Box::new(light)], Camera::new(Vec3 { x: 0.0, y: -5.0, z: 1.0 }, Rot3::new(), 40.0));
+
+	let root = scene.outline();
+	let names: Vec<&str> = root.children.iter().map(|child| child.name.as_str()).collect();
+	assert_eq!(names, ["camera", "garage", "sun"]);
+	// The car came after its wheel but still holds it
+	let garage = &root.children[1];
+	assert_eq!((garage.kind.as_str(), garage.id.as_str()), ("Reference", ""));
+	let car = &garage.children[0];
+	assert_eq!((car.kind.as_str(), car.materials.as_slice()), ("Sphere", &[String::from("#ff0000")][..]));
+	assert_eq!(car.children[0].id, "garage/car/wheel");
+
+	let text = scene.dump_hierarchy(OutlineFormat::Text);
+	assert!(text.lines().any(|line| line.starts_with("      wheel [Sphere] at (1.000, 0.000, 0.000)")), "{}", text);
+	let json: serde_json::Value = serde_json::from_str(&scene.dump_hierarchy(OutlineFormat::Json)).unwrap();
+	assert_eq!(json["children"][1]["children"][0]["children"][0]["name"], "wheel");
+	let dot = scene.dump_hierarchy(OutlineFormat::Dot);
+	assert!(dot.starts_with("digraph scene {") && dot.contains("\"scene/garage/car\" -> \"scene/garage/car/wheel\";"));
+}
@@ -0,0 +1,105 @@
+//
+//	Outliner
+//
+
+// The scene's objects as an indented list to step through from the keyboard, with the selected
+// object's transform above it. The selection is the cursor, so picking an object moves it too.
+// Hidden objects are greyed out like references
+
+use crate::frontend::Presenter;
+use crate::outline::OutlineNode;
+use crate::structs::{Color, Scene};
+
+const LINE_HEIGHT: i32 = 10;
+const COLUMNS: usize = 19; // characters that fit beside the edge of the viewport
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlinerRow {
+	pub depth: usize,
+	pub name: String,
+	pub kind: String,
+	pub id: String, // empty for references, which can't be selected
+}
+
+pub struct Outliner {
+	pub rows_shown: usize, // the list scrolls to keep the selection in view
+}
+
+impl Outliner {
+	pub fn new() -> Self {
+		Outliner { rows_shown: 5 }
+	}
+
+	// Everything in Scene::outline but the scene itself and the camera, depth first
+	pub fn rows(scene: &Scene) -> Vec<OutlinerRow> {
+		fn walk(node: &OutlineNode, depth: usize, rows: &mut Vec<OutlinerRow>) {
+			for child in node.children.iter().filter(|child| child.kind != "Camera") {
+				rows.push(OutlinerRow { depth, name: child.name.clone(), kind: child.kind.clone(), id: child.id.clone() });
+				walk(child, depth + 1, rows);
+			}
+		}
+		let mut rows = Vec::new();
+		walk(&scene.outline(), 0, &mut rows);
+		rows
+	}
+
+	// Id of the object steps rows on from selected, wrapping around and skipping references;
+	// from nothing selected a step down lands on the first object and a step up on the last
+	pub fn step(rows: &[OutlinerRow], selected: Option<&str>, steps: i32) -> Option<String> {
+		let objects: Vec<&OutlinerRow> = rows.iter().filter(|row| !row.id.is_empty()).collect();
+		if objects.is_empty() { return None }
+		let count = objects.len() as i32;
+		let current = selected.and_then(|id| objects.iter().position(|row| row.id == id));
+		let next = match current {
+			Some(index) => (index as i32 + steps).rem_euclid(count),
+			None if steps < 0 => (count + steps).rem_euclid(count),
+			None => (steps - 1).rem_euclid(count),
+		};
+		Some(objects[next as usize].id.clone())
+	}
+
+	// Transform lines then the list, from top down the left edge
+	pub fn draw(&self, presenter: &mut dyn Presenter, scene: &Scene, selected: Option<&str>, top: i32) {
+		let white = Color { r: 255, g: 255, b: 255 };
+		let mut y = top;
+		if let Some(object) = selected.and_then(|id| scene.objects.iter().find(|object| object.get_id() == id)) {
+			let (pos, rot) = (object.get_pos(), object.get_rot());
+			presenter.draw_string(1, y, &format!("pos {:.1} {:.1} {:.1}", pos.x, pos.y, pos.z), white);
+			presenter.draw_string(1, y + LINE_HEIGHT, &format!("rot {:.0} {:.0} {:.0}", rot.yaw.to_degrees(), rot.pitch.to_degrees(), rot.roll.to_degrees()), white);
+			y += 2 * LINE_HEIGHT;
+		}
+		let rows = Outliner::rows(scene);
+		let current = selected.and_then(|id| rows.iter().position(|row| row.id == id)).unwrap_or(0);
+		let first = current.saturating_sub(self.rows_shown / 2).min(rows.len().saturating_sub(self.rows_shown));
+		for (line, row) in rows.iter().enumerate().skip(first).take(self.rows_shown) {
+			let chosen = selected == Some(row.id.as_str());
+			let text: String = format!("{}{}{}", if chosen { ">" } else { " " }, " ".repeat(row.depth), row.name).chars().take(COLUMNS).collect();
+			let color = if chosen { Color { r: 255, g: 220, b: 80 } }
+				else if row.id.is_empty() || scene.hidden.iter().any(|(hidden, _)| *hidden == row.id) { Color { r: 140, g: 140, b: 140 } }
+				else { white };
+			presenter.draw_string(1, y + (line - first) as i32 * LINE_HEIGHT, &text, color);
+		}
+	}
+}
+
+impl Default for Outliner {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+#[test]
+fn stepping_skips_references() {
+	let row = |depth: usize, name: &str, id: &str| OutlinerRow { depth, name: name.to_string(), kind: String::from("Mesh"), id: id.to_string() };
+	let rows = vec![row(0, "floor", "floor"), row(0, "car", ""), row(1, "wheel", "car/wheel"), row(0, "sun", "sun")];
+	assert_eq!(Outliner::step(&rows, None, 1).as_deref(), Some("floor"));
+	assert_eq!(Outliner::step(&rows, None, -1).as_deref(), Some("sun"));
+	assert_eq!(Outliner::step(&rows, Some("floor"), 1).as_deref(), Some("car/wheel"));
+	assert_eq!(Outliner::step(&rows, Some("sun"), 1).as_deref(), Some("floor"));
+	assert_eq!(Outliner::step(&[], Some("sun"), 1), None);
+
+	let scene = Scene::default_scene();
+	let rows = Outliner::rows(&scene);
+	assert_eq!(rows.len(), scene.objects.len());
+	assert_eq!(rows[1].name, "fuckin' cube");
+}
@@ -0,0 +1,172 @@
+//
+//	Path Tracing
+//
+
+// Every sample follows one path from the camera. At each surface it's mirrored, sent through or
+// scattered diffusely, picked at random by the same Fresnel weights the direct integrator blends
+// by, diffuse bounces leaving in a cosine weighted direction. Diffuse surfaces along the way are lit
+// by a shadow ray to one light, picked in proportion to its intensity, so point lights that a bounce
+// could never hit by chance still count. Paths end after max_bounces bounces, or sooner by Russian
+// roulette once they carry little, and what leaves the scene sees the environment. Random numbers
+// come from the render's sampler and the camera segment goes through media, as with the direct
+// integrator. Noisy at few samples, but the only integrator with diffuse light bouncing from surface
+// to surface. Brightness is as transport.rs says
+
+use crate::render::Scratch;
+use crate::sampler::{sample_cosine_hemisphere, sample_disk, Sampler};
+use crate::structs::{Color, LightSource, ObjectHit, Ray, RayMask, Scene, Vec3};
+use crate::transport::{albedo, around, environment, facing, specular_bounce, Rgb, OFFSET};
+
+const ROULETTE_DEPTH: u32 = 3; // bounces before a path can be cut short
+
+// samples_per_pixel paths through pixel index of a width x height frame, from first_sample on,
+// averaged, with the fraction that hit something or the backplate as coverage
+pub fn trace_pixel(scene: &Scene, index: u64, width: i32, height: i32, first_sample: u32, scratch: &mut Scratch) -> (Color, u8) {
+	let (x, y) = (index as i32 % width, index as i32 / width);
+	let samples = scene.render_settings.samples_per_pixel.max(1);
+	let scale = 255.0 * 2f64.powf(scene.render_settings.exposure);
+	scratch.prepare(&scene.render_settings);
+	let sampler = &mut *scratch.sampler;
+	let mut sum = [0.0; 3];
+	let mut hits = 0;
+	for sample_index in first_sample..first_sample + samples {
+		sampler.start_pixel_sample(x as u32, y as u32, sample_index);
+		let jitter = sampler.next_2d();
+		let offset = if samples == 1 && first_sample == 0 { (0.5, 0.5) } else { jitter };
+		let ray = scene.primary_ray(x, y, offset, width, height);
+		let hit = scene.trace_primary_object(&ray);
+		let distance = hit.as_ref().map(|(_, hit)| hit.0.dist(&ray.origin) / Vec3::dot(&ray.direction, &ray.direction).sqrt());
+		// Clamped sample by sample, as the direct integrator's are
+		let color = hit.map(|hit| {
+			let channel = |channel: f64| (channel * scale).clamp(0.0, 255.0).round() as u8;
+			let [r, g, b] = radiance(scene, &ray, hit, sampler);
+			Color { r: channel(r), g: channel(g), b: channel(b) }
+		});
+		let color = match scene.through_media(&ray, distance, color).or_else(|| scene.backplate_color(x, y, offset, width, height)) {
+			Some(color) => color,
+			None => continue,
+		};
+		sum = [sum[0] + color.r as f64, sum[1] + color.g as f64, sum[2] + color.b as f64];
+		hits += 1;
+	}
+	let inv = 1.0 / samples as f64;
+	let channel = |channel: f64| (channel * inv).round() as u8;
+	(Color { r: channel(sum[0]), g: channel(sum[1]), b: channel(sum[2]) }, (hits as f64 * inv * 255.0).round() as u8)
+}
+
+// Light carried back along a camera ray from what it hit
+fn radiance(scene: &Scene, ray: &Ray, hit: ObjectHit, sampler: &mut dyn Sampler) -> Rgb {
+	let (mut ray, mut hit) = (Ray { differential: None, ..*ray }, hit);
+	let (mut throughput, mut sum) = ([1.0; 3], [0.0; 3]);
+	let mut depth = 0;
+	loop {
+		let (index, (point, material, normal)) = hit;
+		let incident = ray.direction.normalize();
+		let outside = facing(&normal, &incident);
+		let choice = sampler.next_1d();
+		let bounce = specular_bounce(&material, &point, &incident, &normal, choice, || sampler.next_2d());
+		let (origin, direction, kind) = if let Some((origin, direction)) = bounce {
+			(origin, direction, RayMask::REFLECTION)
+		} else {
+			let albedo = albedo(&material);
+			let object = &scene.objects[index];
+			let direct = direct_light(scene, object.get_id(), (&point, &object.shadow_origin(&ray, &point)), &outside, ray.mask.layers, sampler);
+			for channel in 0..3 {
+				sum[channel] += throughput[channel] * albedo[channel] * direct[channel];
+				throughput[channel] *= albedo[channel];
+			}
+			let (u, v) = sampler.next_2d();
+			let direction = around(&outside, sample_cosine_hemisphere(u, v));
+			(point.add(&outside.mul(OFFSET)), direction, RayMask::GI)
+		};
+
+		depth += 1;
+		if depth > scene.render_settings.max_bounces { break }
+		if depth > ROULETTE_DEPTH {
+			let survive = throughput[0].max(throughput[1]).max(throughput[2]).min(1.0);
+			if sampler.next_1d() >= survive { break }
+			throughput = throughput.map(|channel| channel / survive);
+		}
+		ray = Ray { origin, direction, mask: ray.mask.with_kinds(kind), differential: None };
+		hit = match scene.trace_object(&ray) {
+			Some(hit) => hit,
+			None => {
+				let seen = environment(scene, &direction);
+				(0..3).for_each(|channel| sum[channel] += throughput[channel] * seen[channel]);
+				break;
+			}
+		};
+	}
+	sum
+}
+
+// Light reaching point on a surface facing normal from one light picked in proportion to its
+// intensity, weighted by how likely it was picked so on average it's all of them together. The shadow
+// ray leaves from shadow_origin, see SceneObject::shadow_origin
+fn direct_light(scene: &Scene, id: &str, (point, shadow_origin): (&Vec3, &Vec3), normal: &Vec3, layers: u32, sampler: &mut dyn Sampler) -> Rgb {
+	let lights = || scene.light_sources().filter(|light| light.intensity > 0.0 && light.illuminates(id));
+	let total: f64 = lights().map(|light| light.intensity as f64).sum();
+	if total <= 0.0 { return [0.0; 3] }
+	let mut pick = sampler.next_1d() * total;
+	let light: &LightSource = match lights().find(|light| { pick -= light.intensity as f64; pick <= 0.0 }).or_else(|| lights().last()) {
+		Some(light) => light,
+		None => return [0.0; 3],
+	};
+
+	// Somewhere on the disk a spherical light presents towards the point
+	let to_center = light.pos.sub(point).normalize();
+	let (tangent, bitangent) = to_center.orthonormal_basis();
+	let (u, v) = sampler.next_2d();
+	let (dx, dy) = sample_disk(u, v);
+	let target = light.pos.add(&tangent.mul(dx * light.radius as f64)).add(&bitangent.mul(dy * light.radius as f64));
+	let distance = target.dist(point);
+	let direction = target.sub(point).mul(1.0 / distance);
+	let cos = Vec3::dot(normal, &direction);
+	if cos <= 0.0 { return [0.0; 3] }
+	let origin = shadow_origin.add(&normal.mul(OFFSET));
+	let shadow_ray = Ray::from_to(&origin, &target).with_mask(RayMask::new(RayMask::SHADOW, layers));
+	if scene.trace(&shadow_ray).is_some_and(|hit| hit.0.dist(&origin) < target.dist(&origin)) { return [0.0; 3] }
+
+	// The light's own intensity cancels against the chance of picking it
+	let (strength, color) = light.emission(point);
+	let light = total * strength * cos / (distance * distance);
+	[light * color.r as f64 / 255.0, light * color.g as f64 / 255.0, light * color.b as f64 / 255.0]
+}
+
+#[test]
+fn diffuse_light_bounces_between_surfaces() {
+	use crate::render::Integrator;
+	use crate::structs::{Camera, Material, Mesh, Rot3, SceneObject, Tri};
+	let quad = |a: Vec3, b: Vec3, c: Vec3, d: Vec3, mat: Material| Mesh::new(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, Rot3::new(), vec![Tri { a, b, c, mat }, Tri { a, b: c, c: d, mat }]);
+	let gray = Material { color: Color { r: 128, g: 128, b: 128 }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+	let red = Material { color: Color { r: 255, g: 0, b: 0 }, ..gray };
+	// A gray floor lit from above, a red wall beside it the light can't reach the far side of
+	let floor = quad(Vec3 { x: -20.0, y: -20.0, z: 0.0 }, Vec3 { x: 20.0, y: -20.0, z: 0.0 }, Vec3 { x: 20.0, y: 20.0, z: 0.0 }, Vec3 { x: -20.0, y: 20.0, z: 0.0 }, gray);
+	let wall = quad(Vec3 { x: 0.5, y: -5.0, z: 0.0 }, Vec3 { x: 0.5, y: 5.0, z: 0.0 }, Vec3 { x: 0.5, y: 5.0, z: 3.0 }, Vec3 { x: 0.5, y: -5.0, z: 3.0 }, red);
+	let light = LightSource::new(Vec3 { x: 0.0, y: 0.0, z: 2.0 }, Rot3::new(), 2.0);
+	let mut camera = Camera::new(Vec3 { x: 0.0, y: -0.01, z: 1.0 }, Rot3::new(), 40.0);
+	camera.look_at(&Vec3 { x: 0.0, y: 0.0, z: 0.0 }, &Vec3 { x: 0.0, y: 0.0, z: 1.0 });
+	let objects: Vec<Box<dyn SceneObject>> = vec![Box::new(floor), Box::new(light)];
+	let mut scene = Scene::new(objects, camera);
+	scene.render_settings.integrator = Integrator::Path;
+	scene.render_settings.samples_per_pixel = 64;
+
+	// Straight below the light the floor comes out as bright as direct lighting makes it
+	let center = 4 * 8 + 4;
+	let mut scratch = Scratch::new(&scene.render_settings);
+	let direct = trace_pixel(&scene, center, 8, 8, 0, &mut scratch);
+	let expected = 2.0 / 4.0 * 128.0;
+	assert_eq!(direct.1, 255);
+	assert!((direct.0.r as f64 - expected).abs() < expected * 0.1, "{:?}", direct);
+	assert_eq!(scene.render_framebuffer(8, 8).color[center as usize], direct.0);
+
+	// With the wall up beside it some of the light reaching the floor has bounced off red first,
+	// none of it without bounces
+	scene.objects.push(Box::new(wall));
+	let bounced = trace_pixel(&scene, center, 8, 8, 0, &mut scratch).0;
+	assert!(bounced.r > bounced.g + 4, "{:?}", bounced);
+	scene.render_settings.max_bounces = 0;
+	let unbounced = trace_pixel(&scene, center, 8, 8, 0, &mut scratch).0;
+	assert_eq!(unbounced.r, unbounced.g);
+	assert_eq!(Integrator::from_name("path"), Some(Integrator::Path));
+}
@@ -0,0 +1,105 @@
+//
+//	Photon Map
+//
+
+use crate::structs::Vec3;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Photon {
+	pub pos: Vec3,
+	pub direction: Vec3, // the way it was travelling when it landed
+	pub power: [f64; 3],
+}
+
+// Balanced kd-tree kept in one array: the median of every range is a node, the halves either side its children
+pub struct PhotonMap {
+	photons: Vec<Photon>,
+	axes: Vec<u8>, // split axis of the node at the same index
+}
+
+impl PhotonMap {
+	pub fn new(mut photons: Vec<Photon>) -> Self {
+		let mut axes = vec![0; photons.len()];
+		build(&mut photons, &mut axes);
+		PhotonMap { photons, axes }
+	}
+
+	pub fn len(&self) -> usize {
+		self.photons.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.photons.is_empty()
+	}
+
+	// Calls f with every photon no further than radius from point, in no particular order
+	pub fn for_each_within(&self, point: &Vec3, radius: f64, mut f: impl FnMut(&Photon)) {
+		search(&self.photons, &self.axes, point, radius * radius, &mut f);
+	}
+}
+
+fn coordinate(v: &Vec3, axis: u8) -> f64 {
+	match axis {
+		0 => v.x,
+		1 => v.y,
+		_ => v.z,
+	}
+}
+
+fn build(photons: &mut [Photon], axes: &mut [u8]) {
+	if photons.len() <= 1 { return }
+	// Split across the widest extent
+	let (mut min, mut max) = (photons[0].pos, photons[0].pos);
+	for photon in photons.iter() {
+		min = Vec3 { x: min.x.min(photon.pos.x), y: min.y.min(photon.pos.y), z: min.z.min(photon.pos.z) };
+		max = Vec3 { x: max.x.max(photon.pos.x), y: max.y.max(photon.pos.y), z: max.z.max(photon.pos.z) };
+	}
+	let extent = max.sub(&min);
+	let axis = if extent.x >= extent.y && extent.x >= extent.z { 0 } else if extent.y >= extent.z { 1 } else { 2 };
+
+	let mid = photons.len() / 2;
+	photons.select_nth_unstable_by(mid, |a, b| coordinate(&a.pos, axis).total_cmp(&coordinate(&b.pos, axis)));
+	axes[mid] = axis;
+	let (left, rest) = photons.split_at_mut(mid);
+	let (left_axes, rest_axes) = axes.split_at_mut(mid);
+	build(left, left_axes);
+	build(&mut rest[1..], &mut rest_axes[1..]);
+}
+
+fn search(photons: &[Photon], axes: &[u8], point: &Vec3, radius_squared: f64, f: &mut impl FnMut(&Photon)) {
+	if photons.is_empty() { return }
+	let mid = photons.len() / 2;
+	let node = &photons[mid];
+	let offset = node.pos.sub(point);
+	if Vec3::dot(&offset, &offset) <= radius_squared {
+		f(node);
+	}
+	let axis = axes[mid];
+	let delta = coordinate(point, axis) - coordinate(&node.pos, axis);
+	let (near, near_axes, far, far_axes) = if delta < 0.0 {
+		(&photons[..mid], &axes[..mid], &photons[mid + 1..], &axes[mid + 1..])
+	} else {
+		(&photons[mid + 1..], &axes[mid + 1..], &photons[..mid], &axes[..mid])
+	};
+	search(near, near_axes, point, radius_squared, f);
+	if delta * delta <= radius_squared {
+		search(far, far_axes, point, radius_squared, f);
+	}
+}
+
+#[test]
+fn finds_the_same_photons_as_brute_force() {
+	let mut rng = crate::random::Rng::new(3);
+	let mut random_point = || Vec3 { x: rng.next_f64() * 4.0 - 2.0, y: rng.next_f64() * 4.0 - 2.0, z: rng.next_f64() };
+	let photons: Vec<Photon> = (0..2000).map(|i| Photon { pos: random_point(), direction: Vec3 { x: 0.0, y: 0.0, z: -1.0 }, power: [i as f64, 0.0, 0.0] }).collect();
+	let map = PhotonMap::new(photons.clone());
+	assert_eq!(map.len(), photons.len());
+	for _ in 0..50 {
+		let point = random_point();
+		let mut found = Vec::new();
+		map.for_each_within(&point, 0.3, |photon| found.push(photon.power[0] as usize));
+		found.sort_unstable();
+		let expected: Vec<usize> = photons.iter().filter(|photon| photon.pos.dist(&point) <= 0.3).map(|photon| photon.power[0] as usize).collect();
+		assert_eq!(found, expected);
+	}
+}
@@ -0,0 +1,282 @@
+//
+//	Physics
+//
+
+// Rigid body preview: spheres and boxes falling under gravity, bouncing off the rest of the scene
+// and each other. Steps are fixed so a scene always plays out the same way, and contacts are
+// resolved by pushing bodies apart, which looks right for a preview without being a real solver
+
+use crate::structs::{Aabb, Mesh, Rot3, SceneObject, Sphere, Tri, Vec3};
+use std::collections::HashSet;
+
+pub const STANDARD_GRAVITY: f64 = 9.81; // m/s^2, along -Z
+const RESTING_SPEED: f64 = 0.1; // m/s, slower impacts don't bounce, so resting bodies don't jitter
+const TOPPLE_RATE: f64 = 30.0; // how hard a box on an edge or corner is tipped onto a face
+
+#[derive(Debug, Clone, Copy)]
+pub enum Shape {
+	Sphere { radius: f64 },
+	Box { half_extents: Vec3 }, // about the object's position, in its own space
+}
+
+impl Shape {
+	// Collision shape of a scene object: spheres as themselves, meshes as their object space box
+	pub fn of(object: &dyn SceneObject) -> Option<Shape> {
+		let any = object.as_any_immut();
+		if let Some(sphere) = any.downcast_ref::<Sphere>() {
+			return Some(Shape::Sphere { radius: sphere.radius as f64 });
+		}
+		let mesh = any.downcast_ref::<Mesh>()?;
+		let mut bounds = Aabb::empty();
+		for position in mesh.geometry.positions.iter() {
+			bounds.grow(position);
+		}
+		if mesh.geometry.positions.is_empty() { return None }
+		// Big enough to hold the mesh even when it isn't centered on its anchor
+		let half_extents = Vec3 { x: bounds.max.x.max(-bounds.min.x), y: bounds.max.y.max(-bounds.min.y), z: bounds.max.z.max(-bounds.min.z) };
+		Some(Shape::Box { half_extents })
+	}
+
+	// Distance from the center to the surface along a unit direction, turned by rot
+	pub fn extent(&self, rot: &Rot3, direction: &Vec3) -> f64 {
+		match self {
+			Shape::Sphere { radius } => *radius,
+			Shape::Box { half_extents } => {
+				let local = direction.unrotate(rot);
+				half_extents.x * local.x.abs() + half_extents.y * local.y.abs() + half_extents.z * local.z.abs()
+			}
+		}
+	}
+
+	// Furthest the surface gets from the center in any direction
+	pub fn radius(&self) -> f64 {
+		match self {
+			Shape::Sphere { radius } => *radius,
+			Shape::Box { half_extents } => Vec3::dot(half_extents, half_extents).sqrt(),
+		}
+	}
+
+	fn volume(&self) -> f64 {
+		match self {
+			Shape::Sphere { radius } => 4.0 / 3.0 * std::f64::consts::PI * radius.powi(3),
+			Shape::Box { half_extents } => 8.0 * half_extents.x * half_extents.y * half_extents.z,
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct Body {
+	pub target: String, // id of the object it moves
+	pub shape: Shape,
+	pub velocity: Vec3,
+	pub angular_velocity: Vec3, // axis times radians per second
+	pub restitution: f64, // fraction of the speed kept bouncing off things
+	pub friction: f64,
+}
+
+impl Body {
+	pub fn new(target: &str, shape: Shape) -> Self {
+		let zero = Vec3 { x: 0.0, y: 0.0, z: 0.0 };
+		Body { target: target.to_string(), shape, velocity: zero, angular_velocity: zero, restitution: 0.3, friction: 0.5 }
+	}
+}
+
+// Everything bodies bump into, in world space as of the start of the frame
+enum Collider {
+	Triangles(Aabb, Vec<Tri>),
+	Sphere(Vec3, f64),
+}
+
+pub struct Physics {
+	pub bodies: Vec<Body>,
+	pub gravity: Vec3,
+	pub timestep: f64, // seconds
+	leftover: f64, // time not yet stepped through
+	start: Vec<(Body, Vec3, Rot3)>, // for reset
+}
+
+impl Physics {
+	// Starts the bodies off from where their objects are now, skipping bodies with no object
+	pub fn new(objects: &[Box<dyn SceneObject>], bodies: Vec<Body>) -> Self {
+		let start = bodies.into_iter().filter_map(|body| {
+			let object = objects.iter().find(|object| *object.get_id() == body.target)?;
+			Some((body, *object.get_pos(), *object.get_rot()))
+		}).collect::<Vec<_>>();
+		Physics {
+			bodies: start.iter().map(|(body, _, _)| body.clone()).collect(),
+			gravity: Vec3 { x: 0.0, y: 0.0, z: -STANDARD_GRAVITY },
+			timestep: 1.0 / 240.0,
+			leftover: 0.0,
+			start,
+		}
+	}
+
+	// Initial state of every body along with the pose it started in
+	pub fn start(&self) -> &[(Body, Vec3, Rot3)] {
+		&self.start
+	}
+
+	// Back to how things were when the simulation was created
+	pub fn reset(&mut self, objects: &mut [Box<dyn SceneObject>]) {
+		self.bodies = self.start.iter().map(|(body, _, _)| body.clone()).collect();
+		self.leftover = 0.0;
+		for (body, pos, rot) in self.start.iter() {
+			if let Some(object) = objects.iter_mut().find(|object| *object.get_id() == body.target) {
+				object.set_transform(*pos, *rot);
+			}
+		}
+	}
+
+	// Advances the simulation by elapsed seconds, in as many whole steps as fit
+	pub fn step(&mut self, objects: &mut [Box<dyn SceneObject>], elapsed: f64) {
+		self.leftover += elapsed;
+		if self.leftover < self.timestep { return }
+
+		let indices: Vec<Option<usize>> = self.bodies.iter().map(|body| objects.iter().position(|object| *object.get_id() == body.target)).collect();
+		let colliders = colliders(objects, &self.bodies);
+		let mut poses: Vec<(Vec3, Rot3)> = indices.iter().map(|index| match index {
+			Some(index) => (*objects[*index].get_pos(), *objects[*index].get_rot()),
+			None => (Vec3 { x: 0.0, y: 0.0, z: 0.0 }, Rot3::new()),
+		}).collect();
+		while self.leftover >= self.timestep {
+			self.substep(&mut poses, &colliders);
+			self.leftover -= self.timestep;
+		}
+		for (index, (pos, rot)) in indices.iter().zip(poses) {
+			if let Some(index) = index {
+				objects[*index].set_transform(pos, rot);
+			}
+		}
+	}
+
+	fn substep(&mut self, poses: &mut [(Vec3, Rot3)], colliders: &[Collider]) {
+		let dt = self.timestep;
+		for (body, (pos, rot)) in self.bodies.iter_mut().zip(poses.iter_mut()) {
+			body.velocity = body.velocity.add(&self.gravity.mul(dt));
+			*pos = pos.add(&body.velocity.mul(dt));
+			let spin = Vec3::dot(&body.angular_velocity, &body.angular_velocity).sqrt();
+			if spin > 1e-12 {
+				*rot = Rot3::from_axis_angle(&body.angular_velocity, spin * dt).compose(rot);
+			}
+			for collider in colliders.iter() {
+				match collider {
+					Collider::Sphere(center, radius) => {
+						let normal = pos.sub(center).normalize();
+						collide(body, pos, rot, &center.add(&normal.mul(*radius)), &normal, dt);
+					}
+					Collider::Triangles(bounds, triangles) => {
+						if bounds.closest_point(pos).dist(pos) > body.shape.radius() { continue }
+						for triangle in triangles.iter() {
+							let point = triangle.closest_point(pos);
+							let away = pos.sub(&point);
+							let normal = if Vec3::dot(&away, &away) > 1e-18 { away.normalize() } else { face_normal(triangle) };
+							collide(body, pos, rot, &point, &normal, dt);
+						}
+					}
+				}
+			}
+		}
+
+		// Bodies against each other, pushed apart in proportion to how light each one is
+		for first in 0..self.bodies.len() {
+			for second in first + 1..self.bodies.len() {
+				let away = poses[second].0.sub(&poses[first].0);
+				let distance = Vec3::dot(&away, &away).sqrt();
+				if distance < 1e-9 { continue }
+				let normal = away.div(distance);
+				let (a, b) = (&self.bodies[first], &self.bodies[second]);
+				let overlap = a.shape.extent(&poses[first].1, &normal) + b.shape.extent(&poses[second].1, &normal.mul(-1.0)) - distance;
+				if overlap <= 0.0 { continue }
+				let (mass_a, mass_b) = (a.shape.volume(), b.shape.volume());
+				let share_a = mass_b / (mass_a + mass_b);
+				poses[first].0 = poses[first].0.sub(&normal.mul(overlap * share_a));
+				poses[second].0 = poses[second].0.add(&normal.mul(overlap * (1.0 - share_a)));
+				let closing = Vec3::dot(&a.velocity.sub(&b.velocity), &normal);
+				if closing <= 0.0 { continue }
+				let restitution = if closing < RESTING_SPEED { 0.0 } else { a.restitution.min(b.restitution) };
+				let impulse = normal.mul((1.0 + restitution) * closing);
+				self.bodies[first].velocity = self.bodies[first].velocity.sub(&impulse.mul(share_a));
+				self.bodies[second].velocity = self.bodies[second].velocity.add(&impulse.mul(1.0 - share_a));
+			}
+		}
+	}
+}
+
+// Pushes body out of whatever it's sunk into at point, bouncing and sliding off it
+fn collide(body: &mut Body, pos: &mut Vec3, rot: &mut Rot3, point: &Vec3, normal: &Vec3, dt: f64) {
+	let depth = body.shape.extent(rot, normal) - pos.dist(point);
+	if depth <= 0.0 { return }
+	*pos = pos.add(&normal.mul(depth));
+	let approach = -Vec3::dot(&body.velocity, normal);
+	if approach <= 0.0 { return }
+	let restitution = if approach < RESTING_SPEED { 0.0 } else { body.restitution };
+	body.velocity = body.velocity.add(&normal.mul((1.0 + restitution) * approach));
+	// Coulomb friction, taking off at most what the contact pushed back with
+	let sliding = body.velocity.sub(&normal.mul(Vec3::dot(&body.velocity, normal)));
+	let speed = Vec3::dot(&sliding, &sliding).sqrt();
+	if speed > 1e-12 {
+		body.velocity = body.velocity.sub(&sliding.mul((body.friction * approach / speed).min(1.0)));
+	}
+	body.angular_velocity = body.angular_velocity.mul((1.0 - body.friction * approach * 10.0 * dt).max(0.0));
+	// Boxes don't balance on edges, tip them towards the face closest to lying flat
+	if let Shape::Box { .. } = body.shape {
+		let down = normal.mul(-1.0).unrotate(rot);
+		let axis = [Vec3 { x: 1.0, y: 0.0, z: 0.0 }, Vec3 { x: 0.0, y: 1.0, z: 0.0 }, Vec3 { x: 0.0, y: 0.0, z: 1.0 }].iter()
+			.map(|axis| axis.mul(Vec3::dot(axis, &down).signum()))
+			.max_by(|a, b| Vec3::dot(a, &down).total_cmp(&Vec3::dot(b, &down)))
+			.unwrap_or(down);
+		let tilt = Vec3::cross(&axis.rotate(rot), &normal.mul(-1.0));
+		body.angular_velocity = body.angular_velocity.add(&tilt.mul(TOPPLE_RATE * dt));
+	}
+}
+
+fn colliders(objects: &[Box<dyn SceneObject>], bodies: &[Body]) -> Vec<Collider> {
+	let moving: HashSet<&str> = bodies.iter().map(|body| body.target.as_str()).collect();
+	objects.iter().filter(|object| !moving.contains(object.get_id().as_str())).filter_map(|object| {
+		let any = object.as_any_immut();
+		if let Some(sphere) = any.downcast_ref::<Sphere>() {
+			return Some(Collider::Sphere(sphere.center, sphere.radius as f64));
+		}
+		let mesh = any.downcast_ref::<Mesh>()?;
+		let triangles = mesh.world_tris();
+		Some(Collider::Triangles(mesh.bounds()?, triangles))
+	}).collect()
+}
+
+fn face_normal(triangle: &Tri) -> Vec3 {
+	Vec3::cross(&triangle.b.sub(&triangle.a), &triangle.c.sub(&triangle.a)).normalize()
+}
+
+#[test]
+fn bodies_come_to_rest_on_the_ground() {
+	use crate::structs::Scene;
+	let mut scene = Scene::default_scene();
+	scene.animations.clear();
+	let cube = String::from("fuckin' cube");
+	let mut ball = Box::new(Sphere::new(Vec3 { x: 2.5, y: 2.5, z: 3.0 }, 0.5, scene.objects[1].get_material().unwrap()));
+	ball.id = String::from("ball");
+	scene.objects.push(ball);
+	let mut bodies = vec![Body::new("ball", Shape::Sphere { radius: 0.5 }), Body::new(&cube, Shape::of(scene.find_object(&cube).unwrap().as_ref()).unwrap())];
+	bodies[0].restitution = 0.6;
+	let mut physics = Physics::new(&scene.objects, bodies);
+
+	// Falling, then bouncing back up off the 4x4 plane, then settled on it
+	physics.step(&mut scene.objects, 0.5);
+	let ball_height = |scene: &mut Scene| scene.find_object("ball").unwrap().get_pos().z;
+	assert!(ball_height(&mut scene) < 3.0 - 0.5 * 9.81 * 0.25 * 0.9 && physics.bodies[0].velocity.z < 0.0);
+	let mut bounced = false;
+	for _ in 0..150 {
+		physics.step(&mut scene.objects, 1.0 / 30.0);
+		bounced |= physics.bodies[0].velocity.z > 1.0;
+	}
+	assert!(bounced);
+	assert!((ball_height(&mut scene) - 0.5).abs() < 0.02, "{}", ball_height(&mut scene));
+	// The tilted cube ends up flat on a face, a unit above the ground
+	let cube_object = scene.find_object(&cube).unwrap();
+	let (pos, rot) = (*cube_object.get_pos(), *cube_object.get_rot());
+	assert!((pos.z - 1.0).abs() < 0.05, "{:?}", pos);
+	assert!((physics.bodies[1].shape.extent(&rot, &Vec3 { x: 0.0, y: 0.0, z: -1.0 }) - 1.0).abs() < 0.05);
+
+	physics.reset(&mut scene.objects);
+	assert_eq!(ball_height(&mut scene), 3.0);
+}
@@ -0,0 +1,234 @@
+//
+//	Point Clouds
+//
+
+// Scanned points drawn directly, each one a small colored sphere. A bounding volume hierarchy over
+// the points keeps a ray from testing more than a handful of them, so millions stay interactive
+
+use crate::structs::{Aabb, Color, Material, Ray, RayMask, Rot3, SceneObject, Vec3};
+use core::any::Any;
+use std::fs;
+use std::path::Path;
+use uuid::Uuid;
+
+const LEAF_SIZE: usize = 4;
+
+// Interior nodes have their first child right after them and the second at second,
+// leaves own count points from first
+struct Node {
+	bounds: Aabb,
+	first: usize,
+	second: usize,
+	count: usize,
+}
+
+pub struct PointCloud {
+	pub anchor: Vec3,
+	pub rot: Rot3,
+	pub radius: f64, // of every point
+	pub mask: RayMask,
+	pub id: String,
+	pub path: String, // where it was loaded from, empty for generated ones
+	// Reordered to match the hierarchy, which is built once up front
+	positions: Vec<Vec3>,
+	colors: Vec<Color>,
+	nodes: Vec<Node>,
+}
+
+impl PointCloud {
+	pub fn new(anchor: Vec3, positions: Vec<Vec3>, colors: Vec<Color>, radius: f64) -> Self {
+		let mut cloud = PointCloud {
+			anchor,
+			rot: Rot3::new(),
+			radius,
+			mask: RayMask::ALL,
+			id: Uuid::new_v4().to_hyphenated().to_string(),
+			path: String::new(),
+			positions: Vec::new(),
+			colors: Vec::new(),
+			nodes: Vec::new(),
+		};
+		let mut order: Vec<usize> = (0..positions.len()).collect();
+		if !order.is_empty() {
+			cloud.build(&positions, &mut order, 0);
+		}
+		let white = Color { r: 255, g: 255, b: 255 };
+		cloud.colors = order.iter().map(|index| colors.get(*index).copied().unwrap_or(white)).collect();
+		cloud.positions = order.iter().map(|index| positions[*index]).collect();
+		cloud
+	}
+
+	// Plain text, a point per line as `x y z` or `x y z r g b` with 0-255 colors, `#` starting a comment
+	pub fn parse(text: &str, radius: f64) -> Result<Self, String> {
+		let (mut positions, mut colors) = (Vec::new(), Vec::new());
+		for (line_no, line) in text.lines().enumerate() {
+			let line = line.split('#').next().unwrap_or("").trim();
+			if line.is_empty() { continue }
+			let numbers = line.split_whitespace().map(|word| word.parse::<f64>()).collect::<Result<Vec<f64>, _>>();
+			let numbers = match numbers {
+				Ok(numbers) if numbers.len() == 3 || numbers.len() == 6 => numbers,
+				_ => return Err(format!("line {}: expected `x y z` or `x y z r g b`", line_no + 1)),
+			};
+			positions.push(Vec3 { x: numbers[0], y: numbers[1], z: numbers[2] });
+			let channel = |value: f64| value.clamp(0.0, 255.0) as u8;
+			colors.push(match numbers.len() {
+				6 => Color { r: channel(numbers[3]), g: channel(numbers[4]), b: channel(numbers[5]) },
+				_ => Color { r: 255, g: 255, b: 255 },
+			});
+		}
+		Ok(PointCloud::new(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, positions, colors, radius))
+	}
+
+	pub fn load(path: &Path, radius: f64) -> Result<Self, String> {
+		let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+		let mut cloud = PointCloud::parse(&text, radius).map_err(|e| format!("{}: {}", path.display(), e))?;
+		cloud.path = path.to_string_lossy().into_owned();
+		Ok(cloud)
+	}
+
+	pub fn len(&self) -> usize {
+		self.positions.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.positions.is_empty()
+	}
+
+	// Points spread out by factor about the anchor, each one that much bigger
+	pub fn scaled(self, factor: f64) -> Self {
+		let positions = self.positions.iter().map(|position| position.mul(factor)).collect();
+		let cloud = PointCloud::new(self.anchor, positions, self.colors, self.radius * factor);
+		PointCloud { rot: self.rot, mask: self.mask, id: self.id, path: self.path, ..cloud }
+	}
+
+	// Splits order[..] in half along the longest side of its bounds until the halves are small,
+	// order ending up in the order the leaves store the points
+	fn build(&mut self, positions: &[Vec3], order: &mut [usize], offset: usize) {
+		let mut bounds = Aabb::empty();
+		for index in order.iter() {
+			bounds.grow(&positions[*index]);
+		}
+		let node = self.nodes.len();
+		self.nodes.push(Node { bounds, first: offset, second: 0, count: order.len() });
+		if order.len() <= LEAF_SIZE { return }
+
+		let size = bounds.max.sub(&bounds.min);
+		let along = |point: &Vec3| if size.x >= size.y && size.x >= size.z { point.x } else if size.y >= size.z { point.y } else { point.z };
+		let half = order.len() / 2;
+		order.select_nth_unstable_by(half, |a, b| along(&positions[*a]).total_cmp(&along(&positions[*b])));
+		let (left, right) = order.split_at_mut(half);
+		self.build(positions, left, offset);
+		self.nodes[node].second = self.nodes.len();
+		self.nodes[node].count = 0;
+		self.build(positions, right, offset + half);
+	}
+
+	// Nearest point the ray hits in object space, as its index and distance
+	fn nearest(&self, ray: &Ray, min_distance: f64) -> Option<(usize, f64)> {
+		let padding = Vec3 { x: self.radius, y: self.radius, z: self.radius };
+		let mut nearest: Option<(usize, f64)> = None;
+		let mut stack = vec![0];
+		while let Some(index) = stack.pop() {
+			let node = &self.nodes[index];
+			let bounds = Aabb { min: node.bounds.min.sub(&padding), max: node.bounds.max.add(&padding) };
+			match bounds.ray_distance(ray) {
+				Some(distance) if nearest.is_none_or(|(_, nearest)| distance < nearest) => {}
+				_ => continue,
+			}
+			if node.count == 0 {
+				stack.push(node.second);
+				stack.push(index + 1);
+				continue;
+			}
+			for point in node.first..node.first + node.count {
+				let oc = ray.origin.sub(&self.positions[point]);
+				let b = Vec3::dot(&oc, &ray.direction);
+				let discriminant = b * b - (Vec3::dot(&oc, &oc) - self.radius * self.radius);
+				if discriminant < 0.0 { continue }
+				let root = discriminant.sqrt();
+				// Near side, or the far side when the ray starts inside
+				let distance = if -b - root >= min_distance { -b - root } else { -b + root };
+				if distance >= min_distance && nearest.is_none_or(|(_, nearest)| distance < nearest) {
+					nearest = Some((point, distance));
+				}
+			}
+		}
+		nearest
+	}
+}
+
+impl SceneObject for PointCloud {
+	fn get_pos(&self) -> &Vec3 { &self.anchor }
+	fn get_rot(&self) -> &Rot3 { &self.rot }
+	fn ray_hit(&self, ray: &Ray) -> Option<(Vec3, Material, Vec3)> {
+		if self.is_empty() { return None }
+		let local = Ray { origin: ray.origin.sub(&self.anchor).unrotate(&self.rot), direction: ray.direction.unrotate(&self.rot), mask: ray.mask, differential: None };
+		let (point, distance) = self.nearest(&local, 0.01)?;
+		let hit = local.origin.add(&local.direction.mul(distance));
+		let normal = hit.sub(&self.positions[point]).div(self.radius).rotate(&self.rot);
+		let material = Material { color: self.colors[point], reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+		Some((hit.rotate(&self.rot).add(&self.anchor), material, normal))
+	}
+	fn as_any(&mut self) -> &mut dyn Any { self }
+	fn as_any_immut(&self) -> &dyn Any { self }
+	fn get_id(&self) -> &String { &self.id }
+	fn bounds(&self) -> Option<Aabb> {
+		let root = self.nodes.first()?;
+		let r = self.radius;
+		let mut bounds = Aabb::empty();
+		for corner in 0..8 {
+			let pick = |bit: usize, min: f64, max: f64| if corner & bit == 0 { min - r } else { max + r };
+			let point = Vec3 { x: pick(1, root.bounds.min.x, root.bounds.max.x), y: pick(2, root.bounds.min.y, root.bounds.max.y), z: pick(4, root.bounds.min.z, root.bounds.max.z) };
+			bounds.grow(&point.rotate(&self.rot).add(&self.anchor));
+		}
+		Some(bounds)
+	}
+	fn get_mask(&self) -> RayMask { self.mask }
+	fn set_mask(&mut self, mask: RayMask) { self.mask = mask }
+	fn set_transform(&mut self, pos: Vec3, rot: Rot3) { self.anchor = pos; self.rot = rot }
+	fn closest_point(&self, point: &Vec3) -> Option<Vec3> {
+		let local = point.sub(&self.anchor).unrotate(&self.rot);
+		let nearest = self.positions.iter().min_by(|a, b| a.dist(&local).total_cmp(&b.dist(&local)))?;
+		let surface = nearest.add(&local.sub(nearest).normalize().mul(self.radius));
+		Some(surface.rotate(&self.rot).add(&self.anchor))
+	}
+}
+
+#[test]
+fn hierarchy_finds_the_nearest_point() {
+	use crate::random::Rng;
+	let mut rng = Rng::new(7);
+	let positions: Vec<Vec3> = (0..500).map(|_| Vec3 { x: rng.next_f64() * 10.0, y: rng.next_f64() * 10.0, z: rng.next_f64() * 10.0 }).collect();
+	let colors: Vec<Color> = (0..500).map(|index| Color { r: (index % 256) as u8, g: (index / 256) as u8, b: 0 }).collect();
+	let mut cloud = PointCloud::new(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, positions.clone(), colors, 0.2);
+	assert_eq!(cloud.len(), 500);
+	cloud.anchor = Vec3 { x: 0.0, y: 0.0, z: 5.0 };
+	cloud.rot = Rot3::from_axis_angle(&Vec3 { x: 0.0, y: 1.0, z: 1.0 }, 0.7);
+
+	// Same answer as testing every point
+	for _ in 0..200 {
+		let from = Vec3 { x: rng.next_f64() * 30.0 - 10.0, y: rng.next_f64() * 30.0 - 10.0, z: 20.0 };
+		let to = Vec3 { x: rng.next_f64() * 10.0, y: rng.next_f64() * 10.0, z: rng.next_f64() * 10.0 }.rotate(&cloud.rot).add(&cloud.anchor);
+		let ray = Ray::from_to(&from, &to);
+		let brute = positions.iter().enumerate().filter_map(|(index, position)| {
+			let center = position.rotate(&cloud.rot).add(&cloud.anchor);
+			let oc = ray.origin.sub(&center);
+			let b = Vec3::dot(&oc, &ray.direction);
+			let discriminant = b * b - (Vec3::dot(&oc, &oc) - 0.04);
+			if discriminant < 0.0 { None } else { Some((index, -b - discriminant.sqrt())) }
+		}).min_by(|a, b| a.1.total_cmp(&b.1));
+		match (cloud.ray_hit(&ray), brute) {
+			(Some((point, material, normal)), Some((index, distance))) => {
+				assert!((point.dist(&from) - distance).abs() < 1e-9);
+				assert_eq!(material.color, Color { r: (index % 256) as u8, g: (index / 256) as u8, b: 0 });
+				assert!((Vec3::dot(&normal, &normal) - 1.0).abs() < 1e-9);
+			}
+			(None, None) => {}
+			(hit, brute) => panic!("{:?} vs {:?}", hit.map(|hit| hit.0), brute),
+		}
+	}
+
+	let parsed = PointCloud::parse("# scan\n0 0 0\n1 2 3 255 0 0\n", 0.1).unwrap();
+	assert_eq!(parsed.len(), 2);
+	assert!(PointCloud::parse("1 2", 0.1).is_err());
+}
@@ -0,0 +1,145 @@
+//
+//	Render Presets
+//
+
+// Named bundles of render settings, so a final render is one name rather than a dozen flags. Every
+// scene has draft, preview and final, and its file can change those or add its own; whatever a preset
+// leaves out stays as it was. In a scene file:
+//
+//	presets: { "final": (samples_per_pixel: Some(64), aovs: [Depth, Normals]), "turntable": (max_bounces: Some(2)) }
+
+use crate::render::RenderSettings;
+use crate::structs::Scene;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Images written beside a headless render's output, named after it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Aov {
+	Depth,
+	Normals,
+	Sky,
+	Motion,
+	Analysis,
+}
+
+impl Aov {
+	// As in its command line flag and the file it's written to
+	pub fn name(&self) -> &'static str {
+		match self {
+			Aov::Depth => "depth",
+			Aov::Normals => "normals",
+			Aov::Sky => "sky",
+			Aov::Motion => "motion",
+			Aov::Analysis => "analysis",
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderPreset {
+	pub samples_per_pixel: Option<u32>,
+	pub light_samples: Option<u32>,
+	pub max_bounces: Option<u32>,
+	pub glossy_samples: Option<u32>,
+	pub ao_samples: Option<u32>,
+	pub render_scale: Option<f64>, // interactive, the fraction of the viewport traced
+	pub aovs: Vec<Aov>,
+}
+
+impl RenderPreset {
+	pub fn apply(&self, settings: &mut RenderSettings) {
+		let fields = [
+			(self.samples_per_pixel, &mut settings.samples_per_pixel),
+			(self.light_samples, &mut settings.light_samples),
+			(self.max_bounces, &mut settings.max_bounces),
+			(self.glossy_samples, &mut settings.glossy_samples),
+			(self.ao_samples, &mut settings.ao_samples),
+		];
+		for (value, setting) in fields {
+			if let Some(value) = value {
+				*setting = value;
+			}
+		}
+	}
+}
+
+// What every scene starts out with
+pub fn builtin_presets() -> BTreeMap<String, RenderPreset> {
+	let draft = RenderPreset {
+		samples_per_pixel: Some(1),
+		light_samples: Some(1),
+		max_bounces: Some(0),
+		glossy_samples: Some(0),
+		ao_samples: Some(0),
+		render_scale: Some(0.5),
+		aovs: Vec::new(),
+	};
+	let preview = RenderPreset { light_samples: Some(4), max_bounces: Some(1), render_scale: Some(1.0), ..draft.clone() };
+	let final_render = RenderPreset {
+		samples_per_pixel: Some(16),
+		light_samples: Some(16),
+		max_bounces: Some(4),
+		glossy_samples: Some(16),
+		ao_samples: Some(32),
+		render_scale: Some(1.0),
+		aovs: vec![Aov::Depth, Aov::Normals, Aov::Sky],
+	};
+	vec![("draft", draft), ("preview", preview), ("final", final_render)].into_iter().map(|(name, preset)| (name.to_string(), preset)).collect()
+}
+
+impl Scene {
+	// Applies the preset called name to the render settings, None when there's no such preset
+	pub fn apply_preset(&mut self, name: &str) -> Option<&RenderPreset> {
+		let preset = self.presets.get(name)?;
+		preset.apply(&mut self.render_settings);
+		Some(preset)
+	}
+
+	// The preset after name in order of their names, wrapping round, or the first when name is None
+	pub fn next_preset(&self, name: Option<&str>) -> Option<&str> {
+		let mut names = self.presets.keys().map(String::as_str);
+		match name {
+			Some(name) => names.clone().skip_while(|other| *other != name).nth(1).or_else(|| names.next()),
+			None => names.next(),
+		}
+	}
+}
+
+#[test]
+fn presets_change_only_what_they_set() {
+	let mut scene = Scene::default_scene();
+	scene.render_settings.exposure = 1.5;
+	let draft = scene.apply_preset("draft").unwrap().clone();
+	assert_eq!(draft.render_scale, Some(0.5));
+	assert_eq!((scene.render_settings.samples_per_pixel, scene.render_settings.max_bounces, scene.render_settings.exposure), (1, 0, 1.5));
+	assert_eq!(scene.apply_preset("final").unwrap().aovs.len(), 3);
+	assert_eq!((scene.render_settings.samples_per_pixel, scene.render_settings.glossy_samples), (16, 16));
+	assert!(scene.apply_preset("nothing").is_none());
+
+	// A scene's own, left out fields untouched
+	scene.presets.insert(String::from("turntable"), RenderPreset { max_bounces: Some(2), ..RenderPreset::default() });
+	scene.apply_preset("turntable");
+	assert_eq!((scene.render_settings.samples_per_pixel, scene.render_settings.max_bounces), (16, 2));
+
+	// Cycled through by name
+	assert_eq!(scene.next_preset(None), Some("draft"));
+	assert_eq!(scene.next_preset(Some("draft")), Some("final"));
+	assert_eq!(scene.next_preset(Some("turntable")), Some("draft"));
+
+	// From a scene file, where only the ones that differ from the built in presets are saved
+	let dir = std::env::temp_dir().join(format!("presets_test_{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	std::fs::write(dir.join("scene.ron"), r#"(
+		camera: Some((pos: (0.0, -10.0, 2.0), fov: 40.0)),
+		presets: { "final": (samples_per_pixel: Some(64), aovs: [Depth]), "turntable": (max_bounces: Some(2)) },
+	)"#).unwrap();
+	let loaded = crate::scene_file::load_scene(&dir.join("scene.ron")).unwrap();
+	std::fs::remove_dir_all(&dir).unwrap();
+	assert_eq!(loaded.presets.len(), 4);
+	assert_eq!((loaded.presets["final"].samples_per_pixel, loaded.presets["final"].light_samples), (Some(64), None));
+	let saved = crate::scene_file::SceneFile::from_scene(&loaded);
+	assert_eq!(saved.presets.keys().collect::<Vec<_>>(), ["final", "turntable"]);
+}
+
@@ -0,0 +1,150 @@
+//
+//	Frame Profiler
+//
+
+// Where a frame's time went, split into moving things into place and the three kinds of rays, for
+// finding out what to make faster before guessing at it. Off until enabled, as timing every ray isn't
+// free. The host brackets each frame with begin_frame and end_frame and reads it back from last_frame.
+// Rays are timed on whichever thread traced them and added up, so with several threads the ray stages
+// together can come to more than the frame did
+
+use crate::frontend::Presenter;
+use crate::structs::{Color, RayMask};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+	Transforms, // animation, physics and refitting for the new poses, see Scene::update_culling
+	Primary,
+	Shadow,
+	Reflection,
+}
+
+pub const STAGES: [Stage; 4] = [Stage::Transforms, Stage::Primary, Stage::Shadow, Stage::Reflection];
+
+impl Stage {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Stage::Transforms => "transforms",
+			Stage::Primary => "primary",
+			Stage::Shadow => "shadow",
+			Stage::Reflection => "reflection",
+		}
+	}
+
+	// The stage rays with mask are timed in, None for queries and the like
+	pub fn of_rays(mask: &RayMask) -> Option<Stage> {
+		match mask.kinds {
+			RayMask::CAMERA => Some(Stage::Primary),
+			RayMask::SHADOW => Some(Stage::Shadow),
+			RayMask::REFLECTION => Some(Stage::Reflection),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct FrameProfile {
+	pub stages: [f64; STAGES.len()], // seconds, in the order of STAGES
+	pub total: f64, // seconds from begin_frame to end_frame
+}
+
+impl FrameProfile {
+	pub fn seconds(&self, stage: Stage) -> f64 {
+		self.stages[stage as usize]
+	}
+
+	// Whatever the frame spent outside the stages, shading, drawing and the rest
+	pub fn other(&self) -> f64 {
+		(self.total - self.stages.iter().sum::<f64>()).max(0.0)
+	}
+
+	// One line a stage in milliseconds, then the rest and the whole frame
+	pub fn lines(&self) -> Vec<String> {
+		let line = |name: &str, seconds: f64| format!("{:<10}{:6.2}ms", name, seconds * 1000.0);
+		let mut lines: Vec<String> = STAGES.iter().map(|stage| line(stage.name(), self.seconds(*stage))).collect();
+		lines.push(line("other", self.other()));
+		lines.push(line("frame", self.total));
+		lines
+	}
+
+	pub fn draw(&self, presenter: &mut dyn Presenter, x: i32, y: i32) {
+		for (index, line) in self.lines().iter().enumerate() {
+			presenter.draw_string(x, y + index as i32 * 10, line, Color { r: 255, g: 255, b: 255 });
+		}
+	}
+}
+
+#[derive(Default)]
+pub struct Profiler {
+	pub enabled: bool,
+	nanos: [AtomicU64; STAGES.len()],
+	started: Option<Instant>, // the frame being profiled
+	last: Option<FrameProfile>,
+}
+
+impl Profiler {
+	pub fn begin_frame(&mut self) {
+		for nanos in self.nanos.iter() {
+			nanos.store(0, Ordering::Relaxed);
+		}
+		self.started = self.enabled.then(Instant::now);
+	}
+
+	pub fn end_frame(&mut self) {
+		self.last = self.started.take().filter(|_| self.enabled).map(|started| FrameProfile {
+			stages: STAGES.map(|stage| self.nanos[stage as usize].load(Ordering::Relaxed) as f64 * 1e-9),
+			total: started.elapsed().as_secs_f64(),
+		});
+	}
+
+	// The frame last ended, None when the profiler was off for it
+	pub fn last_frame(&self) -> Option<FrameProfile> {
+		self.last
+	}
+
+	// When something to be timed started, None when the profiler is off, to go to stop
+	pub fn start(&self) -> Option<Instant> {
+		self.enabled.then(Instant::now)
+	}
+
+	pub fn stop(&self, stage: Stage, started: Option<Instant>) {
+		if let Some(started) = started {
+			self.nanos[stage as usize].fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+		}
+	}
+
+	// stop for rays traced with mask
+	pub fn stop_rays(&self, mask: &RayMask, started: Option<Instant>) {
+		if let Some(stage) = Stage::of_rays(mask) {
+			self.stop(stage, started);
+		}
+	}
+}
+
+#[test]
+fn frame_split_into_stages() {
+	use crate::structs::{Scene, Vec3};
+	let mut scene = Scene::default_scene();
+	scene.current_camera.look_at(&Vec3 { x: 0.0, y: 0.0, z: 1.5 }, &Vec3 { x: 0.0, y: 0.0, z: 1.0 });
+	let frame = |scene: &mut Scene| {
+		scene.profiler.begin_frame();
+		// Culling is updated first, which counts as transforms
+		scene.render(32, 18);
+		scene.profiler.end_frame();
+	};
+	// Nothing unless it's on
+	frame(&mut scene);
+	assert_eq!(scene.profiler.last_frame(), None);
+
+	scene.profiler.enabled = true;
+	frame(&mut scene);
+	let profile = scene.profiler.last_frame().unwrap();
+	for stage in [Stage::Transforms, Stage::Primary, Stage::Shadow] {
+		assert!(profile.seconds(stage) > 0.0, "{:?}", stage);
+	}
+	assert!(profile.total > 0.0 && profile.other() <= profile.total);
+	assert_eq!(profile.lines().len(), STAGES.len() + 2);
+	assert!(profile.lines()[1].starts_with("primary"));
+}
@@ -0,0 +1,326 @@
+//
+//	Progressive Rendering
+//
+
+// A still view built up over many updates: each one traces the next budget of pixels in the
+// traversal order, and every pass over the frame after the first adds more samples that are
+// averaged in, so the image keeps getting cleaner until max_passes. Anything that changes what
+// the camera sees starts it over, exposure aside: samples are kept as the light they measured and
+// exposed when shown, so auto exposure can move without throwing them away. Given a focus, the tiles around the cursor or an object being
+// looked at, the rest of its passes go there once the whole frame has had its first, so the part
+// being inspected cleans up before the rest
+
+use crate::render::RenderSettings;
+use crate::regions;
+use crate::structs::{Aabb, Color, Material, RayMask, Scene, Vec3};
+use crate::tiles::{Tile, TILE_SIZE};
+
+// Id, position and rotation, material, mask and bounds
+type ObjectState = (String, [f64; 6], Option<Material>, RayMask, Option<[f64; 6]>);
+
+// Everything a change to restarts the image, settings without exposure
+#[derive(PartialEq)]
+struct View {
+	camera: [f64; 7], // position, rotation, fov
+	lens: Option<crate::structs::PhysicalLens>,
+	layers: u32,
+	settings: RenderSettings,
+	objects: Vec<ObjectState>,
+}
+
+impl View {
+	fn of(scene: &Scene) -> Self {
+		let camera = &scene.current_camera;
+		View {
+			camera: [camera.pos.x, camera.pos.y, camera.pos.z, camera.rot.yaw, camera.rot.pitch, camera.rot.roll, camera.fov as f64],
+			lens: camera.lens,
+			layers: camera.layers,
+			settings: RenderSettings { exposure: 0.0, ..scene.render_settings },
+			objects: scene.objects.iter().map(|object| {
+				let (pos, rot) = (object.get_pos(), object.get_rot());
+				let bounds = object.bounds().map(|bounds| [bounds.min.x, bounds.min.y, bounds.min.z, bounds.max.x, bounds.max.y, bounds.max.z]);
+				(object.get_id().clone(), [pos.x, pos.y, pos.z, rot.yaw, rot.pitch, rot.roll], object.get_material(), object.get_mask(), bounds)
+			}).collect(),
+		}
+	}
+
+	// Which objects differ from before, None when anything else does as well
+	fn changed_objects(&self, before: &View) -> Option<Vec<usize>> {
+		let same_objects = self.objects.len() == before.objects.len()
+			&& self.objects.iter().zip(before.objects.iter()).all(|(now, then)| now.0 == then.0);
+		if self.camera != before.camera || self.lens != before.lens || self.layers != before.layers || self.settings != before.settings || !same_objects {
+			return None;
+		}
+		Some((0..self.objects.len()).filter(|index| self.objects[*index] != before.objects[*index]).collect())
+	}
+}
+
+// What update_regions started over
+pub enum ViewChange {
+	Unchanged,
+	Everything,
+	Pixels(Vec<bool>), // every pixel, true for those started over
+}
+
+// Samples of every pixel summed up for as long as the view stays the same, so a still image keeps
+// getting cleaner rather than being traced the same way over again
+pub struct Accumulation {
+	sums: Vec<(f64, f64, f64)>, // brought back to exposure 0
+	passes: Vec<u32>, // per pixel, how many have been averaged in
+	view: Option<View>,
+}
+
+impl Accumulation {
+	pub fn new(pixel_count: usize) -> Self {
+		Accumulation { sums: vec![(0.0, 0.0, 0.0); pixel_count], passes: vec![0; pixel_count], view: None }
+	}
+
+	pub fn restart(&mut self) {
+		self.sums.iter_mut().for_each(|sum| *sum = (0.0, 0.0, 0.0));
+		self.passes.iter_mut().for_each(|passes| *passes = 0);
+	}
+
+	// Restarts when the camera, an object, its material or visibility or the settings changed since the last call,
+	// true when it did
+	pub fn update_view(&mut self, scene: &Scene) -> bool {
+		let view = View::of(scene);
+		if self.view.as_ref() == Some(&view) { return false }
+		self.view = Some(view);
+		self.restart();
+		true
+	}
+
+	// update_view for a width x height frame, starting over only the pixels a change could have reached
+	// when nothing but a few objects changed, see regions
+	pub fn update_regions(&mut self, scene: &Scene, width: i32, height: i32) -> ViewChange {
+		let view = View::of(scene);
+		let affected = match &self.view {
+			Some(before) if *before == view => return ViewChange::Unchanged,
+			Some(before) => view.changed_objects(before).and_then(|changed| {
+				let changed: Vec<(usize, Option<Aabb>)> = changed.into_iter().map(|index| {
+					let bounds = before.objects[index].4.map(|[min_x, min_y, min_z, max_x, max_y, max_z]| {
+						Aabb { min: Vec3 { x: min_x, y: min_y, z: min_z }, max: Vec3 { x: max_x, y: max_y, z: max_z } }
+					});
+					(index, bounds)
+				}).collect();
+				regions::affected_pixels(scene, &changed, width, height)
+			}),
+			None => None,
+		};
+		self.view = Some(view);
+		match affected {
+			Some(affected) => {
+				for (index, _) in affected.iter().enumerate().filter(|(_, affected)| **affected) {
+					self.sums[index] = (0.0, 0.0, 0.0);
+					self.passes[index] = 0;
+				}
+				ViewChange::Pixels(affected)
+			}
+			None => {
+				self.restart();
+				ViewChange::Everything
+			}
+		}
+	}
+
+	// Where pixel index's samples carry on from, so each pass adds new ones rather than repeating them
+	pub fn first_sample(&self, index: u64, settings: &RenderSettings) -> u32 {
+		self.passes[index as usize] * settings.samples_per_pixel.max(1)
+	}
+
+	// Averages a pass of pixel index rendered at exposure, in stops, in with the ones before it,
+	// returning the result at that exposure
+	pub fn accumulate(&mut self, index: u64, color: Color, exposure: f64) -> Color {
+		let (sum, passes) = (&mut self.sums[index as usize], &mut self.passes[index as usize]);
+		let inv = 2f64.powf(-exposure);
+		*sum = (sum.0 + color.r as f64 * inv, sum.1 + color.g as f64 * inv, sum.2 + color.b as f64 * inv);
+		*passes += 1;
+		self.resolve(index, exposure).unwrap_or(color)
+	}
+
+	// The average so far of pixel index at exposure, None before its first pass
+	pub fn resolve(&self, index: u64, exposure: f64) -> Option<Color> {
+		let (sum, passes) = (self.sums[index as usize], self.passes[index as usize]);
+		if passes == 0 { return None }
+		let scale = 2f64.powf(exposure) / passes as f64;
+		let channel = |channel: f64| (channel * scale).round().clamp(0.0, 255.0) as u8;
+		Some(Color { r: channel(sum.0), g: channel(sum.1), b: channel(sum.2) })
+	}
+}
+
+pub struct Progressive {
+	pub budget: usize, // pixels traced per update
+	pub max_passes: u32, // over the whole frame, then it's complete
+	pub render_index: usize, // next pixel of the traversal order
+	pub complete: bool,
+	accumulation: Accumulation,
+	traced: Vec<u32>, // per pixel, the passes handed out so far
+	focus: Option<(Tile, usize)>, // and the width of the frame it's in
+}
+
+impl Progressive {
+	pub fn new(budget: usize, max_passes: u32, pixel_count: usize) -> Self {
+		Progressive {
+			budget: budget.max(1),
+			max_passes: max_passes.max(1),
+			render_index: 0,
+			complete: false,
+			accumulation: Accumulation::new(pixel_count),
+			traced: vec![0; pixel_count],
+			focus: None,
+		}
+	}
+
+	pub fn restart(&mut self) {
+		self.render_index = 0;
+		self.complete = false;
+		self.accumulation.restart();
+		self.traced.iter_mut().for_each(|traced| *traced = 0);
+	}
+
+	// Where to refine first in a frame width pixels wide, None for everywhere alike. Can move at any time
+	pub fn focus_on(&mut self, area: Option<Tile>, width: usize) {
+		self.focus = area.map(|area| (area, width));
+	}
+
+	// Restarts the same way an Accumulation does, true when it did
+	pub fn update_view(&mut self, scene: &Scene) -> bool {
+		if !self.accumulation.update_view(scene) { return false }
+		self.restart();
+		true
+	}
+
+	// The next budget's worth of order, wrapping around into the next pass; none once complete.
+	// After the first pass, focus pixels short of max_passes come first, those with fewest passes,
+	// and are skipped when order comes round to them again
+	pub fn next_pixels(&mut self, order: &[u64]) -> Vec<u64> {
+		if order.is_empty() || self.complete { return Vec::new() }
+		let mut pixels = Vec::with_capacity(self.budget);
+		if let Some((area, width)) = self.focus.filter(|_| self.render_index >= order.len()) {
+			let inside = |index: u64| {
+				let (x, y) = (index as usize % width, index as usize / width);
+				x >= area.x && x < area.x + area.width && y >= area.y && y < area.y + area.height
+			};
+			let wanting = order.iter().copied().filter(|index| inside(*index) && self.traced[*index as usize] < self.max_passes);
+			if let Some(fewest) = wanting.clone().map(|index| self.traced[index as usize]).min() {
+				pixels.extend(wanting.filter(|index| self.traced[*index as usize] == fewest).take(self.budget));
+				for index in pixels.iter() {
+					self.traced[*index as usize] += 1;
+				}
+				return pixels;
+			}
+		}
+		let total = order.len() * self.max_passes as usize;
+		while pixels.len() < self.budget && self.render_index < total {
+			let index = order[self.render_index % order.len()];
+			self.render_index += 1;
+			if self.traced[index as usize] < self.max_passes {
+				self.traced[index as usize] += 1;
+				pixels.push(index);
+			}
+		}
+		self.complete = self.render_index == total;
+		pixels
+	}
+
+	pub fn first_sample(&self, index: u64, settings: &RenderSettings) -> u32 {
+		self.accumulation.first_sample(index, settings)
+	}
+
+	pub fn accumulate(&mut self, index: u64, color: Color, exposure: f64) -> Color {
+		self.accumulation.accumulate(index, color, exposure)
+	}
+
+	pub fn resolve(&self, index: u64, exposure: f64) -> Option<Color> {
+		self.accumulation.resolve(index, exposure)
+	}
+}
+
+// The tiles of a width x height frame the box from min to max is in, and the ring of tiles around
+// those, None when it's entirely off the frame
+pub fn focus_area(min: (f64, f64), max: (f64, f64), width: usize, height: usize) -> Option<Tile> {
+	if max.0 < 0.0 || max.1 < 0.0 || min.0 >= width as f64 || min.1 >= height as f64 { return None }
+	let tile = |value: f64, size: usize| (value.max(0.0) as usize).min(size - 1) / TILE_SIZE;
+	let (left, top) = (tile(min.0, width).saturating_sub(1) * TILE_SIZE, tile(min.1, height).saturating_sub(1) * TILE_SIZE);
+	let (right, bottom) = (((tile(max.0, width) + 2) * TILE_SIZE).min(width), ((tile(max.1, height) + 2) * TILE_SIZE).min(height));
+	Some(Tile { x: left, y: top, width: right - left, height: bottom - top })
+}
+
+// Where the object with id shows on a width x height frame, the box round its bounds' corners.
+// None when it's unbounded or partly behind the camera
+pub fn screen_bounds(scene: &Scene, id: &str, width: i32, height: i32) -> Option<((f64, f64), (f64, f64))> {
+	let bounds = scene.objects.iter().find(|object| object.get_id() == id)?.bounds()?;
+	let (mut min, mut max) = ((f64::INFINITY, f64::INFINITY), (f64::NEG_INFINITY, f64::NEG_INFINITY));
+	for corner in 0..8 {
+		let pick = |bit: usize, low: f64, high: f64| if corner & bit == 0 { low } else { high };
+		let point = Vec3 { x: pick(1, bounds.min.x, bounds.max.x), y: pick(2, bounds.min.y, bounds.max.y), z: pick(4, bounds.min.z, bounds.max.z) };
+		let (x, y) = scene.current_camera.project(&point, width, height)?;
+		min = (min.0.min(x), min.1.min(y));
+		max = (max.0.max(x), max.1.max(y));
+	}
+	Some((min, max))
+}
+
+#[test]
+fn refines_until_something_moves() {
+	let mut scene = Scene::default_scene();
+	let order: Vec<u64> = (0..10).collect();
+	let mut progressive = Progressive::new(4, 2, 10);
+	assert!(progressive.update_view(&scene));
+	assert!(!progressive.update_view(&scene));
+
+	// Two passes of ten pixels, four at a time
+	assert_eq!(progressive.next_pixels(&order), [0, 1, 2, 3]);
+	progressive.next_pixels(&order);
+	assert_eq!(progressive.next_pixels(&order), [8, 9, 0, 1]);
+	progressive.next_pixels(&order);
+	assert_eq!(progressive.next_pixels(&order), [6, 7, 8, 9]);
+	assert!(progressive.complete && progressive.next_pixels(&order).is_empty());
+
+	let grey = |value: u8| Color { r: value, g: value, b: value };
+	assert_eq!(progressive.accumulate(3, grey(100), 0.0), grey(100));
+	assert_eq!(progressive.first_sample(3, &scene.render_settings), 1);
+	assert_eq!(progressive.accumulate(3, grey(50), 0.0), grey(75));
+
+	scene.find_object("fuckin' cube").unwrap().set_transform(crate::structs::Vec3 { x: 1.0, y: 0.0, z: 0.0 }, crate::structs::Rot3::new());
+	assert!(progressive.update_view(&scene));
+	assert!(!progressive.complete && progressive.render_index == 0);
+	assert_eq!(progressive.first_sample(3, &scene.render_settings), 0);
+
+	// Whole frames accumulate the same way, and start over on the same changes
+	let mut accumulation = Accumulation::new(10);
+	assert!(accumulation.update_view(&scene));
+	accumulation.accumulate(3, grey(100), 0.0);
+	assert!(!accumulation.update_view(&scene));
+	assert_eq!(accumulation.accumulate(3, grey(50), 0.0), grey(75));
+	// Exposure only changes how they're shown: a stop up, the same light comes out twice as bright
+	scene.render_settings.exposure = 1.0;
+	assert!(!accumulation.update_view(&scene));
+	assert_eq!(accumulation.accumulate(3, grey(150), 1.0), grey(150));
+	assert_eq!(accumulation.resolve(3, 0.0), Some(grey(75)));
+	scene.render_settings.samples_per_pixel = 2;
+	assert!(accumulation.update_view(&scene));
+	assert_eq!((accumulation.first_sample(3, &scene.render_settings), accumulation.accumulate(3, grey(50), 0.0)), (0, grey(50)));
+	assert_eq!(accumulation.resolve(4, 0.0), None);
+
+	// A focus gets all its passes once the frame has had one, then the rest catch up
+	let mut progressive = Progressive::new(4, 3, 10);
+	progressive.focus_on(Some(Tile { x: 2, y: 0, width: 2, height: 1 }), 10);
+	assert_eq!(progressive.next_pixels(&order), [0, 1, 2, 3]);
+	progressive.next_pixels(&order);
+	assert_eq!(progressive.next_pixels(&order), [8, 9, 0, 1]);
+	assert_eq!((progressive.next_pixels(&order), progressive.next_pixels(&order)), (vec![2, 3], vec![2, 3]));
+	assert_eq!(progressive.next_pixels(&order), [4, 5, 6, 7]);
+	let rest: Vec<u64> = (0..3).flat_map(|_| progressive.next_pixels(&order)).collect();
+	assert_eq!(rest, [8, 9, 0, 1, 4, 5, 6, 7, 8, 9]);
+	assert!(progressive.complete);
+
+	// Tiles round the cursor, and round an object on screen
+	assert_eq!(focus_area((40.0, 20.0), (40.0, 20.0), 160, 90), Some(Tile { x: 16, y: 0, width: 48, height: 48 }));
+	assert_eq!(focus_area((150.0, 80.0), (170.0, 100.0), 160, 90), Some(Tile { x: 128, y: 64, width: 32, height: 26 }));
+	assert_eq!(focus_area((-20.0, 0.0), (-1.0, 10.0), 160, 90), None);
+	scene.current_camera.look_at(&Vec3 { x: 1.0, y: 0.0, z: 1.0 }, &Vec3 { x: 0.0, y: 0.0, z: 1.0 });
+	let (min, max) = screen_bounds(&scene, "fuckin' cube", 160, 90).unwrap();
+	assert!(min.0 < 80.0 && max.0 > 80.0 && min.1 < 45.0 && max.1 > 45.0);
+	assert!(screen_bounds(&scene, "nothing", 160, 90).is_none());
+}
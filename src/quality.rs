@@ -0,0 +1,78 @@
+//
+//	Adaptive Quality
+//
+
+use crate::render::RenderSettings;
+
+// Cheapest setting last, each level gives up one more bit of quality
+const LEVELS: u32 = 8;
+// Frames in a row comfortably under budget before trying a better level
+const RECOVERY_FRAMES: u32 = 10;
+
+// Sits between the settings the user asked for and the renderer, trading quality for speed
+// whenever frames take longer than the budget, and going back to full quality when idle
+pub struct QualityController {
+	pub budget: f32, // seconds per frame
+	pub level: u32, // 0 renders exactly what was requested
+	fast_frames: u32,
+}
+
+impl QualityController {
+	pub fn new(budget: f32) -> Self {
+		QualityController { budget, level: 0, fast_frames: 0 }
+	}
+
+	// Feed it the last frame's duration, idle meaning nothing moved since that frame
+	pub fn update(&mut self, frame_time: f32, idle: bool) {
+		if idle {
+			self.level = 0;
+			self.fast_frames = 0;
+		} else if frame_time > self.budget {
+			self.level = (self.level + 1).min(LEVELS - 1);
+			self.fast_frames = 0;
+		} else if frame_time < self.budget * 0.5 && self.level > 0 {
+			self.fast_frames += 1;
+			if self.fast_frames >= RECOVERY_FRAMES {
+				self.level -= 1;
+				self.fast_frames = 0;
+			}
+		} else {
+			self.fast_frames = 0;
+		}
+	}
+
+	// What to actually render this frame given what was requested
+	pub fn apply(&self, requested: &RenderSettings) -> RenderSettings {
+		degrade(requested, self.level)
+	}
+}
+
+// Samples go first, then shadow samples, bounces and finally resolution
+pub fn degrade(requested: &RenderSettings, level: u32) -> RenderSettings {
+	let mut settings = *requested;
+	if level >= 1 { settings.samples_per_pixel = (settings.samples_per_pixel / 2).max(1) }
+	if level >= 2 { settings.samples_per_pixel = 1; settings.light_samples = (settings.light_samples / 2).max(1) }
+	if level >= 3 { settings.light_samples = 1 }
+	if level >= 4 { settings.max_bounces = 0 }
+	if level >= 5 { settings.pixel_scale = settings.pixel_scale.max(level - 3) }
+	settings
+}
+
+#[test]
+fn quality_follows_budget() {
+	let requested = RenderSettings { samples_per_pixel: 8, light_samples: 8, ..RenderSettings::new() };
+	let mut controller = QualityController::new(0.033);
+	for _ in 0..LEVELS * 2 {
+		controller.update(0.1, false);
+	}
+	let cheapest = controller.apply(&requested);
+	assert_eq!((cheapest.samples_per_pixel, cheapest.light_samples, cheapest.max_bounces, cheapest.pixel_scale), (1, 1, 0, 4));
+
+	// Fast frames win quality back slowly, idling restores it at once
+	for _ in 0..RECOVERY_FRAMES {
+		controller.update(0.001, false);
+	}
+	assert_eq!(controller.level, LEVELS - 2);
+	controller.update(0.1, true);
+	assert_eq!(controller.apply(&requested), requested);
+}
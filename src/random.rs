@@ -0,0 +1,53 @@
+//
+//	Random Numbers
+//
+
+// Small SplitMix64 generator, good enough for sample jitter and shuffling
+// without pulling in a dependency.
+#[derive(Clone, Copy, Debug)]
+pub struct Rng {
+	state: u64,
+}
+
+impl Rng {
+	pub fn new(seed: u64) -> Self {
+		Rng { state: seed }
+	}
+
+	pub fn next_u64(&mut self) -> u64 {
+		self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+		let mut z = self.state;
+		z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+		z ^ (z >> 31)
+	}
+
+	// Uniform in [0, 1)
+	pub fn next_f64(&mut self) -> f64 {
+		(self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+	}
+
+	// Uniform in [0, bound)
+	pub fn next_below(&mut self, bound: u64) -> u64 {
+		self.next_u64() % bound
+	}
+
+	pub fn shuffle<T>(&mut self, items: &mut [T]) {
+		for i in (1..items.len()).rev() {
+			let j = self.next_below(i as u64 + 1) as usize;
+			items.swap(i, j);
+		}
+	}
+}
+
+// Stateless 64-bit mix, used to decorrelate per-pixel sequences
+pub fn hash_u64(v: u64) -> u64 {
+	let mut z = v.wrapping_add(0x9E3779B97F4A7C15);
+	z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+	z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+	z ^ (z >> 31)
+}
+
+pub fn hash_pixel(x: u32, y: u32, dimension: u32, seed: u64) -> u64 {
+	hash_u64(((x as u64) << 40) ^ ((y as u64) << 16) ^ dimension as u64 ^ hash_u64(seed))
+}
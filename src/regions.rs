@@ -0,0 +1,96 @@
+//
+//	Regions of Interest
+//
+
+// When all that changed since the last frame is a few objects, most of the picture stays the same.
+// A pixel can only look different if one of its camera rays passes through a changed object's bounds,
+// before or after the change, or if the surface it shows sees a light past them, meaning a shadow
+// arrived or left. Reflective and transparent surfaces could show the change anywhere so they're always
+// included, and changes that light the whole scene differently give up and take every pixel
+
+use crate::structs::{Aabb, LightSource, Ray, RayMask, Scene, Vec3};
+use crate::tiles::render_tiles;
+
+// Where in a pixel the camera rays tested go, its corners and center
+const PIXEL_POINTS: [(f64, f64); 5] = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0), (0.5, 0.5)];
+
+// Whether the segment from start to end passes through bounds
+fn segment_hits(start: &Vec3, end: &Vec3, bounds: &Aabb) -> bool {
+	let ray = Ray { origin: *start, direction: end.sub(start), mask: RayMask::ALL, differential: None };
+	bounds.ray_distance(&ray).is_some_and(|distance| distance <= 1.0)
+}
+
+fn grown(bounds: &Aabb, by: f64) -> Aabb {
+	let by = Vec3 { x: by, y: by, z: by };
+	Aabb { min: bounds.min.sub(&by), max: bounds.max.add(&by) }
+}
+
+// The pixels of a width x height frame that changing objects[index] for each (index, bounds before)
+// could have affected, None when that's anything
+pub fn affected_pixels(scene: &Scene, changed: &[(usize, Option<Aabb>)], width: i32, height: i32) -> Option<Vec<bool>> {
+	// Light reaching everything, through a lens or through media, can change everywhere
+	if scene.current_camera.lens.is_some() || !scene.volumes.is_empty() { return None }
+	let mut bounds = Vec::new();
+	for (index, before) in changed.iter() {
+		let object = &scene.objects[*index];
+		if object.as_any_immut().is::<LightSource>() { return None }
+		bounds.push((*before)?);
+		bounds.push(object.bounds()?);
+	}
+	let lights = scene.get_all_light_sources_immut();
+	let affected = render_tiles(scene, width as usize, height as usize, |_, _| true, |x, y, _| {
+		let (x, y) = (x as i32, y as i32);
+		let ray = |offset: (f64, f64)| scene.primary_ray(x, y, offset, width, height);
+		if PIXEL_POINTS.iter().any(|offset| bounds.iter().any(|bounds| bounds.ray_distance(&ray(*offset)).is_some())) { return true }
+		match scene.trace(&ray((0.5, 0.5))) {
+			Some((_, material, _)) if material.reflectivity > 0.0 || material.transparency > 0.0 => true,
+			Some((point, _, _)) => lights.iter().any(|light| {
+				bounds.iter().any(|bounds| segment_hits(&point, &light.pos, &grown(bounds, light.radius as f64)))
+			}),
+			None => false,
+		}
+	});
+	// One more pixel all round, for samples landing away from the points tested
+	let affected: Vec<bool> = affected.into_iter().map(|pixel| pixel.unwrap_or(true)).collect();
+	Some((0..affected.len()).map(|index| {
+		let (x, y) = ((index % width as usize) as i32, (index / width as usize) as i32);
+		(-1..=1).any(|dy| (-1..=1).any(|dx| {
+			let (x, y) = (x + dx, y + dy);
+			x >= 0 && y >= 0 && x < width && y < height && affected[(y * width + x) as usize]
+		}))
+	}).collect())
+}
+
+#[test]
+fn moving_an_object_affects_its_pixels_and_shadow() {
+	use crate::structs::{Camera, Color, Material, Rot3, Sphere};
+	let material = |reflectivity: f32| Material { color: Color { r: 200, g: 200, b: 200 }, reflectivity, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+	let at = |x: f64, y: f64, z: f64| Vec3 { x, y, z };
+	// A small ball over a big one standing in for the floor, lit from straight above
+	let mut camera = Camera::new(at(0.0, -12.0, 4.0), Rot3::new(), 40.0);
+	camera.look_at(&at(0.0, 0.0, 0.0), &at(0.0, 0.0, 1.0));
+	let light = LightSource::new(at(0.0, 0.0, 8.0), Rot3::new(), 20.0);
+	let floor = Sphere::new(at(0.0, 0.0, -100.0), 100.0, material(0.0));
+	let ball = Sphere::new(at(-2.0, 0.0, 1.0), 0.5, material(0.0));
+	let mut scene = Scene::new(vec![Box::new(light), Box::new(floor), Box::new(ball)], camera);
+	let (width, height) = (64, 36);
+	let pixel_of = |scene: &Scene, point: Vec3| {
+		let (x, y) = scene.current_camera.project(&point, width, height).unwrap();
+		(y as i32 * width + x as i32) as usize
+	};
+
+	let before = scene.objects[2].bounds();
+	scene.objects[2].set_transform(at(2.0, 0.0, 1.0), Rot3::new());
+	let affected = affected_pixels(&scene, &[(2, before)], width, height).unwrap();
+	// Where it was and is, and the floor under both, but not the floor in between
+	for point in [at(-2.0, 0.0, 1.0), at(2.0, 0.0, 1.0), at(-2.0, 0.0, 0.0), at(2.0, 0.0, 0.0)] {
+		assert!(affected[pixel_of(&scene, point)]);
+	}
+	assert!(!affected[pixel_of(&scene, at(0.0, 0.0, 0.0))]);
+	assert!(affected.iter().filter(|pixel| **pixel).count() < affected.len() / 4);
+
+	// A mirror floor could show it anywhere, and moving the light changes the lighting everywhere
+	scene.objects[1].set_material(material(0.5));
+	assert!(affected_pixels(&scene, &[(2, before)], width, height).unwrap()[pixel_of(&scene, at(0.0, 0.0, 0.0))]);
+	assert!(affected_pixels(&scene, &[(0, None)], width, height).is_none());
+}
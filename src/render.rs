@@ -0,0 +1,315 @@
+//
+//	Render Settings
+//
+
+use crate::accelerator::AcceleratorKind;
+use crate::random::Rng;
+use crate::sampler::{Sampler, SamplerKind};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraversalOrder {
+	Scanline,
+	Spiral,
+	Hilbert,
+	Random,
+	BlueNoise,
+}
+
+impl TraversalOrder {
+	pub fn next(&self) -> TraversalOrder {
+		match self {
+			TraversalOrder::Scanline => TraversalOrder::Spiral,
+			TraversalOrder::Spiral => TraversalOrder::Hilbert,
+			TraversalOrder::Hilbert => TraversalOrder::Random,
+			TraversalOrder::Random => TraversalOrder::BlueNoise,
+			TraversalOrder::BlueNoise => TraversalOrder::Scanline,
+		}
+	}
+
+	// Every pixel index of a width x height buffer exactly once, in visiting order
+	pub fn pixel_order(&self, width: u64, height: u64, seed: u64) -> Vec<u64> {
+		match self {
+			TraversalOrder::Scanline => (0..width * height).collect(),
+			TraversalOrder::Spiral => spiral_order(width, height),
+			TraversalOrder::Hilbert => hilbert_order(width, height),
+			TraversalOrder::Random => {
+				let mut order: Vec<u64> = (0..width * height).collect();
+				Rng::new(seed).shuffle(&mut order);
+				order
+			}
+			TraversalOrder::BlueNoise => blue_noise_order(width, height, seed),
+		}
+	}
+}
+
+// The tile blue noise orders repeat, and how far each pixel's claim on its surroundings reaches
+const BLUE_NOISE_SIZE: usize = 32;
+const BLUE_NOISE_SIGMA: f64 = 1.9;
+
+// How light is gathered at what camera rays hit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Integrator {
+	Direct, // shadow rays to every light plus mirror reflections and refraction max_bounces deep, fast enough for interactive use
+	Sppm, // stochastic progressive photon mapping, one pass per sample, the whole frame each update_culling, for final renders
+	Path, // Monte Carlo path tracing, samples_per_pixel paths max_bounces bounces long, for final renders
+}
+
+impl Integrator {
+	pub fn from_name(name: &str) -> Option<Integrator> {
+		match name {
+			"direct" => Some(Integrator::Direct),
+			"sppm" => Some(Integrator::Sppm),
+			"path" => Some(Integrator::Path),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderSettings {
+	pub traversal_order: TraversalOrder,
+	pub sampler: SamplerKind,
+	pub samples_per_pixel: u32,
+	pub light_samples: u32, // shadow rays per area light, per pixel sample
+	pub seed: u64, // drives every stochastic choice, same seed + scene + settings = same image
+	pub cull_distance: f64, // objects entirely further than this are invisible to camera rays
+	pub interleaved: bool, // interactive mode tracing half the pixels per frame in a checkerboard
+	pub max_bounces: u32, // reflections and refractions followed after the camera hit, 0 skips them, for path tracing any bounce
+	pub pixel_scale: u32, // interactive only, one traced pixel covers a pixel_scale x pixel_scale block
+	pub ao_samples: u32, // hemisphere rays for the sky visibility AOV, 0 leaves it out
+	pub integrator: Integrator,
+	pub photons_per_pass: u32, // sppm only
+	pub exposure: f64, // stops, scales all light before shading clamps it
+	pub accelerator: AcceleratorKind, // what finds the objects a ray might hit
+	pub glossy_samples: u32, // rays per rough reflection, 0 for one cone traced ray as interactive mode uses
+	pub sampled_lights: u32, // lights picked per shading point from a light tree, 0 shades with every light
+}
+
+impl Default for RenderSettings {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl RenderSettings {
+	pub fn new() -> Self {
+		RenderSettings {
+			traversal_order: TraversalOrder::BlueNoise,
+			sampler: SamplerKind::Sobol,
+			samples_per_pixel: 1,
+			light_samples: 4,
+			seed: 0,
+			cull_distance: f64::INFINITY,
+			interleaved: false,
+			max_bounces: 1,
+			pixel_scale: 1,
+			ao_samples: 0,
+			integrator: Integrator::Direct,
+			photons_per_pass: 100000,
+			exposure: 0.0,
+			accelerator: AcceleratorKind::Bvh,
+			glossy_samples: 0,
+			sampled_lights: 0,
+		}
+	}
+}
+
+// The pixels of order to trace on the given frame: the top left pixel of every pixel_scale block,
+// and when interleaved only the checkerboard half of those whose parity matches the frame,
+// leaving the other half showing last frame
+pub fn frame_pixels<'a>(order: &'a [u64], width: u64, frame: u64, settings: &RenderSettings) -> impl Iterator<Item = u64> + 'a {
+	let settings = *settings;
+	order.iter().copied().filter(move |index| traced_on_frame(*index, width, frame, &settings))
+}
+
+// Whether frame_pixels has pixel index in it
+pub fn traced_on_frame(index: u64, width: u64, frame: u64, settings: &RenderSettings) -> bool {
+	let (scale, interleaved) = (settings.pixel_scale.max(1) as u64, settings.interleaved);
+	let (x, y) = (index % width, index / width);
+	x.is_multiple_of(scale) && y.is_multiple_of(scale) && (!interleaved || (x / scale + y / scale + frame).is_multiple_of(2))
+}
+
+// Buffers reused from ray to ray so steady state rendering doesn't touch the heap,
+// each rendering thread keeps its own and hands it down the trace call chain
+pub struct Scratch {
+	pub sampler: Box<dyn Sampler>,
+	pub light_samples: Vec<(f64, f64)>,
+	pub glossy_samples: Vec<(f64, f64)>,
+	pub picked_lights: Vec<(usize, f64)>, // by the light tree, object index and weight
+	sampler_key: (SamplerKind, u32, u64),
+}
+
+impl Scratch {
+	pub fn new(settings: &RenderSettings) -> Self {
+		let key = Self::sampler_key(settings);
+		Scratch {
+			sampler: settings.sampler.build(key.1, key.2),
+			light_samples: Vec::with_capacity(settings.light_samples as usize),
+			glossy_samples: Vec::with_capacity(settings.glossy_samples as usize),
+			picked_lights: Vec::with_capacity(settings.sampled_lights as usize),
+			sampler_key: key,
+		}
+	}
+
+	fn sampler_key(settings: &RenderSettings) -> (SamplerKind, u32, u64) {
+		(settings.sampler, settings.samples_per_pixel.max(1), settings.seed)
+	}
+
+	// Rebuilds the sampler only when the settings it was built from changed
+	pub fn prepare(&mut self, settings: &RenderSettings) {
+		let key = Self::sampler_key(settings);
+		if key != self.sampler_key {
+			self.sampler = settings.sampler.build(key.1, key.2);
+			self.sampler_key = key;
+		}
+	}
+}
+
+// Walks a square spiral out from the buffer center, skipping the legs that fall off-screen
+fn spiral_order(width: u64, height: u64) -> Vec<u64> {
+	let total = width * height;
+	let mut order = Vec::with_capacity(total as usize);
+	let (mut x, mut y) = ((width / 2) as i64, (height / 2) as i64);
+	let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+	let mut leg_len = 1;
+	let mut dir = 0;
+	let push = |x: i64, y: i64, order: &mut Vec<u64>| {
+		if x >= 0 && y >= 0 && (x as u64) < width && (y as u64) < height {
+			order.push(y as u64 * width + x as u64);
+		}
+	};
+	push(x, y, &mut order);
+	while (order.len() as u64) < total {
+		for _ in 0..2 {
+			let (dx, dy) = directions[dir];
+			for _ in 0..leg_len {
+				x += dx;
+				y += dy;
+				push(x, y, &mut order);
+			}
+			dir = (dir + 1) % 4;
+		}
+		leg_len += 1;
+	}
+	order
+}
+
+// Hilbert curve over the smallest power-of-two square covering the buffer
+fn hilbert_order(width: u64, height: u64) -> Vec<u64> {
+	let side = width.max(height).next_power_of_two();
+	let mut order = Vec::with_capacity((width * height) as usize);
+	for d in 0..side * side {
+		let (x, y) = hilbert_d2xy(side, d);
+		if x < width && y < height {
+			order.push(y * width + x);
+		}
+	}
+	order
+}
+
+fn hilbert_d2xy(side: u64, d: u64) -> (u64, u64) {
+	let (mut x, mut y) = (0, 0);
+	let mut t = d;
+	let mut s = 1;
+	while s < side {
+		let rx = 1 & (t / 2);
+		let ry = 1 & (t ^ rx);
+		if ry == 0 {
+			if rx == 1 {
+				x = s - 1 - x;
+				y = s - 1 - y;
+			}
+			std::mem::swap(&mut x, &mut y);
+		}
+		x += s * rx;
+		y += s * ry;
+		t /= 4;
+		s *= 2;
+	}
+	(x, y)
+}
+
+// Every prefix spread evenly over the buffer with no two of its pixels close together, so a pass
+// that's partly done looks like fine grain all over rather than a front sweeping across. Pixels are
+// ranked within a tile that repeats seamlessly, each rank visited across the tiles in a shuffled order
+fn blue_noise_order(width: u64, height: u64, seed: u64) -> Vec<u64> {
+	let side = BLUE_NOISE_SIZE as u64;
+	let (tiles_x, tiles_y) = (width.div_ceil(side), height.div_ceil(side));
+	let mut tiles: Vec<u64> = (0..tiles_x * tiles_y).collect();
+	let mut rng = Rng::new(seed);
+	let mut order = Vec::with_capacity((width * height) as usize);
+	for position in blue_noise_ranks() {
+		rng.shuffle(&mut tiles);
+		for tile in tiles.iter() {
+			let x = tile % tiles_x * side + (position % BLUE_NOISE_SIZE) as u64;
+			let y = tile / tiles_x * side + (position / BLUE_NOISE_SIZE) as u64;
+			if x < width && y < height {
+				order.push(y * width + x);
+			}
+		}
+	}
+	order
+}
+
+// The positions of a BLUE_NOISE_SIZE square tile by rank, each the middle of the biggest gap left by
+// the ones before it: the least covered by a gaussian around every one already placed, wrapping
+// round the edges so the tile repeats without seams
+fn blue_noise_ranks() -> Vec<usize> {
+	let side = BLUE_NOISE_SIZE;
+	let wrapped = |d: usize| d.min(side - d) as f64;
+	let kernel: Vec<f64> = (0..side * side).map(|index| {
+		let (dx, dy) = (wrapped(index % side), wrapped(index / side));
+		(-(dx * dx + dy * dy) / (2.0 * BLUE_NOISE_SIGMA * BLUE_NOISE_SIGMA)).exp()
+	}).collect();
+	let (mut covered, mut placed) = (vec![0.0f64; side * side], vec![false; side * side]);
+	let mut ranks = Vec::with_capacity(side * side);
+	while ranks.len() < side * side {
+		let next = (0..side * side).filter(|index| !placed[*index]).min_by(|a, b| covered[*a].total_cmp(&covered[*b])).unwrap_or(0);
+		placed[next] = true;
+		ranks.push(next);
+		for (index, covered) in covered.iter_mut().enumerate() {
+			let (dx, dy) = ((index % side + side - next % side) % side, (index / side + side - next / side) % side);
+			*covered += kernel[dy * side + dx];
+		}
+	}
+	ranks
+}
+
+#[test]
+fn traversal_orders_cover_every_pixel() {
+	let orders = [TraversalOrder::Scanline, TraversalOrder::Spiral, TraversalOrder::Hilbert, TraversalOrder::Random, TraversalOrder::BlueNoise];
+	for order in orders.iter() {
+		let mut pixels = order.pixel_order(160, 90, 1);
+		assert_eq!(pixels.len(), 160 * 90);
+		pixels.sort_unstable();
+		pixels.dedup();
+		assert_eq!(pixels.len(), 160 * 90);
+	}
+
+	// A blue noise order's first few hundred pixels are all well apart, unlike a random one's
+	let closest = |order: &[u64]| {
+		let mut closest = f64::INFINITY;
+		for (i, a) in order.iter().enumerate() {
+			for b in order[i + 1..].iter() {
+				let (dx, dy) = ((a % 160) as f64 - (b % 160) as f64, (a / 160) as f64 - (b / 160) as f64);
+				closest = closest.min((dx * dx + dy * dy).sqrt());
+			}
+		}
+		closest
+	};
+	let (blue, random) = (TraversalOrder::BlueNoise.pixel_order(160, 90, 1), TraversalOrder::Random.pixel_order(160, 90, 1));
+	assert!(closest(&blue[..14 * 20]) >= 4.0, "{}", closest(&blue[..14 * 20]));
+	assert!(closest(&random[..14 * 20]) < 2.0);
+}
+
+#[test]
+fn interleaved_frames_alternate() {
+	let order = TraversalOrder::Hilbert.pixel_order(160, 90, 0);
+	let settings = RenderSettings { interleaved: true, ..RenderSettings::new() };
+	let even: Vec<u64> = frame_pixels(&order, 160, 4, &settings).collect();
+	let mut both: Vec<u64> = even.iter().copied().chain(frame_pixels(&order, 160, 5, &settings)).collect();
+	assert_eq!(even.len(), 160 * 90 / 2);
+	both.sort_unstable();
+	both.dedup();
+	assert_eq!(both.len(), 160 * 90);
+}
@@ -0,0 +1,90 @@
+//
+//	Dynamic Resolution
+//
+
+// Interactive frames traced at a fraction of the viewport's size and stretched back up to it, for
+// when every pixel costs too much. The scale can be fixed, or given a frame time to chase, in which
+// case it drops while the camera moves and goes back to the fixed scale once everything stops
+
+use crate::structs::Color;
+
+pub const MIN_SCALE: f64 = 0.25;
+// How far towards the scale that would hit the target one frame goes, so it settles rather than jumps
+const STEP: f64 = 0.5;
+
+pub struct DynamicResolution {
+	pub scale: f64, // what's asked for, and what idle frames render at
+	pub target: Option<f32>, // seconds per frame to aim for while moving, None keeps to scale
+	pub current: f64,
+}
+
+impl DynamicResolution {
+	pub fn new(scale: f64, target: Option<f32>) -> Self {
+		let scale = scale.clamp(MIN_SCALE, 1.0);
+		DynamicResolution { scale, target, current: scale }
+	}
+
+	// Feed it the last frame's duration, idle meaning nothing moved since that frame
+	pub fn update(&mut self, frame_time: f32, idle: bool) {
+		self.current = match self.target {
+			Some(target) if !idle && frame_time > 0.0 => {
+				// Frame time goes with the pixel count, the square of the scale
+				let wanted = self.current * (target as f64 / frame_time as f64).sqrt();
+				(self.current + (wanted - self.current) * STEP).clamp(MIN_SCALE, self.scale)
+			}
+			_ => self.scale,
+		};
+	}
+
+	// The size to trace at for a viewport of width x height
+	pub fn size(&self, width: usize, height: usize) -> (usize, usize) {
+		let scaled = |length: usize| ((length as f64 * self.current).round() as usize).clamp(1, length);
+		(scaled(width), scaled(height))
+	}
+}
+
+// Bilinear stretch of a width x height image to to_width x to_height, pixel centers lined up
+pub fn upscale(colors: &[Color], width: usize, height: usize, to_width: usize, to_height: usize) -> Vec<Color> {
+	// Source position and blend for a destination pixel along one axis
+	let source = |to: usize, from: usize, to_length: usize| {
+		let position = ((to as f64 + 0.5) * from as f64 / to_length as f64 - 0.5).clamp(0.0, (from - 1) as f64);
+		let low = position.floor() as usize;
+		(low, (low + 1).min(from - 1), position - low as f64)
+	};
+	let mut upscaled = Vec::with_capacity(to_width * to_height);
+	for y in 0..to_height {
+		let (top, bottom, v) = source(y, height, to_height);
+		for x in 0..to_width {
+			let (left, right, u) = source(x, width, to_width);
+			let upper = colors[top * width + left].lerp(&colors[top * width + right], u);
+			let lower = colors[bottom * width + left].lerp(&colors[bottom * width + right], u);
+			upscaled.push(upper.lerp(&lower, v));
+		}
+	}
+	upscaled
+}
+
+#[test]
+fn resolution_scales_and_stretches() {
+	// Half size chasing 10ms: slow moving frames shrink it, never below the floor, idling restores it
+	let mut resolution = DynamicResolution::new(0.5, Some(0.01));
+	assert_eq!(resolution.size(160, 90), (80, 45));
+	resolution.update(0.04, false);
+	assert!(resolution.current < 0.5 && resolution.current > MIN_SCALE);
+	for _ in 0..20 {
+		resolution.update(1.0, false);
+	}
+	assert_eq!(resolution.size(160, 90), (40, 23));
+	resolution.update(0.001, false);
+	assert!(resolution.current > MIN_SCALE && resolution.current <= 0.5);
+	resolution.update(1.0, true);
+	assert_eq!(resolution.current, 0.5);
+	assert_eq!(DynamicResolution::new(3.0, None).size(7, 3), (7, 3));
+
+	// Black then white, doubled: the ends keep their colors and the middle blends
+	let (black, white) = (Color { r: 0, g: 0, b: 0 }, Color { r: 255, g: 255, b: 255 });
+	let wide = upscale(&[black, white], 2, 1, 4, 2);
+	let reds: Vec<u8> = wide.iter().map(|color| color.r).collect();
+	assert_eq!(reds, [0, 64, 191, 255, 0, 64, 191, 255]);
+	assert_eq!(upscale(&[white], 1, 1, 3, 3).len(), 9);
+}
@@ -0,0 +1,435 @@
+//
+//	Samplers
+//
+
+use crate::random::{Rng, hash_pixel};
+use crate::structs::Vec3;
+
+// Supplies sample points in [0, 1) for one pixel sample at a time. Each call to
+// next_1d/next_2d consumes the next dimension(s) of the sample vector, so callers
+// should request dimensions in a fixed order (AA first, then lens, lights, ...).
+pub trait Sampler: Send + Sync {
+	fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32);
+	fn next_1d(&mut self) -> f64;
+
+	fn next_2d(&mut self) -> (f64, f64) {
+		(self.next_1d(), self.next_1d())
+	}
+
+	// A set of 2D points for one integration domain within the current pixel sample
+	// (e.g. all shadow samples of an area light). Stratifying samplers spread the
+	// set over the domain; the rest just hand out consecutive dimensions.
+	fn next_2d_set(&mut self, count: u32, out: &mut Vec<(f64, f64)>) {
+		out.clear();
+		for _ in 0..count {
+			out.push(self.next_2d());
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SamplerKind {
+	Independent,
+	Halton,
+	Sobol,
+	BlueNoise,
+	Stratified,
+}
+
+impl SamplerKind {
+	pub fn next(&self) -> SamplerKind {
+		match self {
+			SamplerKind::Independent => SamplerKind::Halton,
+			SamplerKind::Halton => SamplerKind::Sobol,
+			SamplerKind::Sobol => SamplerKind::BlueNoise,
+			SamplerKind::BlueNoise => SamplerKind::Stratified,
+			SamplerKind::Stratified => SamplerKind::Independent,
+		}
+	}
+
+	pub fn build(&self, samples_per_pixel: u32, seed: u64) -> Box<dyn Sampler> {
+		match self {
+			SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+			SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+			SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+			SamplerKind::BlueNoise => Box::new(BlueNoiseSampler::new(seed)),
+			SamplerKind::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+		}
+	}
+}
+
+fn hash_to_unit(h: u64) -> f64 {
+	(h >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+}
+
+// Plain white noise, the baseline everything else is compared against
+pub struct IndependentSampler {
+	rng: Rng,
+	seed: u64,
+}
+
+impl IndependentSampler {
+	pub fn new(seed: u64) -> Self {
+		IndependentSampler { rng: Rng::new(seed), seed }
+	}
+}
+
+impl Sampler for IndependentSampler {
+	fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+		self.rng = Rng::new(hash_pixel(x, y, sample_index, self.seed));
+	}
+
+	fn next_1d(&mut self) -> f64 {
+		self.rng.next_f64()
+	}
+}
+
+const PRIMES: [u32; 16] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53];
+
+fn radical_inverse(base: u32, mut index: u32) -> f64 {
+	let inv_base = 1.0 / base as f64;
+	let mut inv_base_n = 1.0;
+	let mut reversed = 0.0;
+	while index > 0 {
+		let digit = index % base;
+		inv_base_n *= inv_base;
+		reversed += digit as f64 * inv_base_n;
+		index /= base;
+	}
+	reversed
+}
+
+// Halton sequence with a per-pixel Cranley-Patterson rotation so neighbouring
+// pixels don't share the same sample pattern
+pub struct HaltonSampler {
+	seed: u64,
+	pixel: (u32, u32),
+	sample_index: u32,
+	dimension: u32,
+}
+
+impl HaltonSampler {
+	pub fn new(seed: u64) -> Self {
+		HaltonSampler { seed, pixel: (0, 0), sample_index: 0, dimension: 0 }
+	}
+}
+
+impl Sampler for HaltonSampler {
+	fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+		self.pixel = (x, y);
+		self.sample_index = sample_index;
+		self.dimension = 0;
+	}
+
+	fn next_1d(&mut self) -> f64 {
+		let dim = self.dimension;
+		self.dimension += 1;
+		let offset = hash_to_unit(hash_pixel(self.pixel.0, self.pixel.1, dim, self.seed));
+		if (dim as usize) >= PRIMES.len() {
+			return offset;
+		}
+		(radical_inverse(PRIMES[dim as usize], self.sample_index) + offset).fract()
+	}
+}
+
+// (s, a, m_i) from the Joe-Kuo direction number tables; dimension 0 is van der Corput
+const SOBOL_PARAMS: [(u32, u32, [u32; 5]); 9] = [
+	(1, 0, [1, 0, 0, 0, 0]),
+	(2, 1, [1, 3, 0, 0, 0]),
+	(3, 1, [1, 3, 1, 0, 0]),
+	(3, 2, [1, 1, 1, 0, 0]),
+	(4, 1, [1, 1, 3, 3, 0]),
+	(4, 4, [1, 3, 5, 13, 0]),
+	(5, 2, [1, 1, 5, 5, 17]),
+	(5, 4, [1, 1, 5, 5, 5]),
+	(5, 7, [1, 1, 7, 11, 19]),
+];
+
+fn sobol_directions(dimension: usize) -> [u32; 32] {
+	let mut v = [0u32; 32];
+	if dimension == 0 {
+		for (i, d) in v.iter_mut().enumerate() {
+			*d = 1 << (31 - i);
+		}
+		return v;
+	}
+	let (s, a, m) = SOBOL_PARAMS[dimension - 1];
+	let s = s as usize;
+	for i in 0..s {
+		v[i] = m[i] << (31 - i);
+	}
+	for i in s..32 {
+		v[i] = v[i - s] ^ (v[i - s] >> s);
+		for k in 1..s {
+			v[i] ^= ((a >> (s - 1 - k)) & 1) * v[i - k];
+		}
+	}
+	v
+}
+
+// Sobol (0,2)-sequence points with per-pixel random digit scrambling (xor),
+// which keeps the stratification of each pixel's sample set intact
+pub struct SobolSampler {
+	directions: Vec<[u32; 32]>,
+	seed: u64,
+	pixel: (u32, u32),
+	sample_index: u32,
+	dimension: u32,
+}
+
+impl SobolSampler {
+	pub fn new(seed: u64) -> Self {
+		SobolSampler {
+			directions: (0..=SOBOL_PARAMS.len()).map(sobol_directions).collect(),
+			seed,
+			pixel: (0, 0),
+			sample_index: 0,
+			dimension: 0,
+		}
+	}
+}
+
+impl Sampler for SobolSampler {
+	fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+		self.pixel = (x, y);
+		self.sample_index = sample_index;
+		self.dimension = 0;
+	}
+
+	fn next_1d(&mut self) -> f64 {
+		let dim = self.dimension;
+		self.dimension += 1;
+		let scramble = hash_pixel(self.pixel.0, self.pixel.1, dim, self.seed);
+		if (dim as usize) >= self.directions.len() {
+			return hash_to_unit(scramble);
+		}
+		let v = &self.directions[dim as usize];
+		let mut result = 0u32;
+		let mut index = self.sample_index;
+		let mut bit = 0;
+		while index > 0 {
+			if index & 1 == 1 {
+				result ^= v[bit];
+			}
+			index >>= 1;
+			bit += 1;
+		}
+		(result ^ scramble as u32) as f64 * (1.0 / 4294967296.0)
+	}
+}
+
+// Interleaved gradient noise gives a cheap blue-noise-like per-pixel offset; successive
+// samples advance along the golden ratio so each pixel's samples stay well spread.
+pub struct BlueNoiseSampler {
+	offset: f64,
+	pixel: (u32, u32),
+	sample_index: u32,
+	dimension: u32,
+}
+
+impl BlueNoiseSampler {
+	pub fn new(seed: u64) -> Self {
+		// The seed only shifts the sequence; the spatial noise pattern itself is fixed
+		BlueNoiseSampler { offset: hash_to_unit(seed), pixel: (0, 0), sample_index: 0, dimension: 0 }
+	}
+}
+
+fn interleaved_gradient_noise(x: f64, y: f64) -> f64 {
+	(52.9829189 * (0.06711056 * x + 0.00583715 * y).fract()).fract()
+}
+
+impl Sampler for BlueNoiseSampler {
+	fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+		self.pixel = (x, y);
+		self.sample_index = sample_index;
+		self.dimension = 0;
+	}
+
+	fn next_1d(&mut self) -> f64 {
+		let dim = self.dimension;
+		self.dimension += 1;
+		// Shift the noise tile per dimension so dimensions aren't correlated
+		let x = self.pixel.0 as f64 + 5.588238 * dim as f64;
+		let y = self.pixel.1 as f64 + 3.348227 * dim as f64;
+		let golden = 0.618_033_988_749_894_8;
+		(interleaved_gradient_noise(x, y) + golden * self.sample_index as f64 + self.offset).fract()
+	}
+}
+
+// Kensler's hash-based permutation of [0, len), different for every pattern value
+fn permute(mut i: u32, len: u32, pattern: u32) -> u32 {
+	let mut w = len - 1;
+	w |= w >> 1;
+	w |= w >> 2;
+	w |= w >> 4;
+	w |= w >> 8;
+	w |= w >> 16;
+	loop {
+		i ^= pattern;
+		i = i.wrapping_mul(0xe170893d);
+		i ^= pattern >> 16;
+		i ^= (i & w) >> 4;
+		i ^= pattern >> 8;
+		i = i.wrapping_mul(0x0929eb3f);
+		i ^= pattern >> 23;
+		i ^= (i & w) >> 1;
+		i = i.wrapping_mul(1 | pattern >> 27);
+		i = i.wrapping_mul(0x6935fa69);
+		i ^= (i & w) >> 11;
+		i = i.wrapping_mul(0x74dcb303);
+		i ^= (i & w) >> 2;
+		i = i.wrapping_mul(0x9e501cc3);
+		i ^= (i & w) >> 2;
+		i = i.wrapping_mul(0xc860a3df);
+		i &= w;
+		i ^= i >> 5;
+		if i < len { break }
+	}
+	(i.wrapping_add(pattern)) % len
+}
+
+fn rand_unit(mut i: u32, pattern: u32) -> f64 {
+	i ^= pattern;
+	i ^= i >> 17;
+	i ^= i >> 10;
+	i = i.wrapping_mul(0xb36534e5);
+	i ^= i >> 12;
+	i ^= i >> 21;
+	i = i.wrapping_mul(0x93fc4795);
+	i ^= 0xdf6e307f;
+	i ^= i >> 17;
+	i = i.wrapping_mul(1 | pattern >> 18);
+	i as f64 * (1.0 / 4294967808.0)
+}
+
+// Correlated multi-jittered point `index` of a `count` point set: jittered in an m x n
+// grid and n-rooks stratified along both axes at the same time
+fn cmj(index: u32, count: u32, pattern: u32) -> (f64, f64) {
+	let m = (count as f64).sqrt().ceil().max(1.0) as u32;
+	let n = count.div_ceil(m).max(1);
+	let s = permute(index, m * n, pattern.wrapping_mul(0x51633e2d));
+	let sx = permute(s % m, m, pattern.wrapping_mul(0xa511e9b3));
+	let sy = permute(s / m, n, pattern.wrapping_mul(0x63d83595));
+	let jx = rand_unit(s, pattern.wrapping_mul(0xa399d265));
+	let jy = rand_unit(s, pattern.wrapping_mul(0x711ad6a5));
+	(
+		((s % m) as f64 + (sy as f64 + jx) / n as f64) / m as f64,
+		((s / m) as f64 + (sx as f64 + jy) / m as f64) / n as f64,
+	)
+}
+
+// Stratified jitter across the samples of a pixel. Every pixel and dimension gets its
+// own permutation of the strata, so neighbouring pixels don't line up into bands.
+pub struct StratifiedSampler {
+	samples_per_pixel: u32,
+	seed: u64,
+	pixel: (u32, u32),
+	sample_index: u32,
+	dimension: u32,
+}
+
+impl StratifiedSampler {
+	pub fn new(samples_per_pixel: u32, seed: u64) -> Self {
+		StratifiedSampler { samples_per_pixel: samples_per_pixel.max(1), seed, pixel: (0, 0), sample_index: 0, dimension: 0 }
+	}
+
+	fn pattern(&mut self) -> u32 {
+		let dim = self.dimension;
+		self.dimension += 1;
+		hash_pixel(self.pixel.0, self.pixel.1, dim, self.seed) as u32
+	}
+}
+
+impl Sampler for StratifiedSampler {
+	fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+		self.pixel = (x, y);
+		self.sample_index = sample_index % self.samples_per_pixel;
+		self.dimension = 0;
+	}
+
+	fn next_1d(&mut self) -> f64 {
+		let pattern = self.pattern();
+		let stratum = permute(self.sample_index, self.samples_per_pixel, pattern);
+		(stratum as f64 + rand_unit(self.sample_index, pattern.wrapping_mul(0x68bc21eb))) / self.samples_per_pixel as f64
+	}
+
+	fn next_2d(&mut self) -> (f64, f64) {
+		let pattern = self.pattern();
+		cmj(self.sample_index, self.samples_per_pixel, pattern)
+	}
+
+	fn next_2d_set(&mut self, count: u32, out: &mut Vec<(f64, f64)>) {
+		// Mixing in the sample index gives every pixel sample its own set
+		let pattern = self.pattern() ^ self.sample_index.wrapping_mul(0x9e3779b9);
+		out.clear();
+		for i in 0..count {
+			out.push(cmj(i, count, pattern));
+		}
+	}
+}
+
+// Maps a unit square sample to a unit disk (concentric mapping), for lens and disk light sampling
+pub fn sample_disk(u: f64, v: f64) -> (f64, f64) {
+	let ox = 2.0 * u - 1.0;
+	let oy = 2.0 * v - 1.0;
+	if ox == 0.0 && oy == 0.0 {
+		return (0.0, 0.0);
+	}
+	let quarter_pi = std::f64::consts::FRAC_PI_4;
+	let (r, theta) = if ox.abs() > oy.abs() {
+		(ox, quarter_pi * (oy / ox))
+	} else {
+		(oy, 2.0 * quarter_pi - quarter_pi * (ox / oy))
+	};
+	(r * theta.cos(), r * theta.sin())
+}
+
+// Cosine-weighted direction around the +z axis
+pub fn sample_cosine_hemisphere(u: f64, v: f64) -> Vec3 {
+	let (x, y) = sample_disk(u, v);
+	let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+	Vec3 { x, y, z }
+}
+
+// Uniform direction within cos_max of the +z axis, for glossy reflections
+pub fn sample_cone(u: f64, v: f64, cos_max: f64) -> Vec3 {
+	let z = 1.0 - u * (1.0 - cos_max);
+	let r = (1.0 - z * z).max(0.0).sqrt();
+	let phi = 2.0 * std::f64::consts::PI * v;
+	Vec3 { x: r * phi.cos(), y: r * phi.sin(), z }
+}
+
+// Uniform direction on the unit sphere, for spherical light sampling
+pub fn sample_sphere(u: f64, v: f64) -> Vec3 {
+	let z = 1.0 - 2.0 * u;
+	let r = (1.0 - z * z).max(0.0).sqrt();
+	let phi = 2.0 * std::f64::consts::PI * v;
+	Vec3 { x: r * phi.cos(), y: r * phi.sin(), z }
+}
+
+#[test]
+fn samplers_stay_in_unit_interval() {
+	let kinds = [SamplerKind::Independent, SamplerKind::Halton, SamplerKind::Sobol, SamplerKind::BlueNoise, SamplerKind::Stratified];
+	for kind in kinds.iter() {
+		let mut sampler = kind.build(64, 7);
+		for sample_index in 0..64 {
+			sampler.start_pixel_sample(17, 5, sample_index);
+			for _ in 0..20 {
+				let v = sampler.next_1d();
+				assert!((0.0..1.0).contains(&v));
+			}
+		}
+	}
+}
+
+#[test]
+fn stratified_samples_cover_every_stratum() {
+	let mut sampler = StratifiedSampler::new(16, 7);
+	let mut strata = [false; 16];
+	for sample_index in 0..16 {
+		sampler.start_pixel_sample(3, 9, sample_index);
+		let (u, v) = sampler.next_2d();
+		strata[(v * 4.0) as usize * 4 + (u * 4.0) as usize] = true;
+	}
+	assert!(strata.iter().all(|s| *s));
+}
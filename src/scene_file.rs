@@ -0,0 +1,1138 @@
+//
+//	Scene Files
+//
+
+// Scenes on disk are RON. Positions are (x, y, z), rotations (yaw, pitch, roll) in degrees.
+// A file can pull in other files as references, which places their objects under its own transform:
+//
+//	Reference(path: "car.ron", pos: (4.0, 0.0, 0.0), rot: (90.0, 0.0, 0.0), instance: true)
+//
+// Flattened references (the default) bake the transform into copies of the geometry, references
+// nested in them included; instanced ones share the geometry with every other instance of the same file.
+//
+// Scenes are in meters. A file in other units says so, and can be scaled as a whole on top of that:
+//
+//	(units: Centimeters, scale: 2.0, objects: [...])
+//
+// Both apply to everything in the file including what it references, which converts from its own units.
+//
+// Objects with ids can be animated, speeds in degrees per second:
+//
+//	animations: [ (target: "fan", modifiers: [ Spin(axis: (0.0, 0.0, 1.0), speed: 90.0) ]) ]
+//
+// Meshes can carry morph targets, per-vertex offsets blended in by weight, and animations can key those weights:
+//
+//	animations: [ (target: "face", weights: [ (morph: "smile", keys: [(0.0, 0.0), (1.0, 1.0), (2.0, 0.0)]) ]) ]
+//
+// or dropped as rigid bodies, spheres as themselves and meshes as boxes, with gravity in m/s^2:
+//
+//	(gravity: Some((0.0, 0.0, -9.81)), bodies: [ (target: "ball", velocity: (1.0, 0.0, 0.0)) ])
+//
+// or filled with a medium, see media.rs. The file that gets loaded can cut the scene open, see clipping.rs,
+// put a photograph behind it, see backplate.rs, light it from all round, see environment.rs, and name
+// bundles of render settings, see presets.rs. Big meshes' hierarchies are kept on disk between loads,
+// see bvh_cache.rs
+
+use crate::animation::{Animation, Modifier, WeightTrack};
+use crate::archive;
+use crate::backplate::{Backplate, BackplateFit};
+use crate::bvh_cache::BvhCache;
+use crate::clipping::ClipPlane;
+use crate::environment::Environment;
+use crate::physics::{Body, Physics, Shape, STANDARD_GRAVITY};
+use crate::presets::{builtin_presets, RenderPreset};
+use crate::structs::{Camera, Color, LightSource, Material, Mesh, MeshGeometry, MorphTarget, Rot3, Scene, SceneObject, Sphere, Spot, Tri, Vec3};
+use crate::label::text_tris;
+use crate::media::{Medium, Volume};
+use crate::point_cloud::PointCloud;
+use crate::texture::Texture;
+use crate::voxel::VoxelGrid;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SceneFile {
+	pub units: Units,
+	pub scale: f64,
+	pub camera: Option<CameraDesc>, // only used in the file that gets loaded, not in references
+	pub materials: BTreeMap<String, MaterialDesc>,
+	pub objects: Vec<ObjectDesc>,
+	pub animations: Vec<AnimationDesc>,
+	pub gravity: Option<[f64; 3]>, // standard gravity when left out, only used in the file that gets loaded
+	pub bodies: Vec<BodyDesc>,
+	pub volumes: Vec<VolumeDesc>,
+	pub clip_planes: Vec<ClipPlaneDesc>, // only used in the file that gets loaded
+	pub backplate: Option<BackplateDesc>, // likewise
+	pub environment: Option<EnvironmentDesc>, // likewise
+	pub presets: BTreeMap<String, RenderPreset>, // likewise, over the built in ones
+}
+
+impl Default for SceneFile {
+	fn default() -> Self {
+		SceneFile {
+			units: Units::Meters,
+			scale: 1.0,
+			camera: None,
+			materials: BTreeMap::new(),
+			objects: Vec::new(),
+			animations: Vec::new(),
+			gravity: None,
+			bodies: Vec::new(),
+			volumes: Vec::new(),
+			clip_planes: Vec::new(),
+			backplate: None,
+			environment: None,
+			presets: BTreeMap::new(),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Units {
+	Meters,
+	Centimeters,
+	Millimeters,
+	Inches,
+	Feet,
+}
+
+impl Units {
+	pub fn meters(&self) -> f64 {
+		match self {
+			Units::Meters => 1.0,
+			Units::Centimeters => 0.01,
+			Units::Millimeters => 0.001,
+			Units::Inches => 0.0254,
+			Units::Feet => 0.3048,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CameraDesc {
+	pub pos: [f64; 3],
+	#[serde(default)]
+	pub rot: [f64; 3],
+	#[serde(default)]
+	pub look_at: Option<[f64; 3]>, // takes over from rot when set
+	pub fov: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaterialDesc {
+	pub color: [u8; 3],
+	pub reflectivity: f32,
+	pub transparency: f32,
+	pub roughness: f32,
+	pub ior: f32,
+}
+
+impl Default for MaterialDesc {
+	fn default() -> Self {
+		MaterialDesc { color: [255, 255, 255], reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 }
+	}
+}
+
+// Objects refer to materials by their name in the file's material table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ObjectDesc {
+	Mesh {
+		#[serde(default)]
+		id: Option<String>,
+		#[serde(default)]
+		pos: [f64; 3],
+		#[serde(default)]
+		rot: [f64; 3],
+		positions: Vec<[f64; 3]>,
+		#[serde(default)]
+		normals: Vec<[f64; 3]>,
+		indices: Vec<[u32; 3]>,
+		materials: Vec<String>,
+		#[serde(default)]
+		material_ids: Vec<u32>, // per triangle, all 0 when left out
+		#[serde(default)]
+		uvs: Vec<[f64; 2]>, // per vertex, for the texture
+		#[serde(default)]
+		texture: Option<String>, // png, relative to the file, tinting the materials
+		#[serde(default)]
+		morph_targets: Vec<MorphTargetDesc>,
+		#[serde(default)]
+		weights: Vec<f64>, // of the morph targets, zero when left out
+	},
+	Cube {
+		#[serde(default)]
+		id: Option<String>,
+		#[serde(default)]
+		pos: [f64; 3],
+		#[serde(default)]
+		rot: [f64; 3],
+		size: f64, // edge length
+		material: String,
+	},
+	Plane {
+		#[serde(default)]
+		id: Option<String>,
+		#[serde(default)]
+		pos: [f64; 3],
+		#[serde(default)]
+		rot: [f64; 3],
+		size: f64, // edge length, lying in the local xy plane
+		material: String,
+	},
+	// Extruded text, see label::text_tris
+	Text {
+		#[serde(default)]
+		id: Option<String>,
+		#[serde(default)]
+		pos: [f64; 3],
+		#[serde(default)]
+		rot: [f64; 3],
+		text: String,
+		height: f64, // of a capital
+		#[serde(default)]
+		depth: Option<f64>, // a fifth of the height when left out
+		material: String,
+	},
+	Sphere {
+		#[serde(default)]
+		id: Option<String>,
+		pos: [f64; 3],
+		radius: f32,
+		material: String,
+	},
+	// Text chunk, see VoxelGrid::parse, with the materials in it
+	Voxels {
+		#[serde(default)]
+		id: Option<String>,
+		#[serde(default)]
+		pos: [f64; 3], // corner of the first cell
+		#[serde(default)]
+		rot: [f64; 3],
+		path: String, // relative to the file
+		size: f64, // edge length of a cell
+	},
+	// Text point list, see PointCloud::parse
+	Points {
+		#[serde(default)]
+		id: Option<String>,
+		#[serde(default)]
+		pos: [f64; 3],
+		#[serde(default)]
+		rot: [f64; 3],
+		path: String, // relative to the file
+		radius: f64, // of every point
+	},
+	Light {
+		#[serde(default)]
+		id: Option<String>,
+		pos: [f64; 3],
+		#[serde(default)]
+		rot: [f64; 3], // aims spot lights
+		intensity: f32,
+		#[serde(default = "white")]
+		color: [u8; 3],
+		#[serde(default)]
+		radius: f32,
+		// Light linking, ids of objects in the same file
+		#[serde(default)]
+		include: Vec<String>,
+		#[serde(default)]
+		exclude: Vec<String>,
+		#[serde(default)]
+		spot: Option<SpotDesc>,
+	},
+	Reference {
+		#[serde(default)]
+		id: Option<String>, // prefixed to the ids of everything the reference brings in
+		path: String, // relative to the referencing file
+		#[serde(default)]
+		pos: [f64; 3],
+		#[serde(default)]
+		rot: [f64; 3],
+		#[serde(default)]
+		instance: bool,
+		// Material names used anywhere under the reference, mapped to materials of this file
+		#[serde(default)]
+		overrides: BTreeMap<String, String>,
+	},
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpotDesc {
+	pub angle: f64, // degrees from the axis to the edge
+	#[serde(default)]
+	pub blend: f64,
+	#[serde(default)]
+	pub gobo: Option<String>, // png, relative to the file
+}
+
+// Offsets for every vertex of the mesh, in the same order as its positions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MorphTargetDesc {
+	pub name: String,
+	pub positions: Vec<[f64; 3]>,
+	#[serde(default)]
+	pub normals: Vec<[f64; 3]>,
+}
+
+// Animated meshes keep their own transform even in flattened references, so they can move
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnimationDesc {
+	pub target: String, // id of an object in the same file
+	#[serde(default)]
+	pub modifiers: Vec<ModifierDesc>,
+	#[serde(default)]
+	pub weights: Vec<WeightTrackDesc>,
+}
+
+// Keys are (seconds, weight), see WeightTrack
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeightTrackDesc {
+	pub morph: String, // name of one of the target's morph targets
+	pub keys: Vec<(f64, f64)>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum ModifierDesc {
+	Spin { axis: [f64; 3], speed: f64 },
+	Orbit { center: [f64; 3], axis: [f64; 3], speed: f64 },
+	Bob {
+		offset: [f64; 3],
+		frequency: f64,
+		#[serde(default)]
+		phase: f64, // degrees
+	},
+}
+
+// Like animated ones, meshes that are bodies keep their own transform
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BodyDesc {
+	pub target: String, // id of an object in the same file
+	#[serde(default)]
+	pub velocity: [f64; 3],
+	#[serde(default)]
+	pub spin: [f64; 3], // axis times degrees per second
+	#[serde(default = "default_restitution")]
+	pub restitution: f64,
+	#[serde(default = "default_friction")]
+	pub friction: f64,
+}
+
+// Coefficients are per unit of the file's length, red green blue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolumeDesc {
+	pub target: String, // id of a sphere or closed mesh in the same file
+	pub absorption: [f64; 3],
+	#[serde(default)]
+	pub scatter: [f64; 3],
+	#[serde(default)]
+	pub priority: i32, // which volume fills where they overlap, highest first
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClipPlaneDesc {
+	pub point: [f64; 3],
+	pub normal: [f64; 3], // towards the side that's cut away
+	#[serde(default)]
+	pub cap: Option<String>, // material for the faces cut open
+	#[serde(default)]
+	pub objects: Vec<String>, // ids of what it cuts, everything when empty
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackplateDesc {
+	pub path: String, // png, relative to the file
+	#[serde(default = "default_fit")]
+	pub fit: BackplateFit,
+}
+
+fn default_fit() -> BackplateFit {
+	BackplateFit::Fill
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnvironmentDesc {
+	pub path: String, // lat-long png, relative to the file
+	#[serde(default = "default_environment_intensity")]
+	pub intensity: f64,
+}
+
+fn default_environment_intensity() -> f64 {
+	1.0
+}
+
+fn default_restitution() -> f64 {
+	0.3
+}
+
+fn default_friction() -> f64 {
+	0.5
+}
+
+fn white() -> [u8; 3] {
+	[255, 255, 255]
+}
+
+impl SceneFile {
+	pub fn parse(text: &str) -> Result<SceneFile, String> {
+		ron::from_str(text).map_err(|e| e.to_string())
+	}
+
+	pub fn load(path: &Path) -> Result<SceneFile, String> {
+		let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+		SceneFile::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+	}
+
+	// Everything in the scene as plain objects; references and instancing don't survive the trip
+	pub fn from_scene(scene: &Scene) -> SceneFile {
+		let mut file = SceneFile::default();
+		let camera = &scene.current_camera;
+		file.camera = Some(CameraDesc { pos: array(&camera.pos), rot: degrees(&camera.rot), look_at: None, fov: camera.fov });
+
+		for object in scene.objects.iter() {
+			let any = object.as_any_immut();
+			let id = Some(object.get_id().clone());
+			// Animated objects are saved in their rest pose, bodies where the simulation started them
+			let start = scene.physics.as_ref().and_then(|physics| physics.start().iter().find(|(body, _, _)| body.target == *object.get_id()));
+			let (pos, rot) = match (scene.animations.iter().find(|animation| animation.target == *object.get_id()), start) {
+				(Some(animation), _) => (animation.pos, animation.rot),
+				(None, Some((_, pos, rot))) => (*pos, *rot),
+				(None, None) => (*object.get_pos(), *object.get_rot()),
+			};
+			if let Some(mesh) = any.downcast_ref::<Mesh>() {
+				// Morphed meshes are saved as their base shape and weights
+				let geometry = mesh.morph.as_ref().map_or(&mesh.geometry, |morph| &morph.base);
+				let materials = mesh.materials.iter().map(|material| file.material_name(material)).collect();
+				let morph_targets = mesh.morph.as_ref().map_or(Vec::new(), |morph| morph.targets.iter().map(|target| MorphTargetDesc {
+					name: target.name.clone(),
+					positions: target.positions.iter().map(array).collect(),
+					normals: target.normals.iter().map(array).collect(),
+				}).collect());
+				file.objects.push(ObjectDesc::Mesh {
+					id,
+					pos: array(&pos),
+					rot: degrees(&rot),
+					positions: geometry.positions.iter().map(array).collect(),
+					normals: geometry.normals.iter().map(array).collect(),
+					indices: geometry.indices.clone(),
+					materials,
+					material_ids: geometry.material_ids.clone(),
+					uvs: geometry.uvs.iter().map(|(u, v)| [*u, *v]).collect(),
+					texture: mesh.texture.as_ref().map(|texture| texture.path.clone()),
+					morph_targets,
+					weights: mesh.morph.as_ref().map_or(Vec::new(), |morph| morph.weights.clone()),
+				});
+			} else if let Some(sphere) = any.downcast_ref::<Sphere>() {
+				let material = file.material_name(&sphere.material);
+				file.objects.push(ObjectDesc::Sphere { id, pos: array(&pos), radius: sphere.radius, material });
+			} else if let Some(grid) = any.downcast_ref::<VoxelGrid>() {
+				// Generated grids have nothing on disk to point at
+				if !grid.path.is_empty() {
+					file.objects.push(ObjectDesc::Voxels { id, pos: array(&pos), rot: degrees(&rot), path: grid.path.clone(), size: grid.voxel_size });
+				}
+			} else if let Some(cloud) = any.downcast_ref::<PointCloud>() {
+				if !cloud.path.is_empty() {
+					file.objects.push(ObjectDesc::Points { id, pos: array(&pos), rot: degrees(&rot), path: cloud.path.clone(), radius: cloud.radius });
+				}
+			} else if let Some(light) = any.downcast_ref::<LightSource>() {
+				let color = [light.color.r, light.color.g, light.color.b];
+				let spot = light.spot.as_ref().map(|spot| SpotDesc {
+					angle: spot.angle,
+					blend: spot.blend,
+					gobo: spot.gobo.as_ref().map(|gobo| gobo.path.clone()),
+				});
+				file.objects.push(ObjectDesc::Light {
+					id,
+					pos: array(&pos),
+					rot: degrees(&rot),
+					intensity: light.intensity,
+					color,
+					radius: light.radius,
+					include: light.include.clone(),
+					exclude: light.exclude.clone(),
+					spot,
+				});
+			}
+		}
+		for animation in scene.animations.iter() {
+			let modifiers = animation.modifiers.iter().map(|modifier| match *modifier {
+				Modifier::Spin { axis, speed } => ModifierDesc::Spin { axis: array(&axis), speed: speed.to_degrees() },
+				Modifier::Orbit { center, axis, speed } => ModifierDesc::Orbit { center: array(&center), axis: array(&axis), speed: speed.to_degrees() },
+				Modifier::Bob { offset, frequency, phase } => ModifierDesc::Bob { offset: array(&offset), frequency, phase: phase.to_degrees() },
+			}).collect();
+			let morph = scene.objects.iter().find(|object| *object.get_id() == animation.target)
+				.and_then(|object| object.as_any_immut().downcast_ref::<Mesh>())
+				.and_then(|mesh| mesh.morph.as_ref());
+			let weights = animation.weights.iter().filter_map(|track| Some(WeightTrackDesc {
+				morph: morph?.targets.get(track.morph)?.name.clone(),
+				keys: track.keys.clone(),
+			})).collect();
+			file.animations.push(AnimationDesc { target: animation.target.clone(), modifiers, weights });
+		}
+		if let Some(physics) = &scene.physics {
+			file.gravity = Some(array(&physics.gravity));
+			file.bodies = physics.start().iter().map(|(body, _, _)| BodyDesc {
+				target: body.target.clone(),
+				velocity: array(&body.velocity),
+				spin: array(&body.angular_velocity.mul(1f64.to_degrees())),
+				restitution: body.restitution,
+				friction: body.friction,
+			}).collect();
+		}
+		file.volumes = scene.volumes.iter().map(|volume| VolumeDesc {
+			target: volume.target.clone(),
+			absorption: volume.medium.absorption,
+			scatter: volume.medium.scatter,
+			priority: volume.priority,
+		}).collect();
+		let clip_planes = scene.clip_planes.iter().map(|plane| ClipPlaneDesc {
+			point: array(&plane.point),
+			normal: array(&plane.normal),
+			cap: plane.cap.as_ref().map(|cap| file.material_name(cap)),
+			objects: plane.objects.clone(),
+		}).collect();
+		file.clip_planes = clip_planes;
+		file.backplate = scene.backplate.as_ref().map(|backplate| BackplateDesc { path: backplate.texture.path.clone(), fit: backplate.fit });
+		file.environment = scene.environment.as_ref().map(|environment| EnvironmentDesc { path: environment.texture.path.clone(), intensity: environment.intensity });
+		let builtin = builtin_presets();
+		file.presets = scene.presets.iter().filter(|(name, preset)| builtin.get(*name) != Some(*preset)).map(|(name, preset)| (name.clone(), preset.clone())).collect();
+		file
+	}
+
+	// Name of an identical material already in the table, or a new entry for it
+	fn material_name(&mut self, material: &Material) -> String {
+		let desc = MaterialDesc { color: [material.color.r, material.color.g, material.color.b], reflectivity: material.reflectivity, transparency: material.transparency, roughness: material.roughness, ior: material.ior };
+		if let Some((name, _)) = self.materials.iter().find(|(_, existing)| **existing == desc) {
+			return name.clone();
+		}
+		let name = format!("material_{}", self.materials.len());
+		self.materials.insert(name.clone(), desc);
+		name
+	}
+
+	pub fn to_ron(&self) -> String {
+		ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).unwrap_or_default()
+	}
+
+	pub fn save(&self, path: &Path) -> Result<(), String> {
+		fs::write(path, self.to_ron()).map_err(|e| format!("{}: {}", path.display(), e))
+	}
+}
+
+// Loads a scene file along with everything it references, or a scene archive of them
+pub fn load_scene(path: &Path) -> Result<Scene, String> {
+	load_scene_with_cache(path, Some(&BvhCache::default()))
+}
+
+// load_scene, keeping big meshes' hierarchies in cache or always building them when it's None
+pub fn load_scene_with_cache(path: &Path, cache: Option<&BvhCache>) -> Result<Scene, String> {
+	if archive::is_archive(path) {
+		return archive::load(path);
+	}
+	let file = SceneFile::load(path)?;
+	let camera = file.camera.as_ref().ok_or(format!("{}: no camera", path.display()))?;
+	let mut loader = Loader { files: HashMap::new(), textures: HashMap::new(), geometry: HashMap::new(), cache: cache.cloned(), open: Vec::new(), objects: Vec::new(), animations: Vec::new(), bodies: Vec::new(), volumes: Vec::new() };
+	loader.add_file(&file, path, &Placement::identity(), false, None, &HashMap::new())?;
+
+	let meters = file.scale * file.units.meters();
+	let mut scene_camera = Camera::new(vec3(&camera.pos).mul(meters), rot3(&camera.rot), camera.fov);
+	if let Some(target) = camera.look_at {
+		scene_camera.look_at(&vec3(&target).mul(meters), &Vec3 { x: 0.0, y: 0.0, z: 1.0 });
+	}
+	let backplate = match &file.backplate {
+		Some(backplate) => Some(Backplate { texture: loader.texture(&path.parent().unwrap_or(Path::new("")).join(&backplate.path))?, fit: backplate.fit }),
+		None => None,
+	};
+	let environment = match &file.environment {
+		Some(environment) => Some(Environment::new(loader.texture(&path.parent().unwrap_or(Path::new("")).join(&environment.path))?, environment.intensity)),
+		None => None,
+	};
+	let mut scene = Scene::new(loader.objects, scene_camera);
+	scene.backplate = backplate;
+	scene.environment = environment;
+	scene.presets.extend(file.presets.clone());
+	scene.animations = loader.animations;
+	scene.volumes = loader.volumes;
+	for plane in file.clip_planes.iter() {
+		let cap = match &plane.cap {
+			Some(name) => Some(material(file.materials.get(name).ok_or(format!("{}: unknown material `{}`", path.display(), name))?)),
+			None => None,
+		};
+		scene.clip_planes.push(ClipPlane { point: vec3(&plane.point).mul(meters), normal: vec3(&plane.normal), cap, objects: plane.objects.clone() });
+	}
+	if !loader.bodies.is_empty() {
+		let mut physics = Physics::new(&scene.objects, loader.bodies);
+		physics.gravity = file.gravity.map_or(Vec3 { x: 0.0, y: 0.0, z: -STANDARD_GRAVITY }, |gravity| vec3(&gravity));
+		scene.physics = Some(physics);
+	}
+	Ok(scene)
+}
+
+fn vec3(v: &[f64; 3]) -> Vec3 {
+	Vec3 { x: v[0], y: v[1], z: v[2] }
+}
+
+fn array(v: &Vec3) -> [f64; 3] {
+	[v.x, v.y, v.z]
+}
+
+fn degrees(rot: &Rot3) -> [f64; 3] {
+	[rot.yaw.to_degrees(), rot.pitch.to_degrees(), rot.roll.to_degrees()]
+}
+
+fn rot3(degrees: &[f64; 3]) -> Rot3 {
+	Rot3 { yaw: degrees[0].to_radians(), pitch: degrees[1].to_radians(), roll: degrees[2].to_radians() }
+}
+
+fn color(c: &[u8; 3]) -> Color {
+	Color { r: c[0], g: c[1], b: c[2] }
+}
+
+pub fn material(desc: &MaterialDesc) -> Material {
+	Material { color: color(&desc.color), reflectivity: desc.reflectivity, transparency: desc.transparency, roughness: desc.roughness, ior: desc.ior }
+}
+
+// Where a file's objects end up in the scene
+#[derive(Clone, Copy)]
+struct Placement {
+	pos: Vec3,
+	rot: Rot3,
+	scale: f64,
+}
+
+impl Placement {
+	fn identity() -> Self {
+		Placement { pos: Vec3 { x: 0.0, y: 0.0, z: 0.0 }, rot: Rot3::new(), scale: 1.0 }
+	}
+
+	fn apply(&self, point: &Vec3) -> Vec3 {
+		point.mul(self.scale).rotate(&self.rot).add(&self.pos)
+	}
+
+	// Placement of something at pos, rot in the space this one puts into the scene
+	fn then(&self, pos: &[f64; 3], rot: &[f64; 3]) -> Placement {
+		Placement { pos: self.apply(&vec3(pos)), rot: self.rot.compose(&rot3(rot)), scale: self.scale }
+	}
+}
+
+struct Loader {
+	files: HashMap<PathBuf, SceneFile>,
+	textures: HashMap<PathBuf, Arc<Texture>>,
+	// Built once per mesh per file and scale, shared by every instance of it
+	geometry: HashMap<(PathBuf, usize, u64), Arc<MeshGeometry>>,
+	cache: Option<BvhCache>,
+	open: Vec<PathBuf>, // chain of files being loaded, to catch references to themselves
+	objects: Vec<Box<dyn SceneObject>>,
+	animations: Vec<Animation>,
+	bodies: Vec<Body>,
+	volumes: Vec<Volume>,
+}
+
+impl Loader {
+	fn texture(&mut self, path: &Path) -> Result<Arc<Texture>, String> {
+		if let Some(texture) = self.textures.get(path) {
+			return Ok(texture.clone());
+		}
+		let texture = Arc::new(Texture::load_png(path).map_err(|e| format!("{}: {}", path.display(), e))?);
+		self.textures.insert(path.to_path_buf(), texture.clone());
+		Ok(texture)
+	}
+
+	// Flattening bakes placements into the geometry, otherwise meshes keep them as their transform,
+	// with any scaling in the geometry. Overrides take precedence over the file's own material table.
+	// The placement's scale is whatever files further out scaled this one by, on top of its own units
+	fn add_file(&mut self, file: &SceneFile, path: &Path, placement: &Placement, flatten: bool, prefix: Option<&str>, overrides: &HashMap<String, Material>) -> Result<(), String> {
+		let outer_scale = placement.scale * file.scale;
+		let placement = &Placement { scale: outer_scale * file.units.meters(), ..*placement };
+		let key = path.canonicalize().map_err(|e| format!("{}: {}", path.display(), e))?;
+		if self.open.contains(&key) {
+			return Err(format!("{}: references itself", path.display()));
+		}
+		self.open.push(key.clone());
+		let resolve = |name: &String| -> Result<Material, String> {
+			if let Some(material) = overrides.get(name) { return Ok(*material) }
+			let desc = file.materials.get(name).ok_or(format!("{}: unknown material `{}`", path.display(), name))?;
+			Ok(material(desc))
+		};
+		let scoped = |id: &String| match prefix {
+			Some(prefix) => format!("{}/{}", prefix, id),
+			None => id.clone(),
+		};
+		let name = |id: &Option<String>| id.as_ref().map(scoped);
+		let moves = |id: &Option<String>| id.as_ref().is_some_and(|id| {
+			file.animations.iter().any(|animation| animation.target == *id) || file.bodies.iter().any(|body| body.target == *id)
+		});
+
+		for (index, object) in file.objects.iter().enumerate() {
+			match object {
+				ObjectDesc::Mesh { .. } | ObjectDesc::Cube { .. } | ObjectDesc::Plane { .. } | ObjectDesc::Text { .. } => {
+					let (id, pos, rot, names) = mesh_header(object);
+					let mesh_materials = names.iter().map(resolve).collect::<Result<Vec<Material>, String>>()?;
+					let geometry_key = (key.clone(), index, placement.scale.to_bits());
+					let geometry = match self.geometry.get(&geometry_key) {
+						Some(geometry) => geometry.clone(),
+						None => {
+							let built = build_geometry(object, mesh_materials.len(), self.cache.as_ref()).map_err(|e| format!("{}: object {}: {}", path.display(), index, e))?;
+							// Only copied when there's scaling to bake into it
+							let geometry = Arc::new(if placement.scale == 1.0 { built } else {
+								bake(&built, &Placement { scale: placement.scale, ..Placement::identity() }, self.cache.as_ref())
+							});
+							self.geometry.insert(geometry_key, geometry.clone());
+							geometry
+						}
+					};
+					// Morph targets are offsets in object space, baking would leave them pointing the wrong way
+					let (morph_targets, weights) = match object {
+						ObjectDesc::Mesh { morph_targets, weights, .. } => (morph_targets.as_slice(), weights.as_slice()),
+						_ => (&[] as &[MorphTargetDesc], &[] as &[f64]),
+					};
+					// The geometry is scaled already
+					let scale = placement.scale;
+					let placement = Placement { scale: 1.0, ..placement.then(pos, rot) };
+					let mut mesh = if flatten && !moves(id) && morph_targets.is_empty() {
+						Mesh::instance(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, Rot3::new(), Arc::new(bake(&geometry, &placement, self.cache.as_ref())), mesh_materials)
+					} else {
+						Mesh::instance(placement.pos, placement.rot, geometry, mesh_materials)
+					};
+					if !morph_targets.is_empty() {
+						let targets = morph_targets.iter().map(|target| MorphTarget {
+							name: target.name.clone(),
+							positions: target.positions.iter().map(|offset| vec3(offset).mul(scale)).collect(),
+							normals: target.normals.iter().map(vec3).collect(),
+						}).collect();
+						mesh.set_morph_targets(targets, weights.to_vec()).map_err(|e| format!("{}: object {}: {}", path.display(), index, e))?;
+					}
+					if let ObjectDesc::Mesh { texture: Some(texture), .. } = object {
+						mesh.texture = Some(self.texture(&path.parent().unwrap_or(Path::new("")).join(texture))?);
+					}
+					if let Some(id) = name(id) { mesh.id = id }
+					self.objects.push(Box::new(mesh));
+				}
+				ObjectDesc::Sphere { id, pos, radius, material } => {
+					let mut sphere = Sphere::new(placement.apply(&vec3(pos)), (*radius as f64 * placement.scale) as f32, resolve(material)?);
+					if let Some(id) = name(id) { sphere.id = id }
+					self.objects.push(Box::new(sphere));
+				}
+				ObjectDesc::Voxels { id, pos, rot, path: chunk, size } => {
+					let placement = placement.then(pos, rot);
+					let mut grid = VoxelGrid::load(&path.parent().unwrap_or(Path::new("")).join(chunk), size * placement.scale)?;
+					grid.anchor = placement.pos;
+					grid.rot = placement.rot;
+					if let Some(id) = name(id) { grid.id = id }
+					self.objects.push(Box::new(grid));
+				}
+				ObjectDesc::Points { id, pos, rot, path: points, radius } => {
+					let placement = placement.then(pos, rot);
+					let mut cloud = PointCloud::load(&path.parent().unwrap_or(Path::new("")).join(points), *radius)?;
+					if placement.scale != 1.0 {
+						cloud = cloud.scaled(placement.scale);
+					}
+					cloud.anchor = placement.pos;
+					cloud.rot = placement.rot;
+					if let Some(id) = name(id) { cloud.id = id }
+					self.objects.push(Box::new(cloud));
+				}
+				ObjectDesc::Light { id, pos, rot, intensity, color: light_color, radius, include, exclude, spot } => {
+					let placement = placement.then(pos, rot);
+					let mut light = LightSource::new(placement.pos, placement.rot, *intensity);
+					light.color = color(light_color);
+					light.radius = (*radius as f64 * placement.scale) as f32;
+					light.include = include.iter().map(scoped).collect();
+					light.exclude = exclude.iter().map(scoped).collect();
+					if let Some(spot) = spot {
+						let gobo = match &spot.gobo {
+							Some(gobo) => Some(self.texture(&path.parent().unwrap_or(Path::new("")).join(gobo))?),
+							None => None,
+						};
+						light.spot = Some(Spot { angle: spot.angle, blend: spot.blend, gobo });
+					}
+					if let Some(id) = name(id) { light.id = id }
+					self.objects.push(Box::new(light));
+				}
+				ObjectDesc::Reference { id, path: reference, pos, rot, instance, overrides: names } => {
+					// Overrides from further out win over the ones made here
+					let mut nested = HashMap::new();
+					for (name, material) in names.iter() {
+						nested.insert(name.clone(), resolve(material)?);
+					}
+					nested.extend(overrides.iter().map(|(name, material)| (name.clone(), *material)));
+					let reference = path.parent().unwrap_or(Path::new("")).join(reference);
+					let reference_key = reference.canonicalize().map_err(|e| format!("{}: {}", reference.display(), e))?;
+					if !self.files.contains_key(&reference_key) {
+						let referenced = SceneFile::load(&reference)?;
+						self.files.insert(reference_key.clone(), referenced);
+					}
+					// Taken out while it loads so the loader can be borrowed mutably, nested references to it are cycles anyway
+					let referenced = self.files.remove(&reference_key).unwrap();
+					let prefix = name(id);
+					let nested_placement = Placement { scale: outer_scale, ..placement.then(pos, rot) };
+					let result = self.add_file(&referenced, &reference, &nested_placement, flatten || !instance, prefix.as_deref(), &nested);
+					self.files.insert(reference_key, referenced);
+					result?;
+				}
+			}
+		}
+
+		// Modifiers are in the file's space, like everything else in it
+		for animation in file.animations.iter() {
+			let target = scoped(&animation.target);
+			let object = self.objects.iter().find(|object| *object.get_id() == target)
+				.ok_or(format!("{}: animation of unknown object `{}`", path.display(), animation.target))?;
+			let direction = |v: &[f64; 3]| vec3(v).rotate(&placement.rot);
+			let modifiers = animation.modifiers.iter().map(|modifier| match modifier {
+				ModifierDesc::Spin { axis, speed } => Modifier::Spin { axis: direction(axis), speed: speed.to_radians() },
+				ModifierDesc::Orbit { center, axis, speed } => Modifier::Orbit { center: placement.apply(&vec3(center)), axis: direction(axis), speed: speed.to_radians() },
+				ModifierDesc::Bob { offset, frequency, phase } => Modifier::Bob { offset: direction(offset).mul(placement.scale), frequency: *frequency, phase: phase.to_radians() },
+			}).collect();
+			let targets = object.as_any_immut().downcast_ref::<Mesh>().and_then(|mesh| mesh.morph.as_ref()).map_or(&[] as &[MorphTarget], |morph| &morph.targets);
+			let weights = animation.weights.iter().map(|track| {
+				let morph = targets.iter().position(|target| target.name == track.morph)
+					.ok_or(format!("{}: animation of `{}` has no morph target `{}`", path.display(), animation.target, track.morph))?;
+				Ok(WeightTrack { morph, keys: track.keys.clone() })
+			}).collect::<Result<Vec<WeightTrack>, String>>()?;
+			self.animations.push(Animation { target, pos: *object.get_pos(), rot: *object.get_rot(), modifiers, weights });
+		}
+		for desc in file.bodies.iter() {
+			let target = scoped(&desc.target);
+			let object = self.objects.iter().find(|object| *object.get_id() == target)
+				.ok_or(format!("{}: body for unknown object `{}`", path.display(), desc.target))?;
+			let shape = Shape::of(object.as_ref()).ok_or(format!("{}: `{}` can't be a body, only spheres and meshes can", path.display(), desc.target))?;
+			let mut body = Body::new(&target, shape);
+			body.velocity = vec3(&desc.velocity).rotate(&placement.rot).mul(placement.scale);
+			body.angular_velocity = vec3(&desc.spin).rotate(&placement.rot).mul(1f64.to_radians());
+			body.restitution = desc.restitution;
+			body.friction = desc.friction;
+			self.bodies.push(body);
+		}
+		// Nothing sees a volume's surface, only where its medium starts
+		for desc in file.volumes.iter() {
+			let target = scoped(&desc.target);
+			let object = self.objects.iter_mut().find(|object| *object.get_id() == target)
+				.ok_or(format!("{}: volume for unknown object `{}`", path.display(), desc.target))?;
+			let any = object.as_any_immut();
+			if !any.is::<Sphere>() && !any.is::<Mesh>() {
+				return Err(format!("{}: `{}` can't be a volume, only spheres and meshes can", path.display(), desc.target));
+			}
+			object.set_mask(object.get_mask().with_kinds(0));
+			let per_meter = |coefficients: &[f64; 3]| coefficients.map(|c| c / placement.scale);
+			let medium = Medium { absorption: per_meter(&desc.absorption), scatter: per_meter(&desc.scatter) };
+			self.volumes.push(Volume { target, medium, priority: desc.priority });
+		}
+		self.open.pop();
+		Ok(())
+	}
+}
+
+// Id, position, rotation and material names of the objects that become meshes
+fn mesh_header(object: &ObjectDesc) -> (&Option<String>, &[f64; 3], &[f64; 3], &[String]) {
+	match object {
+		ObjectDesc::Mesh { id, pos, rot, materials, .. } => (id, pos, rot, materials),
+		ObjectDesc::Cube { id, pos, rot, material, .. } | ObjectDesc::Plane { id, pos, rot, material, .. } | ObjectDesc::Text { id, pos, rot, material, .. } => {
+			(id, pos, rot, std::slice::from_ref(material))
+		}
+		_ => unreachable!(),
+	}
+}
+
+fn build_geometry(object: &ObjectDesc, material_count: usize, cache: Option<&BvhCache>) -> Result<MeshGeometry, String> {
+	let white = Material { color: color(&white()), reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+	match object {
+		ObjectDesc::Cube { size, .. } => Ok(MeshGeometry::from_tris(&cube_tris(size * 0.5, white)).0),
+		ObjectDesc::Plane { size, .. } => Ok(MeshGeometry::from_tris(&plane_tris(size * 0.5, white)).0),
+		ObjectDesc::Text { text, height, depth, .. } => {
+			let tris = text_tris(text, *height, depth.unwrap_or(height * 0.2), white);
+			if tris.is_empty() { return Err(String::from("no visible characters")) }
+			Ok(MeshGeometry::from_tris(&tris).0)
+		}
+		ObjectDesc::Mesh { positions, normals, uvs, indices, material_ids, .. } => {
+			if !normals.is_empty() && normals.len() != positions.len() {
+				return Err(String::from("normals and positions differ in length"));
+			}
+			if !uvs.is_empty() && uvs.len() != positions.len() {
+				return Err(String::from("uvs and positions differ in length"));
+			}
+			if indices.iter().flatten().any(|i| *i as usize >= positions.len()) {
+				return Err(String::from("index out of range"));
+			}
+			let material_ids = if material_ids.is_empty() { vec![0; indices.len()] } else { material_ids.clone() };
+			if material_ids.len() != indices.len() || material_ids.iter().any(|id| *id as usize >= material_count) {
+				return Err(String::from("material_ids don't match the triangles and materials"));
+			}
+			Ok(MeshGeometry::with_cache(
+				positions.iter().map(vec3).collect(),
+				normals.iter().map(|n| vec3(n).normalize()).collect(),
+				uvs.iter().map(|[u, v]| (*u, *v)).collect(),
+				indices.clone(),
+				material_ids,
+				cache,
+			))
+		}
+		_ => unreachable!(),
+	}
+}
+
+fn bake(geometry: &MeshGeometry, placement: &Placement, cache: Option<&BvhCache>) -> MeshGeometry {
+	let rotation = placement.rot.to_matrix();
+	MeshGeometry::with_cache(
+		geometry.positions.iter().map(|p| rotation.apply(&p.mul(placement.scale)).add(&placement.pos)).collect(),
+		geometry.normals.iter().map(|n| rotation.apply(n)).collect(),
+		geometry.uvs.clone(),
+		geometry.indices.clone(),
+		geometry.material_ids.clone(),
+		cache,
+	)
+}
+
+fn cube_tris(half: f64, material: Material) -> Vec<Tri> {
+	let mut tris = Vec::new();
+	for axis in 0..3 {
+		for side in [-1.0, 1.0].iter() {
+			let corner = |u: f64, v: f64| {
+				let mut c = [0.0; 3];
+				c[axis] = side * half;
+				c[(axis + 1) % 3] = u * half;
+				c[(axis + 2) % 3] = v * half;
+				vec3(&c)
+			};
+			tris.push(Tri { a: corner(-1.0, -1.0), b: corner(1.0, -1.0), c: corner(1.0, 1.0), mat: material });
+			tris.push(Tri { a: corner(-1.0, -1.0), b: corner(1.0, 1.0), c: corner(-1.0, 1.0), mat: material });
+		}
+	}
+	tris
+}
+
+fn plane_tris(half: f64, material: Material) -> Vec<Tri> {
+	let corner = |x: f64, y: f64| Vec3 { x: x * half, y: y * half, z: 0.0 };
+	vec![
+		Tri { a: corner(-1.0, -1.0), b: corner(1.0, -1.0), c: corner(1.0, 1.0), mat: material },
+		Tri { a: corner(-1.0, -1.0), b: corner(1.0, 1.0), c: corner(-1.0, 1.0), mat: material },
+	]
+}
+
+#[test]
+fn nested_references() {
+	let dir = std::env::temp_dir().join(format!("scene_file_test_{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	fs::write(dir.join("wheel.ron"), r#"(
+		materials: { "rubber": (color: (20, 20, 20)) },
+		objects: [ Cube(id: Some("tyre"), pos: (1.0, 0.0, 0.0), size: 0.5, material: "rubber") ],
+	)"#).unwrap();
+	fs::write(dir.join("car.ron"), r#"(
+		objects: [
+			Reference(id: Some("front"), path: "wheel.ron", instance: true),
+			Reference(id: Some("back"), path: "wheel.ron", pos: (-3.0, 0.0, 0.0), instance: true),
+		],
+	)"#).unwrap();
+	fs::write(dir.join("street.ron"), r#"(
+		camera: Some((pos: (0.0, -10.0, 2.0), look_at: Some((0.0, 0.0, 0.0)), fov: 40.0)),
+		objects: [
+			Reference(id: Some("parked"), path: "car.ron", pos: (10.0, 0.0, 0.0), rot: (90.0, 0.0, 0.0), instance: true),
+			Reference(id: Some("baked"), path: "car.ron"),
+		],
+	)"#).unwrap();
+	fs::write(dir.join("loop.ron"), r#"(
+		camera: Some((pos: (0.0, 0.0, 0.0), fov: 40.0)),
+		objects: [ Reference(path: "loop.ron") ],
+	)"#).unwrap();
+
+	let scene = load_scene(&dir.join("street.ron")).unwrap();
+	let meshes = scene.get_all_meshes_immut();
+	let ids: Vec<&str> = meshes.iter().map(|mesh| mesh.id.as_str()).collect();
+	assert_eq!(ids, ["parked/front/tyre", "parked/back/tyre", "baked/front/tyre", "baked/back/tyre"]);
+	// Instances share one copy of the geometry and carry the composed transform
+	assert!(Arc::ptr_eq(&meshes[0].geometry, &meshes[1].geometry));
+	assert!(meshes[0].anchor.dist(&Vec3 { x: 10.0, y: 1.0, z: 0.0 }) < 1e-9);
+	// Flattened ones have it baked into their own copy
+	assert!(!Arc::ptr_eq(&meshes[2].geometry, &meshes[0].geometry));
+	let bounds = meshes[3].bounds().unwrap();
+	assert!(bounds.min.dist(&Vec3 { x: -2.25, y: -0.25, z: -0.25 }) < 1e-9);
+
+	assert!(load_scene(&dir.join("loop.ron")).is_err());
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn material_overrides() {
+	let dir = std::env::temp_dir().join(format!("scene_file_overrides_{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	fs::write(dir.join("car.ron"), r#"(
+		materials: { "body": (color: (200, 200, 200)), "glass": (transparency: 0.9) },
+		objects: [
+			Cube(id: Some("shell"), size: 2.0, material: "body"),
+			Sphere(id: Some("lamp"), pos: (1.0, 0.0, 0.0), radius: 0.2, material: "glass"),
+		],
+	)"#).unwrap();
+	fs::write(dir.join("lot.ron"), r#"(
+		camera: Some((pos: (0.0, -10.0, 2.0), fov: 40.0)),
+		materials: { "red_paint": (color: (255, 0, 0)), "blue_paint": (color: (0, 0, 255)) },
+		objects: [
+			Reference(id: Some("red"), path: "car.ron", instance: true, overrides: { "body": "red_paint" }),
+			Reference(id: Some("blue"), path: "car.ron", pos: (5.0, 0.0, 0.0), instance: true, overrides: { "body": "blue_paint" }),
+			Reference(id: Some("stock"), path: "car.ron", pos: (10.0, 0.0, 0.0), instance: true),
+		],
+	)"#).unwrap();
+
+	let mut scene = load_scene(&dir.join("lot.ron")).unwrap();
+	let meshes = scene.get_all_meshes_immut();
+	let colors: Vec<Color> = meshes.iter().map(|mesh| mesh.materials[0].color).collect();
+	assert_eq!(colors, [Color { r: 255, g: 0, b: 0 }, Color { r: 0, g: 0, b: 255 }, Color { r: 200, g: 200, b: 200 }]);
+	assert!(meshes.iter().all(|mesh| Arc::ptr_eq(&mesh.geometry, &meshes[0].geometry)));
+	// Materials nobody overrode come from the car file
+	let lamp = scene.find_object("blue/lamp").unwrap().get_material().unwrap();
+	assert_eq!(lamp.transparency, 0.9);
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn saved_scene_loads_back() {
+	let mut scene = Scene::default_scene();
+	let cube = scene.find_object("fuckin' cube").unwrap();
+	let edited = crate::structs::MaterialParam::Reflectivity.adjust(&cube.get_material().unwrap(), 4.0);
+	cube.set_material(edited);
+
+	let path = std::env::temp_dir().join(format!("scene_file_saved_{}.ron", std::process::id()));
+	SceneFile::from_scene(&scene).save(&path).unwrap();
+	let mut loaded = load_scene(&path).unwrap();
+	fs::remove_file(&path).unwrap();
+	assert_eq!(loaded.objects.len(), scene.objects.len());
+	assert!(loaded.find_object("fuckin' cube").unwrap().get_material() == Some(edited));
+	// Same picture, up to the degree round trip of the angles
+	let (before, after) = (scene.render_framebuffer(32, 18), loaded.render_framebuffer(32, 18));
+	assert_eq!(before.alpha, after.alpha);
+}
+
+#[test]
+fn units_convert_to_meters() {
+	let dir = std::env::temp_dir().join(format!("scene_file_units_{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	fs::write(dir.join("screw.ron"), r#"(
+		units: Millimeters,
+		materials: { "steel": () },
+		objects: [ Cube(id: Some("head"), pos: (0.0, 0.0, 100.0), size: 200.0, material: "steel") ],
+	)"#).unwrap();
+	fs::write(dir.join("desk.ron"), r#"(
+		units: Centimeters,
+		scale: 2.0,
+		camera: Some((pos: (0.0, -500.0, 0.0), fov: 40.0)),
+		materials: { "wood": () },
+		objects: [
+			Sphere(id: Some("knob"), pos: (100.0, 0.0, 0.0), radius: 10.0, material: "wood"),
+			Light(pos: (0.0, 0.0, 100.0), intensity: 1.0, radius: 5.0),
+			Reference(id: Some("screw"), path: "screw.ron", pos: (-100.0, 0.0, 0.0), instance: true),
+		],
+	)"#).unwrap();
+
+	let mut scene = load_scene(&dir.join("desk.ron")).unwrap();
+	fs::remove_dir_all(&dir).unwrap();
+	assert!(scene.current_camera.pos.dist(&Vec3 { x: 0.0, y: -10.0, z: 0.0 }) < 1e-9);
+	let knob = scene.find_object("knob").unwrap().bounds().unwrap();
+	assert!(knob.min.dist(&Vec3 { x: 1.8, y: -0.2, z: -0.2 }) < 1e-6);
+	assert!((scene.get_all_light_sources()[0].radius - 0.1).abs() < 1e-6);
+	// The screw keeps its own millimeters, under the desk's doubling
+	let head = scene.find_object("screw/head").unwrap().bounds().unwrap();
+	assert!(head.min.dist(&Vec3 { x: -2.2, y: -0.2, z: 0.0 }) < 1e-9, "{:?}", head.min);
+	assert!(head.max.dist(&Vec3 { x: -1.8, y: 0.2, z: 0.4 }) < 1e-9, "{:?}", head.max);
+}
+
+#[test]
+fn animations_load_in_scene_space() {
+	let dir = std::env::temp_dir().join(format!("scene_file_animations_{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	fs::write(dir.join("fan.ron"), r#"(
+		materials: { "metal": () },
+		objects: [ Cube(id: Some("blade"), pos: (1.0, 0.0, 0.0), size: 0.2, material: "metal") ],
+		animations: [ (target: "blade", modifiers: [ Orbit(center: (0.0, 0.0, 0.0), axis: (0.0, 0.0, 1.0), speed: 90.0) ]) ],
+	)"#).unwrap();
+	fs::write(dir.join("room.ron"), r#"(
+		camera: Some((pos: (0.0, -10.0, 2.0), fov: 40.0)),
+		objects: [ Reference(id: Some("fan"), path: "fan.ron", pos: (0.0, 0.0, 3.0)) ],
+	)"#).unwrap();
+
+	let mut scene = load_scene(&dir.join("room.ron")).unwrap();
+	// Flattened, but animated, so the blade keeps a transform to move with
+	assert!(scene.find_object("fan/blade").unwrap().get_pos().dist(&Vec3 { x: 1.0, y: 0.0, z: 3.0 }) < 1e-9);
+	scene.animate(1.0);
+	assert!(scene.find_object("fan/blade").unwrap().get_pos().dist(&Vec3 { x: 0.0, y: 1.0, z: 3.0 }) < 1e-9);
+
+	// Saved mid-animation, it comes back in its rest pose with the animation intact
+	SceneFile::from_scene(&scene).save(&dir.join("saved.ron")).unwrap();
+	let mut loaded = load_scene(&dir.join("saved.ron")).unwrap();
+	fs::remove_dir_all(&dir).unwrap();
+	assert!(loaded.find_object("fan/blade").unwrap().get_pos().dist(&Vec3 { x: 1.0, y: 0.0, z: 3.0 }) < 1e-9);
+	loaded.animate(1.0);
+	assert!(loaded.find_object("fan/blade").unwrap().get_pos().dist(&Vec3 { x: 0.0, y: 1.0, z: 3.0 }) < 1e-9);
+}
+
+#[test]
+fn bodies_fall_onto_the_floor() {
+	let dir = std::env::temp_dir().join(format!("scene_file_bodies_{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	fs::write(dir.join("crate.ron"), r#"(
+		units: Centimeters,
+		materials: { "wood": () },
+		objects: [ Cube(id: Some("box"), pos: (0.0, 0.0, 200.0), size: 50.0, material: "wood") ],
+		bodies: [ (target: "box", velocity: (0.0, 100.0, 0.0)) ],
+	)"#).unwrap();
+	fs::write(dir.join("yard.ron"), r#"(
+		camera: Some((pos: (0.0, -10.0, 2.0), fov: 40.0)),
+		gravity: Some((0.0, 0.0, -5.0)),
+		materials: { "grass": () },
+		objects: [
+			Plane(size: 20.0, material: "grass"),
+			Reference(id: Some("crate"), path: "crate.ron", rot: (90.0, 0.0, 0.0)),
+		],
+	)"#).unwrap();
+
+	let mut scene = load_scene(&dir.join("yard.ron")).unwrap();
+	// A meter per second along the crate's y, which the reference turned to -x
+	let body = &scene.physics.as_ref().unwrap().bodies[0];
+	assert!(body.velocity.dist(&Vec3 { x: -1.0, y: 0.0, z: 0.0 }) < 1e-9, "{:?}", body.velocity);
+	scene.simulate(0.5);
+	let pos = *scene.find_object("crate/box").unwrap().get_pos();
+	assert!((pos.z - (2.0 - 0.5 * 5.0 * 0.25)).abs() < 0.02, "{:?}", pos);
+	scene.simulate(3.0);
+	let pos = *scene.find_object("crate/box").unwrap().get_pos();
+	assert!((pos.z - 0.25).abs() < 0.01 && pos.x < -0.3, "{:?}", pos);
+
+	// Saved after it landed, it starts from the top again
+	SceneFile::from_scene(&scene).save(&dir.join("saved.ron")).unwrap();
+	let loaded = load_scene(&dir.join("saved.ron")).unwrap();
+	fs::remove_dir_all(&dir).unwrap();
+	assert!(loaded.objects.iter().find(|object| object.get_id() == "crate/box").unwrap().get_pos().dist(&Vec3 { x: 0.0, y: 0.0, z: 2.0 }) < 1e-9);
+	assert_eq!(loaded.physics.unwrap().gravity.z, -5.0);
+}
+
+#[test]
+fn morph_targets_round_trip() {
+	let dir = std::env::temp_dir().join(format!("scene_file_morph_{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	fs::write(dir.join("flap.ron"), r#"(
+		camera: Some((pos: (0.0, -500.0, 100.0), fov: 40.0)),
+		units: Centimeters,
+		materials: { "paper": () },
+		objects: [ Mesh(
+			id: Some("flap"),
+			positions: [(0.0, 0.0, 0.0), (100.0, 0.0, 0.0), (0.0, 100.0, 0.0)],
+			indices: [(0, 1, 2)],
+			materials: ["paper"],
+			morph_targets: [ (name: "up", positions: [(0.0, 0.0, 0.0), (0.0, 0.0, 0.0), (0.0, 0.0, 100.0)]) ],
+		) ],
+		animations: [ (target: "flap", weights: [ (morph: "up", keys: [(0.0, 0.0), (2.0, 1.0)]) ]) ],
+	)"#).unwrap();
+
+	let mut scene = load_scene(&dir.join("flap.ron")).unwrap();
+	scene.animate(1.0);
+	assert!((scene.find_object("flap").unwrap().bounds().unwrap().max.z - 0.5).abs() < 1e-9);
+	// Saved mid-blend, it comes back at its base shape with the track intact
+	SceneFile::from_scene(&scene).save(&dir.join("saved.ron")).unwrap();
+	let mut loaded = load_scene(&dir.join("saved.ron")).unwrap();
+	fs::remove_dir_all(&dir).unwrap();
+	assert_eq!(loaded.animations[0].weights.len(), 1);
+	loaded.animate(2.0 - 1e-6);
+	assert!((loaded.find_object("flap").unwrap().bounds().unwrap().max.z - 1.0).abs() < 1e-5);
+}
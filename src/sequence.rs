@@ -0,0 +1,167 @@
+//
+//	Frame Sequences
+//
+
+// An animation rendered frame by frame to a directory, for tools that interpolate between frames by
+// optical flow or add motion blur afterwards. Each frame is a png of the beauty and an exr with the
+// same color as floats alongside depth and motion vectors, and frames.json has every frame's camera as
+// matrices, along with the one before it, so nothing has to be guessed back from the images. Motion is
+// in pixels, x right and y down, from where the surface under a pixel was the frame before to where it
+// is now; the first frame has none. Matrices are row major, for column vectors, with the camera looking
+// down its -z and y up as in OpenGL
+
+use crate::exr;
+use crate::structs::{Camera, Scene};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Near plane of the projection matrices, which have no far one
+pub const NEAR: f64 = 0.01;
+
+pub type Matrix = [[f64; 4]; 4];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CameraMatrices {
+	pub camera_to_world: Matrix,
+	pub world_to_camera: Matrix,
+	pub projection: Matrix, // camera space to clip space, x and y in -1 to 1 across the image
+}
+
+impl CameraMatrices {
+	pub fn new(camera: &Camera, width: usize, height: usize) -> Self {
+		let rows = camera.rot.to_matrix().rows.map(|row| [row.x, row.y, row.z]);
+		let pos = [camera.pos.x, camera.pos.y, camera.pos.z];
+		let mut camera_to_world = [[0.0, 0.0, 0.0, 1.0]; 4];
+		let mut world_to_camera = [[0.0, 0.0, 0.0, 1.0]; 4];
+		for i in 0..3 {
+			camera_to_world[i] = [rows[i][0], rows[i][1], rows[i][2], pos[i]];
+			// The rotation's inverse is its transpose
+			let column = [rows[0][i], rows[1][i], rows[2][i]];
+			world_to_camera[i] = [column[0], column[1], column[2], -(0..3).map(|j| column[j] * pos[j]).sum::<f64>()];
+		}
+		let (half_width, half_height) = camera.half_extents(width as f64 / height as f64);
+		let (shift_x, shift_y) = camera.image_shift();
+		let projection = [
+			[1.0 / half_width, 0.0, shift_x / half_width, 0.0],
+			[0.0, 1.0 / half_height, shift_y / half_height, 0.0],
+			[0.0, 0.0, -1.0, -2.0 * NEAR],
+			[0.0, 0.0, -1.0, 0.0],
+		];
+		CameraMatrices { camera_to_world, world_to_camera, projection }
+	}
+}
+
+// Sixteen numbers row by row, for an exr header
+fn matrix_string(matrix: &Matrix) -> String {
+	matrix.iter().flatten().map(|value| value.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FrameMetadata {
+	pub frame: u32,
+	pub time: f64, // seconds into the animation
+	pub image: String, // file names, beside frames.json
+	pub layers: String,
+	pub camera: CameraMatrices,
+	pub previous_camera: Option<CameraMatrices>, // what the motion vectors were measured from
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SequenceMetadata {
+	pub width: usize,
+	pub height: usize,
+	pub fps: f64,
+	pub near: f64,
+	pub frames: Vec<FrameMetadata>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sequence {
+	pub frames: u32,
+	pub fps: f64,
+	pub start: f64, // seconds into the animation of the first frame
+}
+
+impl Sequence {
+	// Renders every frame into dir, made if it isn't there, then frames.json describing them
+	pub fn export(&self, scene: &mut Scene, width: usize, height: usize, dir: &Path) -> io::Result<SequenceMetadata> {
+		fs::create_dir_all(dir)?;
+		let mut metadata = SequenceMetadata { width, height, fps: self.fps, near: NEAR, frames: Vec::new() };
+		// Whatever was shown before isn't part of the sequence
+		scene.previous_frame = None;
+		let mut previous_camera: Option<CameraMatrices> = None;
+		for frame in 0..self.frames {
+			let time = self.start + frame as f64 / self.fps;
+			scene.animate(time);
+			if frame > 0 {
+				scene.simulate(1.0 / self.fps);
+			}
+			let framebuffer = scene.render_framebuffer(width as i32, height as i32);
+			let camera = CameraMatrices::new(&scene.current_camera, width, height);
+			let (image, layers) = (format!("frame.{:04}.png", frame), format!("frame.{:04}.exr", frame));
+			framebuffer.write_png(&dir.join(&image))?;
+
+			let channel = |f: &dyn Fn(usize) -> f32| -> Vec<f32> { (0..width * height).map(f).collect() };
+			let (r, g, b) = (channel(&|i| framebuffer.color[i].r as f32 / 255.0), channel(&|i| framebuffer.color[i].g as f32 / 255.0), channel(&|i| framebuffer.color[i].b as f32 / 255.0));
+			let (a, x, y) = (channel(&|i| framebuffer.alpha[i] as f32 / 255.0), channel(&|i| framebuffer.motion[i].0), channel(&|i| framebuffer.motion[i].1));
+			let channels = [("R", &r[..]), ("G", &g[..]), ("B", &b[..]), ("A", &a[..]), ("Z", &framebuffer.depth[..]), ("motion.X", &x[..]), ("motion.Y", &y[..])];
+			let mut header = vec![
+				(String::from("time"), time.to_string()),
+				(String::from("cameraToWorld"), matrix_string(&camera.camera_to_world)),
+				(String::from("worldToCamera"), matrix_string(&camera.world_to_camera)),
+				(String::from("projection"), matrix_string(&camera.projection)),
+			];
+			if let Some(previous) = &previous_camera {
+				header.push((String::from("previousWorldToCamera"), matrix_string(&previous.world_to_camera)));
+			}
+			exr::write_with_metadata(&dir.join(&layers), width, height, &channels, &header)?;
+
+			metadata.frames.push(FrameMetadata { frame, time, image, layers, camera, previous_camera });
+			previous_camera = Some(camera);
+			scene.remember_frame();
+		}
+		let json = serde_json::to_string_pretty(&metadata).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+		fs::write(dir.join("frames.json"), json + "\n")?;
+		Ok(metadata)
+	}
+}
+
+#[test]
+fn frames_carry_their_cameras_and_motion() {
+	use crate::structs::Vec3;
+	let mut scene = Scene::default_scene();
+	scene.current_camera.look_at(&Vec3 { x: 0.0, y: 0.0, z: 1.5 }, &Vec3 { x: 0.0, y: 0.0, z: 1.0 });
+	let (width, height) = (32, 18);
+
+	// The matrices take a point to the same pixel as the camera does
+	let matrices = CameraMatrices::new(&scene.current_camera, width, height);
+	let point = Vec3 { x: 0.3, y: 0.2, z: 1.2 };
+	let apply = |matrix: &Matrix, v: [f64; 4]| -> [f64; 4] { matrix.map(|row| (0..4).map(|i| row[i] * v[i]).sum()) };
+	let local = apply(&matrices.world_to_camera, [point.x, point.y, point.z, 1.0]);
+	let clip = apply(&matrices.projection, local);
+	let pixel = ((clip[0] / clip[3] + 1.0) * 0.5 * width as f64, (1.0 - clip[1] / clip[3]) * 0.5 * height as f64);
+	let expected = scene.current_camera.project(&point, width as i32, height as i32).unwrap();
+	assert!((pixel.0 - expected.0).abs() < 1e-9 && (pixel.1 - expected.1).abs() < 1e-9);
+	let back = apply(&matrices.camera_to_world, local);
+	assert!(Vec3 { x: back[0], y: back[1], z: back[2] }.dist(&point) < 1e-9);
+
+	// The cube tumbles while the camera holds still, the motion measured from each frame to the next
+	let dir = std::env::temp_dir().join(format!("sequence_test_{}", std::process::id()));
+	scene.remember_frame();
+	let metadata = Sequence { frames: 3, fps: 10.0, start: 0.0 }.export(&mut scene, width, height, &dir).unwrap();
+	let written: SequenceMetadata = serde_json::from_str(&fs::read_to_string(dir.join("frames.json")).unwrap()).unwrap();
+	let layers = fs::read(dir.join(&metadata.frames[1].layers)).unwrap();
+	let files = fs::read_dir(&dir).unwrap().count();
+	fs::remove_dir_all(&dir).unwrap();
+	assert_eq!((written, files), (metadata.clone(), 7));
+	assert_eq!((metadata.frames[2].time, metadata.frames[2].image.as_str()), (0.2, "frame.0002.png"));
+	assert_eq!(metadata.frames[0].previous_camera, None);
+	assert_eq!(metadata.frames[2].previous_camera, Some(metadata.frames[1].camera));
+	assert_eq!(metadata.frames[2].camera, matrices);
+	// Seven float channels a pixel, and the cameras in the header
+	assert!(layers.len() > width * height * 7 * 4);
+	assert!(layers.windows(21).any(|window| window == b"previousWorldToCamera"));
+	assert!(scene.previous_frame.is_some());
+}
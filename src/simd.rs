@@ -0,0 +1,143 @@
+//
+//	Packets of Four
+//
+
+// Four lanes of f64 side by side, laid out so the compiler turns the per lane loops into vector
+// instructions, and a ray against four triangles at once built on them. The arithmetic is the same
+// as the one at a time code lane for lane, so both find exactly the same hits. Meshes use it with
+// the simd feature, this is here either way so it stays tested
+
+use crate::structs::{PreparedTri, Ray, Vec3};
+use std::ops::{Add, Div, Mul, Sub};
+
+pub const LANES: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct F64x4(pub [f64; LANES]);
+
+impl F64x4 {
+	pub fn splat(value: f64) -> Self {
+		F64x4([value; LANES])
+	}
+
+	fn zip(self, other: F64x4, op: impl Fn(f64, f64) -> f64) -> Self {
+		F64x4(std::array::from_fn(|lane| op(self.0[lane], other.0[lane])))
+	}
+}
+
+impl Add for F64x4 {
+	type Output = F64x4;
+	fn add(self, other: F64x4) -> F64x4 { self.zip(other, |a, b| a + b) }
+}
+
+impl Sub for F64x4 {
+	type Output = F64x4;
+	fn sub(self, other: F64x4) -> F64x4 { self.zip(other, |a, b| a - b) }
+}
+
+impl Mul for F64x4 {
+	type Output = F64x4;
+	fn mul(self, other: F64x4) -> F64x4 { self.zip(other, |a, b| a * b) }
+}
+
+impl Div for F64x4 {
+	type Output = F64x4;
+	fn div(self, other: F64x4) -> F64x4 { self.zip(other, |a, b| a / b) }
+}
+
+// Four vectors, one per lane
+#[derive(Debug, Clone, Copy)]
+pub struct Vec3x4 {
+	pub x: F64x4,
+	pub y: F64x4,
+	pub z: F64x4,
+}
+
+impl Vec3x4 {
+	pub fn splat(v: &Vec3) -> Self {
+		Vec3x4 { x: F64x4::splat(v.x), y: F64x4::splat(v.y), z: F64x4::splat(v.z) }
+	}
+
+	pub fn from_lanes(vs: [Vec3; LANES]) -> Self {
+		Vec3x4 { x: F64x4(vs.map(|v| v.x)), y: F64x4(vs.map(|v| v.y)), z: F64x4(vs.map(|v| v.z)) }
+	}
+
+	pub fn sub(&self, other: &Vec3x4) -> Self {
+		Vec3x4 { x: self.x - other.x, y: self.y - other.y, z: self.z - other.z }
+	}
+
+	// Same order of operations as Vec3::cross and Vec3::dot
+	pub fn cross(u: &Vec3x4, v: &Vec3x4) -> Self {
+		Vec3x4 {
+			x: u.y * v.z - u.z * v.y,
+			y: u.z * v.x - u.x * v.z,
+			z: u.x * v.y - u.y * v.x,
+		}
+	}
+
+	pub fn dot(u: &Vec3x4, v: &Vec3x4) -> F64x4 {
+		u.x * v.x + u.y * v.y + u.z * v.z
+	}
+}
+
+// Four triangles for one ray test, lanes past the end of what's there repeat the last one
+#[derive(Debug, Clone, Copy)]
+pub struct Tri4 {
+	pub a: Vec3x4,
+	pub edge1: Vec3x4,
+	pub edge2: Vec3x4,
+}
+
+impl Tri4 {
+	pub fn new(tris: &[PreparedTri]) -> Self {
+		let lane = |i: usize| tris[i.min(tris.len() - 1)];
+		let pick = |field: fn(&PreparedTri) -> Vec3| Vec3x4::from_lanes([0, 1, 2, 3].map(|i| field(&lane(i))));
+		Tri4 { a: pick(|tri| tri.a), edge1: pick(|tri| tri.edge1), edge2: pick(|tri| tri.edge2) }
+	}
+
+	// PreparedTri::ray_hit_barycentric four at a time: per lane the hit point and the barycentric
+	// weights of b and c, None where it misses
+	pub fn ray_hit_barycentric(&self, ray: &Ray) -> [Option<(Vec3, f64, f64)>; LANES] {
+		let epsilon = 0.0000001;
+		let (origin, direction) = (Vec3x4::splat(&ray.origin), Vec3x4::splat(&ray.direction));
+		let ray_dir_edge2 = Vec3x4::cross(&direction, &self.edge2);
+		let det = Vec3x4::dot(&self.edge1, &ray_dir_edge2);
+		let inv_det = F64x4::splat(1.0) / det;
+		let orig_minus_a = origin.sub(&self.a);
+		let u = Vec3x4::dot(&ray_dir_edge2, &orig_minus_a) * inv_det;
+		let cross_oma_a = Vec3x4::cross(&orig_minus_a, &self.edge1);
+		let v = Vec3x4::dot(&direction, &cross_oma_a) * inv_det;
+		let t = Vec3x4::dot(&self.edge2, &cross_oma_a) * inv_det;
+		[0, 1, 2, 3].map(|lane| {
+			let (det, u, v, t) = (det.0[lane], u.0[lane], v.0[lane], t.0[lane]);
+			if det > -epsilon && det < epsilon { return None }
+			if !(0.0..=1.0).contains(&u) { return None }
+			if v < 0.0 || v + u > 1.0 { return None }
+			if t < epsilon { return None }
+			Some((Vec3::add(&ray.origin, &ray.direction.mul(t)), u, v))
+		})
+	}
+}
+
+#[test]
+fn packets_match_one_at_a_time() {
+	use crate::random::Rng;
+	use crate::structs::{Color, Material, Tri};
+	let mut rng = Rng::new(11);
+	let mut point = || Vec3 { x: rng.next_f64() * 4.0 - 2.0, y: rng.next_f64() * 4.0 - 2.0, z: rng.next_f64() * 4.0 - 2.0 };
+	let material = Material { color: Color { r: 255, g: 255, b: 255 }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+	let tris: Vec<PreparedTri> = (0..203).map(|_| PreparedTri::new(&Tri { a: point(), b: point(), c: point(), mat: material })).collect();
+	let mut hits = 0;
+	for _ in 0..200 {
+		let ray = Ray::from_to(&point().mul(3.0), &point());
+		for chunk in tris.chunks(LANES) {
+			let packet = Tri4::new(chunk).ray_hit_barycentric(&ray);
+			for (lane, tri) in chunk.iter().enumerate() {
+				let (one, four) = (tri.ray_hit_barycentric(&ray), packet[lane]);
+				assert_eq!(one.map(|(p, u, v)| (p.x, p.y, p.z, u, v)), four.map(|(p, u, v)| (p.x, p.y, p.z, u, v)));
+				hits += usize::from(one.is_some());
+			}
+		}
+	}
+	assert!(hits > 100);
+}
@@ -0,0 +1,271 @@
+//
+//	Stochastic Progressive Photon Mapping
+//
+
+// Every pass follows one camera ray per pixel through mirrors and glass to the first diffuse
+// surface, shoots a batch of photons from the lights, and gathers the photons that landed around
+// each of those points, the gather radius shrinking as photons pile up. Photons and camera rays
+// both pick between mirroring, refracting and scattering as the path tracer does. Slow, but it
+// finds light focused by mirrors and glass (caustics) that the direct integrator never sees. What
+// camera rays see of the environment, the backplate and media comes in as with the path tracer.
+// Brightness is as transport.rs says
+
+use crate::photon::{Photon, PhotonMap};
+use crate::random::{hash_pixel, Rng};
+use crate::sampler::{sample_cosine_hemisphere, sample_sphere};
+use crate::structs::{Color, LightSource, Ray, RayMask, Scene, Vec3};
+use crate::transport::{albedo, around, environment, facing, specular_bounce, Rgb, OFFSET};
+
+const ALPHA: f64 = 0.7; // share of each pass' photons kept, smaller shrinks the radius faster
+const MAX_DEPTH: u32 = 8;
+
+// Where a pixel's camera ray ended up this pass
+struct VisiblePoint {
+	pos: Vec3,
+	normal: Vec3,
+	albedo: [f64; 3],
+}
+
+struct PixelState {
+	radius: f64,
+	photons: f64, // accumulated photon count, N in the papers
+	flux: [f64; 3], // tau
+	covered: u32, // passes whose camera ray hit something
+	point: Option<VisiblePoint>,
+	seen: Rgb, // environment seen through mirrors and glass, summed over the covered passes
+	backplate: Rgb, // summed over the passes that hit nothing
+	behind: u32, // passes that hit nothing but the backplate
+}
+
+pub struct Sppm {
+	width: i32,
+	height: i32,
+	pixels: Vec<PixelState>,
+	passes: u32,
+	emitted: u64,
+}
+
+impl Sppm {
+	pub fn new(scene: &Scene, width: i32, height: i32) -> Self {
+		// Start gathering over about a hundredth of the scene
+		let radius = scene.bounds().map_or(0.1, |bounds| (bounds.max.dist(&bounds.min) * 0.01).max(1e-3));
+		let pixels = (0..width * height).map(|_| PixelState { radius, photons: 0.0, flux: [0.0; 3], covered: 0, point: None, seen: [0.0; 3], backplate: [0.0; 3], behind: 0 }).collect();
+		Sppm { width, height, pixels, passes: 0, emitted: 0 }
+	}
+
+	// One samples_per_pixel's worth of passes, as (color, coverage) per pixel
+	pub fn render(scene: &Scene, width: i32, height: i32) -> Vec<(Color, u8)> {
+		let mut sppm = Sppm::new(scene, width, height);
+		for _ in 0..scene.render_settings.samples_per_pixel.max(1) {
+			sppm.pass(scene);
+		}
+		sppm.image(scene)
+	}
+
+	pub fn pass(&mut self, scene: &Scene) {
+		let seed = scene.render_settings.seed;
+		for (index, pixel) in self.pixels.iter_mut().enumerate() {
+			let (x, y) = (index as i32 % self.width, index as i32 / self.width);
+			let mut rng = Rng::new(hash_pixel(x as u32, y as u32, self.passes, seed));
+			let offset = (rng.next_f64(), rng.next_f64());
+			let ray = scene.primary_ray(x, y, offset, self.width, self.height);
+			pixel.point = None;
+			match visible_point(scene, ray, &mut rng) {
+				Some((point, seen)) => {
+					pixel.point = point;
+					pixel.covered += 1;
+					(0..3).for_each(|channel| pixel.seen[channel] += seen[channel]);
+				}
+				None => if let Some(color) = scene.backplate_color(x, y, offset, self.width, self.height) {
+					pixel.behind += 1;
+					pixel.backplate = [pixel.backplate[0] + color.r as f64, pixel.backplate[1] + color.g as f64, pixel.backplate[2] + color.b as f64];
+				},
+			}
+		}
+
+		let photon_count = scene.render_settings.photons_per_pass.max(1);
+		let mut rng = Rng::new(hash_pixel(u32::MAX, u32::MAX, self.passes, seed));
+		let map = PhotonMap::new(shoot_photons(scene, photon_count, &mut rng));
+		self.emitted += photon_count as u64;
+
+		for pixel in self.pixels.iter_mut() {
+			let point = match &pixel.point {
+				Some(point) => point,
+				None => continue,
+			};
+			let mut found = 0.0;
+			let mut flux = [0.0; 3];
+			map.for_each_within(&point.pos, pixel.radius, |photon| {
+				// Only photons that arrived on the side the camera sees
+				if Vec3::dot(&photon.direction, &point.normal) >= 0.0 { return }
+				found += 1.0;
+				for ((flux, power), albedo) in flux.iter_mut().zip(photon.power.iter()).zip(point.albedo.iter()) {
+					*flux += power * albedo;
+				}
+			});
+			if found == 0.0 { continue }
+			let photons = pixel.photons + ALPHA * found;
+			let shrink = photons / (pixel.photons + found);
+			for (total, flux) in pixel.flux.iter_mut().zip(flux.iter()) {
+				*total = (*total + flux) * shrink;
+			}
+			pixel.radius *= shrink.sqrt();
+			pixel.photons = photons;
+		}
+		self.passes += 1;
+	}
+
+	// As (color, coverage) per pixel. Media and the backplate go in front of and behind what the
+	// pixel's center sees, as the other integrators put them
+	pub fn image(&self, scene: &Scene) -> Vec<(Color, u8)> {
+		let scale = 255.0 * 2f64.powf(scene.render_settings.exposure);
+		self.pixels.iter().enumerate().map(|(index, pixel)| {
+			let (x, y) = (index as i32 % self.width, index as i32 / self.width);
+			let area = std::f64::consts::PI * pixel.radius * pixel.radius;
+			let gathered = 1.0 / (self.emitted.max(1) as f64 * area);
+			let covered = pixel.covered.max(1) as f64;
+			let channel = |channel: usize| ((pixel.flux[channel] * gathered + pixel.seen[channel] / covered) * scale).round().clamp(0.0, 255.0) as u8;
+			let lit = (pixel.covered > 0).then(|| Color { r: channel(0), g: channel(1), b: channel(2) });
+
+			let ray = scene.primary_ray(x, y, (0.5, 0.5), self.width, self.height);
+			let distance = scene.trace_primary(&ray).map(|hit| hit.0.dist(&ray.origin) / Vec3::dot(&ray.direction, &ray.direction).sqrt());
+			let behind = pixel.behind.max(1) as f64;
+			let backplate = (pixel.behind > 0).then(|| {
+				let channel = |channel: usize| (pixel.backplate[channel] / behind).round() as u8;
+				Color { r: channel(0), g: channel(1), b: channel(2) }
+			});
+			let color = scene.through_media(&ray, distance, lit).or(backplate).unwrap_or(Color { r: 0, g: 0, b: 0 });
+			(color, ((pixel.covered + pixel.behind) as f64 / self.passes.max(1) as f64 * 255.0).round() as u8)
+		}).collect()
+	}
+}
+
+// Follows the camera ray through mirrors and glass to a diffuse surface. None when it hits nothing,
+// otherwise the surface if it got to one, and what it saw of the environment if it left the scene
+// instead
+fn visible_point(scene: &Scene, mut ray: Ray, rng: &mut Rng) -> Option<(Option<VisiblePoint>, Rgb)> {
+	let mut hit = scene.trace_primary(&ray)?;
+	for _ in 0..MAX_DEPTH {
+		let incident = ray.direction.normalize();
+		let choice = rng.next_f64();
+		let (origin, direction) = match specular_bounce(&hit.1, &hit.0, &incident, &hit.2, choice, || (rng.next_f64(), rng.next_f64())) {
+			Some(bounce) => bounce,
+			None => return Some((Some(VisiblePoint { pos: hit.0, normal: facing(&hit.2, &incident), albedo: albedo(&hit.1) }), [0.0; 3])),
+		};
+		ray = Ray { origin, direction, mask: ray.mask.with_kinds(RayMask::REFLECTION), differential: None };
+		hit = match scene.trace(&ray) {
+			Some(hit) => hit,
+			None => return Some((None, environment(scene, &direction))),
+		};
+	}
+	Some((None, [0.0; 3]))
+}
+
+// Photons from every light in proportion to its intensity, each carrying the whole scene's power;
+// dividing by the number emitted happens when the image is made
+fn shoot_photons(scene: &Scene, count: u32, rng: &mut Rng) -> Vec<Photon> {
+	let lights: Vec<&LightSource> = scene.light_sources().filter(|light| light.intensity > 0.0).collect();
+	let total: f64 = lights.iter().map(|light| light.intensity as f64).sum();
+	let mut photons = Vec::new();
+	if lights.is_empty() { return photons }
+
+	for _ in 0..count {
+		let mut pick = rng.next_f64() * total;
+		let light = lights.iter().find(|light| { pick -= light.intensity as f64; pick <= 0.0 }).unwrap_or(&lights[lights.len() - 1]);
+		let direction = sample_sphere(rng.next_f64(), rng.next_f64());
+		let origin = light.pos.add(&direction.mul(light.radius as f64));
+		// Spot cones and gobos shape the emission, through the same lookup shading uses
+		let (strength, color) = light.emission(&light.pos.add(&direction));
+		if strength <= 0.0 { continue }
+		let power = 4.0 * std::f64::consts::PI * total * strength;
+		let mut power = [power * color.r as f64 / 255.0, power * color.g as f64 / 255.0, power * color.b as f64 / 255.0];
+
+		let mut ray = Ray { origin, direction, mask: RayMask::new(RayMask::GI, u32::MAX), differential: None };
+		for depth in 0..MAX_DEPTH {
+			let (index, hit) = match scene.trace_object(&ray) {
+				Some(hit) => hit,
+				None => break,
+			};
+			let incident = ray.direction.normalize();
+			let normal = facing(&hit.2, &incident);
+			let choice = rng.next_f64();
+			let (origin, next) = if let Some(bounce) = specular_bounce(&hit.1, &hit.0, &incident, &hit.2, choice, || (rng.next_f64(), rng.next_f64())) {
+				bounce
+			} else {
+				// Light linking only holds back the light's own first landing
+				if depth > 0 || light.illuminates(scene.objects[index].get_id()) {
+					photons.push(Photon { pos: hit.0, direction: ray.direction, power });
+				}
+				// Russian roulette on the surface color decides whether it bounces on
+				let albedo = albedo(&hit.1);
+				let survive = albedo[0].max(albedo[1]).max(albedo[2]);
+				if rng.next_f64() >= survive { break }
+				for channel in 0..3 {
+					power[channel] *= albedo[channel] / survive;
+				}
+				(hit.0.add(&normal.mul(OFFSET)), around(&normal, sample_cosine_hemisphere(rng.next_f64(), rng.next_f64())))
+			};
+			ray = Ray { origin, direction: next, mask: ray.mask, differential: None };
+		}
+	}
+	photons
+}
+
+#[test]
+fn lit_floor_converges_to_direct_brightness() {
+	use crate::structs::{Material, Mesh, Rot3, SceneObject, Tri};
+	// A dark floor under a light, so bounced light barely adds anything
+	let gray = Material { color: Color { r: 128, g: 128, b: 128 }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+	let corner = |x: f64, y: f64| Vec3 { x, y, z: 0.0 };
+	let floor = Mesh::new(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, Rot3::new(), vec![
+		Tri { a: corner(-20.0, -20.0), b: corner(20.0, -20.0), c: corner(20.0, 20.0), mat: gray },
+		Tri { a: corner(-20.0, -20.0), b: corner(20.0, 20.0), c: corner(-20.0, 20.0), mat: gray },
+	]);
+	let mut scene = Scene::default_scene();
+	let objects: Vec<Box<dyn SceneObject>> = vec![Box::new(floor), Box::new(LightSource::new(Vec3 { x: 0.0, y: 0.0, z: 2.0 }, Rot3::new(), 2.0))];
+	scene.objects = objects;
+	scene.current_camera.pos = Vec3 { x: 0.0, y: -0.01, z: 1.0 };
+	scene.current_camera.look_at(&Vec3 { x: 0.0, y: 0.0, z: 0.0 }, &Vec3 { x: 0.0, y: 0.0, z: 1.0 });
+	scene.render_settings.photons_per_pass = 20000;
+
+	let mut sppm = Sppm::new(&scene, 8, 8);
+	for _ in 0..8 {
+		sppm.pass(&scene);
+	}
+	// Intensity 2 from 2 away, times the floor's color
+	let expected = 2.0 / 4.0 * 128.0;
+	let image = sppm.image(&scene);
+	let center = image[4 * 8 + 4];
+	assert_eq!(center.1, 255);
+	assert!((center.0.r as f64 - expected).abs() < expected * 0.2, "{:?}", center);
+}
+
+#[test]
+fn glass_focuses_light_into_caustics() {
+	use crate::structs::{Material, Mesh, Rot3, SceneObject, Sphere, Tri};
+	// A glass ball between a light and the floor, the camera under it looking at the floor
+	let gray = Material { color: Color { r: 128, g: 128, b: 128 }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+	let glass = Material { color: Color { r: 255, g: 255, b: 255 }, transparency: 1.0, ..gray };
+	let corner = |x: f64, y: f64| Vec3 { x, y, z: 0.0 };
+	let floor = Mesh::new(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, Rot3::new(), vec![
+		Tri { a: corner(-20.0, -20.0), b: corner(20.0, -20.0), c: corner(20.0, 20.0), mat: gray },
+		Tri { a: corner(-20.0, -20.0), b: corner(20.0, 20.0), c: corner(-20.0, 20.0), mat: gray },
+	]);
+	let mut scene = Scene::default_scene();
+	let objects: Vec<Box<dyn SceneObject>> = vec![Box::new(floor), Box::new(Sphere::new(Vec3 { x: 0.0, y: 0.0, z: 1.0 }, 0.5, glass)), Box::new(LightSource::new(Vec3 { x: 0.0, y: 0.0, z: 3.0 }, Rot3::new(), 2.0))];
+	scene.objects = objects;
+	scene.current_camera.pos = Vec3 { x: 0.0, y: -0.01, z: 0.3 };
+	scene.current_camera.look_at(&Vec3 { x: 0.0, y: 0.0, z: 0.0 }, &Vec3 { x: 0.0, y: 0.0, z: 1.0 });
+	scene.render_settings.photons_per_pass = 20000;
+
+	let mut sppm = Sppm::new(&scene, 8, 8);
+	for _ in 0..8 {
+		sppm.pass(&scene);
+	}
+	// Light refracted through the ball gathers below it, brighter than the floor would be with no
+	// ball there at all
+	let unfocused = 2.0 / 9.0 * 128.0;
+	let center = sppm.image(&scene)[4 * 8 + 4];
+	assert_eq!(center.1, 255);
+	assert!(center.0.r as f64 > unfocused * 2.0, "{:?}", center);
+}
@@ -0,0 +1,139 @@
+//
+//	Trace Statistics
+//
+
+// Counts of the work a scene's rays did, kept as they're traced on whichever thread and read back
+// whole with Scene::stats, so a change meant to speed things up can be measured rather than guessed
+// at. Rays are counted by the kind their mask gives them, along with how many hit something and how
+// many triangles were tested to find out. Time is what was spent rendering tiles, see tiles.rs
+
+use crate::structs::{RayMask, Scene};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+thread_local! {
+	static TRIANGLE_TESTS: Cell<u64> = const { Cell::new(0) };
+}
+
+// Meshes call this with the triangles they tested a ray against
+pub fn count_triangle_tests(tests: u64) {
+	TRIANGLE_TESTS.with(|count| count.set(count.get() + tests));
+}
+
+// The triangle tests counted on this thread since the last call
+fn take_triangle_tests() -> u64 {
+	TRIANGLE_TESTS.with(|count| count.replace(0))
+}
+
+#[derive(Default)]
+pub struct TraceCounters {
+	rays: AtomicU64,
+	camera_rays: AtomicU64,
+	shadow_rays: AtomicU64,
+	reflection_rays: AtomicU64,
+	hits: AtomicU64,
+	triangle_tests: AtomicU64,
+	render_nanos: AtomicU64,
+}
+
+impl TraceCounters {
+	// rays traced with mask, hits of them having hit something, and the triangle tests they took
+	pub fn count_rays(&self, mask: &RayMask, rays: u64, hits: u64) {
+		self.rays.fetch_add(rays, Ordering::Relaxed);
+		let kind = match mask.kinds {
+			RayMask::CAMERA => Some(&self.camera_rays),
+			RayMask::SHADOW => Some(&self.shadow_rays),
+			RayMask::REFLECTION => Some(&self.reflection_rays),
+			_ => None, // queries and the like, only in the total
+		};
+		if let Some(kind) = kind {
+			kind.fetch_add(rays, Ordering::Relaxed);
+		}
+		if hits > 0 {
+			self.hits.fetch_add(hits, Ordering::Relaxed);
+		}
+		let tests = take_triangle_tests();
+		if tests > 0 {
+			self.triangle_tests.fetch_add(tests, Ordering::Relaxed);
+		}
+	}
+
+	pub fn add_render_time(&self, time: Duration) {
+		self.render_nanos.fetch_add(time.as_nanos() as u64, Ordering::Relaxed);
+	}
+
+	fn reset(&self) {
+		for counter in [&self.rays, &self.camera_rays, &self.shadow_rays, &self.reflection_rays, &self.hits, &self.triangle_tests, &self.render_nanos] {
+			counter.store(0, Ordering::Relaxed);
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TraceStats {
+	pub rays: u64, // of every kind
+	pub camera_rays: u64,
+	pub shadow_rays: u64,
+	pub reflection_rays: u64,
+	pub hits: u64,
+	pub triangle_tests: u64,
+	pub seconds: f64, // rendering
+}
+
+impl TraceStats {
+	pub fn rays_per_second(&self) -> f64 {
+		self.rays as f64 / self.seconds.max(1e-9)
+	}
+
+	pub fn triangle_tests_per_ray(&self) -> f64 {
+		self.triangle_tests as f64 / self.rays.max(1) as f64
+	}
+
+	// Fraction of rays that hit something
+	pub fn hit_rate(&self) -> f64 {
+		self.hits as f64 / self.rays.max(1) as f64
+	}
+}
+
+impl Scene {
+	// Everything counted since the scene was made or reset_stats was last called
+	pub fn stats(&self) -> TraceStats {
+		let counters = &self.counters;
+		let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+		TraceStats {
+			rays: load(&counters.rays),
+			camera_rays: load(&counters.camera_rays),
+			shadow_rays: load(&counters.shadow_rays),
+			reflection_rays: load(&counters.reflection_rays),
+			hits: load(&counters.hits),
+			triangle_tests: load(&counters.triangle_tests),
+			seconds: load(&counters.render_nanos) as f64 * 1e-9,
+		}
+	}
+
+	pub fn reset_stats(&self) {
+		self.counters.reset();
+	}
+}
+
+#[test]
+fn rays_counted_by_kind() {
+	use crate::structs::Vec3;
+	let mut scene = Scene::default_scene();
+	scene.renderer.threads = 2;
+	// Looking at the cube
+	scene.current_camera.look_at(&Vec3 { x: 0.0, y: 0.0, z: 1.5 }, &Vec3 { x: 0.0, y: 0.0, z: 1.0 });
+	scene.render(32, 18);
+	let stats = scene.stats();
+	// One camera ray a pixel, and shadow rays from wherever they hit
+	assert_eq!(stats.camera_rays, 32 * 18);
+	assert!(stats.shadow_rays > 0);
+	assert!(stats.rays >= stats.camera_rays + stats.shadow_rays + stats.reflection_rays);
+	assert!(stats.hits > 0 && stats.hit_rate() <= 1.0);
+	// The cube and the floor are both meshes
+	assert!(stats.triangle_tests_per_ray() > 0.0 && stats.seconds > 0.0 && stats.rays_per_second() > 0.0);
+
+	scene.reset_stats();
+	assert_eq!(scene.stats(), TraceStats::default());
+}
@@ -0,0 +1,135 @@
+//
+//	Stereo
+//
+
+// Two renders from either side of the camera, one per eye, put together into a single image: side by
+// side at half width each, for headsets and 3D displays that split the frame themselves, or as a
+// red/cyan anaglyph for cheap glasses on any display. The eyes look parallel, so only things infinitely
+// far away sit at the screen and everything nearer comes out of it
+
+use crate::framebuffer::Framebuffer;
+use crate::structs::{Color, Scene, Vec3};
+
+pub const DEFAULT_SEPARATION: f64 = 0.065; // meters, about the distance between a person's eyes
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StereoMode {
+	SideBySide, // left eye in the left half
+	Anaglyph, // left eye in the red channel, right in green and blue
+}
+
+impl StereoMode {
+	pub fn from_name(name: &str) -> Option<StereoMode> {
+		match name {
+			"side-by-side" => Some(StereoMode::SideBySide),
+			"anaglyph" => Some(StereoMode::Anaglyph),
+			_ => None,
+		}
+	}
+
+	// How wide each eye renders for a frame width wide
+	pub fn eye_width(&self, width: usize) -> usize {
+		match self {
+			StereoMode::SideBySide => (width / 2).max(1),
+			StereoMode::Anaglyph => width,
+		}
+	}
+
+	// Where pixel (x, y) of the frame comes from: which eye, right being true, and the pixel in it
+	pub fn source(&self, x: usize, y: usize, width: usize) -> (bool, usize, usize) {
+		let eye_width = self.eye_width(width);
+		match self {
+			StereoMode::SideBySide if x >= eye_width => (true, (x - eye_width).min(eye_width - 1), y),
+			_ => (false, x, y),
+		}
+	}
+
+	// Both eyes' width x height images, eye_width wide each, as one frame
+	pub fn compose(&self, left: &[Color], right: &[Color], width: usize, height: usize) -> Vec<Color> {
+		let eye_width = self.eye_width(width);
+		(0..width * height).map(|index| {
+			let (is_right, x, y) = self.source(index % width, index / width, width);
+			let pixel = y * eye_width + x;
+			match self {
+				StereoMode::SideBySide => if is_right { right[pixel] } else { left[pixel] },
+				StereoMode::Anaglyph => anaglyph(&left[pixel], &right[pixel]),
+			}
+		}).collect()
+	}
+}
+
+pub fn anaglyph(left: &Color, right: &Color) -> Color {
+	Color { r: left.r, g: right.g, b: right.b }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stereo {
+	pub mode: StereoMode,
+	pub separation: f64, // between the eyes, meters
+}
+
+impl Scene {
+	// Calls render with the camera moved to the left eye then the right, putting it back after
+	pub fn for_each_eye<T>(&mut self, separation: f64, mut render: impl FnMut(&mut Scene) -> T) -> (T, T) {
+		let center = self.current_camera.pos;
+		let right = Vec3 { x: 1.0, y: 0.0, z: 0.0 }.rotate(&self.current_camera.rot).mul(separation / 2.0);
+		self.current_camera.pos = center.sub(&right);
+		let left_eye = render(self);
+		self.current_camera.pos = center.add(&right);
+		let right_eye = render(self);
+		self.current_camera.pos = center;
+		(left_eye, right_eye)
+	}
+
+	// render_framebuffer for both eyes, as one width x height frame. Everything but color comes from
+	// the left eye where the two overlap
+	pub fn render_stereo(&mut self, width: i32, height: i32, stereo: &Stereo) -> Framebuffer {
+		let eye_width = stereo.mode.eye_width(width as usize);
+		let (left, right) = self.for_each_eye(stereo.separation, |scene| scene.render_framebuffer(eye_width as i32, height));
+		let mut framebuffer = Framebuffer::new(width as usize, height as usize);
+		let color = stereo.mode.compose(&left.color, &right.color, width as usize, height as usize);
+		for (index, color) in color.into_iter().enumerate() {
+			let (is_right, x, y) = stereo.mode.source(index % width as usize, index / width as usize, width as usize);
+			let (eye, pixel) = (if is_right { &right } else { &left }, y * eye_width + x);
+			let alpha = match stereo.mode {
+				StereoMode::SideBySide => eye.alpha[pixel],
+				StereoMode::Anaglyph => left.alpha[pixel].max(right.alpha[pixel]),
+			};
+			framebuffer.color[index] = color;
+			framebuffer.alpha[index] = alpha;
+			framebuffer.depth[index] = eye.depth[pixel];
+			framebuffer.normal[index] = eye.normal[pixel];
+			framebuffer.sky_visibility[index] = eye.sky_visibility[pixel];
+			framebuffer.bent_normal[index] = eye.bent_normal[pixel];
+			framebuffer.motion[index] = eye.motion[pixel];
+		}
+		framebuffer
+	}
+}
+
+#[test]
+fn stereo_eyes() {
+	use crate::structs::{Camera, LightSource, Material, Rot3, Sphere};
+	// A white ball close enough for the eyes to disagree on where it is
+	let white = Material { color: Color { r: 255, g: 255, b: 255 }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+	let origin = Vec3 { x: 0.0, y: 0.0, z: 0.0 };
+	let mut camera = Camera::new(Vec3 { x: 0.0, y: -3.0, z: 0.0 }, Rot3::new(), 40.0);
+	camera.look_at(&origin, &Vec3 { x: 0.0, y: 0.0, z: 1.0 });
+	let light = LightSource::new(Vec3 { x: 0.0, y: -4.0, z: 3.0 }, Rot3::new(), 20.0);
+	let mut scene = Scene::new(vec![Box::new(light), Box::new(Sphere::new(origin, 0.5, white))], camera);
+	let stereo = Stereo { mode: StereoMode::Anaglyph, separation: 0.5 };
+	let (left, right) = scene.for_each_eye(stereo.separation, |scene| scene.current_camera.pos);
+	assert!((left.x + 0.25).abs() < 1e-9 && (right.x - 0.25).abs() < 1e-9 && scene.current_camera.pos.x == 0.0);
+
+	// The anaglyph has red where only the left eye sees the ball and cyan where only the right one does
+	let framebuffer = scene.render_stereo(24, 24, &stereo);
+	assert!(framebuffer.color.iter().any(|color| color.r > 0 && color.g == 0));
+	assert!(framebuffer.color.iter().any(|color| color.r == 0 && color.g > 0 && color.b > 0));
+	assert_eq!(anaglyph(&Color { r: 10, g: 20, b: 30 }, &Color { r: 40, g: 50, b: 60 }), Color { r: 10, g: 50, b: 60 });
+
+	// Side by side, each half is that eye on its own
+	let halves = scene.render_stereo(24, 24, &Stereo { mode: StereoMode::SideBySide, ..stereo });
+	let (left_eye, _) = scene.for_each_eye(0.5, |scene| scene.render_framebuffer(12, 24));
+	assert!((0..24 * 12).all(|pixel| halves.color[(pixel / 12) * 24 + pixel % 12] == left_eye.color[pixel]));
+	assert_eq!(StereoMode::from_name("side-by-side"), Some(StereoMode::SideBySide));
+}
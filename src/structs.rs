@@ -3,8 +3,34 @@
 //
 
 use uuid::Uuid;
-use std::cell::RefCell;
 use core::any::Any;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::render::{Integrator, RenderSettings, Scratch};
+use crate::sppm::Sppm;
+use crate::path;
+use crate::framebuffer::Framebuffer;
+use crate::sampler::{sample_cone, sample_cosine_hemisphere, sample_disk};
+use crate::texture::Texture;
+use crate::animation::{Animation, Modifier};
+use crate::physics::Physics;
+use crate::accelerator::Accelerator;
+use crate::media::{Interior, Volume};
+use crate::clipping::ClipPlane;
+use crate::backplate::Backplate;
+use crate::environment::Environment;
+use crate::light_tree::LightTree;
+use crate::stats::{count_triangle_tests, TraceCounters};
+use crate::profiler::{Profiler, Stage};
+use crate::presets::{builtin_presets, RenderPreset};
+use crate::bvh::{padded, Bvh};
+use crate::bvh_cache::BvhCache;
+use crate::arena::Arena;
+use crate::tiles::{render_tile_packets, CostMap, RenderStats, RendererConfig};
+use crate::random::Rng;
+use crate::simd::LANES;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Vec3 {
@@ -48,40 +74,66 @@ impl Vec3 {
 			let inv = 1.0 / len_squared.sqrt();
 			return Vec3 { x: self.x * inv, y: self.y * inv, z: self.z * inv };
 		}
-		return self.clone();
+		*self
+	}
+
+	// Two unit vectors perpendicular to self (assumed normalized) and to each other
+	pub fn orthonormal_basis(&self) -> (Vec3, Vec3) {
+		let helper = if self.x.abs() > 0.9 { Vec3 { x: 0.0, y: 1.0, z: 0.0 } } else { Vec3 { x: 1.0, y: 0.0, z: 0.0 } };
+		let tangent = Vec3::cross(&helper, self).normalize();
+		let bitangent = Vec3::cross(self, &tangent);
+		(tangent, bitangent)
 	}
 
 	pub fn rotate(&self, rot: &Rot3) -> Vec3 {
-        let su = rot.roll.sin();
-        let cu = rot.roll.cos();
-        let sv = rot.pitch.sin();
-        let cv = rot.pitch.cos();
-        let sw = rot.yaw.sin();
-        let cw = rot.yaw.cos();
-
-		let r11 = cv*cw;
-        let r12 = su*sv*cw - cu*sw;
-        let r13 = su*sw + cu*sv*cw;
-        let r21 = cv*sw;
-        let r22 = cu*cw + su*sv*sw;
-        let r23 = cu*sv*sw - su*cw;
-        let r31 = -sv;
-        let r32 = su*cv;
-        let r33 = cu*cv; 
-		let x = r11* self.x + r12 * self.y + r13 * self.z;
-		let y = r21* self.x + r22 * self.y + r23 * self.z;
-		let z = r31* self.x + r32 * self.y + r33 * self.z;
-
-		Vec3 { x, y, z }
+		rot.to_matrix().apply(self)
+	}
+
+	// Rodrigues' formula, axis needn't be normalized
+	pub fn rotate_about(&self, axis: &Vec3, angle: f64) -> Vec3 {
+		let k = axis.normalize();
+		let (sin, cos) = angle.sin_cos();
+		self.mul(cos).add(&Vec3::cross(&k, self).mul(sin)).add(&k.mul(Vec3::dot(&k, self) * (1.0 - cos)))
+	}
+
+	// Undoes rotate
+	pub fn unrotate(&self, rot: &Rot3) -> Vec3 {
+		rot.to_matrix().apply_inverse(self)
+	}
+}
+
+// A rotation worked out once, for turning many vectors the same way without the trig every time
+#[derive(Debug, Clone, Copy)]
+pub struct Mat3 {
+	pub rows: [Vec3; 3],
+}
+
+impl Mat3 {
+	pub fn apply(&self, v: &Vec3) -> Vec3 {
+		let [r1, r2, r3] = &self.rows;
+		Vec3 { x: Vec3::dot(r1, v), y: Vec3::dot(r2, v), z: Vec3::dot(r3, v) }
+	}
+
+	// The matrix is orthonormal so its transpose is its inverse
+	pub fn apply_inverse(&self, v: &Vec3) -> Vec3 {
+		let [r1, r2, r3] = &self.rows;
+		r1.mul(v.x).add(&r2.mul(v.y)).add(&r3.mul(v.z))
 	}
 }
 
+#[derive(Debug, Clone, Copy)]
 pub struct Rot3 {
 	pub yaw: f64,
 	pub pitch: f64,
 	pub roll: f64,
 }
 
+impl Default for Rot3 {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
 impl Rot3 {
 	pub fn new() -> Self {
 		Self {
@@ -91,9 +143,44 @@ impl Rot3 {
 		}
 	}
 
+	// Roll about x, then pitch about y, then yaw about z
+	pub fn to_matrix(&self) -> Mat3 {
+		let (su, cu) = self.roll.sin_cos();
+		let (sv, cv) = self.pitch.sin_cos();
+		let (sw, cw) = self.yaw.sin_cos();
+		Mat3 { rows: [
+			Vec3 { x: cv*cw, y: su*sv*cw - cu*sw, z: su*sw + cu*sv*cw },
+			Vec3 { x: cv*sw, y: cu*cw + su*sv*sw, z: cu*sv*sw - su*cw },
+			Vec3 { x: -sv, y: su*cv, z: cu*cv },
+		] }
+	}
+
 	pub fn to_vec(rot: &Rot3) -> Vec3 {
 		Vec3 { x: rot.yaw.cos() * rot.pitch.cos(), y: rot.yaw.sin() * rot.pitch.cos(), z: rot.pitch.sin() }
 	}
+
+	// Rotating by the result is rotating by inner and then by self
+	pub fn compose(&self, inner: &Rot3) -> Rot3 {
+		let x = Vec3 { x: 1.0, y: 0.0, z: 0.0 }.rotate(inner).rotate(self);
+		let y = Vec3 { x: 0.0, y: 1.0, z: 0.0 }.rotate(inner).rotate(self);
+		let z = Vec3 { x: 0.0, y: 0.0, z: 1.0 }.rotate(inner).rotate(self);
+		Rot3::from_columns(&x, &y, &z)
+	}
+
+	// Turning angle radians about axis, counterclockwise looking down the axis
+	pub fn from_axis_angle(axis: &Vec3, angle: f64) -> Rot3 {
+		let turn = |v: Vec3| v.rotate_about(axis, angle);
+		Rot3::from_columns(&turn(Vec3 { x: 1.0, y: 0.0, z: 0.0 }), &turn(Vec3 { x: 0.0, y: 1.0, z: 0.0 }), &turn(Vec3 { x: 0.0, y: 0.0, z: 1.0 }))
+	}
+
+	// Same angle extraction as Camera::look_at, from where the rotation takes the x, y and z axes
+	fn from_columns(x: &Vec3, y: &Vec3, z: &Vec3) -> Rot3 {
+		Rot3 {
+			yaw: x.y.atan2(x.x),
+			pitch: (-x.z).clamp(-1.0, 1.0).asin(),
+			roll: y.z.atan2(z.z),
+		}
+	}
 }
 
 pub struct Tri {
@@ -106,9 +193,9 @@ pub struct Tri {
 impl Tri {
 	pub fn transformed_rot(&self, rot: &Rot3) -> Tri {
 		Tri {
-			a: self.a.rotate(&rot),
-			b: self.b.rotate(&rot),
-			c: self.c.rotate(&rot),
+			a: self.a.rotate(rot),
+			b: self.b.rotate(rot),
+			c: self.c.rotate(rot),
 			mat: self.mat
 		}
 	}
@@ -122,90 +209,712 @@ impl Tri {
 		}
 	}
 
+	// Unit length, facing the side the corners go round counterclockwise on
 	pub fn normal(&self) -> Vec3 {
-		let u = self.b.sub(&self.a);
-		let v = self.c.sub(&self.a);
-		Vec3 { x: u.y*v.z - u.z * v.y, y: u.z * v.x - u.z * v.z, z: u.z * v.y - u.y * v.x }
+		Vec3::cross(&self.b.sub(&self.a), &self.c.sub(&self.a)).normalize()
 	}
 
 	pub fn transformed(&self, pos: &Vec3, rot: &Rot3) -> Tri {
-		return self.transformed_rot(rot).transformed_pos(pos);
+		self.transformed_rot(rot).transformed_pos(pos)
 	}
 
 	pub fn ray_hit(&self, ray: &Ray) -> Option<Vec3> {
-		let epsilon = 0.0000001;
-		let edge1 = self.b.sub(&self.a); 
-		let edge2 = self.c.sub(&self.a);
-		let ray_dir_edge2 = Vec3::cross(&ray.direction, &edge2);
-		let det = Vec3::dot(&edge1, &ray_dir_edge2);
-		if det > -epsilon && det < epsilon { return None }
-		let inv_det = 1.0 / det;
-		let orig_minus_a = ray.origin.sub(&self.a);
-		let barymetric_u = Vec3::dot(&ray_dir_edge2, &orig_minus_a) * inv_det;
-		if barymetric_u < 0.0 || barymetric_u > 1.0 { return None }
-		let cross_oma_a = Vec3::cross(&orig_minus_a, &edge1);
-		let barymetric_v = Vec3::dot(&ray.direction, &cross_oma_a) * inv_det;
-		if barymetric_v < 0.0 || barymetric_v + barymetric_u > 1.0 { return None }
-		let ray_t = Vec3::dot(&edge2, &cross_oma_a) * inv_det;
-		if ray_t < epsilon { return None }
-		return Some(Vec3::add(&ray.origin, &ray.direction.mul(ray_t)));
-	}
-}
-
-pub trait SceneObject {
+		self.ray_hit_barycentric(ray).map(|(point, _, _)| point)
+	}
+
+	// Point on the triangle nearest p, edges and corners included
+	pub fn closest_point(&self, p: &Vec3) -> Vec3 {
+		let (a, b, c) = (&self.a, &self.b, &self.c);
+		let (ab, ac, ap) = (b.sub(a), c.sub(a), p.sub(a));
+		let (d1, d2) = (Vec3::dot(&ab, &ap), Vec3::dot(&ac, &ap));
+		if d1 <= 0.0 && d2 <= 0.0 { return *a }
+		let bp = p.sub(b);
+		let (d3, d4) = (Vec3::dot(&ab, &bp), Vec3::dot(&ac, &bp));
+		if d3 >= 0.0 && d4 <= d3 { return *b }
+		let vc = d1 * d4 - d3 * d2;
+		if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 { return a.add(&ab.mul(d1 / (d1 - d3))) }
+		let cp = p.sub(c);
+		let (d5, d6) = (Vec3::dot(&ab, &cp), Vec3::dot(&ac, &cp));
+		if d6 >= 0.0 && d5 <= d6 { return *c }
+		let vb = d5 * d2 - d1 * d6;
+		if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 { return a.add(&ac.mul(d2 / (d2 - d6))) }
+		let va = d3 * d6 - d5 * d4;
+		if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+			return b.add(&c.sub(b).mul((d4 - d3) / ((d4 - d3) + (d5 - d6))));
+		}
+		let denominator = 1.0 / (va + vb + vc);
+		a.add(&ab.mul(vb * denominator)).add(&ac.mul(vc * denominator))
+	}
+
+	// Hit point along with the barycentric weights of b and c there
+	pub fn ray_hit_barycentric(&self, ray: &Ray) -> Option<(Vec3, f64, f64)> {
+		intersect(&self.a, &self.b.sub(&self.a), &self.c.sub(&self.a), ray)
+	}
+}
+
+// A triangle by one corner and the edges from it to the other two, along with its face normal,
+// everything a ray test needs worked out ahead of time
+#[derive(Debug, Clone, Copy)]
+pub struct PreparedTri {
+	pub a: Vec3,
+	pub edge1: Vec3,
+	pub edge2: Vec3,
+	pub normal: Vec3,
+}
+
+impl PreparedTri {
+	pub fn new(tri: &Tri) -> Self {
+		PreparedTri { a: tri.a, edge1: tri.b.sub(&tri.a), edge2: tri.c.sub(&tri.a), normal: tri.normal() }
+	}
+
+	// Same as Tri::ray_hit_barycentric
+	pub fn ray_hit_barycentric(&self, ray: &Ray) -> Option<(Vec3, f64, f64)> {
+		intersect(&self.a, &self.edge1, &self.edge2, ray)
+	}
+
+	// Barycentric weights of b and c at point, projected onto the triangle's plane; outside it they
+	// carry on past 0 and 1
+	pub fn barycentric(&self, point: &Vec3) -> (f64, f64) {
+		let to_point = point.sub(&self.a);
+		let (d11, d12, d22) = (Vec3::dot(&self.edge1, &self.edge1), Vec3::dot(&self.edge1, &self.edge2), Vec3::dot(&self.edge2, &self.edge2));
+		let (d1p, d2p) = (Vec3::dot(&self.edge1, &to_point), Vec3::dot(&self.edge2, &to_point));
+		let inv = 1.0 / (d11 * d22 - d12 * d12);
+		((d22 * d1p - d12 * d2p) * inv, (d11 * d2p - d12 * d1p) * inv)
+	}
+}
+
+// The same triangles a column per coordinate, the corner and both edges split into x, y and z arrays,
+// laid out in the order the hierarchy's leaves list them so a leaf's triangles sit side by side.
+// Slot s holds triangle order[s]; its material is an index into the mesh's materials
+pub struct TriangleBuffer {
+	a: [Vec<f64>; 3],
+	edge1: [Vec<f64>; 3],
+	edge2: [Vec<f64>; 3],
+	normals: Vec<Vec3>, // read once per hit, not per test, so left whole
+	pub materials: Vec<u32>,
+}
+
+impl TriangleBuffer {
+	pub fn new(tris: &[PreparedTri], order: &[u32], material_ids: &[u32]) -> Self {
+		let column = |field: fn(&PreparedTri) -> f64| order.iter().map(|i| field(&tris[*i as usize])).collect::<Vec<f64>>();
+		TriangleBuffer {
+			a: [column(|tri| tri.a.x), column(|tri| tri.a.y), column(|tri| tri.a.z)],
+			edge1: [column(|tri| tri.edge1.x), column(|tri| tri.edge1.y), column(|tri| tri.edge1.z)],
+			edge2: [column(|tri| tri.edge2.x), column(|tri| tri.edge2.y), column(|tri| tri.edge2.z)],
+			normals: order.iter().map(|i| tris[*i as usize].normal).collect(),
+			materials: order.iter().map(|i| material_ids[*i as usize]).collect(),
+		}
+	}
+
+	pub fn len(&self) -> usize { self.normals.len() }
+	pub fn is_empty(&self) -> bool { self.normals.is_empty() }
+
+	pub fn get(&self, slot: usize) -> PreparedTri {
+		let at = |column: &[Vec<f64>; 3]| Vec3 { x: column[0][slot], y: column[1][slot], z: column[2][slot] };
+		PreparedTri { a: at(&self.a), edge1: at(&self.edge1), edge2: at(&self.edge2), normal: self.normals[slot] }
+	}
+
+	// Same as PreparedTri::ray_hit_barycentric, without touching the normal
+	pub fn ray_hit_barycentric(&self, slot: usize, ray: &Ray) -> Option<(Vec3, f64, f64)> {
+		let at = |column: &[Vec<f64>; 3]| Vec3 { x: column[0][slot], y: column[1][slot], z: column[2][slot] };
+		intersect(&at(&self.a), &at(&self.edge1), &at(&self.edge2), ray)
+	}
+}
+
+fn intersect(a: &Vec3, edge1: &Vec3, edge2: &Vec3, ray: &Ray) -> Option<(Vec3, f64, f64)> {
+	let epsilon = 0.0000001;
+	let ray_dir_edge2 = Vec3::cross(&ray.direction, edge2);
+	let det = Vec3::dot(edge1, &ray_dir_edge2);
+	if det > -epsilon && det < epsilon { return None }
+	let inv_det = 1.0 / det;
+	let orig_minus_a = ray.origin.sub(a);
+	let barymetric_u = Vec3::dot(&ray_dir_edge2, &orig_minus_a) * inv_det;
+	if !(0.0..=1.0).contains(&barymetric_u) { return None }
+	let cross_oma_a = Vec3::cross(&orig_minus_a, edge1);
+	let barymetric_v = Vec3::dot(&ray.direction, &cross_oma_a) * inv_det;
+	if barymetric_v < 0.0 || barymetric_v + barymetric_u > 1.0 { return None }
+	let ray_t = Vec3::dot(edge2, &cross_oma_a) * inv_det;
+	if ray_t < epsilon { return None }
+	Some((Vec3::add(&ray.origin, &ray.direction.mul(ray_t)), barymetric_u, barymetric_v))
+}
+
+// Axis aligned bounding box
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+	pub min: Vec3,
+	pub max: Vec3,
+}
+
+impl Aabb {
+	// Inside out, so growing it by any point gives a box around just that point
+	pub fn empty() -> Self {
+		Aabb {
+			min: Vec3 { x: f64::INFINITY, y: f64::INFINITY, z: f64::INFINITY },
+			max: Vec3 { x: f64::NEG_INFINITY, y: f64::NEG_INFINITY, z: f64::NEG_INFINITY },
+		}
+	}
+
+	pub fn grow(&mut self, point: &Vec3) {
+		self.min = Vec3 { x: self.min.x.min(point.x), y: self.min.y.min(point.y), z: self.min.z.min(point.z) };
+		self.max = Vec3 { x: self.max.x.max(point.x), y: self.max.y.max(point.y), z: self.max.z.max(point.z) };
+	}
+
+	pub fn area(&self) -> f64 {
+		let size = self.max.sub(&self.min);
+		2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+	}
+
+	pub fn closest_point(&self, point: &Vec3) -> Vec3 {
+		Vec3 { x: point.x.clamp(self.min.x, self.max.x), y: point.y.clamp(self.min.y, self.max.y), z: point.z.clamp(self.min.z, self.max.z) }
+	}
+
+	pub fn center(&self) -> Vec3 {
+		self.min.add(&self.max).mul(0.5)
+	}
+
+	// Distance along the ray to where it enters the box, zero from inside, None when it misses
+	pub fn ray_distance(&self, ray: &Ray) -> Option<f64> {
+		let (mut near, mut far) = (0.0, f64::INFINITY);
+		for (origin, direction, min, max) in [
+			(ray.origin.x, ray.direction.x, self.min.x, self.max.x),
+			(ray.origin.y, ray.direction.y, self.min.y, self.max.y),
+			(ray.origin.z, ray.direction.z, self.min.z, self.max.z),
+		] {
+			let (t0, t1) = ((min - origin) / direction, (max - origin) / direction);
+			near = t0.min(t1).max(near);
+			far = t0.max(t1).min(far);
+		}
+		if near <= far { Some(near) } else { None }
+	}
+
+	// Corner furthest along direction
+	pub fn support(&self, direction: &Vec3) -> Vec3 {
+		Vec3 {
+			x: if direction.x >= 0.0 { self.max.x } else { self.min.x },
+			y: if direction.y >= 0.0 { self.max.y } else { self.min.y },
+			z: if direction.z >= 0.0 { self.max.z } else { self.min.z },
+		}
+	}
+}
+
+pub trait SceneObject: Send + Sync {
 	fn get_pos(&self) -> &Vec3;
 	fn get_rot(&self) -> &Rot3;
 	fn ray_hit(&self, ray: &Ray) -> Option<(Vec3, Material, Vec3)>;
+	// ray_hit for a few neighbouring rays at once, for things that can share work between them
+	fn ray_hit_packet(&self, rays: &[Ray; LANES]) -> [Option<(Vec3, Material, Vec3)>; LANES] {
+		rays.each_ref().map(|ray| self.ray_hit(ray))
+	}
 	fn as_any(&mut self) -> &mut dyn Any;
 	fn as_any_immut(&self) -> &dyn Any;
 	fn get_id(&self) -> &String;
+	// Editable surface material, for objects that have a single one to speak of
+	fn get_material(&self) -> Option<Material> { None }
+	fn set_material(&mut self, _material: Material) {}
+	// World space bounds, None for things rays can't hit or that have no finite extent
+	fn bounds(&self) -> Option<Aabb> { None }
+	fn get_mask(&self) -> RayMask { RayMask::ALL }
+	fn set_mask(&mut self, _mask: RayMask) {}
+	// Moves the object, for animation; things with no transform of their own ignore it
+	fn set_transform(&mut self, _pos: Vec3, _rot: Rot3) {}
+	// Point on the surface nearest point, None for things with no surface
+	fn closest_point(&self, _point: &Vec3) -> Option<Vec3> { None }
+	// Where shadow rays leave from for the point ray hit, off anything of the object's own that would
+	// wrongly shadow it
+	fn shadow_origin(&self, _ray: &Ray, point: &Vec3) -> Vec3 { *point }
+}
+
+// Geometry kept as separate indexed arrays rather than a list of self contained triangles,
+// so the hot loops only pull in the data they actually read. It can't change once made, which
+// keeps the hierarchy over its triangles in step with them
+pub struct MeshGeometry {
+	pub positions: Vec<Vec3>,
+	pub normals: Vec<Vec3>, // per vertex, empty for flat shading
+	pub uvs: Vec<(f64, f64)>, // per vertex, may be empty
+	pub indices: Vec<[u32; 3]>,
+	pub material_ids: Vec<u32>, // per triangle, into the mesh's materials
+	bvh: Bvh, // over the triangles, in object space
+}
+
+impl MeshGeometry {
+	pub fn new(positions: Vec<Vec3>, normals: Vec<Vec3>, uvs: Vec<(f64, f64)>, indices: Vec<[u32; 3]>, material_ids: Vec<u32>) -> Self {
+		MeshGeometry::with_cache(positions, normals, uvs, indices, material_ids, None)
+	}
+
+	// new, reading the hierarchy from cache when it's there already, see bvh_cache.rs
+	pub fn with_cache(positions: Vec<Vec3>, normals: Vec<Vec3>, uvs: Vec<(f64, f64)>, indices: Vec<[u32; 3]>, material_ids: Vec<u32>, cache: Option<&BvhCache>) -> Self {
+		let bounds = triangle_bounds(&positions, &indices);
+		let bvh = match cache {
+			Some(cache) => cache.bvh(&bounds),
+			None => Bvh::new(&bounds),
+		};
+		MeshGeometry { positions, normals, uvs, indices, material_ids, bvh }
+	}
+
+	// Same triangles with their corners moved, refitting the hierarchy rather than building it again
+	pub fn reshaped(&self, positions: Vec<Vec3>, normals: Vec<Vec3>) -> Self {
+		let mut bvh = self.bvh.clone();
+		bvh.refit(&triangle_bounds(&positions, &self.indices));
+		MeshGeometry { positions, normals, uvs: self.uvs.clone(), indices: self.indices.clone(), material_ids: self.material_ids.clone(), bvh }
+	}
+
+	// Same triangles with other material ids
+	pub fn recolored(&self, material_ids: Vec<u32>) -> Self {
+		MeshGeometry { material_ids, ..self.reshaped(self.positions.clone(), self.normals.clone()) }
+	}
+
+	// Bytes its vertices, triangles and hierarchy take up
+	pub fn memory(&self) -> usize {
+		use std::mem::size_of;
+		self.positions.len() * size_of::<Vec3>() + self.normals.len() * size_of::<Vec3>() + self.uvs.len() * size_of::<(f64, f64)>()
+			+ self.indices.len() * size_of::<[u32; 3]>() + self.material_ids.len() * size_of::<u32>() + self.bvh.memory()
+	}
+
+	// Splits triangles into shared vertices, returning the materials material_ids refer to
+	pub fn from_tris(tris: &[Tri]) -> (MeshGeometry, Vec<Material>) {
+		let mut positions = Vec::new();
+		let mut indices = Vec::with_capacity(tris.len());
+		let mut material_ids = Vec::with_capacity(tris.len());
+		let mut materials: Vec<Material> = Vec::new();
+		// Triangles share corners, store each distinct position once
+		let mut vertex_ids: HashMap<[u64; 3], u32> = HashMap::new();
+		for tri in tris.iter() {
+			let mut index = [0; 3];
+			for (slot, vertex) in index.iter_mut().zip([tri.a, tri.b, tri.c].iter()) {
+				*slot = *vertex_ids.entry([vertex.x.to_bits(), vertex.y.to_bits(), vertex.z.to_bits()]).or_insert_with(|| {
+					positions.push(*vertex);
+					positions.len() as u32 - 1
+				});
+			}
+			let material_id = match materials.iter().position(|material| *material == tri.mat) {
+				Some(id) => id,
+				None => { materials.push(tri.mat); materials.len() - 1 }
+			};
+			indices.push(index);
+			material_ids.push(material_id as u32);
+		}
+		(MeshGeometry::new(positions, Vec::new(), Vec::new(), indices, material_ids), materials)
+	}
+}
+
+fn triangle_bounds(positions: &[Vec3], indices: &[[u32; 3]]) -> Vec<Aabb> {
+	indices.iter().map(|corners| {
+		let mut bounds = Aabb::empty();
+		for corner in corners {
+			bounds.grow(&positions[*corner as usize]);
+		}
+		bounds
+	}).collect()
+}
+
+// Offsets from the base shape, per vertex, that a weight blends in
+#[derive(Debug, Clone)]
+pub struct MorphTarget {
+	pub name: String,
+	pub positions: Vec<Vec3>,
+	pub normals: Vec<Vec3>, // may be empty
+}
+
+// Blend shapes: the mesh's geometry is the base plus every target times its weight
+pub struct Morph {
+	pub base: Arc<MeshGeometry>,
+	pub targets: Vec<MorphTarget>,
+	pub weights: Vec<f64>,
+}
+
+impl Morph {
+	pub fn blend(&self) -> MeshGeometry {
+		let base = &self.base;
+		let mut positions = base.positions.clone();
+		let mut normals = base.normals.clone();
+		for (target, weight) in self.targets.iter().zip(self.weights.iter()) {
+			if *weight == 0.0 { continue }
+			for (position, offset) in positions.iter_mut().zip(target.positions.iter()) {
+				*position = position.add(&offset.mul(*weight));
+			}
+			for (normal, offset) in normals.iter_mut().zip(target.normals.iter()) {
+				*normal = normal.add(&offset.mul(*weight));
+			}
+		}
+		base.reshaped(positions, normals.iter().map(|normal| normal.normalize()).collect())
+	}
 }
 
+// Geometry is shared between instances, each mesh has its own placement and materials
 pub struct Mesh {
 	pub anchor: Vec3,
 	pub rot: Rot3,
-	pub tri_list: Vec<Tri>,
-	pub id: String
+	pub geometry: Arc<MeshGeometry>,
+	pub materials: Vec<Material>,
+	pub mask: RayMask,
+	pub id: String,
+	pub morph: Option<Morph>,
+	pub texture: Option<Arc<Texture>>, // tints the materials, by the geometry's uvs
+	moved: Option<Vec<Vec3>>, // positions edited since the hierarchy was last refit, see refit_bvh
+	// Triangles and bounds in world space as of the placement and geometry they were made for, see world_triangles
+	world: RwLock<Option<Arc<WorldTriangles>>>,
+}
+
+struct WorldTriangles {
+	placement: [f64; 6], // anchor then rot
+	geometry: Arc<MeshGeometry>,
+	rotation: Mat3,
+	bounds: Option<Aabb>,
+	tris: TriangleBuffer, // slot by slot as the hierarchy's leaves hold them
+	#[cfg(feature = "simd")]
+	packets: Vec<crate::simd::Tri4>, // tris four at a time, in the order the hierarchy's leaves hold them
 }
 
 impl Mesh {
 	pub fn new(anchor: Vec3, rot: Rot3, tris: Vec<Tri>) -> Self {
+		let (geometry, materials) = MeshGeometry::from_tris(&tris);
+		Mesh::instance(anchor, rot, Arc::new(geometry), materials)
+	}
+
+	pub fn instance(anchor: Vec3, rot: Rot3, geometry: Arc<MeshGeometry>, materials: Vec<Material>) -> Self {
 		Mesh {
 			anchor,
 			rot,
-			tri_list: tris,
-			id: Uuid::new_v4().to_hyphenated().to_string()
+			geometry,
+			materials,
+			mask: RayMask::ALL,
+			id: Uuid::new_v4().to_hyphenated().to_string(),
+			morph: None,
+			texture: None,
+			moved: None,
+			world: RwLock::new(None),
 		}
 	}
-}
 
-impl SceneObject for Mesh {
-	fn get_pos(&self) -> &Vec3 { return &self.anchor }
-	fn get_rot(&self) -> &Rot3 { return &self.rot }
-	fn ray_hit(&self, ray: &Ray) -> Option<(Vec3, Material, Vec3)> { 
+	// Takes the current geometry as the base shape for targets, which need a position offset for every vertex
+	pub fn set_morph_targets(&mut self, targets: Vec<MorphTarget>, weights: Vec<f64>) -> Result<(), String> {
+		let base = match self.morph.take() {
+			Some(morph) => morph.base,
+			None => self.geometry.clone(),
+		};
+		for target in targets.iter() {
+			if target.positions.len() != base.positions.len() || !(target.normals.is_empty() || target.normals.len() == base.normals.len()) {
+				self.morph = None;
+				self.geometry = base;
+				return Err(format!("morph target `{}` doesn't match the mesh's vertices", target.name));
+			}
+		}
+		self.morph = Some(Morph { base, targets, weights: Vec::new() });
+		self.set_morph_weights(&weights);
+		Ok(())
+	}
+
+	// Reshapes the mesh, weights past the number of targets are ignored and missing ones are zero
+	pub fn set_morph_weights(&mut self, weights: &[f64]) {
+		if let Some(morph) = self.morph.as_mut() {
+			morph.weights = (0..morph.targets.len()).map(|i| weights.get(i).copied().unwrap_or(0.0)).collect();
+			self.geometry = Arc::new(morph.blend());
+		}
+	}
+
+	pub fn triangle_count(&self) -> usize {
+		self.geometry.indices.len()
+	}
+
+	// Every vertex in world space, rotating them all with the one matrix
+	pub fn world_positions(&self) -> Vec<Vec3> {
+		let rotation = self.rot.to_matrix();
+		self.geometry.positions.iter().map(|position| rotation.apply(position).add(&self.anchor)).collect()
+	}
+
+	// Same for the vertex normals, empty when the mesh has none
+	pub fn world_normals(&self) -> Vec<Vec3> {
+		let rotation = self.rot.to_matrix();
+		self.geometry.normals.iter().map(|normal| rotation.apply(normal)).collect()
+	}
+
+	// All the triangles in world space
+	pub fn world_tris(&self) -> Vec<Tri> {
+		self.tris_at(&self.world_positions())
+	}
+
+	// The triangles with their corners at positions
+	fn tris_at(&self, positions: &[Vec3]) -> Vec<Tri> {
+		(0..self.geometry.indices.len()).map(|i| self.tri_at(positions, i)).collect()
+	}
+
+	// Triangle i with its corners at positions
+	fn tri_at(&self, positions: &[Vec3], i: usize) -> Tri {
+		let [a, b, c] = self.geometry.indices[i];
+		Tri {
+			a: positions[a as usize],
+			b: positions[b as usize],
+			c: positions[c as usize],
+			mat: self.materials[self.geometry.material_ids[i] as usize],
+		}
+	}
+
+	// Gives triangle i material materials[material_ids[i]], in a copy of the geometry so other
+	// instances of it keep theirs
+	pub fn set_triangle_materials(&mut self, materials: Vec<Material>, material_ids: Vec<u32>) {
+		if let Some(morph) = self.morph.as_mut() {
+			morph.base = Arc::new(morph.base.recolored(material_ids.clone()));
+		}
+		self.geometry = Arc::new(self.geometry.recolored(material_ids));
+		self.materials = materials;
+	}
+
+	// Moves a vertex, in object space. Edits pile up until refit_bvh, which Scene calls before it
+	// renders, so a mesh can be reshaped a vertex at a time without fitting the hierarchy each time.
+	// A morphed mesh goes back to its blend on the next set_morph_weights
+	pub fn set_vertex(&mut self, vertex: usize, position: Vec3) {
+		let geometry = &self.geometry;
+		self.moved.get_or_insert_with(|| geometry.positions.clone())[vertex] = position;
+	}
+
+	// Moves the corners of triangle i, and with them the triangles sharing them
+	pub fn set_triangle_corners(&mut self, i: usize, corners: [Vec3; 3]) {
+		let vertices = self.geometry.indices[i];
+		for (vertex, corner) in vertices.iter().zip(corners.iter()) {
+			self.set_vertex(*vertex as usize, *corner);
+		}
+	}
+
+	pub fn is_dirty(&self) -> bool {
+		self.moved.is_some()
+	}
+
+	// Takes on the vertices moved since last time, growing and shrinking the hierarchy's boxes to
+	// fit them rather than building it again. Nothing to do when nothing moved
+	pub fn refit_bvh(&mut self) {
+		if let Some(positions) = self.moved.take() {
+			self.geometry = Arc::new(self.geometry.reshaped(positions, self.geometry.normals.clone()));
+		}
+	}
+
+	// Triangle i in object space
+	pub fn triangle(&self, i: usize) -> Tri {
+		self.tri_at(&self.geometry.positions, i)
+	}
+
+	// Every triangle moved into place, made again only once the mesh has moved or changed shape
+	fn world_triangles(&self) -> Arc<WorldTriangles> {
+		let (anchor, rot) = (&self.anchor, &self.rot);
+		let placement = [anchor.x, anchor.y, anchor.z, rot.yaw, rot.pitch, rot.roll];
+		let current = |world: &WorldTriangles| world.placement == placement && Arc::ptr_eq(&world.geometry, &self.geometry);
+		if let Some(world) = self.world.read().unwrap_or_else(PoisonError::into_inner).as_ref().filter(|world| current(world)) {
+			return world.clone();
+		}
+		let mut cached = self.world.write().unwrap_or_else(PoisonError::into_inner);
+		// Another thread may have got here first
+		if let Some(world) = cached.as_ref().filter(|world| current(world)) {
+			return world.clone();
+		}
+		let positions = self.world_positions();
+		let mut bounds = Aabb::empty();
+		for position in positions.iter() {
+			bounds.grow(position);
+		}
+		let bounds = if positions.is_empty() { None } else { Some(bounds) };
+		// Straight from the shared vertices, without a list of triangles each with its material in between
+		let prepared: Vec<PreparedTri> = (0..self.geometry.indices.len()).map(|i| PreparedTri::new(&self.tri_at(&positions, i))).collect();
+		let tris = TriangleBuffer::new(&prepared, self.geometry.bvh.items(), &self.geometry.material_ids);
+		#[cfg(feature = "simd")]
+		let packets = (0..tris.len()).step_by(crate::simd::LANES)
+			.map(|first| crate::simd::Tri4::new(&(first..tris.len().min(first + crate::simd::LANES)).map(|slot| tris.get(slot)).collect::<Vec<PreparedTri>>()))
+			.collect();
+		let world = Arc::new(WorldTriangles {
+			placement,
+			geometry: self.geometry.clone(),
+			rotation: rot.to_matrix(),
+			bounds,
+			tris,
+			#[cfg(feature = "simd")]
+			packets,
+		});
+		*cached = Some(world.clone());
+		world
+	}
+
+	// The triangle ray hits first, by slot, with where and at what (u, v)
+	fn nearest(&self, world: &WorldTriangles, ray: &Ray) -> Option<(usize, Vec3, f64, f64)> {
+		// Rays that miss the whole mesh go no further. Padded like the hierarchy's boxes are, so it
+		// never turns away a ray they'd let through
+		if world.bounds.is_none_or(|bounds| padded(bounds).ray_distance(ray).is_none()) { return None }
+		// The hierarchy is walked in object space, the triangles it turns up are hit in world space
+		let local = Ray { origin: ray.origin.sub(&self.anchor).unrotate(&self.rot), direction: ray.direction.unrotate(&self.rot), mask: ray.mask, differential: None };
+		let length = Vec3::dot(&ray.direction, &ray.direction).sqrt();
 		let mut min = f64::MAX;
-		let mut final_val = None;
-		let mut final_tri = None;
-		for tri in &self.tri_list {
-			let tr = tri.transformed(&self.get_pos(), &self.get_rot());
-			let dist = tr.ray_hit(&ray);
-			if dist.is_some() {
-				let val = ray.origin.dist(&dist.unwrap());
-				if val > 0.01 {
-					if val < min { min = val; final_val = Some(dist.unwrap()); final_tri = Some(tr) }
+		let order = self.geometry.bvh.items();
+		let mut closest: Option<(usize, Vec3, f64, f64)> = None; // by slot
+		// Hands back the reach from then on
+		let mut consider = |slot: usize, hit: Option<(Vec3, f64, f64)>| {
+			if let Some((point, u, v)) = hit {
+				let val = ray.origin.dist(&point);
+				// Ties go to the first triangle, whichever order they were found in
+				if val > 0.01 && (val < min || val == min && closest.as_ref().is_some_and(|closest| order[slot] < order[closest.0])) {
+					min = val;
+					closest = Some((slot, point, u, v));
+				}
+			}
+			min / length
+		};
+		let mut tests = 0;
+		#[cfg(not(feature = "simd"))]
+		self.geometry.bvh.traverse_leaves(&local, f64::MAX, |first, items, _| {
+			tests += items.len() as u64;
+			(first..first + items.len()).fold(f64::MAX, |_, slot| consider(slot, world.tris.ray_hit_barycentric(slot, ray)))
+		});
+		// A leaf's triangles four at a time, leaving out lanes of the packets it shares with its neighbours
+		#[cfg(feature = "simd")]
+		self.geometry.bvh.traverse_leaves(&local, f64::MAX, |first, items, _| {
+			tests += items.len() as u64;
+			let (lanes, mut reach) = (crate::simd::LANES, f64::MAX);
+			for packet in first / lanes..=(first + items.len() - 1) / lanes {
+				for (lane, hit) in world.packets[packet].ray_hit_barycentric(ray).iter().enumerate() {
+					let slot = packet * lanes + lane;
+					if (first..first + items.len()).contains(&slot) {
+						reach = consider(slot, *hit);
+					}
 				}
-				
 			}
+			reach
+		});
+		count_triangle_tests(tests);
+		closest
+	}
+
+	// Point, material and normal where ray hit the triangle in slot at (u, v)
+	fn surface(&self, world: &WorldTriangles, ray: &Ray, (slot, point, u, v): (usize, Vec3, f64, f64)) -> (Vec3, Material, Vec3) {
+		let (i, tri) = (self.geometry.bvh.items()[slot] as usize, world.tris.get(slot));
+		let mut material = self.materials[world.tris.materials[slot] as usize];
+		if let Some(texel) = self.texel(i, &tri, ray, &point, u, v) {
+			let tint = |channel: u8, texel: u8| (channel as u32 * texel as u32 / 255) as u8;
+			material.color = Color { r: tint(material.color.r, texel.r), g: tint(material.color.g, texel.g), b: tint(material.color.b, texel.b) };
 		}
-		if final_tri.is_some() {
-			let trr = final_tri.unwrap();
-			if min == f64::MAX { return None } else { return Some((final_val.unwrap(), trr.mat, trr.normal())) }
+		(point, material, self.shading_normal(i, &tri.normal, &world.rotation, u, v))
+	}
+
+	// The texture at (u, v) on triangle i, blurred over as much of it as the ray's differential says one
+	// pixel covers there. None without a texture or uvs
+	fn texel(&self, i: usize, tri: &PreparedTri, ray: &Ray, point: &Vec3, u: f64, v: f64) -> Option<Color> {
+		let (texture, uvs) = (self.texture.as_ref()?, &self.geometry.uvs);
+		if uvs.is_empty() { return None }
+		let [a, b, c] = self.geometry.indices[i].map(|corner| uvs[corner as usize]);
+		let uv = |(u, v): (f64, f64)| (a.0 * (1.0 - u - v) + b.0 * u + c.0 * v, a.1 * (1.0 - u - v) + b.1 * u + c.1 * v);
+		let (tu, tv) = uv((u, v));
+		let differential = match &ray.differential {
+			Some(differential) => differential,
+			None => return Some(texture.sample(tu, tv)),
+		};
+		let (dx, dy) = differential.footprint(point, &Vec3::cross(&tri.edge1, &tri.edge2));
+		let (xu, xv) = uv(tri.barycentric(&point.add(&dx)));
+		let (yu, yv) = uv(tri.barycentric(&point.add(&dy)));
+		Some(texture.sample_footprint(tu, tv, (xu - tu).abs().max((yu - tu).abs()), (xv - tv).abs().max((yv - tv).abs())))
+	}
+
+	// Where the smooth surface the vertex normals describe would be, rather than the flat triangle
+	// under it, so light grazing a low poly curve isn't cut off by the facets next to it
+	// (Hanika, "Hacking the Shadow Terminator"). Each corner's tangent plane lifts point up to it
+	// when it's below, weighted by how close to that corner it is. Only shadow rays start there, the
+	// hit itself stays on the triangle
+	fn terminator_offset(&self, i: usize, tri: &PreparedTri, rotation: &Mat3, point: &Vec3, u: f64, v: f64) -> Vec3 {
+		let normals = &self.geometry.normals;
+		if normals.is_empty() { return *point }
+		let corners = [tri.a, tri.a.add(&tri.edge1), tri.a.add(&tri.edge2)];
+		let weights = [1.0 - u - v, u, v];
+		let mut lifted = *point;
+		for ((corner, index), weight) in corners.iter().zip(self.geometry.indices[i].iter()).zip(weights.iter()) {
+			let normal = rotation.apply(&normals[*index as usize]);
+			let below = Vec3::dot(&point.sub(corner), &normal).min(0.0);
+			lifted = lifted.sub(&normal.mul(below * weight));
 		}
-		return None
+		lifted
+	}
+
+	// Interpolated vertex normal in world space, or the face normal when the mesh has none
+	fn shading_normal(&self, i: usize, face_normal: &Vec3, rotation: &Mat3, u: f64, v: f64) -> Vec3 {
+		let normals = &self.geometry.normals;
+		if normals.is_empty() { return *face_normal }
+		let [a, b, c] = self.geometry.indices[i];
+		let normal = normals[a as usize].mul(1.0 - u - v).add(&normals[b as usize].mul(u)).add(&normals[c as usize].mul(v));
+		rotation.apply(&normal).normalize()
+	}
+}
+
+impl SceneObject for Mesh {
+	fn get_pos(&self) -> &Vec3 { &self.anchor }
+	fn get_rot(&self) -> &Rot3 { &self.rot }
+	fn ray_hit(&self, ray: &Ray) -> Option<(Vec3, Material, Vec3)> { 
+		let world = self.world_triangles();
+		self.nearest(&world, ray).map(|closest| self.surface(&world, ray, closest))
+	}
+	// One lookup of the world triangles and one walk through the hierarchy for all of them
+	fn ray_hit_packet(&self, rays: &[Ray; LANES]) -> [Option<(Vec3, Material, Vec3)>; LANES] {
+		let world = self.world_triangles();
+		let missed = rays.each_ref().map(|ray| world.bounds.is_none_or(|bounds| padded(bounds).ray_distance(ray).is_none()));
+		if !missed.contains(&false) { return [None; LANES] }
+		let local = rays.each_ref().map(|ray| Ray { origin: world.rotation.apply_inverse(&ray.origin.sub(&self.anchor)), direction: world.rotation.apply_inverse(&ray.direction), mask: ray.mask, differential: None });
+		let lengths = rays.each_ref().map(|ray| Vec3::dot(&ray.direction, &ray.direction).sqrt());
+		let mut min = [f64::MAX; LANES];
+		let mut closest: [Option<(usize, Vec3, f64, f64)>; LANES] = [None; LANES];
+		// Rays that missed the mesh start out with nowhere left to reach
+		let reach = missed.map(|missed| if missed { -1.0 } else { f64::MAX });
+		let order = self.geometry.bvh.items();
+		let mut tests = 0;
+		self.geometry.bvh.traverse_packet(&local, reach, |first, items, active| {
+			for lane in (0..LANES).filter(|lane| active[*lane]) {
+				tests += items.len() as u64;
+				let (ray, min, closest) = (&rays[lane], &mut min[lane], &mut closest[lane]);
+				for slot in first..first + items.len() {
+					if let Some((point, u, v)) = world.tris.ray_hit_barycentric(slot, ray) {
+						let val = ray.origin.dist(&point);
+						if val > 0.01 && (val < *min || val == *min && closest.as_ref().is_some_and(|closest| order[slot] < order[closest.0])) {
+							*min = val;
+							*closest = Some((slot, point, u, v));
+						}
+					}
+				}
+			}
+			std::array::from_fn(|lane| if missed[lane] { -1.0 } else { min[lane] / lengths[lane] })
+		});
+		count_triangle_tests(tests);
+		std::array::from_fn(|lane| closest[lane].map(|closest| self.surface(&world, &rays[lane], closest)))
 	}
 	fn as_any(&mut self) -> &mut dyn Any { self }
 	fn as_any_immut(&self) -> &dyn Any { self }
 	fn get_id(&self) -> &String { &self.id }
+	fn get_material(&self) -> Option<Material> { self.materials.first().copied() }
+	// Leaves the shared geometry alone, every slot gets the same material instead
+	fn set_material(&mut self, material: Material) {
+		for slot in self.materials.iter_mut() {
+			*slot = material;
+		}
+	}
+	fn bounds(&self) -> Option<Aabb> { self.world_triangles().bounds }
+	fn get_mask(&self) -> RayMask { self.mask }
+	fn set_mask(&mut self, mask: RayMask) { self.mask = mask }
+	fn set_transform(&mut self, pos: Vec3, rot: Rot3) { self.anchor = pos; self.rot = rot }
+	fn closest_point(&self, point: &Vec3) -> Option<Vec3> {
+		// In object space, so the triangles stay as they are
+		let local = point.sub(&self.anchor).unrotate(&self.rot);
+		let mut closest: Option<(Vec3, f64)> = None;
+		self.geometry.bvh.nearest(&local, f64::INFINITY, |i, _| {
+			let candidate = self.triangle(i).closest_point(&local);
+			let distance = candidate.dist(&local);
+			if closest.is_none_or(|(_, nearest)| distance < nearest) { closest = Some((candidate, distance)) }
+			closest.map_or(f64::INFINITY, |(_, nearest)| nearest)
+		});
+		closest.map(|(closest, _)| closest.rotate(&self.rot).add(&self.anchor))
+	}
+	// Lifted by terminator_offset, which needs the triangle ray hit and where on it, so that's found
+	// again. Left alone when ray finds something else first, as it can past a clip plane
+	fn shadow_origin(&self, ray: &Ray, point: &Vec3) -> Vec3 {
+		if self.geometry.normals.is_empty() { return *point }
+		let world = self.world_triangles();
+		match self.nearest(&world, ray) {
+			Some((slot, hit, u, v)) if hit.dist(point) < 1e-9 => {
+				let i = self.geometry.bvh.items()[slot] as usize;
+				self.terminator_offset(i, &world.tris.get(slot), &world.rotation, point, u, v)
+			}
+			_ => *point,
+		}
+	}
 }
 
 pub struct Sphere {
@@ -213,6 +922,7 @@ pub struct Sphere {
 	pub radius: f32,
 	pub rot: Rot3,
 	pub material: Material,
+	pub mask: RayMask,
 	pub id: String,
 }
 
@@ -223,6 +933,7 @@ impl Sphere {
 			radius: rad,
 			material: mat,
 			rot: Rot3::new(),
+			mask: RayMask::ALL,
 			id: Uuid::new_v4().to_hyphenated().to_string()
 		}
 	}
@@ -230,47 +941,199 @@ impl Sphere {
 
 
 impl SceneObject for Sphere {
-	fn get_pos(&self) -> &Vec3 { return &self.center }
-	fn get_rot(&self) -> &Rot3 { return &self.rot }
+	fn get_pos(&self) -> &Vec3 { &self.center }
+	fn get_rot(&self) -> &Rot3 { &self.rot }
 	fn ray_hit(&self, ray: &Ray) -> Option<(Vec3, Material, Vec3)> { 
 		let oc = ray.origin.sub(&self.center);
 		let oc_d = Vec3::dot(&oc, &ray.direction);
-		if oc_d > 0.0 || Vec3::dot(&oc, &oc) < (self.radius * self.radius) as f64 { return None }
+		// Transparent ones are seen from inside too, as rays through glass are, where the ray leaves them
+		let inside = Vec3::dot(&oc, &oc) < (self.radius * self.radius) as f64;
+		if inside && self.material.transparency <= 0.0 { return None }
+		if oc_d > 0.0 && !inside { return None }
 		let a = oc.sub(&ray.direction.mul(oc_d));
 		let adot = Vec3::dot(&a, &a);
 		if adot > (self.radius * self.radius) as f64 { return None }
 		let h = (((self.radius * self.radius) as f64) - adot).sqrt();
-		let i = a.sub(&ray.direction.mul(h));
+		if inside && h - oc_d <= 1e-9 { return None }
+		let i = if inside { a.add(&ray.direction.mul(h)) } else { a.sub(&ray.direction.mul(h)) };
 		let intersection = self.center.add(&i);
 		Some((intersection, self.material, i.div(self.radius as f64)))
 	}
 	fn as_any(&mut self) -> &mut dyn Any { self }
 	fn as_any_immut(&self) -> &dyn Any { self }
 	fn get_id(&self) -> &String { &self.id }
+	fn get_material(&self) -> Option<Material> { Some(self.material) }
+	fn set_material(&mut self, material: Material) { self.material = material }
+	fn bounds(&self) -> Option<Aabb> {
+		let r = self.radius as f64;
+		Some(Aabb { min: self.center.sub(&Vec3 { x: r, y: r, z: r }), max: self.center.add(&Vec3 { x: r, y: r, z: r }) })
+	}
+	fn get_mask(&self) -> RayMask { self.mask }
+	fn set_mask(&mut self, mask: RayMask) { self.mask = mask }
+	fn set_transform(&mut self, pos: Vec3, rot: Rot3) { self.center = pos; self.rot = rot }
+	fn closest_point(&self, point: &Vec3) -> Option<Vec3> {
+		let away = point.sub(&self.center);
+		let direction = if Vec3::dot(&away, &away) > 0.0 { away.normalize() } else { Vec3 { x: 0.0, y: 0.0, z: 1.0 } };
+		Some(self.center.add(&direction.mul(self.radius as f64)))
+	}
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 pub struct Material {
 	pub transparency: f32,
 	pub reflectivity: f32,
+	pub roughness: f32, // 0 for a mirror, blurring reflections more towards 1
+	pub ior: f32, // index of refraction, bending what's seen through transparent materials
 	pub color: Color
 }
 
-#[derive(Clone, Copy)]
+// Reflection strengthens towards grazing angles, Schlick's approximation to Fresnel's equations
+// ("An Inexpensive BRDF Model for Physically-based Rendering"). For opaque materials reflectivity is
+// how much they reflect looking straight on, F0, and for transparent ones it's worked out from ior
+impl Material {
+	// Fraction reflected straight on by the boundary between air and a transparent material
+	pub fn f0(&self) -> f64 {
+		let ior = self.ior as f64;
+		((ior - 1.0) / (ior + 1.0)).powi(2)
+	}
+
+	// Fractions of the light reaching the surface facing normal along incident that are mirrored
+	// and that go through it, given it's as transparent as transparency says
+	pub fn fresnel_weights(&self, incident: &Vec3, normal: &Vec3) -> (f64, f64) {
+		let cos_in = Vec3::dot(incident, normal).abs().min(1.0);
+		let reflected = if self.reflectivity > 0.0 { schlick(cos_in, self.reflectivity as f64) } else { 0.0 };
+		let transparency = self.transparency as f64 * (1.0 - reflected);
+		// Leaving a denser material it's the angle outside that counts, and past the critical angle
+		// everything is reflected
+		let boundary = match refract(incident, normal, self.ior as f64) {
+			Some(through) if Vec3::dot(incident, normal) > 0.0 && self.ior > 1.0 => schlick(Vec3::dot(&through, normal).abs().min(1.0), self.f0()),
+			Some(_) => schlick(cos_in, self.f0()),
+			None => 1.0,
+		};
+		((reflected + transparency * boundary).min(1.0), transparency * (1.0 - boundary))
+	}
+}
+
+// Reflectance at an angle with cosine cos to the normal, f0 straight on
+pub fn schlick(cos: f64, f0: f64) -> f64 {
+	f0 + (1.0 - f0) * (1.0 - cos).powi(5)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Color {
 	pub r: u8,
 	pub g: u8,
 	pub b: u8,
 }
 
+impl Color {
+	pub fn lerp(&self, other: &Color, t: f64) -> Color {
+		Color {
+			r: (self.r as f64 + (other.r as f64 - self.r as f64) * t).round() as u8,
+			g: (self.g as f64 + (other.g as f64 - self.g as f64) * t).round() as u8,
+			b: (self.b as f64 + (other.b as f64 - self.b as f64) * t).round() as u8,
+		}
+	}
+
+	// Hue in degrees, 0 for grays
+	pub fn hue(&self) -> f64 {
+		let (r, g, b) = (self.r as f64, self.g as f64, self.b as f64);
+		let max = r.max(g).max(b);
+		let range = max - r.min(g).min(b);
+		if range == 0.0 { return 0.0 }
+		let hue = if max == r { (g - b) / range } else if max == g { 2.0 + (b - r) / range } else { 4.0 + (r - g) / range };
+		(hue * 60.0).rem_euclid(360.0)
+	}
+
+	// Same saturation and brightness at another hue
+	pub fn with_hue(&self, degrees: f64) -> Color {
+		let max = self.r.max(self.g).max(self.b) as f64;
+		let min = self.r.min(self.g).min(self.b) as f64;
+		let h = degrees.rem_euclid(360.0) / 60.0;
+		let channel = |n: f64| {
+			let k = (n + h) % 6.0;
+			(max - (max - min) * k.min(4.0 - k).clamp(0.0, 1.0)).round() as u8
+		};
+		Color { r: channel(5.0), g: channel(3.0), b: channel(1.0) }
+	}
+}
+
+// Material properties the interactive editor can change
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MaterialParam {
+	Hue,
+	Reflectivity,
+	Transparency,
+	Roughness,
+	Ior,
+}
+
+impl MaterialParam {
+	pub fn next(&self) -> Self {
+		match self {
+			MaterialParam::Hue => MaterialParam::Reflectivity,
+			MaterialParam::Reflectivity => MaterialParam::Transparency,
+			MaterialParam::Transparency => MaterialParam::Roughness,
+			MaterialParam::Roughness => MaterialParam::Ior,
+			MaterialParam::Ior => MaterialParam::Hue,
+		}
+	}
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			MaterialParam::Hue => "hue",
+			MaterialParam::Reflectivity => "reflectivity",
+			MaterialParam::Transparency => "transparency",
+			MaterialParam::Roughness => "roughness",
+			MaterialParam::Ior => "ior",
+		}
+	}
+
+	pub fn value(&self, material: &Material) -> f64 {
+		match self {
+			MaterialParam::Hue => material.color.hue(),
+			MaterialParam::Reflectivity => material.reflectivity as f64,
+			MaterialParam::Transparency => material.transparency as f64,
+			MaterialParam::Roughness => material.roughness as f64,
+			MaterialParam::Ior => material.ior as f64,
+		}
+	}
+
+	// One step is 10 degrees of hue, 0.05 of ior between 1 and 3, or 0.05 of the others, which stay within 0..1
+	pub fn adjust(&self, material: &Material, steps: f64) -> Material {
+		let mut material = *material;
+		match self {
+			MaterialParam::Hue => material.color = material.color.with_hue(material.color.hue() + steps * 10.0),
+			MaterialParam::Reflectivity => material.reflectivity = (material.reflectivity + steps as f32 * 0.05).clamp(0.0, 1.0),
+			MaterialParam::Transparency => material.transparency = (material.transparency + steps as f32 * 0.05).clamp(0.0, 1.0),
+			MaterialParam::Roughness => material.roughness = (material.roughness + steps as f32 * 0.05).clamp(0.0, 1.0),
+			MaterialParam::Ior => material.ior = (material.ior + steps as f32 * 0.05).clamp(1.0, 3.0),
+		}
+		material
+	}
+}
+
 pub struct LightSource {
 	pub pos: Vec3,
 	pub rot: Rot3,
 	pub intensity: f32,
 	pub color: Color,
+	pub radius: f32, // 0 for a point light, otherwise a spherical light casting soft shadows
+	// Light linking by object id: when include isn't empty only those objects are lit, exclude always wins
+	pub include: Vec<String>,
+	pub exclude: Vec<String>,
+	pub spot: Option<Spot>,
 	pub id: String
 }
 
+// Narrows a light to a cone along its rotation (see Rot3::to_vec)
+#[derive(Clone)]
+pub struct Spot {
+	pub angle: f64, // from the axis to the edge of the cone, in degrees
+	pub blend: f64, // fraction of the cone, from the edge in, over which the light fades out
+	pub gobo: Option<Arc<Texture>>, // projected through the cone, filling it edge to edge
+}
+
 impl LightSource {
 	pub fn new(pos: Vec3, rot: Rot3, intensity: f32) -> Self {
 		LightSource {
@@ -278,68 +1141,485 @@ impl LightSource {
 			rot,
 			intensity,
 			color: Color {r: 255, g: 255, b: 255 },
+			radius: 0.0,
+			include: Vec::new(),
+			exclude: Vec::new(),
+			spot: None,
 			id: Uuid::new_v4().to_hyphenated().to_string()
 		}
 	}
+
+	// How strongly the light reaches point (0 to 1) and in what color, after the spot cone and gobo
+	pub fn emission(&self, point: &Vec3) -> (f64, Color) {
+		let spot = match &self.spot {
+			Some(spot) => spot,
+			None => return (1.0, self.color),
+		};
+		let forward = Rot3::to_vec(&self.rot);
+		let direction = point.sub(&self.pos).normalize();
+		let cos = Vec3::dot(&direction, &forward);
+		let half = spot.angle.to_radians();
+		if cos <= half.cos() { return (0.0, self.color) }
+		let edge = ((half - cos.min(1.0).acos()) / (half * spot.blend).max(f64::EPSILON)).min(1.0);
+		let falloff = edge * edge * (3.0 - 2.0 * edge);
+		let gobo = match &spot.gobo {
+			Some(gobo) => gobo,
+			None => return (falloff, self.color),
+		};
+		// Where the direction crosses the image plane at distance 1, the cone's edge being at +-1
+		let (tangent, bitangent) = forward.orthonormal_basis();
+		let scale = 1.0 / (cos * half.tan());
+		let u = Vec3::dot(&direction, &tangent) * scale;
+		let v = Vec3::dot(&direction, &bitangent) * scale;
+		let texel = gobo.sample(u * 0.5 + 0.5, 0.5 - v * 0.5);
+		// Brightness of the texel dims the light, its hue tints it
+		let peak = texel.r.max(texel.g).max(texel.b);
+		if peak == 0 { return (0.0, self.color) }
+		let tint = |light: u8, texel: u8| (light as u32 * texel as u32 / peak as u32) as u8;
+		(falloff * peak as f64 / 255.0, Color { r: tint(self.color.r, texel.r), g: tint(self.color.g, texel.g), b: tint(self.color.b, texel.b) })
+	}
+
+	pub fn illuminates(&self, id: &str) -> bool {
+		(self.include.is_empty() || self.include.iter().any(|included| included == id)) && !self.exclude.iter().any(|excluded| excluded == id)
+	}
 }
 
-pub struct Ray {
-	pub origin: Vec3,
-	pub direction: Vec3,
+// What kind of ray this is and which layers it sees; on objects, which kinds of rays see
+// them and which layers they are on. A ray hits an object when both kinds and layers overlap
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayMask {
+	pub kinds: u32,
+	pub layers: u32,
 }
 
-impl Ray {
-	pub fn from_to(origin: &Vec3, destination: &Vec3) -> Self {
-		Self {
-			origin: origin.clone(),
-			direction: destination.sub(&origin).normalize()
-		}
+impl RayMask {
+	pub const CAMERA: u32 = 1;
+	pub const SHADOW: u32 = 2;
+	pub const REFLECTION: u32 = 4;
+	pub const GI: u32 = 8;
+	pub const ALL: RayMask = RayMask { kinds: u32::MAX, layers: u32::MAX };
+
+	pub fn new(kinds: u32, layers: u32) -> Self {
+		RayMask { kinds, layers }
 	}
 
-	pub fn nudge(&mut self) {
-		self.origin = self.origin.add(&self.direction)
+	pub fn with_kinds(&self, kinds: u32) -> Self {
+		RayMask { kinds, layers: self.layers }
+	}
+
+	pub fn overlaps(&self, other: &RayMask) -> bool {
+		self.kinds & other.kinds != 0 && self.layers & other.layers != 0
 	}
 }
 
-impl SceneObject for LightSource {
-	fn get_pos(&self) -> &Vec3 { return &self.pos }
-	fn get_rot(&self) -> &Rot3 { return &self.rot }
-	fn ray_hit(&self, _ray: &Ray) -> Option<(Vec3, Material, Vec3)> { return None }
-	fn as_any(&mut self) -> &mut dyn Any { self }
-	fn as_any_immut(&self) -> &dyn Any { self }
-	fn get_id(&self) -> &String { &self.id }
+pub struct Ray {
+	pub origin: Vec3,
+	pub direction: Vec3,
+	pub mask: RayMask,
+	// How the ray spreads out, for working out how much of a surface one pixel's worth of it covers
+	pub differential: Option<RayDifferential>,
 }
 
-pub struct Camera {
-	pub pos: Vec3,
+// The rays through the next pixel over to the right and the next one down, alongside a camera ray
+// and carried through the bounces it takes. Where they land, next to where the ray itself does, is
+// how big a patch of surface the pixel sees there
+#[derive(Debug, Clone, Copy)]
+pub struct RayDifferential {
+	pub x_origin: Vec3,
+	pub x_direction: Vec3,
+	pub y_origin: Vec3,
+	pub y_direction: Vec3,
+}
+
+impl RayDifferential {
+	// How far the neighbouring rays land from point, on the plane through it facing normal,
+	// one step right then one step down. Zero for a neighbour running along the plane
+	pub fn footprint(&self, point: &Vec3, normal: &Vec3) -> (Vec3, Vec3) {
+		let offset = |origin: &Vec3, direction: &Vec3| match plane_hit(origin, direction, point, normal) {
+			Some(hit) => hit.sub(point),
+			None => Vec3 { x: 0.0, y: 0.0, z: 0.0 },
+		};
+		(offset(&self.x_origin, &self.x_direction), offset(&self.y_origin, &self.y_direction))
+	}
+
+	// Mirrored off the plane at point along with a ray leaving in direction. The neighbours bounce
+	// at their own hits, keeping the spread between their new directions and the ray's
+	pub fn reflect(&self, incoming: &Vec3, point: &Vec3, normal: &Vec3, direction: &Vec3) -> RayDifferential {
+		let mirror = |v: &Vec3| v.sub(&normal.mul(2.0 * Vec3::dot(v, normal)));
+		let spread = |neighbour: &Vec3| mirror(neighbour).sub(&mirror(incoming));
+		self.restarted(point, normal, direction, spread(&self.x_direction), spread(&self.y_direction))
+	}
+
+	// Bent through the plane at point going from a medium of index 1 into one of index eta, or the
+	// other way round from the side normal points away from; None when anything would reflect instead
+	pub fn refract(&self, incoming: &Vec3, point: &Vec3, normal: &Vec3, eta: f64, direction: &Vec3) -> Option<RayDifferential> {
+		let (normal_in, ratio) = if Vec3::dot(incoming, normal) < 0.0 { (*normal, 1.0 / eta) } else { (normal.mul(-1.0), eta) };
+		let bent = |v: &Vec3| {
+			let v = v.normalize();
+			let cos_in = -Vec3::dot(&v, &normal_in);
+			let sin2_out = ratio * ratio * (1.0 - cos_in * cos_in);
+			if sin2_out > 1.0 { return None }
+			Some(v.mul(ratio).add(&normal_in.mul(ratio * cos_in - (1.0 - sin2_out).sqrt())))
+		};
+		let through = bent(incoming)?;
+		let (x, y) = (bent(&self.x_direction)?, bent(&self.y_direction)?);
+		Some(self.restarted(point, normal, direction, x.sub(&through), y.sub(&through)))
+	}
+
+	// Neighbours at least spread (the tangent of the angle) away from direction, when the ray
+	// stands in for a cone that wide
+	pub fn widened(&self, direction: &Vec3, spread: f64) -> RayDifferential {
+		let (tangent, bitangent) = direction.orthonormal_basis();
+		let widen = |neighbour: &Vec3, across: &Vec3| if neighbour.dist(direction) >= spread { *neighbour } else { direction.add(&across.mul(spread)) };
+		RayDifferential { x_direction: widen(&self.x_direction, &tangent), y_direction: widen(&self.y_direction, &bitangent), ..*self }
+	}
+
+	// Neighbours starting again from where they cross the plane, or from point when they run along
+	// it, heading off in direction plus their spread
+	fn restarted(&self, point: &Vec3, normal: &Vec3, direction: &Vec3, x_spread: Vec3, y_spread: Vec3) -> RayDifferential {
+		RayDifferential {
+			x_origin: plane_hit(&self.x_origin, &self.x_direction, point, normal).unwrap_or(*point),
+			x_direction: direction.add(&x_spread),
+			y_origin: plane_hit(&self.y_origin, &self.y_direction, point, normal).unwrap_or(*point),
+			y_direction: direction.add(&y_spread),
+		}
+	}
+}
+
+// Where a ray from origin along direction crosses the plane through point facing normal
+fn plane_hit(origin: &Vec3, direction: &Vec3, point: &Vec3, normal: &Vec3) -> Option<Vec3> {
+	let along = Vec3::dot(direction, normal);
+	if along.abs() < 1e-12 { return None }
+	Some(origin.add(&direction.mul(Vec3::dot(&point.sub(origin), normal) / along)))
+}
+
+impl Ray {
+	pub fn from_to(origin: &Vec3, destination: &Vec3) -> Self {
+		Self {
+			origin: *origin,
+			direction: destination.sub(origin).normalize(),
+			mask: RayMask::ALL,
+			differential: None,
+		}
+	}
+
+	pub fn with_mask(self, mask: RayMask) -> Self {
+		Ray { mask, ..self }
+	}
+
+	pub fn nudge(&mut self) {
+		self.origin = self.origin.add(&self.direction)
+	}
+}
+
+impl SceneObject for LightSource {
+	fn get_pos(&self) -> &Vec3 { &self.pos }
+	fn get_rot(&self) -> &Rot3 { &self.rot }
+	fn ray_hit(&self, _ray: &Ray) -> Option<(Vec3, Material, Vec3)> { None }
+	fn as_any(&mut self) -> &mut dyn Any { self }
+	fn as_any_immut(&self) -> &dyn Any { self }
+	fn get_id(&self) -> &String { &self.id }
+	fn set_transform(&mut self, pos: Vec3, rot: Rot3) { self.pos = pos; self.rot = rot }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FovAxis {
+	Vertical,
+	Horizontal,
+}
+
+// Real lens parameters in millimeters, so renders can be lined up with photographs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PhysicalLens {
+	pub sensor_width: f64,
+	pub sensor_height: f64,
+	pub focal_length: f64,
+	pub shift_x: f64, // lens shift across the sensor, moves the image without perspective change
+	pub shift_y: f64,
+}
+
+impl PhysicalLens {
+	// 36x24mm, the "full frame" 35mm still format
+	pub fn full_frame(focal_length: f64) -> Self {
+		PhysicalLens { sensor_width: 36.0, sensor_height: 24.0, focal_length, shift_x: 0.0, shift_y: 0.0 }
+	}
+
+	fn sensor_size(&self, axis: FovAxis) -> f64 {
+		match axis {
+			FovAxis::Vertical => self.sensor_height,
+			FovAxis::Horizontal => self.sensor_width,
+		}
+	}
+
+	// Angle of view in degrees across the sensor's width or height
+	pub fn fov(&self, axis: FovAxis) -> f64 {
+		(2.0 * (self.sensor_size(axis) * 0.5 / self.focal_length).atan()).to_degrees()
+	}
+}
+
+#[derive(Clone)]
+pub struct Camera {
+	pub pos: Vec3,
 	pub rot: Rot3,
-	pub fov: u16,
+	pub fov: f32, // degrees, measured along fov_axis
+	pub fov_axis: FovAxis, // with a lens, which sensor dimension is fitted to the image
+	pub lens: Option<PhysicalLens>, // takes over from fov when set
+	pub layers: u32, // render layers this camera sees
 	pub id: String
 }
 
 impl Camera {
-	pub fn new(pos: Vec3, rot: Rot3, fov: u16) -> Self {
+	pub fn new(pos: Vec3, rot: Rot3, fov: f32) -> Self {
 		Camera {
 			pos,
 			rot,
 			fov,
+			fov_axis: FovAxis::Vertical,
+			lens: None,
+			layers: u32::MAX,
 			id: Uuid::new_v4().to_hyphenated().to_string()
 		}
 	}
+
+	// With a lens, zooms it to the new fov by changing the focal length
+	pub fn set_fov(&mut self, degrees: f32) {
+		self.fov = degrees.clamp(1.0, 179.0);
+		if let Some(lens) = self.lens.as_mut() {
+			lens.focal_length = lens.sensor_size(self.fov_axis) * 0.5 / ((self.fov as f64).to_radians() * 0.5).tan();
+		}
+	}
+
+	pub fn set_lens(&mut self, lens: Option<PhysicalLens>) {
+		self.lens = lens;
+		if let Some(lens) = lens {
+			self.fov = lens.fov(self.fov_axis) as f32;
+		}
+	}
+
+	// Switches the axis the fov is measured along without changing the framing,
+	// a lens keeps its focal length and gets fitted along the other sensor dimension instead
+	pub fn set_fov_axis(&mut self, axis: FovAxis, aspect_ratio: f64) {
+		if axis == self.fov_axis { return }
+		if let Some(lens) = self.lens {
+			self.fov_axis = axis;
+			self.fov = lens.fov(axis) as f32;
+			return;
+		}
+		let (half_width, half_height) = self.half_extents(aspect_ratio);
+		let half = match axis {
+			FovAxis::Vertical => half_height,
+			FovAxis::Horizontal => half_width,
+		};
+		self.fov_axis = axis;
+		self.set_fov((2.0 * half.atan()).to_degrees() as f32);
+	}
+
+	// Half width and half height of the image plane at distance 1, for an image of the given aspect ratio
+	pub fn half_extents(&self, aspect_ratio: f64) -> (f64, f64) {
+		let half_tan = match self.lens {
+			Some(lens) => lens.sensor_size(self.fov_axis) * 0.5 / lens.focal_length,
+			None => ((self.fov as f64).to_radians() * 0.5).tan(),
+		};
+		match self.fov_axis {
+			FovAxis::Vertical => (half_tan * aspect_ratio, half_tan),
+			FovAxis::Horizontal => (half_tan, half_tan / aspect_ratio),
+		}
+	}
+
+	// Offset of the image center on the plane at distance 1, from lens shift
+	pub fn image_shift(&self) -> (f64, f64) {
+		match self.lens {
+			Some(lens) => (lens.shift_x / lens.focal_length, lens.shift_y / lens.focal_length),
+			None => (0.0, 0.0),
+		}
+	}
+
+	// Volume seen through an image of the given aspect ratio, out to max_distance
+	pub fn frustum(&self, aspect_ratio: f64, max_distance: f64) -> Frustum {
+		let (half_width, half_height) = self.half_extents(aspect_ratio);
+		let (shift_x, shift_y) = self.image_shift();
+		let forward = self.forward();
+		let right = Vec3 { x: 1.0, y: 0.0, z: 0.0 }.rotate(&self.rot);
+		let up = Vec3 { x: 0.0, y: 1.0, z: 0.0 }.rotate(&self.rot);
+		let corner = |sx: f64, sy: f64| forward.add(&right.mul(shift_x + sx * half_width)).add(&up.mul(shift_y + sy * half_height));
+		let corners = [corner(-1.0, -1.0), corner(1.0, -1.0), corner(1.0, 1.0), corner(-1.0, 1.0)];
+		let center = forward.add(&right.mul(shift_x)).add(&up.mul(shift_y));
+		let mut planes = [Vec3 { x: 0.0, y: 0.0, z: 0.0 }; 4];
+		for (i, plane) in planes.iter_mut().enumerate() {
+			// Side planes pass through the eye, normals point inwards
+			let normal = Vec3::cross(&corners[i], &corners[(i + 1) % 4]);
+			*plane = if Vec3::dot(&normal, &center) < 0.0 { normal.mul(-1.0) } else { normal };
+		}
+		Frustum { origin: self.pos, planes, max_distance }
+	}
+
+	// Where point lands on a width x height image, in pixels from the top left corner, None when behind the camera
+	pub fn project(&self, point: &Vec3, width: i32, height: i32) -> Option<(f64, f64)> {
+		let local = point.sub(&self.pos).unrotate(&self.rot);
+		if local.z >= 0.0 { return None }
+		let (half_width, half_height) = self.half_extents(width as f64 / height as f64);
+		let (shift_x, shift_y) = self.image_shift();
+		let xx = (local.x / -local.z - shift_x) / half_width;
+		let yy = (local.y / -local.z - shift_y) / half_height;
+		Some(((xx + 1.0) * 0.5 * width as f64, (1.0 - yy) * 0.5 * height as f64))
+	}
+
+	// Unit vector the camera is looking along (its local -z)
+	pub fn forward(&self) -> Vec3 {
+		Vec3 { x: 0.0, y: 0.0, z: -1.0 }.rotate(&self.rot)
+	}
+
+	// Moves the camera along its current view direction until a sphere around bounds just fits the
+	// narrower side of an aspect_ratio image, with a little room to spare, and aims it at the center
+	pub fn frame(&mut self, bounds: &Aabb, aspect_ratio: f64) {
+		let center = bounds.center();
+		let radius = (bounds.max.dist(&bounds.min) * 0.5).max(1e-3);
+		let (half_width, half_height) = self.half_extents(aspect_ratio);
+		let half_tan = half_width.min(half_height);
+		let distance = radius * (1.0 + half_tan * half_tan).sqrt() / half_tan * FRAMING_MARGIN;
+		let forward = self.forward();
+		self.pos = center.sub(&forward.mul(distance));
+		// Straight up or down there's no telling which way is up, keep the image's top towards +y then
+		let up = if forward.z.abs() > 0.999 { Vec3 { x: 0.0, y: 1.0, z: 0.0 } } else { Vec3 { x: 0.0, y: 0.0, z: 1.0 } };
+		self.look_at(&center, &up);
+	}
+
+	// Points the camera at target, keeping up as close to the screen's vertical as possible
+	pub fn look_at(&mut self, target: &Vec3, up: &Vec3) {
+		let forward = target.sub(&self.pos).normalize();
+		let right = Vec3::cross(&forward, up).normalize();
+		let true_up = Vec3::cross(&right, &forward);
+		// Columns of the rotation matrix are right, up and -forward; read the angles back out of it
+		self.rot = Rot3 {
+			yaw: right.y.atan2(right.x),
+			pitch: (-right.z).clamp(-1.0, 1.0).asin(),
+			roll: true_up.z.atan2(-forward.z),
+		};
+	}
+}
+
+pub struct Frustum {
+	pub origin: Vec3,
+	pub planes: [Vec3; 4],
+	pub max_distance: f64,
+}
+
+impl Frustum {
+	// Conservative: true unless the box is certainly out of view
+	pub fn intersects(&self, bounds: &Aabb) -> bool {
+		if bounds.closest_point(&self.origin).dist(&self.origin) > self.max_distance { return false }
+		self.planes.iter().all(|normal| Vec3::dot(normal, &bounds.support(normal).sub(&self.origin)) >= 0.0)
+	}
 }
 
 impl SceneObject for Camera {
-	fn get_pos(&self) -> &Vec3 { return &self.pos }
-	fn get_rot(&self) -> &Rot3 { return &self.rot }
-	fn ray_hit(&self, _ray: &Ray) -> Option<(Vec3, Material, Vec3)> { return None; }
+	fn get_pos(&self) -> &Vec3 { &self.pos }
+	fn get_rot(&self) -> &Rot3 { &self.rot }
+	fn ray_hit(&self, _ray: &Ray) -> Option<(Vec3, Material, Vec3)> { None }
 	fn as_any(&mut self) -> &mut dyn Any { self }
 	fn as_any_immut(&self) -> &dyn Any { self }
 	fn get_id(&self) -> &String { &self.id }
+	fn set_transform(&mut self, pos: Vec3, rot: Rot3) { self.pos = pos; self.rot = rot }
+}
+
+// Room left around framed objects, 1 being a tight fit
+const FRAMING_MARGIN: f64 = 1.1;
+
+// Widest a fully rough reflection spreads from the mirror direction, radians
+pub const GLOSSY_MAX_ANGLE: f64 = std::f64::consts::FRAC_PI_4;
+
+// Index of the object a ray hit, then the point, material and normal there
+pub type ObjectHit = (usize, (Vec3, Material, Vec3));
+// Width, height and (color, coverage) by pixel
+type PhotonImage = (i32, i32, Vec<(Color, u8)>);
+
+static RAYS_TRACED: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+	static THREAD_RAYS_TRACED: Cell<u64> = const { Cell::new(0) };
+}
+
+// Rays traced through any scene since the program started, for benchmarking
+pub fn rays_traced() -> u64 {
+	RAYS_TRACED.load(Ordering::Relaxed)
+}
+
+// The same for just the calling thread, so work done at the same time on others isn't counted
+pub fn thread_rays_traced() -> u64 {
+	THREAD_RAYS_TRACED.with(Cell::get)
+}
+
+fn count_rays(rays: u64) {
+	RAYS_TRACED.fetch_add(rays, Ordering::Relaxed);
+	THREAD_RAYS_TRACED.with(|traced| traced.set(traced.get() + rays));
 }
 
 pub struct Scene {
 	pub objects: Vec<Box<dyn SceneObject>>,
-	pub current_camera: Box<Camera>
+	pub current_camera: Box<Camera>,
+	pub render_settings: RenderSettings,
+	// Objects camera rays can hit this frame, see update_culling; None tests everything
+	pub primary_objects: Option<Vec<usize>>,
+	// Camera and object transforms as of the last frame shown, for motion vectors
+	pub previous_frame: Option<FrameTransforms>,
+	pub animations: Vec<Animation>,
+	pub physics: Option<Physics>,
+	// Masks of the objects hide took out of every ray, by id, to put back in unhide_all
+	pub hidden: Vec<(String, RayMask)>,
+	// Objects by their bounds as of the last update_culling, for every other ray; None tests everything
+	hierarchy: Option<ObjectHierarchy>,
+	// How many threads render it and how, see tiles.rs
+	pub renderer: RendererConfig,
+	// How long every pixel took to trace last time, which the next frame's tiles are laid out by
+	pub tile_costs: RwLock<Option<CostMap>>,
+	// Where the time and rays of the last frame's tiles went, see tiles.rs
+	pub render_stats: RwLock<Option<RenderStats>>,
+	// Objects filled with a medium, see media.rs, and the ones the camera was in as of update_culling
+	pub volumes: Vec<Volume>,
+	pub camera_interior: Option<(Vec3, Interior)>,
+	pub clip_planes: Vec<ClipPlane>,
+	// Shown where camera rays miss everything
+	pub backplate: Option<Backplate>,
+	// Lights everything from all round, see environment.rs
+	pub environment: Option<Environment>,
+	// Where render_settings.sampled_lights picks lights from, as of update_culling
+	pub light_tree: Option<LightTree>,
+	// What the rays traced through it did, see stats.rs
+	pub counters: TraceCounters,
+	// Where each frame's time went, when enabled, see profiler.rs
+	pub profiler: Profiler,
+	// Render settings by name, see presets.rs
+	pub presets: BTreeMap<String, RenderPreset>,
+	// Every distinct geometry the meshes use, once however many instances share it, see arena.rs
+	pub geometry: Arena<Arc<MeshGeometry>>,
+	// Buffers for the single threaded cast_ray entry points
+	scratch: Option<Scratch>,
+	// The whole frame, width x height, when the SPPM integrator rendered it in update_culling
+	photon_mapped: Option<PhotonImage>,
+}
+
+// The top level over the objects' world bounds, meshes have their own in object space underneath.
+// Objects that only moved refit it instead of building it again
+struct ObjectHierarchy {
+	accelerator: Box<dyn Accelerator>, // over bounded, by position in it, of render_settings.accelerator's kind
+	bounded: Vec<usize>,
+	unbounded: Vec<usize>, // tested by every ray
+	count: usize, // objects there were, it's ignored once that changes
+	refits: u32, // since it was built
+}
+
+// What a raycast query hit, see Scene::raycast
+#[derive(Debug, Clone, Copy)]
+pub struct RaycastHit {
+	pub object: usize, // index into objects
+	pub point: Vec3,
+	pub normal: Vec3,
+	pub distance: f64, // from the ray origin
+}
+
+pub struct FrameTransforms {
+	pub camera: Camera,
+	pub objects: HashMap<String, (Vec3, Rot3)>, // by id
 }
 
 fn create_cube(center: Vec3, rot: Rot3) -> Mesh {
@@ -347,13 +1627,17 @@ fn create_cube(center: Vec3, rot: Rot3) -> Mesh {
 	let white_difuse = Material {
 		color: Color {r: 255, g: 255, b: 255},
 		transparency: 0.0,
-		reflectivity: 0.0
+		reflectivity: 0.0,
+		roughness: 0.0,
+		ior: 1.5
 	};
 
 	let funky = Material {
 		color: Color {r: 255, g: 10, b: 255},
 		transparency: 0.0,
-		reflectivity: 0.0
+		reflectivity: 0.0,
+		roughness: 0.0,
+		ior: 1.5
 	};
 
 	tris.push(Tri { a: {Vec3 {x: -1.0, y: -1.0, z: -1.0}}, b: {Vec3 {x: -1.0, y: -1.0, z: 1.0}}, c: {Vec3 {x: -1.0, y: 1.0, z: 1.0}}, mat: funky });
@@ -382,7 +1666,9 @@ fn create_big_plane(center: Vec3, rot: Rot3) -> Mesh {
 	let white_difuse = Material {
 		color: Color {r: 255, g: 255, b: 255},
 		transparency: 0.0,
-		reflectivity: 0.0
+		reflectivity: 0.0,
+		roughness: 0.0,
+		ior: 1.5
 	};
 
 	tris.push(Tri { a: {Vec3 {x: 4.0, y: 4.0, z: 0.0}}, b: {Vec3 {x: -4.0, y: 4.0, z: 0.0}}, c: {Vec3 {x: 4.0, y: -4.0, z: 0.0}}, mat: white_difuse });
@@ -405,22 +1691,24 @@ impl Scene {
 	pub fn default_scene() -> Self {
 		let mut objects: Vec<Box<dyn SceneObject>> = Vec::new();
 
-		let white_difuse = Material {
+		let _white_difuse = Material {
 			color: Color {r: 255, g: 255, b: 255},
 			transparency: 0.0,
-			reflectivity: 0.0
+			reflectivity: 0.0,
+			roughness: 0.0,
+			ior: 1.5
 		};
 	
 		let camera = Box::new(Camera::new(
 			Vec3 { x: 3.0, y: 3.0, z: 3.0 }, // pos
 			Rot3 { pitch: deg_to_rad(0.0), yaw: -3.0, roll: 1.5 }, // rot
-			40 // fov
+			40.0 // fov
 		));
 
 		let mut light_souce = Box::new(LightSource::new(
 			Vec3 { x: -1.0, y: -1.0, z: 2.0 },
 			Rot3::new(),
-			10.0,
+			1.0,
 		));
 		light_souce.id = String::from("fuckin' light");
 		objects.push(light_souce);
@@ -428,7 +1716,7 @@ impl Scene {
 		//let light_souce2 = Box::new(LightSource::new(
 		//	Vec3 { x: 1.5, y: 1.5, z: 3.0 },
 		//	Rot3::new(),
-		//	10.0,
+		//	1.0,
 		//));
 		//objects.push(light_souce2);
 
@@ -442,10 +1730,41 @@ impl Scene {
 		//let sphere = Box::new(Sphere::new(Vec3 { x: 1.2, y: 1.2, z: 2.3 }, 0.4, white_difuse));
 		//objects.push(sphere);
 
-		Self {
+		let mut scene = Scene::new(objects, *camera);
+		let tumble = Modifier::Spin { axis: Vec3 { x: 1.0, y: 1.0, z: 1.0 }, speed: 0.6 };
+		scene.animations.extend(Animation::new(&scene, "fuckin' cube", vec![tumble]));
+		scene
+	}
+
+	pub fn new(objects: Vec<Box<dyn SceneObject>>, camera: Camera) -> Self {
+		let mut scene = Self {
 			objects,
-			current_camera: camera
-		}
+			current_camera: Box::new(camera),
+			render_settings: RenderSettings::new(),
+			primary_objects: None,
+			previous_frame: None,
+			animations: Vec::new(),
+			physics: None,
+			hidden: Vec::new(),
+			hierarchy: None,
+			renderer: RendererConfig::default(),
+			tile_costs: RwLock::new(None),
+			render_stats: RwLock::new(None),
+			volumes: Vec::new(),
+			camera_interior: None,
+			clip_planes: Vec::new(),
+			backplate: None,
+			environment: None,
+			light_tree: None,
+			counters: TraceCounters::default(),
+			profiler: Profiler::default(),
+			presets: builtin_presets(),
+			geometry: Arena::new(),
+			scratch: None,
+			photon_mapped: None,
+		};
+		scene.intern_mesh_geometry();
+		scene
 	}
 
 	pub fn get_all_light_sources(&mut self) -> Vec<&mut LightSource> {
@@ -456,7 +1775,7 @@ impl Scene {
 				res.push(hit);
 			}
 		}
-		return res;
+		res
 	}
 
 	pub fn get_all_meshes(&mut self) -> Vec<&mut Mesh> {
@@ -467,17 +1786,16 @@ impl Scene {
 				res.push(hit);
 			}
 		}
-		return res;
+		res
 	}
 
 	pub fn get_all_light_sources_immut(&self) -> Vec<&LightSource> {
-		let mut res = Vec::new();
-		for object in self.objects.iter() {
-			if let Some(hit) = object.as_any_immut().downcast_ref::<LightSource>(){
-				res.push(hit);
-			}
-		}
-		return res;
+		self.light_sources().collect()
+	}
+
+	// Allocation free version of get_all_light_sources_immut, for the trace loop
+	pub fn light_sources(&self) -> impl Iterator<Item = &LightSource> {
+		self.objects.iter().filter_map(|object| object.as_any_immut().downcast_ref::<LightSource>())
 	}
 
 	pub fn get_all_meshes_immut(&self) -> Vec<&Mesh> {
@@ -488,99 +1806,715 @@ impl Scene {
 				res.push(hit);
 			}
 		}
-		return res;
+		res
 	}
 
 	pub fn trace(&self, ray: &Ray) -> Option<(Vec3, Material, Vec3)> {
-		let mut closest_intersect = None;
-		for object in self.objects.iter() {
-			let intersect_opt = object.ray_hit(&ray);
+		self.trace_object(ray).map(|(_, hit)| hit)
+	}
+
+	// Like trace, but also reports the index of the object that was hit
+	pub fn trace_object(&self, ray: &Ray) -> Option<ObjectHit> {
+		match &self.hierarchy {
+			Some(hierarchy) if hierarchy.count == self.objects.len() => {
+				let bounded = hierarchy.accelerator.candidates(ray).map(|item| hierarchy.bounded[item]);
+				self.closest_hit(ray, hierarchy.unbounded.iter().copied().chain(bounded))
+			}
+			_ => self.closest_hit(ray, 0..self.objects.len()),
+		}
+	}
+
+	// Trace for rays leaving the camera, skipping whatever update_culling ruled out
+	pub fn trace_primary(&self, ray: &Ray) -> Option<(Vec3, Material, Vec3)> {
+		self.trace_primary_object(ray).map(|(_, hit)| hit)
+	}
+
+	pub fn trace_primary_object(&self, ray: &Ray) -> Option<ObjectHit> {
+		match &self.primary_objects {
+			Some(indices) => self.closest_hit(ray, indices.iter().copied()),
+			None => self.trace_object(ray),
+		}
+	}
+
+	// Keeps only the objects whose bounds reach into the view frustum and within cull_distance,
+	// and puts all of them in a hierarchy for the rays that don't start at the camera.
+	// Call again whenever the camera, the objects or the image size change. Meshes with vertices
+	// moved get their hierarchies refit first, and the volumes the camera is in are found again
+	pub fn update_culling(&mut self, width: i32, height: i32) {
+		let started = self.profiler.start();
+		self.cull(width, height);
+		self.profiler.stop(Stage::Transforms, started);
+		// Photon mapping only works a frame at a time, so it's done here for pixels to be looked up in
+		self.photon_mapped = None;
+		if self.render_settings.integrator == Integrator::Sppm {
+			self.photon_mapped = Some((width, height, Sppm::render(self, width, height)));
+		}
+	}
+
+	fn cull(&mut self, width: i32, height: i32) {
+		for mesh in self.get_all_meshes() {
+			mesh.refit_bvh();
+		}
+		self.update_media();
+		self.light_tree = (self.render_settings.sampled_lights > 0).then(|| LightTree::new(self));
+		let frustum = self.current_camera.frustum(width as f64 / height as f64, self.render_settings.cull_distance);
+		let camera_mask = RayMask::new(RayMask::CAMERA, self.current_camera.layers);
+		let bounds: Vec<Option<Aabb>> = self.objects.iter().map(|object| object.bounds()).collect();
+		self.primary_objects = Some((0..self.objects.len())
+			.filter(|index| self.objects[*index].get_mask().overlaps(&camera_mask))
+			.filter(|index| bounds[*index].is_none_or(|bounds| frustum.intersects(&bounds)))
+			.collect());
+		let (bounded, unbounded): (Vec<usize>, Vec<usize>) = (0..self.objects.len()).partition(|index| bounds[*index].is_some());
+		let boxes: Vec<Aabb> = bounded.iter().filter_map(|index| bounds[*index]).collect();
+		// The same objects keep the tree, until they've moved far enough apart to leave it too loose
+		let count = self.objects.len();
+		let kind = self.render_settings.accelerator;
+		if let Some(hierarchy) = self.hierarchy.as_mut().filter(|hierarchy| hierarchy.count == count && hierarchy.bounded == bounded && hierarchy.accelerator.kind() == kind) {
+			if hierarchy.accelerator.refit(&boxes) {
+				hierarchy.refits += 1;
+				return;
+			}
+		}
+		self.hierarchy = Some(ObjectHierarchy { accelerator: kind.build(&boxes), bounded, unbounded, count, refits: 0 });
+	}
+
+	// trace_primary_object for a packet of neighbouring camera rays, each object hit by all of them at once
+	pub fn trace_primary_packet(&self, rays: &[Ray; LANES]) -> [Option<ObjectHit>; LANES] {
+		let indices = match &self.primary_objects {
+			Some(indices) => indices,
+			None => return rays.each_ref().map(|ray| self.trace_object(ray)),
+		};
+		count_rays(LANES as u64);
+		let started = self.profiler.start();
+		let mut closest: [Option<ObjectHit>; LANES] = [None; LANES];
+		for index in indices.iter().copied() {
+			let mask = self.objects[index].get_mask();
+			if !rays.iter().any(|ray| mask.overlaps(&ray.mask)) { continue }
+			// Cut objects one ray at a time
+			let hits = match self.is_clipped(index) {
+				true => rays.each_ref().map(|ray| self.clipped_hit(index, ray)),
+				false => self.objects[index].ray_hit_packet(rays),
+			};
+			for (lane, hit) in hits.iter().enumerate() {
+				if let Some(hit) = hit.filter(|_| mask.overlaps(&rays[lane].mask)) {
+					if self.nearer(&rays[lane], index, &hit, &closest[lane]) {
+						closest[lane] = Some((index, hit));
+					}
+				}
+			}
+		}
+		self.counters.count_rays(&rays[0].mask, LANES as u64, closest.iter().filter(|hit| hit.is_some()).count() as u64);
+		self.profiler.stop_rays(&rays[0].mask, started);
+		closest
+	}
+
+	fn closest_hit(&self, ray: &Ray, candidates: impl Iterator<Item = usize>) -> Option<ObjectHit> {
+		count_rays(1);
+		let started = self.profiler.start();
+		let mut closest_intersect: Option<ObjectHit> = None;
+		for index in candidates {
+			if !self.objects[index].get_mask().overlaps(&ray.mask) { continue }
+			let intersect_opt = self.clipped_hit(index, ray);
 			if let Some(intersect) = intersect_opt {
-				if closest_intersect.is_none() { 
-					closest_intersect = Some(intersect);
-					continue;
+				if self.nearer(ray, index, &intersect, &closest_intersect) {
+					closest_intersect = Some((index, intersect));
 				}
-				if self.current_camera.pos.dist(&intersect.0) < self.current_camera.pos.dist(&closest_intersect.unwrap().0) {
-					closest_intersect = Some(intersect);
+			}
+		}
+		self.counters.count_rays(&ray.mask, 1, closest_intersect.is_some() as u64);
+		self.profiler.stop_rays(&ray.mask, started);
+		closest_intersect
+	}
+
+	// Whether objects[index] being hit by ray at intersect beats the closest hit so far, by distance
+	// along the ray. Candidates can come in any order, ties go to the first object
+	fn nearer(&self, ray: &Ray, index: usize, intersect: &(Vec3, Material, Vec3), closest: &Option<ObjectHit>) -> bool {
+		match closest {
+			Some((closest_index, closest)) => {
+				let (distance, closest_distance) = (ray.origin.dist(&intersect.0), ray.origin.dist(&closest.0));
+				distance < closest_distance || distance == closest_distance && index < *closest_index
+			}
+			None => true,
+		}
+	}
+
+	// Spatial queries for host applications, independent of rendering and the camera. Objects are
+	// skipped by their bounds before their geometry is looked at, and by the mask like rays are
+
+	// Nearest hit along the ray within max_distance, through the clipping planes and picked the way
+	// rendering picks, ties going to the first object
+	pub fn raycast(&self, ray: &Ray, max_distance: f64) -> Option<RaycastHit> {
+		let mut nearest: Option<ObjectHit> = None;
+		for (index, object) in self.objects.iter().enumerate() {
+			if !object.get_mask().overlaps(&ray.mask) { continue }
+			let reach = nearest.as_ref().map_or(max_distance, |(_, hit)| ray.origin.dist(&hit.0));
+			if object.bounds().is_some_and(|bounds| bounds.ray_distance(ray).is_none_or(|distance| distance > reach)) { continue }
+			if let Some(hit) = self.clipped_hit(index, ray).filter(|hit| ray.origin.dist(&hit.0) <= max_distance) {
+				if self.nearer(ray, index, &hit, &nearest) {
+					nearest = Some((index, hit));
 				}
 			}
 		}
-		return closest_intersect
+		nearest.map(|(index, (point, _, normal))| RaycastHit { object: index, point, normal: normal.normalize(), distance: ray.origin.dist(&point) })
+	}
+
+	// Nearest point on any surface within max_distance of point, with the index of its object
+	pub fn closest_point(&self, point: &Vec3, max_distance: f64, mask: RayMask) -> Option<(usize, Vec3)> {
+		let mut nearest: Option<(usize, Vec3, f64)> = None;
+		for (index, object) in self.objects.iter().enumerate() {
+			if !object.get_mask().overlaps(&mask) { continue }
+			let reach = nearest.map_or(max_distance, |(_, _, distance)| distance);
+			if object.bounds().is_some_and(|bounds| bounds.closest_point(point).dist(point) > reach) { continue }
+			if let Some(closest) = object.closest_point(point) {
+				let distance = closest.dist(point);
+				if distance <= reach {
+					nearest = Some((index, closest, distance));
+				}
+			}
+		}
+		nearest.map(|(index, closest, _)| (index, closest))
+	}
+
+	// Indices of the objects whose surface comes within radius of center
+	pub fn sphere_overlap(&self, center: &Vec3, radius: f64, mask: RayMask) -> Vec<usize> {
+		(0..self.objects.len()).filter(|index| {
+			let object = &self.objects[*index];
+			object.get_mask().overlaps(&mask)
+				&& object.bounds().is_some_and(|bounds| bounds.closest_point(center).dist(center) <= radius)
+				&& object.closest_point(center).is_some_and(|closest| closest.dist(center) <= radius)
+		}).collect()
+	}
+
+	// Everything rays can hit, None for an empty scene
+	pub fn bounds(&self) -> Option<Aabb> {
+		let mut bounds = Aabb::empty();
+		for object_bounds in self.objects.iter().filter_map(|object| object.bounds()) {
+			bounds.grow(&object_bounds.min);
+			bounds.grow(&object_bounds.max);
+		}
+		if bounds.min.x.is_finite() { Some(bounds) } else { None }
+	}
+
+	// Points the camera at the whole scene, see Camera::frame; returns the center it's looking at
+	pub fn frame_all(&mut self, aspect_ratio: f64) -> Option<Vec3> {
+		let bounds = self.bounds()?;
+		self.current_camera.frame(&bounds, aspect_ratio);
+		Some(bounds.center())
+	}
+
+	// Same for a single object, None when there's no such object or it has no bounds
+	pub fn frame_object(&mut self, id: &str, aspect_ratio: f64) -> Option<Vec3> {
+		let bounds = self.objects.iter().find(|object| object.get_id() == id)?.bounds()?;
+		self.current_camera.frame(&bounds, aspect_ratio);
+		Some(bounds.center())
+	}
+
+	// Id of the object under pixel (x, y) of a width x height view
+	pub fn pick(&self, x: i32, y: i32, width: i32, height: i32) -> Option<String> {
+		let ray = self.primary_ray(x, y, (0.5, 0.5), width, height);
+		self.trace_object(&ray).map(|(index, _)| self.objects[index].get_id().clone())
+	}
+
+	pub fn find_object(&mut self, id: &str) -> Option<&mut Box<dyn SceneObject>> {
+		self.objects.iter_mut().find(|object| object.get_id() == id)
+	}
+
+	// Makes object id invisible to every kind of ray, false when there's no such object or it already is
+	pub fn hide(&mut self, id: &str) -> bool {
+		if self.hidden.iter().any(|(hidden, _)| hidden == id) { return false }
+		let object = match self.find_object(id) {
+			Some(object) => object,
+			None => return false,
+		};
+		let mask = object.get_mask();
+		object.set_mask(RayMask::new(0, mask.layers));
+		self.hidden.push((id.to_string(), mask));
+		true
+	}
+
+	// Hides everything but object id and the lights, false when there's no such object
+	pub fn isolate(&mut self, id: &str) -> bool {
+		if !self.objects.iter().any(|object| object.get_id() == id) { return false }
+		let others: Vec<String> = self.objects.iter()
+			.filter(|object| object.get_id() != id && !object.as_any_immut().is::<LightSource>())
+			.map(|object| object.get_id().clone())
+			.collect();
+		for other in others {
+			self.hide(&other);
+		}
+		true
+	}
+
+	// Puts back what hide took away, false when nothing was hidden
+	pub fn unhide_all(&mut self) -> bool {
+		let hidden = std::mem::take(&mut self.hidden);
+		for (id, mask) in hidden.iter() {
+			if let Some(object) = self.find_object(id) {
+				object.set_mask(*mask);
+			}
+		}
+		!hidden.is_empty()
+	}
+
+	// Paints every mesh triangle, and every other object with a material, with one picked at random
+	// from palette. The same seed always paints the same scene the same way
+	pub fn randomize_materials(&mut self, seed: u64, palette: &[Material]) {
+		if palette.is_empty() { return }
+		let mut rng = Rng::new(seed);
+		for object in self.objects.iter_mut() {
+			if let Some(mesh) = object.as_any().downcast_mut::<Mesh>() {
+				let material_ids = (0..mesh.triangle_count()).map(|_| rng.next_below(palette.len() as u64) as u32).collect();
+				mesh.set_triangle_materials(palette.to_vec(), material_ids);
+			} else if object.get_material().is_some() {
+				object.set_material(palette[rng.next_below(palette.len() as u64) as usize]);
+			}
+		}
 	}
 
 	pub fn cast_ray(&mut self, index: u64, width: i32, height: i32) -> Color {
+		self.cast_ray_alpha(index, width, height).0
+	}
+
+	// Pixel color over a black background or the backplate, plus the fraction of samples that hit geometry or the backplate as alpha
+	pub fn cast_ray_alpha(&mut self, index: u64, width: i32, height: i32) -> (Color, u8) {
+		let mut scratch = self.scratch.take().unwrap_or_else(|| Scratch::new(&self.render_settings));
+		let result = self.cast_ray_with(index, width, height, &mut scratch);
+		self.scratch = Some(scratch);
+		result
+	}
+
+	// cast_ray_alpha for callers that bring their own scratch buffers
+	pub fn cast_ray_with(&self, index: u64, width: i32, height: i32, scratch: &mut Scratch) -> (Color, u8) {
+		self.cast_ray_samples(index, width, height, 0, scratch)
+	}
+
+	// Same, taking the pixel's samples from first_sample on, for adding samples to ones already taken.
+	// Where the integrator is picked, every way of rendering comes through here
+	pub fn cast_ray_samples(&self, index: u64, width: i32, height: i32, first_sample: u32, scratch: &mut Scratch) -> (Color, u8) {
+		match (self.render_settings.integrator, &self.photon_mapped) {
+			(Integrator::Path, _) => path::trace_pixel(self, index, width, height, first_sample, scratch),
+			(Integrator::Sppm, Some((photon_width, photon_height, image))) if (*photon_width, *photon_height) == (width, height) => image[index as usize],
+			// Also photon mapping at a size update_culling didn't render it at
+			_ => self.direct_samples(index, width, height, first_sample, scratch),
+		}
+	}
+
+	fn direct_samples(&self, index: u64, width: i32, height: i32, first_sample: u32, scratch: &mut Scratch) -> (Color, u8) {
 		let x = index as i32 % width;
 		let y = index as i32 / width;
 
-		let aspect_ratio = width as f32 / height as f32;
-		let inv_width = 1.0 / width as f32;
-		let inv_height = 1.0 / height as f32;
-		let angle = (std::f32::consts::PI * 0.5 * (self.current_camera.fov as f32) / 180.0).tan(); 
-		let xx = (2.0 * ((x as f32 + 0.5) * inv_width) - 1.0) * angle * aspect_ratio; 
-		let yy = (1.0 - 2.0 * ((y as f32 + 0.5) * inv_height as f32)) * angle;
-		let direction = (Vec3 {x: xx as f64, y: yy as f64, z: -1.0}).normalize().rotate(&self.current_camera.rot);
-		let ray = Ray { origin: self.current_camera.pos, direction };
+		let samples = self.render_settings.samples_per_pixel.max(1);
+		scratch.prepare(&self.render_settings);
+		let mut sum = (0.0, 0.0, 0.0);
+		let mut hits = 0;
+		for sample_index in first_sample..first_sample + samples {
+			scratch.sampler.start_pixel_sample(x as u32, y as u32, sample_index);
+			let jitter = scratch.sampler.next_2d();
+			// A single sample stays at the pixel center so 1 spp renders don't shimmer
+			let offset = if samples == 1 && first_sample == 0 { (0.5, 0.5) } else { jitter };
+			let ray = self.primary_ray(x, y, offset, width, height);
+			if let Some(color) = self.shade(&ray, scratch).or_else(|| self.backplate_color(x, y, offset, width, height)) {
+				sum = (sum.0 + color.r as f64, sum.1 + color.g as f64, sum.2 + color.b as f64);
+				hits += 1;
+			}
+		}
+		let inv = 1.0 / samples as f64;
+		let color = Color { r: (sum.0 * inv).round() as u8, g: (sum.1 * inv).round() as u8, b: (sum.2 * inv).round() as u8 };
+		(color, (hits as f64 * inv * 255.0).round() as u8)
+	}
 
-		let mut mix_color = Color {
-			r: 0 as u8,
-			g: 0 as u8,
-			b: 0 as u8,
+	// cast_ray_with for up to four neighbouring pixels, their camera rays traced together as a packet.
+	// Results come back lane by lane, None for the lanes without a pixel. Only the direct integrator
+	// traces packets, the others go a pixel at a time
+	pub fn cast_ray_packet(&self, indices: [Option<u64>; LANES], width: i32, height: i32, scratch: &mut Scratch) -> [Option<(Color, u8)>; LANES] {
+		if self.render_settings.integrator != Integrator::Direct {
+			return indices.map(|index| index.map(|index| self.cast_ray_samples(index, width, height, 0, scratch)));
+		}
+		// Empty lanes go along with one of the others
+		let first = match indices.iter().flatten().next() {
+			Some(first) => *first,
+			None => return [None; LANES],
 		};
+		let pixels = indices.map(|index| index.unwrap_or(first)).map(|index| (index as i32 % width, index as i32 / width));
+
+		let samples = self.render_settings.samples_per_pixel.max(1);
+		scratch.prepare(&self.render_settings);
+		let mut sums = [(0.0, 0.0, 0.0); LANES];
+		let mut hits = [0; LANES];
+		for sample_index in 0..samples {
+			let offsets = pixels.map(|(x, y)| {
+				scratch.sampler.start_pixel_sample(x as u32, y as u32, sample_index);
+				let jitter = scratch.sampler.next_2d();
+				if samples == 1 { (0.5, 0.5) } else { jitter }
+			});
+			let rays = std::array::from_fn(|lane| self.primary_ray(pixels[lane].0, pixels[lane].1, offsets[lane], width, height));
+			for (lane, hit) in self.trace_primary_packet(&rays).iter().enumerate() {
+				if indices[lane].is_none() { continue }
+				let ray = &rays[lane];
+				let distance = hit.as_ref().map(|(_, hit)| hit.0.dist(&ray.origin) / Vec3::dot(&ray.direction, &ray.direction).sqrt());
+				let color = hit.map(|hit| {
+					// Each pixel's sampler carries on from where its ray left it, same as one at a time
+					scratch.sampler.start_pixel_sample(pixels[lane].0 as u32, pixels[lane].1 as u32, sample_index);
+					scratch.sampler.next_2d();
+					self.shade_hit(ray, hit, scratch)
+				});
+				let (x, y) = pixels[lane];
+				let color = match self.through_media(ray, distance, color).or_else(|| self.backplate_color(x, y, offsets[lane], width, height)) {
+					Some(color) => color,
+					None => continue,
+				};
+				sums[lane] = (sums[lane].0 + color.r as f64, sums[lane].1 + color.g as f64, sums[lane].2 + color.b as f64);
+				hits[lane] += 1;
+			}
+		}
+		let inv = 1.0 / samples as f64;
+		std::array::from_fn(|lane| indices[lane].map(|_| {
+			let sum = sums[lane];
+			let color = Color { r: (sum.0 * inv).round() as u8, g: (sum.1 * inv).round() as u8, b: (sum.2 * inv).round() as u8 };
+			(color, (hits[lane] as f64 * inv * 255.0).round() as u8)
+		}))
+	}
 
-		let hit = self.trace(&ray);
-		if let Some(hit) = hit {
-			// Cast Shadow Ray
-			let light_sources = self.get_all_light_sources_immut();
-			for ls in light_sources.iter() {
-				let shadow_ray = Ray::from_to(&hit.0, &ls.pos);
-
-				if let Some(shadow_hit) = self.trace(&shadow_ray) {
-					let luminosity = 0.22 / (hit.0.dist(&ls.pos) * hit.0.dist(&ls.pos)); // Inverse Square Law
-					mix_color = Color {
-						r: capped_f64( ls.color.r as f64 * luminosity + hit.1.color.r as f64 * luminosity as f64, 0.0, 255.0) as u8,
-						g: capped_f64( ls.color.g as f64 * luminosity + hit.1.color.g as f64 * luminosity as f64, 0.0, 255.0) as u8,
-						b: capped_f64( ls.color.b as f64 * luminosity + hit.1.color.b as f64 * luminosity  as f64, 0.0, 255.0) as u8,
-					}
-				} else {
-					let luminosity = 1.0 / (hit.0.dist(&ls.pos) * hit.0.dist(&ls.pos)); // Inverse Square Law
-					mix_color = Color {
-						r: capped_f64( ls.color.r as f64 * luminosity + hit.1.color.r as f64 * luminosity as f64, 0.0, 255.0) as u8,
-						g: capped_f64( ls.color.g as f64 * luminosity + hit.1.color.g as f64 * luminosity as f64, 0.0, 255.0) as u8,
-						b: capped_f64( ls.color.b as f64 * luminosity + hit.1.color.b as f64 * luminosity  as f64, 0.0, 255.0) as u8,
-					}
+	// Traces every pixel of a width x height frame, row by row
+	pub fn render(&mut self, width: i32, height: i32) -> Vec<Color> {
+		self.update_culling(width, height);
+		let scene = &*self;
+		let image = render_tile_packets(scene, width as usize, height as usize, |_, _| true, |pixels, scratch| {
+			scene.cast_ray_packet(pixels.map(|pixel| pixel.map(|(x, y)| (y * width as usize + x) as u64)), width, height, scratch).map(|pixel| pixel.map(|(color, _)| color))
+		});
+		image.into_iter().flatten().collect()
+	}
+
+	// Same as render, keeping coverage so the background can be made transparent, plus depth and normals
+	pub fn render_framebuffer(&mut self, width: i32, height: i32) -> Framebuffer {
+		self.update_culling(width, height);
+		let mut framebuffer = Framebuffer::new(width as usize, height as usize);
+		let forward = self.current_camera.forward();
+		// Everything but the writes happens on the render threads
+		let scene = &*self;
+		let pixels = render_tile_packets(scene, width as usize, height as usize, |_, _| true, |pixels, scratch| {
+			let indices = pixels.map(|pixel| pixel.map(|(x, y)| (y * width as usize + x) as u64));
+			let colors = scene.cast_ray_packet(indices, width, height, scratch);
+			std::array::from_fn(|lane| {
+				let ((x, y), (color, alpha)) = (pixels[lane]?, colors[lane]?);
+				let center_ray = scene.primary_ray(x as i32, y as i32, (0.5, 0.5), width, height);
+				let surface = scene.trace_primary_object(&center_ray).map(|(object, hit)| {
+					let motion = scene.motion_vector(object, &hit.0, width, height);
+					let depth = Vec3::dot(&hit.0.sub(&scene.current_camera.pos), &forward);
+					let normal = hit.2.normalize();
+					let sky = if scene.render_settings.ao_samples > 0 {
+						// Facing the camera, whichever way the surface happens to be wound
+						let normal = if Vec3::dot(&normal, &center_ray.direction) > 0.0 { normal.mul(-1.0) } else { normal };
+						scratch.sampler.start_pixel_sample(x as u32, y as u32, scene.render_settings.samples_per_pixel.max(1));
+						Some(scene.sky_visibility(&hit.0, &normal, center_ray.mask.layers, scratch))
+					} else {
+						None
+					};
+					(motion, depth, normal, sky)
+				});
+				Some((color, alpha, surface))
+			})
+		});
+		for (index, (color, alpha, surface)) in pixels.into_iter().flatten().enumerate() {
+			let (x, y) = (index % width as usize, index / width as usize);
+			framebuffer.set(x, y, color, alpha);
+			if let Some(((motion_x, motion_y), depth, normal, sky)) = surface {
+				framebuffer.set_motion(x, y, motion_x as f32, motion_y as f32);
+				framebuffer.set_aovs(x, y, depth as f32, normal);
+				if let Some((visibility, bent)) = sky {
+					framebuffer.set_sky(x, y, visibility as f32, bent);
 				}
 			}
+		}
+		framebuffer
+	}
 
-			// Cast Reflect Rays
-			let reflect_ray = Ray { origin: hit.0, direction: hit.2 };
-			if let Some(reflect_hit) = self.trace(&reflect_ray) {
-				let light_sources = self.get_all_light_sources_immut();
-				for ls in light_sources.iter() {
-					let shadow_ray = Ray::from_to(&hit.0, &ls.pos);
-	
-					if let Some(shadow_hit) = self.trace(&shadow_ray) {
-						let luminosity = 0.22 / (hit.0.dist(&ls.pos) * hit.0.dist(&ls.pos)); // Inverse Square Law
-						mix_color = Color {
-							r: capped_f64( ls.color.r as f64 * luminosity + reflect_hit.1.color.r as f64 * luminosity as f64, 0.0, 255.0) as u8,
-							g: capped_f64( ls.color.g as f64 * luminosity + reflect_hit.1.color.g as f64 * luminosity as f64, 0.0, 255.0) as u8,
-							b: capped_f64( ls.color.b as f64 * luminosity + reflect_hit.1.color.b as f64 * luminosity  as f64, 0.0, 255.0) as u8,
-						}
-					} else {
-						let luminosity = 1.0 / (hit.0.dist(&ls.pos) * hit.0.dist(&ls.pos)); // Inverse Square Law
-						mix_color = Color {
-							r: capped_f64( ls.color.r as f64 * luminosity + mix_color.r as f64, 0.0, 255.0) as u8,
-							g: capped_f64( ls.color.g as f64 * luminosity + mix_color.g as f64, 0.0, 255.0) as u8,
-							b: capped_f64( ls.color.b as f64 * luminosity + mix_color.b as f64, 0.0, 255.0) as u8,
-						}
+	// Puts every animated object where its animation has it time seconds in
+	pub fn animate(&mut self, time: f64) {
+		let started = self.profiler.start();
+		for animation in self.animations.iter() {
+			let (pos, rot) = animation.pose(time);
+			if let Some(object) = self.objects.iter_mut().find(|object| *object.get_id() == animation.target) {
+				object.set_transform(pos, rot);
+				if animation.weights.is_empty() { continue }
+				if let Some(mesh) = object.as_any().downcast_mut::<Mesh>() {
+					let mut weights = mesh.morph.as_ref().map_or(Vec::new(), |morph| morph.weights.clone());
+					for track in animation.weights.iter() {
+						if let Some(weight) = weights.get_mut(track.morph) { *weight = track.value(time) }
 					}
+					mesh.set_morph_weights(&weights);
 				}
-			} else {
-				
 			}
-		} 
+		}
+		self.profiler.stop(Stage::Transforms, started);
+	}
+
+	// Runs the rigid bodies, if there are any, elapsed seconds further on
+	pub fn simulate(&mut self, elapsed: f64) {
+		let started = self.profiler.start();
+		if let Some(physics) = self.physics.as_mut() {
+			physics.step(&mut self.objects, elapsed);
+		}
+		self.profiler.stop(Stage::Transforms, started);
+	}
+
+	// Call once a frame has been shown, the next frame's motion vectors are measured from here
+	pub fn remember_frame(&mut self) {
+		let objects = self.objects.iter().map(|object| (object.get_id().clone(), (*object.get_pos(), *object.get_rot()))).collect();
+		self.previous_frame = Some(FrameTransforms { camera: (*self.current_camera).clone(), objects });
+	}
+
+	// Screen space motion in pixels of point on objects[index] since the previous frame,
+	// pointing from where it was to where it is now. Zero without a previous frame
+	pub fn motion_vector(&self, index: usize, point: &Vec3, width: i32, height: i32) -> (f64, f64) {
+		let previous = match &self.previous_frame {
+			Some(previous) => previous,
+			None => return (0.0, 0.0),
+		};
+		let object = &self.objects[index];
+		// Carried along with the object from its old transform to the current one
+		let old_point = match previous.objects.get(object.get_id()) {
+			Some((pos, rot)) => point.sub(object.get_pos()).unrotate(object.get_rot()).rotate(rot).add(pos),
+			None => *point,
+		};
+		match (self.current_camera.project(point, width, height), previous.camera.project(&old_point, width, height)) {
+			(Some(now), Some(before)) => (now.0 - before.0, now.1 - before.1),
+			_ => (0.0, 0.0),
+		}
+	}
+
+	// Camera ray through the given sub-pixel offset (0.5, 0.5 being the pixel center)
+	pub fn primary_ray(&self, x: i32, y: i32, offset: (f64, f64), width: i32, height: i32) -> Ray {
+		let aspect_ratio = width as f64 / height as f64;
+		let (half_width, half_height) = self.current_camera.half_extents(aspect_ratio);
+		let shift = self.current_camera.image_shift();
+		let xx = (2.0 * ((x as f64 + offset.0) / width as f64) - 1.0) * half_width + shift.0;
+		let yy = (1.0 - 2.0 * ((y as f64 + offset.1) / height as f64)) * half_height + shift.1;
+		let rotation = self.current_camera.rot.to_matrix();
+		let direction = rotation.apply(&(Vec3 {x: xx, y: yy, z: -1.0}).normalize());
+		// One pixel over either way
+		let (step_x, step_y) = (2.0 * half_width / width as f64, 2.0 * half_height / height as f64);
+		let origin = self.current_camera.pos;
+		let differential = RayDifferential {
+			x_origin: origin,
+			x_direction: rotation.apply(&(Vec3 {x: xx + step_x, y: yy, z: -1.0}).normalize()),
+			y_origin: origin,
+			y_direction: rotation.apply(&(Vec3 {x: xx, y: yy - step_y, z: -1.0}).normalize()),
+		};
+		Ray { origin, direction, mask: RayMask::new(RayMask::CAMERA, self.current_camera.layers), differential: Some(differential) }
+	}
 
+	// Fraction of the light visible from point, 1.0 meaning fully lit, as seen by shadow rays on the given layers
+	pub fn light_visibility(&self, point: &Vec3, light: &LightSource, layers: u32, scratch: &mut Scratch) -> f64 {
+		let shadow_mask = RayMask::new(RayMask::SHADOW, layers);
+		if light.radius <= 0.0 {
+			return if self.occluded(point, &light.pos, shadow_mask) { 0.0 } else { 1.0 };
+		}
+
+		// Sample the disk the spherical light presents towards the point
+		let to_light = light.pos.sub(point).normalize();
+		let (tangent, bitangent) = to_light.orthonormal_basis();
+		scratch.sampler.next_2d_set(self.render_settings.light_samples.max(1), &mut scratch.light_samples);
+		let mut visible = 0;
+		for (u, v) in scratch.light_samples.iter() {
+			let (dx, dy) = sample_disk(*u, *v);
+			let target = light.pos.add(&tangent.mul(dx * light.radius as f64)).add(&bitangent.mul(dy * light.radius as f64));
+			if !self.occluded(point, &target, shadow_mask) {
+				visible += 1;
+			}
+		}
+		visible as f64 / scratch.light_samples.len() as f64
+	}
+
+	// Whether something between point and target blocks the way, anything past target not counting
+	fn occluded(&self, point: &Vec3, target: &Vec3, mask: RayMask) -> bool {
+		self.trace(&Ray::from_to(point, target).with_mask(mask)).is_some_and(|hit| hit.0.dist(point) < target.dist(point))
+	}
+
+	// Fraction of the hemisphere around normal that sees the sky, cosine weighted, along with
+	// the bent normal: the average direction the unblocked rays left in, or normal if all were blocked
+	pub fn sky_visibility(&self, point: &Vec3, normal: &Vec3, layers: u32, scratch: &mut Scratch) -> (f64, Vec3) {
+		let (tangent, bitangent) = normal.orthonormal_basis();
+		let origin = point.add(&normal.mul(1e-4));
+		scratch.sampler.next_2d_set(self.render_settings.ao_samples.max(1), &mut scratch.light_samples);
+		let mut visible = 0;
+		let mut bent = Vec3 { x: 0.0, y: 0.0, z: 0.0 };
+		for (u, v) in scratch.light_samples.iter() {
+			let local = sample_cosine_hemisphere(*u, *v);
+			let direction = tangent.mul(local.x).add(&bitangent.mul(local.y)).add(&normal.mul(local.z));
+			let ray = Ray { origin, direction, mask: RayMask::new(RayMask::GI, layers), differential: None };
+			if self.trace(&ray).is_none() {
+				visible += 1;
+				bent = bent.add(&direction);
+			}
+		}
+		let bent = if visible == 0 { *normal } else { bent.normalize() };
+		(visible as f64 / scratch.light_samples.len() as f64, bent)
+	}
+
+	// Color seen along a primary ray, None when it escapes the scene
+	pub fn shade(&self, ray: &Ray, scratch: &mut Scratch) -> Option<Color> {
+		let hit = self.trace_primary_object(ray);
+		let distance = hit.as_ref().map(|(_, hit)| hit.0.dist(&ray.origin) / Vec3::dot(&ray.direction, &ray.direction).sqrt());
+		let color = hit.map(|hit| self.shade_hit(ray, hit, scratch));
+		self.through_media(ray, distance, color)
+	}
+
+	// Color of what a primary ray hit, objects[index] at hit, for when it's been traced already
+	pub fn shade_hit(&self, ray: &Ray, hit: ObjectHit, scratch: &mut Scratch) -> Color {
+		self.shade_bounce(ray, hit, 0, scratch)
+	}
+
+	// shade_hit for a ray that's already been reflected bounce times
+	fn shade_bounce(&self, ray: &Ray, hit: ObjectHit, bounce: u32, scratch: &mut Scratch) -> Color {
+		// With a light tree only the few lights it picks for the point are shaded with, see light_tree.rs
+		let mut picks = std::mem::take(&mut scratch.picked_lights);
+		let picked = match &self.light_tree {
+			Some(tree) if self.render_settings.sampled_lights > 0 => {
+				tree.pick_lights(&hit.1.0, self.render_settings.sampled_lights, &mut *scratch.sampler, &mut picks);
+				Some(picks.as_slice())
+			}
+			_ => None,
+		};
+		let color = self.shade_lit(ray, hit, picked, bounce, scratch);
+		scratch.picked_lights = picks;
+		color
+	}
+
+	// The lights lighting the object with id, every one at full weight or just the picked ones, by
+	// index into objects, at theirs
+	fn shading_lights<'a>(&'a self, (id, picked): (&'a str, Option<&'a [(usize, f64)]>)) -> impl Iterator<Item = (&'a LightSource, f64)> + 'a {
+		let all = picked.is_none().then(move || self.light_sources().map(|light| (light, 1.0)));
+		let picked = picked.map(move |picked| picked.iter().filter_map(move |(index, weight)| self.objects[*index].as_any_immut().downcast_ref::<LightSource>().map(|light| (light, *weight))));
+		all.into_iter().flatten().chain(picked.into_iter().flatten()).filter(move |(light, _)| light.illuminates(id))
+	}
+
+	fn shade_lit(&self, ray: &Ray, (index, hit): ObjectHit, picked: Option<&[(usize, f64)]>, bounce: u32, scratch: &mut Scratch) -> Color {
+		// Every light adds its own, picked ones as much as the lights they stand in for, summed before
+		// clamping so lights too dim for a step of 8 bit color on their own still count together
+		let mut sum = (0.0, 0.0, 0.0);
+
+		let id = self.objects[index].get_id();
+		let shadow_origin = self.objects[index].shadow_origin(ray, &hit.0);
+		let exposure = 2f64.powf(self.render_settings.exposure);
+		// Cast Shadow Ray
+		for (ls, weight) in self.shading_lights((id, picked)) {
+			let visibility = self.light_visibility(&shadow_origin, ls, ray.mask.layers, scratch);
+			let (strength, light_color) = ls.emission(&hit.0);
+			let luminosity = strength * ls.intensity as f64 * (0.22 + 0.78 * visibility) * exposure / (hit.0.dist(&ls.pos) * hit.0.dist(&ls.pos)) * weight; // Inverse Square Law, dimmed in shadow
+			sum.0 += light_color.r as f64 * luminosity + hit.1.color.r as f64 * luminosity;
+			sum.1 += light_color.g as f64 * luminosity + hit.1.color.g as f64 * luminosity;
+			sum.2 += light_color.b as f64 * luminosity + hit.1.color.b as f64 * luminosity;
+		}
+		let mut mix_color = Color {
+			r: capped_f64(sum.0, 0.0, 255.0) as u8,
+			g: capped_f64(sum.1, 0.0, 255.0) as u8,
+			b: capped_f64(sum.2, 0.0, 255.0) as u8,
+		};
+		// Reflections that are traced bring the environment along themselves
+		let traced = bounce < self.render_settings.max_bounces;
+		let surface = if traced { Material { reflectivity: 0.0, ..hit.1 } } else { hit.1 };
+		mix_color = self.environment_lit(mix_color, &ray.direction, &surface, &hit.2);
+
+		// Mirrored and seen through as much as Fresnel says for the material, what the reflection and
+		// transmission rays see shaded the same way in turn until max_bounces deep (Whitted, "An
+		// Improved Illumination Model for Shaded Display")
+		if !traced { return mix_color }
+		let (reflected, transmitted) = hit.1.fresnel_weights(&ray.direction.normalize(), &hit.2);
+		// Through first, so that the two blends add up to the weights
+		if transmitted > 0.0 {
+			mix_color = mix_color.lerp(&self.transmitted(ray, &hit, bounce, scratch), (transmitted / (1.0 - reflected)).min(1.0));
+		}
+		if reflected > 0.0 {
+			mix_color = mix_color.lerp(&self.reflected(ray, &hit, bounce, scratch), reflected);
+		}
 		mix_color
 	}
+
+	// What's mirrored in the surface at hit
+	fn reflected(&self, ray: &Ray, hit: &(Vec3, Material, Vec3), bounce: u32, scratch: &mut Scratch) -> Color {
+		let normal = if Vec3::dot(&hit.2, &ray.direction) > 0.0 { hit.2.mul(-1.0) } else { hit.2 };
+		let direction = reflect(&ray.direction.normalize(), &normal);
+		let differential = ray.differential.map(|differential| differential.reflect(&ray.direction, &hit.0, &normal, &direction));
+		let reflect_ray = Ray { origin: hit.0.add(&normal.mul(1e-4)), direction, mask: ray.mask.with_kinds(RayMask::REFLECTION), differential };
+		let roughness = hit.1.roughness as f64;
+		if roughness <= 0.0 || self.render_settings.glossy_samples == 0 {
+			// Rough surfaces send one ray standing in for the whole cone, its differential spread as
+			// wide so what it lands on is looked up already blurred
+			let reflect_ray = match roughness > 0.0 {
+				true => {
+					let spread = (roughness * GLOSSY_MAX_ANGLE).tan();
+					let cone = differential.unwrap_or(RayDifferential { x_origin: hit.0, x_direction: direction, y_origin: hit.0, y_direction: direction });
+					Ray { differential: Some(cone.widened(&direction, spread)), ..reflect_ray }
+				}
+				false => reflect_ray,
+			};
+			return self.reflection(&reflect_ray, bounce + 1, roughness, scratch);
+		}
+
+		// Final renders average glossy_samples rays spread over the cone instead
+		let (tangent, bitangent) = direction.orthonormal_basis();
+		let cos_max = (roughness * GLOSSY_MAX_ANGLE).cos();
+		let mut directions = std::mem::take(&mut scratch.glossy_samples);
+		scratch.sampler.next_2d_set(self.render_settings.glossy_samples, &mut directions);
+		let mut sum = (0.0, 0.0, 0.0);
+		for (u, v) in directions.iter() {
+			let local = sample_cone(*u, *v, cos_max);
+			let direction = tangent.mul(local.x).add(&bitangent.mul(local.y)).add(&direction.mul(local.z));
+			let color = self.reflection(&Ray { direction, ..reflect_ray }, bounce + 1, 0.0, scratch);
+			sum = (sum.0 + color.r as f64, sum.1 + color.g as f64, sum.2 + color.b as f64);
+		}
+		let inv = 1.0 / directions.len() as f64;
+		scratch.glossy_samples = directions;
+		Color { r: (sum.0 * inv).round() as u8, g: (sum.1 * inv).round() as u8, b: (sum.2 * inv).round() as u8 }
+	}
+
+	// What's seen through the surface at hit, bent by Snell's law going in or out of the material,
+	// or mirrored inside it past the critical angle. Every surface is taken to face out into the air
+	fn transmitted(&self, ray: &Ray, hit: &(Vec3, Material, Vec3), bounce: u32, scratch: &mut Scratch) -> Color {
+		let incident = ray.direction.normalize();
+		let ior = hit.1.ior as f64;
+		let transmit_ray = match refract(&incident, &hit.2, ior) {
+			Some(direction) => {
+				// Starting just past the surface, on the far side from the ray
+				let through = if Vec3::dot(&hit.2, &incident) > 0.0 { hit.2 } else { hit.2.mul(-1.0) };
+				let differential = ray.differential.and_then(|differential| differential.refract(&ray.direction, &hit.0, &hit.2, ior, &direction));
+				Ray { origin: hit.0.add(&through.mul(1e-4)), direction, mask: ray.mask.with_kinds(RayMask::REFLECTION), differential }
+			}
+			None => {
+				let normal = if Vec3::dot(&hit.2, &incident) > 0.0 { hit.2.mul(-1.0) } else { hit.2 };
+				let direction = reflect(&incident, &normal);
+				let differential = ray.differential.map(|differential| differential.reflect(&ray.direction, &hit.0, &normal, &direction));
+				Ray { origin: hit.0.add(&normal.mul(1e-4)), direction, mask: ray.mask.with_kinds(RayMask::REFLECTION), differential }
+			}
+		};
+		self.reflection(&transmit_ray, bounce + 1, 0.0, scratch)
+	}
+
+	// What a reflection or transmission ray sees, shaded as its bounce, or when it leaves the scene the
+	// environment blurred by roughness, black without one
+	fn reflection(&self, ray: &Ray, bounce: u32, roughness: f64, scratch: &mut Scratch) -> Color {
+		match self.trace_object(ray) {
+			Some(hit) => self.shade_bounce(ray, hit, bounce, scratch),
+			None => self.environment_seen(&ray.direction, roughness).unwrap_or(Color { r: 0, g: 0, b: 0 }),
+		}
+	}
+}
+
+// incident mirrored about the plane with normal, which has to be unit length
+pub fn reflect(incident: &Vec3, normal: &Vec3) -> Vec3 {
+	incident.sub(&normal.mul(2.0 * Vec3::dot(incident, normal)))
+}
+
+// incident bent through the plane with normal going from a medium of index 1 into one of index eta,
+// or the other way round from the side normal points away from, both unit length. None past the
+// critical angle, where it's all reflected instead
+pub fn refract(incident: &Vec3, normal: &Vec3, eta: f64) -> Option<Vec3> {
+	let (normal_in, ratio) = if Vec3::dot(incident, normal) < 0.0 { (*normal, 1.0 / eta) } else { (normal.mul(-1.0), eta) };
+	let cos_in = -Vec3::dot(incident, &normal_in);
+	let sin2_out = ratio * ratio * (1.0 - cos_in * cos_in);
+	if sin2_out > 1.0 { return None }
+	Some(incident.mul(ratio).add(&normal_in.mul(ratio * cos_in - (1.0 - sin2_out).sqrt())))
 }
 
 #[test]
@@ -588,16 +2522,779 @@ fn tri_hit() {
 	let white_difuse = Material {
 		color: Color {r: 255, g: 255, b: 255},
 		transparency: 0.0,
-		reflectivity: 0.0
+		reflectivity: 0.0,
+		roughness: 0.0,
+		ior: 1.5
 	};
 	let tri = Tri { a: Vec3 {x: -1.0, y: 0.0, z: 0.0}, b: Vec3 {x: 0.0, y: 1.0, z: 0.0}, c: Vec3 {x: 1.0, y: 0.0, z: 0.0}, mat: white_difuse};
-	let ray = Ray { origin: Vec3 {x: 0.0, y: 0.33, z: 1.0}, direction: Vec3 { x: 0.0, y: 0.0, z: -1.0 }};
+	let ray = Ray { origin: Vec3 {x: 0.0, y: 0.33, z: 1.0}, direction: Vec3 { x: 0.0, y: 0.0, z: -1.0 }, mask: RayMask::ALL, differential: None };
 	let dist = tri.ray_hit(&ray);
-	assert_eq!(dist.is_some(), true);
+	assert!(dist.is_some());
 
 	let origin = Vec3 { x: 0.0, y: 0.0, z: 0.0 };
 	let up = Vec3 { x: 0.0, y: 0.0, z: 1.0 };
 	let right = Vec3 { x: 1.0, y: 0.0, z: 0.0 };
 	assert_eq!(origin.dist(&up), 1.0);
 	assert_eq!(origin.dist(&right), 1.0);
-}
\ No newline at end of file
+}
+
+#[test]
+fn seeded_render_is_reproducible() {
+	let render = |seed: u64| {
+		let mut scene = Scene::default_scene();
+		scene.render_settings.samples_per_pixel = 4;
+		scene.render_settings.seed = seed;
+		for light in scene.get_all_light_sources() {
+			light.radius = 0.5;
+		}
+		scene.render(32, 18)
+	};
+	// Same seed, same image; another seed lands the soft shadow samples elsewhere
+	assert_eq!(render(42), render(42));
+	assert_ne!(render(42), render(43));
+}
+
+#[test]
+fn camera_look_at() {
+	let mut camera = Camera::new(Vec3 { x: 3.0, y: 3.0, z: 3.0 }, Rot3::new(), 40.0);
+	let target = Vec3 { x: 0.0, y: 0.0, z: 1.5 };
+	camera.look_at(&target, &Vec3 { x: 0.0, y: 0.0, z: 1.0 });
+	let expected = target.sub(&camera.pos).normalize();
+	assert!(camera.forward().dist(&expected) < 1e-9);
+}
+
+#[test]
+fn fov_axis_aspect() {
+	let mut camera = Camera::new(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, Rot3::new(), 90.0);
+	let (w, h) = camera.half_extents(2.0);
+	assert!((w - 2.0).abs() < 1e-9 && (h - 1.0).abs() < 1e-9);
+	camera.fov_axis = FovAxis::Horizontal;
+	let (w, h) = camera.half_extents(2.0);
+	assert!((w - 1.0).abs() < 1e-9 && (h - 0.5).abs() < 1e-9);
+}
+
+#[test]
+fn physical_lens() {
+	let mut camera = Camera::new(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, Rot3::new(), 40.0);
+	camera.fov_axis = FovAxis::Horizontal;
+	// An 18mm lens spans 90 degrees across a 36mm wide sensor
+	camera.set_lens(Some(PhysicalLens::full_frame(18.0)));
+	assert!((camera.fov - 90.0).abs() < 1e-4);
+	let (w, h) = camera.half_extents(1.5);
+	assert!((w - 1.0).abs() < 1e-9 && (h - 1.0 / 1.5).abs() < 1e-9);
+	// Zooming through the fov changes the focal length
+	camera.set_fov(2.0 * (0.5_f64).atan().to_degrees() as f32);
+	assert!((camera.lens.unwrap().focal_length - 36.0).abs() < 1e-3);
+	camera.lens.as_mut().unwrap().shift_y = 9.0;
+	assert!((camera.image_shift().1 - 0.25).abs() < 1e-6);
+}
+
+#[test]
+fn frustum_culling() {
+	let mut scene = Scene::default_scene();
+	scene.current_camera.pos = Vec3 { x: 0.0, y: -10.0, z: 1.5 };
+	scene.current_camera.look_at(&Vec3 { x: 0.0, y: 0.0, z: 1.5 }, &Vec3 { x: 0.0, y: 0.0, z: 1.0 });
+	let material = Material { transparency: 0.0, reflectivity: 0.0, roughness: 0.0, ior: 1.5, color: Color { r: 255, g: 0, b: 0 } };
+	let in_view = scene.objects.len();
+	scene.objects.push(Box::new(Sphere::new(Vec3 { x: 0.0, y: 0.0, z: 1.5 }, 0.5, material)));
+	let behind = scene.objects.len();
+	scene.objects.push(Box::new(Sphere::new(Vec3 { x: 0.0, y: -20.0, z: 1.5 }, 0.5, material)));
+	let beside = scene.objects.len();
+	scene.objects.push(Box::new(Sphere::new(Vec3 { x: 30.0, y: -5.0, z: 1.5 }, 0.5, material)));
+
+	scene.update_culling(160, 90);
+	let visible = scene.primary_objects.clone().unwrap();
+	assert!(visible.contains(&in_view) && !visible.contains(&behind) && !visible.contains(&beside));
+
+	scene.render_settings.cull_distance = 5.0;
+	scene.update_culling(160, 90);
+	assert!(!scene.primary_objects.as_ref().unwrap().contains(&in_view));
+}
+
+#[test]
+fn ray_masks() {
+	let mut scene = Scene::default_scene();
+	scene.objects.clear();
+	let material = Material { transparency: 0.0, reflectivity: 0.0, roughness: 0.0, ior: 1.5, color: Color { r: 255, g: 0, b: 0 } };
+	let mut sphere = Sphere::new(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, 1.0, material);
+	// Only shows up in reflections, and only on layer 2
+	sphere.set_mask(RayMask::new(RayMask::REFLECTION, 0b10));
+	scene.objects.push(Box::new(sphere));
+
+	let ray = |kinds: u32, layers: u32| Ray::from_to(&Vec3 { x: 0.0, y: -5.0, z: 0.0 }, &Vec3 { x: 0.0, y: 0.0, z: 0.0 }).with_mask(RayMask::new(kinds, layers));
+	assert!(scene.trace(&ray(RayMask::CAMERA, u32::MAX)).is_none());
+	assert!(scene.trace(&ray(RayMask::SHADOW, u32::MAX)).is_none());
+	assert!(scene.trace(&ray(RayMask::REFLECTION, 0b01)).is_none());
+	assert!(scene.trace(&ray(RayMask::REFLECTION, 0b11)).is_some());
+}
+
+#[test]
+fn scratch_reuse_matches_fresh() {
+	let mut scene = Scene::default_scene();
+	scene.render_settings.samples_per_pixel = 3;
+	let mut scratch = Scratch::new(&scene.render_settings);
+	for kind in [crate::sampler::SamplerKind::Halton, crate::sampler::SamplerKind::Stratified].iter() {
+		// The reused scratch has to notice the sampler change
+		scene.render_settings.sampler = *kind;
+		for index in (0..160 * 90).step_by(97) {
+			let reused = scene.cast_ray_with(index, 160, 90, &mut scratch);
+			let fresh = scene.cast_ray_with(index, 160, 90, &mut Scratch::new(&scene.render_settings));
+			assert_eq!(reused, fresh);
+		}
+	}
+}
+
+#[test]
+fn mesh_shares_vertices() {
+	let cube = create_cube(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, Rot3::new());
+	assert_eq!(cube.geometry.positions.len(), 8);
+	assert_eq!(cube.triangle_count(), 12);
+	assert_eq!(cube.materials.len(), 2);
+	// Rebuilding from the triangles it hands back gives the same mesh
+	let again = Mesh::new(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, Rot3::new(), (0..12).map(|i| cube.triangle(i)).collect());
+	assert_eq!(again.geometry.indices, cube.geometry.indices);
+	assert_eq!(again.geometry.material_ids, cube.geometry.material_ids);
+}
+
+#[test]
+fn light_linking() {
+	let mut scene = Scene::default_scene();
+	scene.objects.clear();
+	let material = Material { transparency: 0.0, reflectivity: 0.0, roughness: 0.0, ior: 1.5, color: Color { r: 200, g: 200, b: 200 } };
+	for (id, x) in [("left", -2.0), ("right", 2.0)].iter() {
+		let mut sphere = Sphere::new(Vec3 { x: *x, y: 0.0, z: 0.0 }, 1.0, material);
+		sphere.id = id.to_string();
+		scene.objects.push(Box::new(sphere));
+	}
+	let mut rim = LightSource::new(Vec3 { x: 0.0, y: -3.0, z: 0.0 }, Rot3::new(), 4.0);
+	rim.include = vec![String::from("left")];
+	scene.objects.push(Box::new(rim));
+
+	let mut scratch = Scratch::new(&scene.render_settings);
+	let shade = |scene: &Scene, x: f64, scratch: &mut Scratch| scene.shade(&Ray::from_to(&Vec3 { x, y: -5.0, z: 0.0 }, &Vec3 { x, y: 0.0, z: 0.0 }), scratch).unwrap();
+	let black = Color { r: 0, g: 0, b: 0 };
+	assert_ne!(shade(&scene, -2.0, &mut scratch), black);
+	assert_eq!(shade(&scene, 2.0, &mut scratch), black);
+	// Excluding beats including
+	scene.get_all_light_sources()[0].exclude = vec![String::from("left")];
+	assert_eq!(shade(&scene, -2.0, &mut scratch), black);
+}
+
+#[test]
+fn spot_gobo() {
+	let white = Color { r: 255, g: 255, b: 255 };
+	let black = Color { r: 0, g: 0, b: 0 };
+	// Pointing straight down from 1 above the ground, with a 45 degree cone reaching out to 1 either side
+	let mut light = LightSource::new(Vec3 { x: 0.0, y: 0.0, z: 1.0 }, Rot3 { yaw: 0.0, pitch: deg_to_rad(-90.0), roll: 0.0 }, 1.0);
+	light.spot = Some(Spot { angle: 45.0, blend: 0.0, gobo: None });
+	assert_eq!(light.emission(&Vec3 { x: 0.5, y: 0.0, z: 0.0 }), (1.0, white));
+	assert_eq!(light.emission(&Vec3 { x: 1.5, y: 0.0, z: 0.0 }).0, 0.0);
+	assert_eq!(light.emission(&Vec3 { x: 0.0, y: 0.0, z: 2.0 }).0, 0.0);
+
+	// Half dark, half red gobo: one side of the cone gets nothing, the other is tinted
+	let gobo = Texture::new(2, 1, vec![black, Color { r: 255, g: 0, b: 0 }], String::new());
+	light.spot = Some(Spot { angle: 45.0, blend: 0.0, gobo: Some(Arc::new(gobo)) });
+	let (tangent, _) = Rot3::to_vec(&light.rot).orthonormal_basis();
+	assert_eq!(light.emission(&tangent.mul(-0.5)).0, 0.0);
+	assert_eq!(light.emission(&tangent.mul(0.5)), (1.0, Color { r: 255, g: 0, b: 0 }));
+}
+
+#[test]
+fn sky_visibility() {
+	let mut scene = Scene::default_scene();
+	scene.render_settings.ao_samples = 64;
+	let mut scratch = Scratch::new(&scene.render_settings);
+	let up = Vec3 { x: 0.0, y: 0.0, z: 1.0 };
+	// Out on the open ground nothing is in the way, right under the cube most of the sky is gone
+	let (open, bent) = scene.sky_visibility(&Vec3 { x: 3.5, y: 3.5, z: 0.0 }, &up, u32::MAX, &mut scratch);
+	assert!(open > 0.9 && Vec3::dot(&bent, &up) > 0.9);
+	let (under, _) = scene.sky_visibility(&Vec3 { x: 0.0, y: 0.0, z: 0.0 }, &up, u32::MAX, &mut scratch);
+	assert!(under < 0.5);
+}
+
+#[test]
+fn motion_vectors() {
+	let mut scene = Scene::default_scene();
+	let ray = scene.primary_ray(5, 7, (0.5, 0.5), 32, 18);
+	let (x, y) = scene.current_camera.project(&ray.origin.add(&ray.direction.mul(5.0)), 32, 18).unwrap();
+	assert!((x - 5.5).abs() < 1e-9 && (y - 7.5).abs() < 1e-9);
+
+	assert_eq!(scene.motion_vector(1, &Vec3 { x: 0.0, y: 0.0, z: 1.5 }, 32, 18), (0.0, 0.0));
+	scene.remember_frame();
+	let offset = Vec3 { x: 0.3, y: -0.2, z: 0.1 };
+	let cube = scene.find_object("fuckin' cube").unwrap().as_any().downcast_mut::<Mesh>().unwrap();
+	cube.anchor = cube.anchor.add(&offset);
+	let point = Vec3 { x: 0.2, y: 0.1, z: 1.7 };
+	let (now, before) = (scene.current_camera.project(&point, 32, 18).unwrap(), scene.current_camera.project(&point.sub(&offset), 32, 18).unwrap());
+	let motion = scene.motion_vector(1, &point, 32, 18);
+	assert!((motion.0 - (now.0 - before.0)).abs() < 1e-9 && (motion.1 - (now.1 - before.1)).abs() < 1e-9);
+	// The floor stayed put
+	assert_eq!(scene.motion_vector(2, &Vec3 { x: 1.0, y: 1.0, z: 0.0 }, 32, 18), (0.0, 0.0));
+	let framebuffer = scene.render_framebuffer(32, 18);
+	assert!(framebuffer.motion.iter().any(|motion| motion.0 != 0.0));
+	assert!(framebuffer.motion.contains(&(0.0, 0.0)));
+}
+
+#[test]
+fn framing() {
+	let mut scene = Scene::default_scene();
+	// Framed, every corner of the cube lands inside the image
+	let center = scene.frame_object("fuckin' cube", 16.0 / 9.0).unwrap();
+	let bounds = scene.find_object("fuckin' cube").unwrap().bounds().unwrap();
+	assert!(center.dist(&bounds.center()) < 1e-9);
+	assert!(scene.current_camera.forward().sub(&center.sub(&scene.current_camera.pos).normalize()).dist(&Vec3 { x: 0.0, y: 0.0, z: 0.0 }) < 1e-6);
+	for corner in 0..8 {
+		let point = Vec3 {
+			x: if corner & 1 == 0 { bounds.min.x } else { bounds.max.x },
+			y: if corner & 2 == 0 { bounds.min.y } else { bounds.max.y },
+			z: if corner & 4 == 0 { bounds.min.z } else { bounds.max.z },
+		};
+		let (x, y) = scene.current_camera.project(&point, 160, 90).unwrap();
+		assert!((0.0..160.0).contains(&x) && (0.0..90.0).contains(&y), "{:?}", (x, y));
+	}
+	assert!(scene.frame_all(16.0 / 9.0).is_some());
+	assert!(scene.frame_object("nope", 1.0).is_none());
+}
+
+#[test]
+fn spatial_queries() {
+	let mut scene = Scene::default_scene();
+	let cube = scene.objects.iter().position(|object| object.get_id() == "fuckin' cube").unwrap();
+	let plane = scene.objects.len() - 1;
+
+	// Straight down from above the cube lands on it, from the side of it on the plane
+	let down = |x: f64| Ray { origin: Vec3 { x, y: 0.0, z: 10.0 }, direction: Vec3 { x: 0.0, y: 0.0, z: -1.0 }, mask: RayMask::ALL, differential: None };
+	let hit = scene.raycast(&down(0.0), 100.0).unwrap();
+	assert_eq!(hit.object, cube);
+	assert!(hit.distance > 7.0 && hit.distance < 8.5);
+	let hit = scene.raycast(&down(3.5), 100.0).unwrap();
+	assert_eq!(hit.object, plane);
+	assert!((hit.distance - 10.0).abs() < 1e-9);
+	assert!(scene.raycast(&down(3.5), 5.0).is_none());
+	// Two balls in the same place, the first is hit as it would be rendered
+	let ball = Material { color: Color { r: 255, g: 255, b: 255 }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+	scene.objects.push(Box::new(Sphere::new(Vec3 { x: 3.5, y: 0.0, z: 1.0 }, 0.5, ball)));
+	scene.objects.push(Box::new(Sphere::new(Vec3 { x: 3.5, y: 0.0, z: 1.0 }, 0.5, ball)));
+	assert_eq!(scene.raycast(&down(3.5), 100.0).unwrap().object, plane + 1);
+	scene.objects.truncate(plane + 1);
+	// Cut away, the cube lets the ray through to the plane
+	scene.clip_planes.push(crate::clipping::ClipPlane { point: Vec3 { x: 0.0, y: 0.0, z: -1.0 }, normal: Vec3 { x: 0.0, y: 0.0, z: 1.0 }, cap: None, objects: vec![String::from("fuckin' cube")] });
+	assert_eq!(scene.raycast(&down(0.0), 100.0).unwrap().object, plane);
+	scene.clip_planes.clear();
+
+	let (object, point) = scene.closest_point(&Vec3 { x: 3.5, y: 3.5, z: 0.5 }, 10.0, RayMask::ALL).unwrap();
+	assert_eq!(object, plane);
+	assert!(point.dist(&Vec3 { x: 3.5, y: 3.5, z: 0.0 }) < 1e-9);
+	assert!(scene.closest_point(&Vec3 { x: 3.5, y: 3.5, z: 0.5 }, 0.4, RayMask::ALL).is_none());
+
+	assert_eq!(scene.sphere_overlap(&Vec3 { x: 0.0, y: 0.0, z: 1.5 }, 1.1, RayMask::ALL), vec![cube]);
+	assert_eq!(scene.sphere_overlap(&Vec3 { x: 3.0, y: 3.0, z: 0.2 }, 0.5, RayMask::ALL), vec![plane]);
+	assert!(scene.sphere_overlap(&Vec3 { x: 3.0, y: 3.0, z: 0.2 }, 0.5, RayMask::new(RayMask::ALL.kinds, 0)).is_empty());
+}
+
+#[test]
+fn hiding() {
+	let mut scene = Scene::default_scene();
+	let cube = scene.objects.iter().position(|object| object.get_id() == "fuckin' cube").unwrap();
+	let plane = scene.objects.len() - 1;
+	let plane_id = scene.objects[plane].get_id().clone();
+	let down = Ray { origin: Vec3 { x: 0.0, y: 0.0, z: 10.0 }, direction: Vec3 { x: 0.0, y: 0.0, z: -1.0 }, mask: RayMask::ALL, differential: None };
+
+	// Rays go straight through to what's under
+	assert!(scene.hide("fuckin' cube") && !scene.hide("fuckin' cube") && !scene.hide("nope"));
+	assert_eq!(scene.raycast(&down, 100.0).unwrap().object, plane);
+	assert!(scene.unhide_all() && !scene.unhide_all());
+	assert_eq!(scene.raycast(&down, 100.0).unwrap().object, cube);
+
+	// Isolating leaves the light be
+	assert!(scene.isolate(&plane_id));
+	assert_eq!(scene.hidden.len(), 1);
+	assert_eq!(scene.raycast(&down, 100.0).unwrap().object, plane);
+	scene.unhide_all();
+	assert_eq!(scene.objects[cube].get_mask(), RayMask::ALL);
+}
+
+#[test]
+fn cached_triangles_follow_the_mesh() {
+	let mut scene = Scene::default_scene();
+	let down = |x: f64| Ray { origin: Vec3 { x, y: 0.0, z: 10.0 }, direction: Vec3 { x: 0.0, y: 0.0, z: -1.0 }, mask: RayMask::ALL, differential: None };
+	let cube = scene.find_object("fuckin' cube").unwrap();
+	assert!(cube.ray_hit(&down(0.0)).is_some());
+	assert!(cube.ray_hit(&down(5.0)).is_none());
+	cube.set_transform(Vec3 { x: 5.0, y: 0.0, z: 1.5 }, Rot3::new());
+	assert!(cube.ray_hit(&down(0.0)).is_none());
+	let (point, _, _) = cube.ray_hit(&down(5.0)).unwrap();
+	// An unrotated cube's top face is flat
+	assert!((point.z - cube.bounds().unwrap().max.z).abs() < 1e-9);
+}
+
+#[test]
+fn randomized_materials() {
+	let palette: Vec<Material> = [(230, 60, 50), (60, 200, 90), (70, 110, 240)].iter()
+		.map(|(r, g, b)| Material { color: Color { r: *r, g: *g, b: *b }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 })
+		.collect();
+	let colors = |scene: &mut Scene| {
+		let cube = scene.find_object("fuckin' cube").unwrap().as_any().downcast_mut::<Mesh>().unwrap();
+		(0..cube.triangle_count()).map(|i| cube.triangle(i).mat.color).map(|color| (color.r, color.g, color.b)).collect::<Vec<_>>()
+	};
+	let mut scene = Scene::default_scene();
+	scene.randomize_materials(7, &palette);
+	let painted = colors(&mut scene);
+	assert!(painted.iter().all(|color| palette.iter().any(|material| (material.color.r, material.color.g, material.color.b) == *color)));
+	// Not all the same, and the same again for the same seed
+	assert!(painted.iter().any(|color| *color != painted[0]));
+	let mut again = Scene::default_scene();
+	again.randomize_materials(7, &palette);
+	assert_eq!(colors(&mut again), painted);
+}
+
+#[test]
+fn matrix_rotation() {
+	let rot = Rot3 { yaw: 0.7, pitch: -0.3, roll: 2.1 };
+	let v = Vec3 { x: 1.5, y: -2.0, z: 0.25 };
+	let matrix = rot.to_matrix();
+	let turned = matrix.apply(&v);
+	assert!(turned.dist(&v.rotate(&rot)) == 0.0 && matrix.apply_inverse(&turned).dist(&v) < 1e-12);
+
+	// In bulk, the same as one vertex at a time
+	let mut mesh = create_cube(Vec3 { x: 1.0, y: 2.0, z: 3.0 }, rot);
+	let one_by_one: Vec<Vec3> = mesh.geometry.positions.iter().map(|position| position.rotate(&rot).add(&mesh.anchor)).collect();
+	assert!(mesh.world_positions().iter().zip(one_by_one.iter()).all(|(a, b)| a.dist(b) == 0.0));
+	assert!(mesh.world_normals().is_empty());
+	let normals = mesh.geometry.positions.iter().map(|position| position.normalize()).collect();
+	mesh.geometry = Arc::new(mesh.geometry.reshaped(mesh.geometry.positions.clone(), normals));
+	assert!(mesh.world_normals().iter().zip(one_by_one.iter()).all(|(normal, position)| normal.dist(&position.sub(&mesh.anchor).normalize()) < 1e-12));
+	let tris = mesh.world_tris();
+	assert_eq!(tris.len(), 12);
+	assert!(tris[5].b.dist(&mesh.triangle(5).transformed(&mesh.anchor, &rot).b) == 0.0);
+}
+
+#[test]
+fn texture_footprints() {
+	// A checkerboard of single texels on a 2 x 2 quad, looked straight down on
+	let (black, white) = (Color { r: 0, g: 0, b: 0 }, Color { r: 255, g: 255, b: 255 });
+	let checker = Texture::new(64, 64, (0..64 * 64).map(|i| if (i % 64 + i / 64) % 2 == 0 { black } else { white }).collect(), String::new());
+	let corner = |x: f64, y: f64| Vec3 { x, y, z: 0.0 };
+	let geometry = MeshGeometry::new(
+		vec![corner(-1.0, -1.0), corner(1.0, -1.0), corner(1.0, 1.0), corner(-1.0, 1.0)],
+		Vec::new(),
+		vec![(0.0, 1.0), (1.0, 1.0), (1.0, 0.0), (0.0, 0.0)],
+		vec![[0, 1, 2], [0, 2, 3]],
+		vec![0, 0],
+	);
+	let mut quad = Mesh::instance(corner(0.0, 0.0), Rot3::new(), Arc::new(geometry), vec![Material { color: white, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 }]);
+	quad.texture = Some(Arc::new(checker));
+	let seen_from = |height: f64| {
+		let scene = Scene::new(Vec::new(), Camera::new(Vec3 { x: 0.01, y: 0.01, z: height }, Rot3::new(), 40.0));
+		let ray = scene.primary_ray(80, 45, (0.5, 0.5), 160, 90);
+		let point_sampled = Ray { differential: None, ..scene.primary_ray(80, 45, (0.5, 0.5), 160, 90) };
+		(quad.ray_hit(&ray).unwrap().1.color, quad.ray_hit(&point_sampled).unwrap().1.color)
+	};
+	// Up close a pixel covers less than a texel, far away a pixel covers many and sees their average
+	let (near, _) = seen_from(0.5);
+	assert!(near == black || near == white);
+	let (far, point_sampled) = seen_from(100.0);
+	assert!((100..156).contains(&far.r), "{:?}", far);
+	assert!(point_sampled == black || point_sampled == white);
+
+	// Off a flat mirror the spread carries on growing as if the surface weren't there
+	let scene = Scene::new(Vec::new(), Camera::new(Vec3 { x: 0.0, y: 0.0, z: 2.0 }, Rot3::new(), 40.0));
+	let ray = scene.primary_ray(80, 45, (0.5, 0.5), 160, 90);
+	let differential = ray.differential.unwrap();
+	let up = Vec3 { x: 0.0, y: 0.0, z: 1.0 };
+	let hit = plane_hit(&ray.origin, &ray.direction, &corner(0.0, 0.0), &up).unwrap();
+	let (on_mirror, _) = differential.footprint(&hit, &up);
+	let mirrored = ray.direction.sub(&up.mul(2.0 * Vec3::dot(&ray.direction, &up)));
+	let bounced = differential.reflect(&ray.direction, &hit, &up, &mirrored);
+	let back_up = bounced.footprint(&hit.add(&mirrored.mul(2.0 / mirrored.z)), &up).0;
+	assert!((Vec3::dot(&back_up, &back_up).sqrt() - 2.0 * Vec3::dot(&on_mirror, &on_mirror).sqrt()).abs() < 1e-9);
+	// Glass bends it, and from inside at a grazing angle there's nothing to bend
+	assert!(differential.refract(&ray.direction, &hit, &up, 1.5, &ray.direction).is_some());
+	let grazing = Vec3 { x: 1.0, y: 0.0, z: 0.2 }.normalize();
+	assert!(differential.refract(&grazing, &hit, &up, 1.5, &grazing).is_none());
+}
+
+#[test]
+fn shadow_terminator() {
+	// One facet of a ball around the origin, normals pointing out from its center
+	let corners = vec![Vec3 { x: 1.0, y: 0.0, z: 1.0 }, Vec3 { x: -0.5, y: 0.866, z: 1.0 }, Vec3 { x: -0.5, y: -0.866, z: 1.0 }];
+	let normals = corners.iter().map(|corner| corner.normalize()).collect();
+	let material = Material { color: Color { r: 255, g: 255, b: 255 }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+	let geometry = MeshGeometry::new(corners.clone(), normals, Vec::new(), vec![[0, 1, 2]], vec![0]);
+	let smooth = Mesh::instance(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, Rot3::new(), Arc::new(geometry), vec![material]);
+	let flat = Mesh::new(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, Rot3::new(), vec![Tri { a: corners[0], b: corners[1], c: corners[2], mat: material }]);
+	let down = |x: f64, y: f64| Ray { origin: Vec3 { x, y, z: 5.0 }, direction: Vec3 { x: 0.0, y: 0.0, z: -1.0 }, mask: RayMask::ALL, differential: None };
+
+	// The hit stays on the facet, along the ray
+	let (middle, _, _) = smooth.ray_hit(&down(0.0, 0.0)).unwrap();
+	assert!((middle.z - 1.0).abs() < 1e-9, "{:?}", middle);
+	// Shadow rays leave lifted off it towards the ball in the middle, not at all at the corners
+	let lifted = smooth.shadow_origin(&down(0.0, 0.0), &middle);
+	assert!(lifted.z > 1.3 && lifted.z < 1.6 && lifted.x.abs() < 1e-5, "{:?}", lifted);
+	let near_corner = smooth.ray_hit(&down(0.999, 0.0)).unwrap().0;
+	assert!(smooth.shadow_origin(&down(0.999, 0.0), &near_corner).z - 1.0 < 1e-3);
+	// Flat shaded meshes stay where they are
+	assert!((flat.shadow_origin(&down(0.0, 0.0), &middle).z - 1.0).abs() < 1e-9);
+}
+
+#[test]
+fn ray_packets() {
+	let mut scene = Scene::default_scene();
+	scene.render_settings.samples_per_pixel = 2;
+	scene.update_culling(40, 20);
+	let mut scratch = Scratch::new(&scene.render_settings);
+	let mut hit = 0;
+	for y in (0..20).step_by(2) {
+		for x in (0..40).step_by(2) {
+			// One lane left empty
+			let indices = [Some(y * 40 + x), None, Some((y + 1) * 40 + x), Some((y + 1) * 40 + x + 1)];
+			for (index, result) in indices.iter().zip(scene.cast_ray_packet(indices, 40, 20, &mut scratch).iter()) {
+				assert_eq!(*result, index.map(|index| scene.cast_ray_with(index, 40, 20, &mut scratch)));
+				hit += usize::from(result.is_some_and(|(_, alpha)| alpha > 0));
+			}
+		}
+	}
+	assert!(hit > 0);
+}
+
+#[test]
+fn moving_objects_refit_the_top_level() {
+	let mut scene = Scene::default_scene();
+	scene.update_culling(40, 20);
+	let mut rng = Rng::new(5);
+	let mut check = |scene: &Scene| {
+		let mut hits = 0;
+		for _ in 0..200 {
+			let mut point = || Vec3 { x: rng.next_f64() * 16.0 - 8.0, y: rng.next_f64() * 16.0 - 8.0, z: rng.next_f64() * 8.0 - 2.0 };
+			let ray = Ray::from_to(&point(), &point());
+			let hit = |hit: Option<ObjectHit>| hit.map(|(index, (point, _, _))| (index, point.x, point.y, point.z));
+			assert_eq!(hit(scene.trace_object(&ray)), hit(scene.closest_hit(&ray, 0..scene.objects.len())));
+			hits += usize::from(scene.trace_object(&ray).is_some());
+		}
+		assert!(hits > 20);
+	};
+	let place = |scene: &mut Scene, x: f64| scene.find_object("fuckin' cube").unwrap().set_transform(Vec3 { x, y: 0.0, z: 0.0 }, Rot3 { yaw: x, pitch: 0.0, roll: 0.0 });
+	place(&mut scene, 0.5);
+	scene.update_culling(40, 20);
+	assert_eq!(scene.hierarchy.as_ref().unwrap().refits, 1);
+	check(&scene);
+	// Far off, the refit tree would be all gaps
+	place(&mut scene, 300.0);
+	scene.update_culling(40, 20);
+	assert_eq!(scene.hierarchy.as_ref().unwrap().refits, 0);
+	check(&scene);
+}
+
+#[test]
+fn waving_meshes_refit() {
+	// A flag in the xz plane, waved one vertex at a time
+	let material = Material { color: Color { r: 255, g: 255, b: 255 }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+	let corner = |x: usize, z: usize| Vec3 { x: x as f64 * 0.5, y: 0.0, z: z as f64 * 0.5 };
+	let mut tris = Vec::new();
+	for x in 0..8 {
+		for z in 0..4 {
+			tris.push(Tri { a: corner(x, z), b: corner(x + 1, z), c: corner(x + 1, z + 1), mat: material });
+			tris.push(Tri { a: corner(x, z), b: corner(x + 1, z + 1), c: corner(x, z + 1), mat: material });
+		}
+	}
+	let mut scene = Scene::new(vec![Box::new(Mesh::new(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, Rot3::new(), tris))], Camera::new(Vec3 { x: 2.0, y: -10.0, z: 1.0 }, Rot3::new(), 40.0));
+	let ray = Ray::from_to(&Vec3 { x: 3.05, y: -5.0, z: 1.2 }, &Vec3 { x: 3.05, y: 0.0, z: 1.2 });
+	assert!(scene.trace(&ray).unwrap().0.y.abs() < 1e-9);
+	let mesh = scene.get_all_meshes().pop().unwrap();
+	let items = mesh.geometry.bvh.items().to_vec();
+	for vertex in 0..mesh.geometry.positions.len() {
+		let position = mesh.geometry.positions[vertex];
+		mesh.set_vertex(vertex, Vec3 { y: (position.x * 2.0).sin(), ..position });
+	}
+	assert!(mesh.is_dirty());
+	scene.update_culling(40, 20);
+	let mesh = scene.get_all_meshes().pop().unwrap();
+	assert!(!mesh.is_dirty() && mesh.geometry.bvh.items() == items.as_slice());
+	// Hits follow the cloth, well off where it was
+	let (point, _, _) = scene.trace(&ray).unwrap();
+	let wave = |x: f64| (x * 2.0).sin();
+	assert!(point.y < -0.1 && point.y >= wave(3.0).min(wave(3.5)) - 1e-9 && point.y <= wave(3.0).max(wave(3.5)) + 1e-9);
+	let mesh = scene.get_all_meshes().pop().unwrap();
+	mesh.set_triangle_corners(0, [Vec3 { x: 0.0, y: -1.0, z: 0.0 }, Vec3 { x: 0.5, y: -1.0, z: 0.0 }, Vec3 { x: 0.5, y: -1.0, z: 0.5 }]);
+	scene.update_culling(40, 20);
+	assert!(scene.trace(&Ray::from_to(&Vec3 { x: 0.4, y: -5.0, z: 0.1 }, &Vec3 { x: 0.4, y: 0.0, z: 0.1 })).unwrap().0.y + 1.0 < 1e-9);
+}
+
+#[test]
+fn rough_reflections_blur() {
+	// Looking up from the floor at a fine checkerboard, lit only from behind it so all there is to
+	// see is the reflection
+	let (black, white) = (Color { r: 0, g: 0, b: 0 }, Color { r: 255, g: 255, b: 255 });
+	let checker = Texture::new(64, 64, (0..64 * 64).map(|i| if (i % 64 + i / 64) % 2 == 0 { black } else { white }).collect(), String::new());
+	let corner = |x: f64, y: f64| Vec3 { x, y, z: 2.0 };
+	let geometry = MeshGeometry::new(
+		vec![corner(-8.0, -8.0), corner(8.0, -8.0), corner(8.0, 8.0), corner(-8.0, 8.0)],
+		Vec::new(),
+		vec![(0.0, 1.0), (1.0, 1.0), (1.0, 0.0), (0.0, 0.0)],
+		vec![[0, 1, 2], [0, 2, 3]],
+		vec![0, 0],
+	);
+	let mut ceiling = Mesh::instance(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, Rot3::new(), Arc::new(geometry), vec![Material { color: white, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 }]);
+	ceiling.texture = Some(Arc::new(checker));
+	let light = LightSource::new(Vec3 { x: 0.0, y: 0.0, z: 3.0 }, Rot3::new(), 1.0);
+	let mut scene = Scene::new(vec![Box::new(ceiling), Box::new(light)], Camera::new(Vec3 { x: 0.0, y: -0.5, z: 1.0 }, Rot3::new(), 40.0));
+	let floor = |scene: &Scene, roughness: f32, x: f64| {
+		let point = Vec3 { x, y: 0.01, z: 0.0 };
+		let ray = Ray::from_to(&scene.current_camera.pos, &point);
+		let material = Material { color: white, reflectivity: 0.5, transparency: 0.0, roughness, ior: 1.5 };
+		let mut scratch = Scratch::new(&scene.render_settings);
+		scene.shade_hit(&ray, (0, (point, material, Vec3 { x: 0.0, y: 0.0, z: 1.0 })), &mut scratch).r
+	};
+	// A mirror sees one square, a step along that moves its reflection a square over sees the other.
+	// Either way a rough floor sees them averaged
+	let (mirror, next) = (floor(&scene, 0.0, 0.01), floor(&scene, 0.0, 0.01 + 16.0 / 64.0 / 3.0));
+	let cone = floor(&scene, 0.6, 0.01);
+	scene.render_settings.glossy_samples = 64;
+	let sampled = floor(&scene, 0.6, 0.01);
+	assert_eq!(floor(&scene, 0.0, 0.01), mirror);
+	let (dark, bright) = (mirror.min(next), mirror.max(next));
+	assert!(bright - dark > 40, "{} {}", dark, bright);
+	for blurred in [cone, sampled] {
+		assert!((dark + 10..bright - 10).contains(&blurred), "{} {}", cone, sampled);
+	}
+}
+
+#[test]
+fn mirrors_reflect_what_they_face() {
+	// A red ball seen in a mirror floor
+	let mirror = Material { color: Color { r: 0, g: 0, b: 0 }, reflectivity: 1.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+	let red = Material { color: Color { r: 255, g: 0, b: 0 }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+	let quad = |a: Vec3, b: Vec3, c: Vec3, d: Vec3| Mesh::new(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, Rot3::new(), vec![Tri { a, b, c, mat: mirror }, Tri { a, b: c, c: d, mat: mirror }]);
+	let floor = quad(Vec3 { x: -5.0, y: -5.0, z: 0.0 }, Vec3 { x: 5.0, y: -5.0, z: 0.0 }, Vec3 { x: 5.0, y: 5.0, z: 0.0 }, Vec3 { x: -5.0, y: 5.0, z: 0.0 });
+	let ball = Sphere::new(Vec3 { x: 0.0, y: 0.0, z: 1.0 }, 0.5, red);
+	let light = LightSource::new(Vec3 { x: 0.0, y: -3.0, z: 3.0 }, Rot3::new(), 2.0);
+	let mut scene = Scene::new(vec![Box::new(floor), Box::new(ball), Box::new(light)], Camera::new(Vec3 { x: 0.0, y: -4.0, z: 1.0 }, Rot3::new(), 40.0));
+	// Down at the floor in front of the ball, whose mirror image is straight ahead
+	let ray = Ray::from_to(&Vec3 { x: 0.0, y: -4.0, z: 1.0 }, &Vec3 { x: 0.0, y: -0.5, z: -0.5 });
+	let shade = |scene: &Scene| scene.shade(&ray, &mut Scratch::new(&scene.render_settings)).unwrap();
+	let reflected = shade(&scene);
+	assert!(reflected.r > reflected.g + 30, "{:?}", reflected);
+
+	// Nothing without bounces, or off a floor that doesn't reflect
+	scene.render_settings.max_bounces = 0;
+	let unreflected = shade(&scene);
+	assert_eq!(unreflected.r, unreflected.g);
+	scene.render_settings.max_bounces = 1;
+	scene.get_all_meshes()[0].materials[0].reflectivity = 0.0;
+	assert_eq!(shade(&scene), unreflected);
+	assert!(reflect(&Vec3 { x: 1.0, y: 0.0, z: -1.0 }, &Vec3 { x: 0.0, y: 0.0, z: 1.0 }).dist(&Vec3 { x: 1.0, y: 0.0, z: 1.0 }) < 1e-12);
+	let tri = Tri { a: Vec3 { x: 0.0, y: 0.0, z: 0.0 }, b: Vec3 { x: 2.0, y: 0.0, z: 0.0 }, c: Vec3 { x: 0.0, y: 3.0, z: 0.0 }, mat: red };
+	assert!(tri.normal().dist(&Vec3 { x: 0.0, y: 0.0, z: 1.0 }) < 1e-12);
+}
+
+#[test]
+fn reflections_see_the_first_thing_along_them() {
+	// A mirror wall reflecting back past a red ball and then a green one, the green one nearer the camera
+	let mirror = Material { color: Color { r: 0, g: 0, b: 0 }, reflectivity: 1.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+	let (a, b, c, d) = (Vec3 { x: -5.0, y: 4.0, z: -5.0 }, Vec3 { x: 5.0, y: 4.0, z: -5.0 }, Vec3 { x: 5.0, y: 4.0, z: 5.0 }, Vec3 { x: -5.0, y: 4.0, z: 5.0 });
+	let wall = Mesh::new(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, Rot3::new(), vec![Tri { a, b, c, mat: mirror }, Tri { a, b: c, c: d, mat: mirror }]);
+	let red = Sphere::new(Vec3 { x: 0.0, y: -1.0, z: -1.0 }, 0.4, Material { color: Color { r: 255, g: 0, b: 0 }, reflectivity: 0.0, ..mirror });
+	let green = Sphere::new(Vec3 { x: 0.0, y: -5.0, z: -1.8 }, 0.4, Material { color: Color { r: 0, g: 255, b: 0 }, reflectivity: 0.0, ..mirror });
+	let light = LightSource::new(Vec3 { x: 0.0, y: -3.0, z: 5.0 }, Rot3::new(), 20.0);
+	let eye = Vec3 { x: 0.0, y: -6.0, z: 2.0 };
+	let scene = Scene::new(vec![Box::new(wall), Box::new(red), Box::new(green), Box::new(light)], Camera::new(eye, Rot3::new(), 40.0));
+	let ray = Ray::from_to(&eye, &Vec3 { x: 0.0, y: 4.0, z: 0.0 });
+	let seen = scene.shade(&ray, &mut Scratch::new(&scene.render_settings)).unwrap();
+	assert!(seen.r > seen.g + 30, "{:?}", seen);
+
+	// The same for any ray, wherever the camera is
+	let back = Ray::from_to(&Vec3 { x: 0.0, y: 4.0, z: 0.0 }, &Vec3 { x: 0.0, y: -6.0, z: -2.0 });
+	assert_eq!(scene.trace(&back).unwrap().1.color.r, 255);
+}
+
+#[test]
+fn glass_bends_what_is_seen_through_it() {
+	// A glass ball in front of a wall, red on the left and green on the right
+	let glass = Material { color: Color { r: 0, g: 0, b: 0 }, reflectivity: 0.0, transparency: 1.0, roughness: 0.0, ior: 1.5 };
+	let wall = |color: Color, x: f64| {
+		let mat = Material { color, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+		let (a, b, c, d) = (Vec3 { x: x - 3.0, y: 2.0, z: -3.0 }, Vec3 { x, y: 2.0, z: -3.0 }, Vec3 { x, y: 2.0, z: 3.0 }, Vec3 { x: x - 3.0, y: 2.0, z: 3.0 });
+		Mesh::new(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, Rot3::new(), vec![Tri { a, b, c, mat }, Tri { a, b: c, c: d, mat }])
+	};
+	let red = wall(Color { r: 255, g: 0, b: 0 }, 0.0);
+	let green = wall(Color { r: 0, g: 255, b: 0 }, 3.0);
+	let ball = Sphere::new(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, 0.5, glass);
+	let light = LightSource::new(Vec3 { x: 0.0, y: -3.0, z: 3.0 }, Rot3::new(), 10.0);
+	let mut scene = Scene::new(vec![Box::new(red), Box::new(green), Box::new(ball), Box::new(light)], Camera::new(Vec3 { x: 0.0, y: -4.0, z: 0.0 }, Rot3::new(), 40.0));
+	scene.render_settings.max_bounces = 2;
+	// Into the ball left of its middle, where it works as a lens and turns what's behind it around
+	let ray = Ray::from_to(&Vec3 { x: 0.0, y: -4.0, z: 0.0 }, &Vec3 { x: -0.3, y: 0.0, z: 0.0 });
+	let shade = |scene: &Scene| scene.shade(&ray, &mut Scratch::new(&scene.render_settings)).unwrap();
+	let bent = shade(&scene);
+	assert!(bent.g > bent.r + 30, "{:?}", bent);
+
+	// Without bending it sees straight through, and without going back out only the inside of the ball
+	for object in scene.objects.iter_mut() {
+		if let Some(mut material) = object.get_material().filter(|material| material.transparency > 0.0) {
+			material.ior = 1.0;
+			object.set_material(material);
+		}
+	}
+	let straight = shade(&scene);
+	assert!(straight.r > straight.g + 30, "{:?}", straight);
+	scene.render_settings.max_bounces = 1;
+	let inside = shade(&scene);
+	assert_eq!(inside.r, inside.g);
+
+	// Snell's law going in, and reflected entirely past the critical angle going out
+	let normal = Vec3 { x: 0.0, y: 0.0, z: 1.0 };
+	let incident = Vec3 { x: 1.0, y: 0.0, z: -1.0 }.normalize();
+	let inside = refract(&incident, &normal, 1.5).unwrap();
+	assert!((inside.x - std::f64::consts::FRAC_1_SQRT_2 / 1.5).abs() < 1e-12 && inside.z < 0.0);
+	assert!((Vec3::dot(&inside, &inside) - 1.0).abs() < 1e-12);
+	assert!(refract(&inside.mul(-1.0), &normal, 1.5).unwrap().dist(&incident.mul(-1.0)) < 1e-12);
+	assert!(refract(&Vec3 { x: 1.0, y: 0.0, z: 0.5 }.normalize(), &normal, 1.5).is_none());
+}
+
+#[test]
+fn light_through_glass_sees_the_first_thing_along_it() {
+	// A right angled glass prism, long side towards the camera, sending what goes in back out beside
+	// it after reflecting inside off both short sides. The ends are left open, nothing reaches them
+	let glass = Material { color: Color { r: 0, g: 0, b: 0 }, reflectivity: 0.0, transparency: 1.0, roughness: 0.0, ior: 1.5 };
+	let corners = [Vec3 { x: -1.0, y: 0.0, z: 0.0 }, Vec3 { x: 1.0, y: 0.0, z: 0.0 }, Vec3 { x: 0.0, y: 1.0, z: 0.0 }];
+	let mut tris = Vec::new();
+	for (from, to) in [(0, 1), (1, 2), (2, 0)] {
+		let (bottom, top) = (Vec3 { x: 0.0, y: 0.0, z: -1.0 }, Vec3 { x: 0.0, y: 0.0, z: 1.0 });
+		let (a, b, c, d) = (corners[from].add(&bottom), corners[to].add(&bottom), corners[to].add(&top), corners[from].add(&top));
+		// Wound to face out, away from the prism's middle
+		let outward = a.add(&b).mul(0.5).sub(&Vec3 { x: 0.0, y: 1.0 / 3.0, z: -1.0 });
+		let (b, d) = if Vec3::dot(&Vec3::cross(&b.sub(&a), &c.sub(&a)), &outward) > 0.0 { (b, d) } else { (d, b) };
+		tris.extend([Tri { a, b, c, mat: glass }, Tri { a, b: c, c: d, mat: glass }]);
+	}
+	let prism = Mesh::new(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, Rot3::new(), tris);
+	// On the way back a red ball, then a green one nearer the camera
+	let red = Sphere::new(Vec3 { x: 0.5, y: -2.0, z: 0.3 }, 0.3, Material { color: Color { r: 255, g: 0, b: 0 }, transparency: 0.0, ..glass });
+	let green = Sphere::new(Vec3 { x: 0.5, y: -9.0, z: 0.3 }, 0.3, Material { color: Color { r: 0, g: 255, b: 0 }, transparency: 0.0, ..glass });
+	let light = LightSource::new(Vec3 { x: 0.0, y: -5.0, z: 5.0 }, Rot3::new(), 8.0);
+	let eye = Vec3 { x: -0.5, y: -10.0, z: 0.3 };
+	let mut scene = Scene::new(vec![Box::new(prism), Box::new(red), Box::new(green), Box::new(light)], Camera::new(eye, Rot3::new(), 40.0));
+	// In, off both sides and out again
+	scene.render_settings.max_bounces = 4;
+	let ray = Ray::from_to(&eye, &Vec3 { x: -0.5, y: 0.0, z: 0.3 });
+	let seen = scene.shade(&ray, &mut Scratch::new(&scene.render_settings)).unwrap();
+	assert!(seen.r > seen.g + 30, "{:?}", seen);
+}
+
+#[test]
+fn grazing_angles_reflect_more() {
+	let up = Vec3 { x: 0.0, y: 0.0, z: 1.0 };
+	let down = Vec3 { x: 0.0, y: 0.0, z: -1.0 };
+	let grazing = Vec3 { x: 1.0, y: 0.0, z: -0.05 }.normalize();
+	let glass = Material { color: Color { r: 0, g: 0, b: 0 }, reflectivity: 0.0, transparency: 1.0, roughness: 0.0, ior: 1.5 };
+	assert!((glass.f0() - 0.04).abs() < 1e-12);
+
+	// Glass is nearly all seen through straight on and mostly a mirror at a glance, what isn't
+	// reflected going through
+	let (reflected, transmitted) = glass.fresnel_weights(&down, &up);
+	assert!((reflected - 0.04).abs() < 1e-12 && (transmitted - 0.96).abs() < 1e-12);
+	let (reflected, transmitted) = glass.fresnel_weights(&grazing, &up);
+	assert!(reflected > 0.6 && (reflected + transmitted - 1.0).abs() < 1e-12);
+	// From inside, past the critical angle, it's all mirrored
+	assert_eq!(glass.fresnel_weights(&Vec3 { x: 1.0, y: 0.0, z: 0.5 }.normalize(), &up), (1.0, 0.0));
+	assert!((glass.fresnel_weights(&up, &up).0 - 0.04).abs() < 1e-12);
+
+	// Opaque materials reflect as much as they say straight on, more at a glance, and nothing if they don't
+	let metal = Material { reflectivity: 0.5, transparency: 0.0, ..glass };
+	assert_eq!(metal.fresnel_weights(&down, &up), (0.5, 0.0));
+	assert!(metal.fresnel_weights(&grazing, &up).0 > 0.85);
+	assert_eq!(Material { reflectivity: 0.0, ..metal }.fresnel_weights(&grazing, &up), (0.0, 0.0));
+	assert_eq!(MaterialParam::Ior.adjust(&glass, 40.0).ior, 3.0);
+}
+
+#[test]
+fn flattened_triangles_hit_like_the_authored_ones() {
+	use crate::random::Rng;
+	let mut rng = Rng::new(13);
+	let mut point = || Vec3 { x: rng.next_f64() * 8.0 - 4.0, y: rng.next_f64() * 8.0 - 4.0, z: rng.next_f64() * 2.0 };
+	let positions: Vec<Vec3> = (0..300).map(|_| point()).collect();
+	let indices: Vec<[u32; 3]> = (0..100).map(|i| [i * 3, i * 3 + 1, i * 3 + 2]).collect();
+	let materials: Vec<Material> = (0..4).map(|i| Material { color: Color { r: i * 60, g: 0, b: 0 }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 }).collect();
+	let geometry = MeshGeometry::new(positions, Vec::new(), Vec::new(), indices, (0..100).map(|i| i % 4).collect());
+	let mesh = Mesh::instance(Vec3 { x: 1.0, y: -0.5, z: 0.2 }, Rot3 { yaw: 0.4, pitch: 0.1, roll: 0.0 }, Arc::new(geometry), materials);
+	let world = mesh.world_triangles();
+	let (tris, order) = (mesh.world_tris(), mesh.geometry.bvh.items());
+	assert_eq!(world.tris.len(), tris.len());
+	// Slots follow the hierarchy's leaves
+	for slot in 0..world.tris.len() {
+		let (flat, authored) = (world.tris.get(slot), PreparedTri::new(&tris[order[slot] as usize]));
+		assert!(flat.a.dist(&authored.a) == 0.0 && flat.edge2.dist(&authored.edge2) == 0.0 && flat.normal.dist(&authored.normal) == 0.0);
+		assert_eq!(world.tris.materials[slot], order[slot] % 4);
+	}
+	for _ in 0..200 {
+		let ray = Ray::from_to(&point().add(&Vec3 { x: 0.0, y: 0.0, z: 6.0 }), &point());
+		let nearest = tris.iter().filter_map(|tri| tri.ray_hit_barycentric(&ray).map(|(point, _, _)| (ray.origin.dist(&point), tri.mat.color.r)))
+			.filter(|(distance, _)| *distance > 0.01)
+			.fold(None, |nearest: Option<(f64, u8)>, hit| if nearest.is_some_and(|nearest| nearest.0 <= hit.0) { nearest } else { Some(hit) });
+		assert_eq!(mesh.ray_hit(&ray).map(|(point, material, _)| (ray.origin.dist(&point), material.color.r)), nearest);
+	}
+}
+
+#[test]
+fn shadows_only_come_from_before_the_light() {
+	let white = Material { color: Color { r: 255, g: 255, b: 255 }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+	let origin = Vec3 { x: 0.0, y: 0.0, z: 0.0 };
+	let camera = Camera::new(Vec3 { x: 0.0, y: -5.0, z: 0.0 }, Rot3::new(), 40.0);
+	let objects: Vec<Box<dyn SceneObject>> = vec![
+		Box::new(LightSource::new(Vec3 { x: 0.0, y: 0.0, z: 5.0 }, Rot3::new(), 1.0)),
+		Box::new(Sphere::new(Vec3 { x: 0.0, y: 0.0, z: 10.0 }, 1.0, white)),
+	];
+	let mut scene = Scene::new(objects, camera);
+	let mut scratch = Scratch::new(&scene.render_settings);
+	let visibility = |scene: &Scene, scratch: &mut Scratch| {
+		let light = scene.light_sources().next().unwrap();
+		scene.light_visibility(&origin, light, RayMask::ALL.layers, scratch)
+	};
+	// A ball past the light casts no shadow back towards the point, whatever the light's size
+	assert_eq!(visibility(&scene, &mut scratch), 1.0);
+	scene.get_all_light_sources()[0].radius = 0.5;
+	assert_eq!(visibility(&scene, &mut scratch), 1.0);
+
+	// One before it does
+	scene.objects.push(Box::new(Sphere::new(Vec3 { x: 0.0, y: 0.0, z: 2.5 }, 1.0, white)));
+	assert_eq!(visibility(&scene, &mut scratch), 0.0);
+}
+
+#[test]
+fn dim_lights_add_up() {
+	let white = Material { color: Color { r: 255, g: 255, b: 255 }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+	let (a, b, c, d) = (Vec3 { x: -5.0, y: -5.0, z: 0.0 }, Vec3 { x: 5.0, y: -5.0, z: 0.0 }, Vec3 { x: 5.0, y: 5.0, z: 0.0 }, Vec3 { x: -5.0, y: 5.0, z: 0.0 });
+	let floor = Mesh::new(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, Rot3::new(), vec![Tri { a, b, c, mat: white }, Tri { a, b: c, c: d, mat: white }]);
+	let mut camera = Camera::new(Vec3 { x: 0.0, y: -0.01, z: 2.0 }, Rot3::new(), 40.0);
+	camera.look_at(&Vec3 { x: 0.0, y: 0.0, z: 0.0 }, &Vec3 { x: 0.0, y: 0.0, z: 1.0 });
+	// Forty lights high up, each of them worth half a step of 8 bit color at most
+	let mut objects: Vec<Box<dyn SceneObject>> = vec![Box::new(floor)];
+	for i in 0..40 {
+		let angle = i as f64 / 40.0 * std::f64::consts::TAU;
+		objects.push(Box::new(LightSource::new(Vec3 { x: angle.cos(), y: angle.sin(), z: 32.0 }, Rot3::new(), 1.0)));
+	}
+	let mut scene = Scene::new(objects, camera);
+	let center = scene.render(4, 4)[2 * 4 + 2];
+	assert!(center.r > 10 && center.r < 25, "{:?}", center);
+}
+
+#[test]
+fn every_render_uses_the_integrator() {
+	let mut scene = Scene::default_scene();
+	scene.render_settings.samples_per_pixel = 2;
+	scene.render_settings.photons_per_pass = 2000;
+	let (width, height) = (16, 9);
+	let mut images = Vec::new();
+	for integrator in [Integrator::Direct, Integrator::Sppm, Integrator::Path] {
+		scene.render_settings.integrator = integrator;
+		let framebuffer = scene.render_framebuffer(width, height).color;
+		assert_eq!(scene.render(width, height), framebuffer, "{:?}", integrator);
+		// The viewer's pixel at a time
+		scene.update_culling(width, height);
+		let mut scratch = Scratch::new(&scene.render_settings);
+		let pixels: Vec<Color> = (0..(width * height) as u64).map(|index| scene.cast_ray_samples(index, width, height, 0, &mut scratch).0).collect();
+		assert_eq!(pixels, framebuffer, "{:?}", integrator);
+		images.push(framebuffer);
+	}
+	assert_ne!(images[0], images[1]);
+	assert_ne!(images[0], images[2]);
+}
@@ -0,0 +1,196 @@
+//
+//	Terminal Frontend
+//
+
+use raytracing_engine::structs::Color;
+use raytracing_engine::input::{ButtonState, Key};
+use raytracing_engine::frontend::{InputSource, Presenter};
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+use crate::ExampleProgram;
+
+const ASCII_RAMP: &[u8] = b" .:-=+*#%@";
+const FRAME_TIME: Duration = Duration::from_millis(33);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TerminalMode {
+	HalfBlock, // two pixels per cell in 24-bit color
+	Ascii, // luminance ramp, for terminals without color
+}
+
+// Terminals only report key presses, so a key counts as held for the frame it arrived in
+struct TerminalInput {
+	keys: Vec<Key>,
+	shift: bool,
+}
+
+impl InputSource for TerminalInput {
+	fn key_state(&self, key: Key) -> ButtonState {
+		let down = self.keys.contains(&key) || (key == Key::Shift && self.shift);
+		ButtonState { pressed: down, held: down, released: false }
+	}
+
+	fn mouse_position(&self) -> (i32, i32) { (0, 0) }
+	fn mouse_button(&self, _button: u32) -> ButtonState { ButtonState::default() }
+	fn mouse_wheel(&self) -> i32 { 0 }
+}
+
+impl TerminalInput {
+	// Decodes the bytes typed since the last frame, returns false on Ctrl-C / Escape
+	fn parse(bytes: &[u8]) -> (TerminalInput, bool) {
+		let mut input = TerminalInput { keys: Vec::new(), shift: false };
+		let mut i = 0;
+		while i < bytes.len() {
+			let byte = bytes[i];
+			i += 1;
+			let key = match byte {
+				3 => return (input, false),
+				0x1b if bytes.get(i) == Some(&b'[') => {
+					let end = bytes[i..].iter().position(|b| b.is_ascii_alphabetic() || *b == b'~').map(|p| i + p);
+					let sequence = match end {
+						Some(end) => &bytes[i + 1..=end],
+						None => &bytes[i + 1..],
+					};
+					i = end.map(|end| end + 1).unwrap_or(bytes.len());
+					match sequence {
+						b"A" => Some(Key::Up),
+						b"B" => Some(Key::Down),
+						b"C" => Some(Key::Right),
+						b"D" => Some(Key::Left),
+						b"H" => Some(Key::Home),
+						b"F" => Some(Key::End),
+						b"2~" => Some(Key::Insert),
+						b"3~" => Some(Key::Delete),
+						b"5~" => Some(Key::PageUp),
+						b"6~" => Some(Key::PageDown),
+						_ => None,
+					}
+				}
+				0x1b => return (input, false),
+				b'\t' => Some(Key::Tab),
+				b'\r' | b'\n' => Some(Key::Return),
+				0x7f | 0x08 => Some(Key::Backspace),
+				b' ' => Some(Key::Space),
+				b'.' => Some(Key::Period),
+				b'+' => Some(Key::NumpadAdd),
+				b'-' => Some(Key::NumpadSub),
+				b'*' => Some(Key::NumpadMul),
+				b'/' => Some(Key::NumpadDiv),
+				b'A'..=b'Z' => {
+					input.shift = true;
+					Key::from_name(&(byte as char).to_string())
+				}
+				b'a'..=b'z' => Key::from_name(&(byte.to_ascii_uppercase() as char).to_string()),
+				b'0'..=b'9' => Key::from_name(&format!("K{}", byte as char)),
+				_ => None,
+			};
+			if let Some(key) = key {
+				input.keys.push(key);
+			}
+		}
+		(input, true)
+	}
+}
+
+struct TerminalPresenter {
+	pixels: Vec<Color>,
+	width: usize,
+	height: usize,
+	mode: TerminalMode,
+}
+
+impl Presenter for TerminalPresenter {
+	fn draw(&mut self, x: i32, y: i32, color: Color) {
+		if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height { return }
+		self.pixels[y as usize * self.width + x as usize] = color;
+	}
+}
+
+impl TerminalPresenter {
+	fn pixel(&self, x: usize, y: usize) -> Color {
+		if y < self.height { self.pixels[y * self.width + x] } else { Color { r: 0, g: 0, b: 0 } }
+	}
+
+	// One frame worth of escape codes, cursor homed so frames overwrite each other
+	fn frame(&self) -> String {
+		let mut out = String::from("\x1b[H");
+		for row in (0..self.height).step_by(2) {
+			for x in 0..self.width {
+				let (top, bottom) = (self.pixel(x, row), self.pixel(x, row + 1));
+				match self.mode {
+					TerminalMode::HalfBlock => {
+						out += &format!("\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m\u{2580}", top.r, top.g, top.b, bottom.r, bottom.g, bottom.b);
+					}
+					TerminalMode::Ascii => {
+						// Cells are about twice as tall as wide, so each one covers two rows
+						let luminance = (luminance(top) + luminance(bottom)) / 2.0;
+						let index = ((luminance * ASCII_RAMP.len() as f32) as usize).min(ASCII_RAMP.len() - 1);
+						out.push(ASCII_RAMP[index] as char);
+					}
+				}
+			}
+			out += "\x1b[0m\r\n";
+		}
+		out
+	}
+}
+
+fn luminance(color: Color) -> f32 {
+	(0.2126 * color.r as f32 + 0.7152 * color.g as f32 + 0.0722 * color.b as f32) / 255.0
+}
+
+// Puts the terminal in raw mode through stty, returning the settings to restore afterwards
+fn enter_raw_mode() -> Option<String> {
+	let saved = Command::new("stty").arg("-g").stdin(Stdio::inherit()).output().ok()?;
+	if !saved.status.success() { return None }
+	Command::new("stty").args(["raw", "-echo"]).stdin(Stdio::inherit()).status().ok()?;
+	Some(String::from_utf8_lossy(&saved.stdout).trim().to_string())
+}
+
+fn leave_raw_mode(saved: &str) {
+	let _ = Command::new("stty").arg(saved).stdin(Stdio::inherit()).status();
+}
+
+fn spawn_stdin_reader() -> Receiver<Vec<u8>> {
+	let (sender, receiver) = mpsc::channel();
+	std::thread::spawn(move || {
+		let mut stdin = std::io::stdin();
+		let mut buffer = [0; 64];
+		while let Ok(read) = stdin.read(&mut buffer) {
+			if read == 0 || sender.send(buffer[..read].to_vec()).is_err() { return }
+		}
+	});
+	receiver
+}
+
+// Renders until Ctrl-C / Escape, or for a fixed number of frames when `frames` is set
+pub fn run(program: &mut ExampleProgram, width: usize, height: usize, mode: TerminalMode, frames: Option<u64>) {
+	let saved = if frames.is_none() { enter_raw_mode() } else { None };
+	let keys = spawn_stdin_reader();
+	let mut presenter = TerminalPresenter { pixels: vec![Color { r: 0, g: 0, b: 0 }; width * height], width, height, mode };
+	let mut stdout = std::io::stdout();
+	let _ = write!(stdout, "\x1b[2J\x1b[?25l");
+
+	let mut frame = 0;
+	let mut last_frame = Instant::now();
+	while frames.is_none_or(|frames| frame < frames) {
+		let (input, running) = TerminalInput::parse(&keys.try_iter().flatten().collect::<Vec<u8>>());
+		if !running { break }
+		let elapsed_time = last_frame.elapsed().as_secs_f32();
+		last_frame = Instant::now();
+		program.update(&mut presenter, &input, elapsed_time);
+		if write!(stdout, "{}", presenter.frame()).and_then(|_| stdout.flush()).is_err() { break }
+		frame += 1;
+		if let Some(rest) = FRAME_TIME.checked_sub(last_frame.elapsed()) {
+			std::thread::sleep(rest);
+		}
+	}
+
+	let _ = write!(stdout, "\x1b[0m\x1b[?25h\r\n");
+	let _ = stdout.flush();
+	if let Some(saved) = saved {
+		leave_raw_mode(&saved);
+	}
+}
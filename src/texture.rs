@@ -0,0 +1,113 @@
+//
+//	Textures
+//
+
+use crate::structs::Color;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+pub struct Texture {
+	pub width: usize,
+	pub height: usize,
+	pub pixels: Vec<Color>, // row by row, top row first
+	pub path: String, // where it was loaded from, empty for generated ones
+	mips: Vec<Mip>, // each half the size of the one before, down to a single texel
+}
+
+struct Mip {
+	width: usize,
+	height: usize,
+	pixels: Vec<Color>,
+}
+
+impl Texture {
+	pub fn new(width: usize, height: usize, pixels: Vec<Color>, path: String) -> Self {
+		let mut mips: Vec<Mip> = Vec::new();
+		let (mut above_width, mut above_height) = (width, height);
+		while above_width > 1 || above_height > 1 {
+			let above = mips.last().map_or(&pixels, |mip| &mip.pixels);
+			let (mip_width, mip_height) = ((above_width / 2).max(1), (above_height / 2).max(1));
+			// Each texel the average of the two by two it covers, just the one or two along a side that's run out
+			let pixels = (0..mip_width * mip_height).map(|index| {
+				let (x, y) = (index % mip_width * 2, index / mip_width * 2);
+				let mut sum = (0u32, 0u32, 0u32, 0u32);
+				for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+					if x + dx < above_width && y + dy < above_height {
+						let texel = above[(y + dy) * above_width + x + dx];
+						sum = (sum.0 + texel.r as u32, sum.1 + texel.g as u32, sum.2 + texel.b as u32, sum.3 + 1);
+					}
+				}
+				Color { r: ((sum.0 + sum.3 / 2) / sum.3) as u8, g: ((sum.1 + sum.3 / 2) / sum.3) as u8, b: ((sum.2 + sum.3 / 2) / sum.3) as u8 }
+			}).collect();
+			mips.push(Mip { width: mip_width, height: mip_height, pixels });
+			(above_width, above_height) = (mip_width, mip_height);
+		}
+		Texture { width, height, pixels, path, mips }
+	}
+
+	// Any png, palettes, grays and 16-bit images get converted to 8-bit color
+	pub fn load_png(path: &Path) -> io::Result<Texture> {
+		let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+		decoder.set_transformations(png::Transformations::normalize_to_color8());
+		let mut reader = decoder.read_info().map_err(io::Error::other)?;
+		let mut data = vec![0; reader.output_buffer_size()];
+		let frame = reader.next_frame(&mut data).map_err(io::Error::other)?;
+		let channels = frame.color_type.samples();
+		let pixels = data[..frame.buffer_size()].chunks(channels).map(|texel| match channels {
+			1 | 2 => Color { r: texel[0], g: texel[0], b: texel[0] },
+			_ => Color { r: texel[0], g: texel[1], b: texel[2] },
+		}).collect();
+		Ok(Texture::new(frame.width as usize, frame.height as usize, pixels, path.to_string_lossy().into_owned()))
+	}
+
+	// Nearest texel, u and v go from 0 to 1 left to right and top to bottom, clamped at the edges
+	pub fn sample(&self, u: f64, v: f64) -> Color {
+		nearest(&self.pixels, self.width, self.height, u, v)
+	}
+
+	// Averaged over roughly a footprint_u by footprint_v patch, sizes in uv units: nearest texels from
+	// the two mip levels either side of the patch's size, blended between
+	pub fn sample_footprint(&self, u: f64, v: f64, footprint_u: f64, footprint_v: f64) -> Color {
+		let texels = (footprint_u * self.width as f64).max(footprint_v * self.height as f64);
+		let level = if texels > 1.0 { texels.log2().min(self.mips.len() as f64) } else { 0.0 };
+		let at = |level: usize| match level {
+			0 => self.sample(u, v),
+			level => {
+				let mip = &self.mips[level - 1];
+				nearest(&mip.pixels, mip.width, mip.height, u, v)
+			}
+		};
+		let (below, blend) = (level.floor() as usize, level.fract());
+		if blend == 0.0 { return at(below) }
+		at(below).lerp(&at(below + 1), blend)
+	}
+}
+
+fn nearest(pixels: &[Color], width: usize, height: usize, u: f64, v: f64) -> Color {
+	let x = ((u * width as f64) as usize).min(width - 1);
+	let y = ((v * height as f64) as usize).min(height - 1);
+	pixels[y * width + x]
+}
+
+#[test]
+fn png_round_trip() {
+	let path = std::env::temp_dir().join(format!("texture_test_{}.png", std::process::id()));
+	let mut encoder = png::Encoder::new(File::create(&path).unwrap(), 2, 2);
+	encoder.set_color(png::ColorType::Rgb);
+	encoder.set_depth(png::BitDepth::Eight);
+	encoder.write_header().unwrap().write_image_data(&[255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255]).unwrap();
+	let texture = Texture::load_png(&path).unwrap();
+	std::fs::remove_file(&path).unwrap();
+	assert_eq!((texture.width, texture.height), (2, 2));
+	assert_eq!(texture.sample(0.0, 0.0), Color { r: 255, g: 0, b: 0 });
+	assert_eq!(texture.sample(0.9, 0.1), Color { r: 0, g: 255, b: 0 });
+	assert_eq!(texture.sample(-1.0, 2.0), Color { r: 0, g: 0, b: 255 });
+
+	// A footprint as big as the whole texture sees all four at once
+	assert_eq!(texture.sample_footprint(0.0, 0.0, 0.01, 0.01), Color { r: 255, g: 0, b: 0 });
+	assert_eq!(texture.sample_footprint(0.0, 0.0, 1.0, 1.0), Color { r: 128, g: 128, b: 128 });
+	// In between, somewhere between the texel and the average
+	let blended = texture.sample_footprint(0.9, 0.1, 0.7, 0.2);
+	assert!((1..128).contains(&blended.r) && (129..255).contains(&blended.g));
+}
@@ -0,0 +1,362 @@
+//
+//	Tiled Rendering
+//
+
+// Frames split into square tiles dealt out between worker threads, so every core traces at once. Each
+// thread works through its own queue and one that runs dry steals from the back of the fullest other,
+// so a thread that lands on cheap tiles just ends up doing more of them. Once a frame has been
+// timed the next is laid out by where the time went, costly regions like glass cut into small tiles
+// so none is left running long after the rest, cheap ones in bigger tiles that aren't worth splitting.
+// How long each tile took and how many rays it traced is kept as RenderStats, for finding what's slow.
+// The scene's RendererConfig says how many threads, how big the tiles and whether to hold back for
+// the rest of the application, see Priority
+
+use crate::render::Scratch;
+use crate::simd::LANES;
+use crate::structs::{thread_rays_traced, Scene};
+use std::collections::VecDeque;
+use std::sync::{Mutex, PoisonError};
+use std::time::Instant;
+
+pub const TILE_SIZE: usize = 16; // unless the config says otherwise
+const MIN_TILE_SIZE: usize = 4;
+const TILES_PER_THREAD: usize = 8; // enough that the last few to finish are short ones
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tile {
+	pub x: usize,
+	pub y: usize,
+	pub width: usize, // smaller than the tile size along the right and bottom edges
+	pub height: usize,
+}
+
+// Covering a width x height frame with size x size tiles, row by row
+pub fn tiles(width: usize, height: usize, size: usize) -> Vec<Tile> {
+	let size = size.max(1);
+	(0..height).step_by(size).flat_map(|y| (0..width).step_by(size).map(move |x| Tile {
+		x,
+		y,
+		width: size.min(width - x),
+		height: size.min(height - y),
+	})).collect()
+}
+
+// Laid out by costs, tiles twice size across quartered until each takes about as long as
+// TILES_PER_THREAD tiles per thread would, or they're down to MIN_TILE_SIZE. Costliest first, so
+// the long ones are started early. Plain tiles when costs is for another size or empty
+pub fn balanced_tiles(width: usize, height: usize, costs: &CostMap, threads: usize, size: usize) -> Vec<Tile> {
+	let total: f64 = costs.seconds.iter().sum();
+	if (costs.width, costs.height) != (width, height) || total <= 0.0 {
+		return tiles(width, height, size);
+	}
+	let target = total / (threads.max(1) * TILES_PER_THREAD) as f64;
+	let largest = (size * 2).max(MIN_TILE_SIZE);
+	let mut pending: Vec<Tile> = (0..height).step_by(largest).flat_map(|y| (0..width).step_by(largest).map(move |x| Tile {
+		x,
+		y,
+		width: largest.min(width - x),
+		height: largest.min(height - y),
+	})).collect();
+	let mut laid_out = Vec::new();
+	while let Some(tile) = pending.pop() {
+		let cost = costs.tile_cost(&tile);
+		if cost <= target || (tile.width <= MIN_TILE_SIZE && tile.height <= MIN_TILE_SIZE) {
+			laid_out.push((tile, cost));
+			continue;
+		}
+		// Halves at even offsets, so pixel packets never straddle two tiles
+		let split = |size: usize| if size > MIN_TILE_SIZE { vec![(0, (size + 2) / 4 * 2), ((size + 2) / 4 * 2, size - (size + 2) / 4 * 2)] } else { vec![(0, size)] };
+		for (y, height) in split(tile.height) {
+			for (x, width) in split(tile.width) {
+				pending.push(Tile { x: tile.x + x, y: tile.y + y, width, height });
+			}
+		}
+	}
+	laid_out.sort_by(|a, b| b.1.total_cmp(&a.1).then((a.0.y, a.0.x).cmp(&(b.0.y, b.0.x))));
+	laid_out.into_iter().map(|(tile, _)| tile).collect()
+}
+
+// Seconds spent on each pixel of a width x height frame, the last time it was traced
+#[derive(Debug, Clone, PartialEq)]
+pub struct CostMap {
+	pub width: usize,
+	pub height: usize,
+	pub seconds: Vec<f64>, // row by row
+}
+
+impl CostMap {
+	pub fn new(width: usize, height: usize) -> Self {
+		CostMap { width, height, seconds: vec![0.0; width * height] }
+	}
+
+	pub fn tile_cost(&self, tile: &Tile) -> f64 {
+		(tile.y..tile.y + tile.height).map(|y| self.seconds[y * self.width + tile.x..y * self.width + tile.x + tile.width].iter().sum::<f64>()).sum()
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileStats {
+	pub tile: Tile,
+	pub seconds: f64,
+	pub rays: u64,
+	pub thread: usize, // which of the render's threads did it, 0 being the calling one
+	pub stolen: bool, // taken from another thread's queue
+}
+
+// One render's tiles in the order they were dealt out
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderStats {
+	pub width: usize,
+	pub height: usize,
+	pub threads: usize,
+	pub seconds: f64, // start to finish, all threads together
+	pub tiles: Vec<TileStats>,
+}
+
+impl RenderStats {
+	pub fn rays(&self) -> u64 {
+		self.tiles.iter().map(|tile| tile.rays).sum()
+	}
+
+	pub fn stolen(&self) -> usize {
+		self.tiles.iter().filter(|tile| tile.stolen).count()
+	}
+
+	// The count tiles that took longest, slowest first
+	pub fn slowest(&self, count: usize) -> Vec<TileStats> {
+		let mut tiles = self.tiles.clone();
+		tiles.sort_by(|a, b| b.seconds.total_cmp(&a.seconds));
+		tiles.truncate(count);
+		tiles
+	}
+}
+
+// How much of the machine rendering takes, for when the tracer isn't the only thing running
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+	Interactive, // every thread flat out, for frames someone is waiting on
+	Background, // a core left free when the thread count is picked, and a yield after every tile
+}
+
+impl Priority {
+	pub fn from_name(name: &str) -> Option<Priority> {
+		match name {
+			"interactive" => Some(Priority::Interactive),
+			"background" => Some(Priority::Background),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RendererConfig {
+	pub threads: usize, // tracing in parallel, 0 for one per core
+	pub tile_size: usize, // pixels across, before costly regions are split finer
+	pub priority: Priority,
+}
+
+impl Default for RendererConfig {
+	fn default() -> Self {
+		RendererConfig { threads: 0, tile_size: TILE_SIZE, priority: Priority::Interactive }
+	}
+}
+
+impl RendererConfig {
+	// threads, or one per core when that's 0, less one in the background
+	pub fn thread_count(&self) -> usize {
+		match (self.threads, self.priority) {
+			(0, Priority::Interactive) => std::thread::available_parallelism().map_or(1, |threads| threads.get()),
+			(0, Priority::Background) => std::thread::available_parallelism().map_or(1, |threads| threads.get() - 1).max(1),
+			(threads, _) => threads,
+		}
+	}
+}
+
+// Calls shade for every pixel of a width x height frame that wanted lets through, spread over the
+// scene's render threads, each with its own scratch buffers. Comes back row by row, None for the
+// pixels left out
+pub fn render_tiles<T: Send>(
+	scene: &Scene,
+	width: usize,
+	height: usize,
+	wanted: impl Fn(usize, usize) -> bool + Sync,
+	shade: impl Fn(usize, usize, &mut Scratch) -> T + Sync,
+) -> Vec<Option<T>> {
+	render(scene, width, height, |tile, scratch, done| {
+		for y in tile.y..tile.y + tile.height {
+			for x in tile.x..tile.x + tile.width {
+				if wanted(x, y) {
+					done.push((y * width + x, shade(x, y, scratch)));
+				}
+			}
+		}
+	})
+}
+
+// Same, handing shade the pixels two by two so their rays can be traced as a packet. Lanes go
+// left to right then top to bottom, None past the frame's edge or where wanted said no
+pub fn render_tile_packets<T: Send>(
+	scene: &Scene,
+	width: usize,
+	height: usize,
+	wanted: impl Fn(usize, usize) -> bool + Sync,
+	shade: impl Fn([Option<(usize, usize)>; LANES], &mut Scratch) -> [Option<T>; LANES] + Sync,
+) -> Vec<Option<T>> {
+	render(scene, width, height, |tile, scratch, done| {
+		for y in (tile.y..tile.y + tile.height).step_by(2) {
+			for x in (tile.x..tile.x + tile.width).step_by(2) {
+				let pixels = [(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)]
+					.map(|(x, y)| Some((x, y)).filter(|_| x < tile.x + tile.width && y < tile.y + tile.height && wanted(x, y)));
+				if pixels.iter().all(Option::is_none) { continue }
+				for (pixel, value) in pixels.iter().zip(shade(pixels, scratch)) {
+					if let (Some((x, y)), Some(value)) = (pixel, value) {
+						done.push((y * width + x, value));
+					}
+				}
+			}
+		}
+	})
+}
+
+// Each of threads queues starts with every threads-th tile, so the costliest, laid out first, are shared
+// between them. A thread takes from the front of its own, then from the back of whichever other has
+// the most left, None once they're all empty. Comes back with whether it was stolen
+fn next_tile(queues: &[Mutex<VecDeque<usize>>], thread: usize) -> Option<(usize, bool)> {
+	let lock = |queue: usize| queues[queue].lock().unwrap_or_else(PoisonError::into_inner);
+	if let Some(tile) = lock(thread).pop_front() { return Some((tile, false)) }
+	loop {
+		let victim = (1..queues.len()).map(|offset| (thread + offset) % queues.len()).max_by_key(|queue| lock(*queue).len())?;
+		if let Some(tile) = lock(victim).pop_back() { return Some((tile, true)) }
+		if (0..queues.len()).all(|queue| lock(queue).is_empty()) { return None }
+	}
+}
+
+// Deals tiles out to the threads until there are none left, fill doing a tile's pixels. Each
+// tile's time is shared out over the pixels it did into scene.tile_costs, and kept whole along
+// with its rays in scene.render_stats
+fn render<T: Send>(scene: &Scene, width: usize, height: usize, fill: impl Fn(&Tile, &mut Scratch, &mut Vec<(usize, T)>) + Sync) -> Vec<Option<T>> {
+	let config = scene.renderer;
+	let threads = config.thread_count();
+	let tiles = match scene.tile_costs.read().unwrap_or_else(PoisonError::into_inner).as_ref() {
+		Some(costs) => balanced_tiles(width, height, costs, threads, config.tile_size),
+		None => tiles(width, height, config.tile_size),
+	};
+	let threads = threads.min(tiles.len()).max(1);
+	let queues: Vec<Mutex<VecDeque<usize>>> = (0..threads).map(|thread| Mutex::new((thread..tiles.len()).step_by(threads).collect())).collect();
+	let start = Instant::now();
+	let work = |thread: usize| {
+		let mut scratch = Scratch::new(&scene.render_settings);
+		let (mut done, mut seconds, mut stats) = (Vec::new(), Vec::new(), Vec::new());
+		while let Some((index, stolen)) = next_tile(&queues, thread) {
+			let (start, first, rays) = (Instant::now(), done.len(), thread_rays_traced());
+			fill(&tiles[index], &mut scratch, &mut done);
+			let elapsed = start.elapsed().as_secs_f64();
+			let each = elapsed / (done.len() - first).max(1) as f64;
+			seconds.extend(done[first..].iter().map(|(index, _)| (*index, each)));
+			stats.push((index, TileStats { tile: tiles[index], seconds: elapsed, rays: thread_rays_traced() - rays, thread, stolen }));
+			if config.priority == Priority::Background {
+				std::thread::yield_now();
+			}
+		}
+		(done, seconds, stats)
+	};
+	let mut image: Vec<Option<T>> = (0..width * height).map(|_| None).collect();
+	let (mut timed, mut tile_stats) = (Vec::new(), Vec::new());
+	std::thread::scope(|scope| {
+		let workers: Vec<_> = (1..threads).map(|thread| scope.spawn(move || work(thread))).collect();
+		// This thread does its share too
+		let mine = work(0);
+		let done = workers.into_iter().map(|worker| worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)));
+		for (done, seconds, stats) in std::iter::once(mine).chain(done) {
+			for (index, value) in done {
+				image[index] = Some(value);
+			}
+			timed.extend(seconds);
+			tile_stats.extend(stats);
+		}
+	});
+	scene.counters.add_render_time(start.elapsed());
+	tile_stats.sort_by_key(|(index, _)| *index);
+	let stats = RenderStats { width, height, threads, seconds: start.elapsed().as_secs_f64(), tiles: tile_stats.into_iter().map(|(_, stats)| stats).collect() };
+	*scene.render_stats.write().unwrap_or_else(PoisonError::into_inner) = Some(stats);
+	let mut costs = scene.tile_costs.write().unwrap_or_else(PoisonError::into_inner);
+	let costs = match costs.as_mut().filter(|costs| (costs.width, costs.height) == (width, height)) {
+		Some(costs) => costs,
+		None => costs.insert(CostMap::new(width, height)),
+	};
+	for (index, seconds) in timed {
+		costs.seconds[index] = seconds;
+	}
+	image
+}
+
+#[test]
+fn tiles_cover_the_frame_once() {
+	let tiles = tiles(40, 20, TILE_SIZE);
+	assert_eq!(tiles.len(), 6);
+	assert_eq!(tiles[2], Tile { x: 32, y: 0, width: 8, height: 16 });
+	assert_eq!(tiles.iter().map(|tile| tile.width * tile.height).sum::<usize>(), 800);
+
+	// Threaded or not, the same image
+	let mut scene = Scene::default_scene();
+	scene.update_culling(40, 20);
+	let single = (0..800).map(|index| scene.cast_ray(index, 40, 20)).collect::<Vec<_>>();
+	scene.renderer.threads = 4;
+	let threaded = render_tiles(&scene, 40, 20, |x, _| x % 3 != 0, |x, y, scratch| scene.cast_ray_with((y * 40 + x) as u64, 40, 20, scratch).0);
+	for (index, color) in threaded.iter().enumerate() {
+		assert_eq!(*color, if index % 40 % 3 != 0 { Some(single[index]) } else { None });
+	}
+	// Every tile timed and its rays counted, by whichever of the threads got to it
+	let stats = scene.render_stats.read().unwrap().clone().unwrap();
+	assert_eq!((stats.threads, stats.tiles.len()), (4, 6));
+	assert!(stats.tiles.iter().zip(tiles.iter()).all(|(stats, tile)| stats.tile == *tile && stats.rays > 0 && stats.thread < 4));
+	assert_eq!(stats.slowest(2).len(), 2);
+	assert!(stats.slowest(6)[0].seconds >= stats.slowest(6)[5].seconds);
+
+	// Smaller tiles, holding back for other work, still the same image
+	scene.renderer = RendererConfig { threads: 0, tile_size: 8, priority: Priority::Background };
+	assert!(scene.renderer.thread_count() >= 1);
+	let background = render_tiles(&scene, 40, 20, |_, _| true, |x, y, scratch| scene.cast_ray_with((y * 40 + x) as u64, 40, 20, scratch).0);
+	assert!(background.iter().enumerate().all(|(index, color)| *color == Some(single[index])));
+	let stats = scene.render_stats.read().unwrap().clone().unwrap();
+	assert!(stats.tiles.len() > 6 && stats.tiles.iter().all(|tile| tile.tile.width <= 16 && tile.tile.height <= 16));
+	assert_eq!(Priority::from_name("background"), Some(Priority::Background));
+
+	// A thread with nothing left steals from the back of the fullest queue
+	let queues: Vec<Mutex<VecDeque<usize>>> = vec![Mutex::new(VecDeque::from([0, 2])), Mutex::new(VecDeque::new()), Mutex::new(VecDeque::from([1, 3, 4]))];
+	assert_eq!(next_tile(&queues, 1), Some((4, true)));
+	assert_eq!(next_tile(&queues, 0), Some((0, false)));
+	assert_eq!((next_tile(&queues, 1), next_tile(&queues, 1), next_tile(&queues, 1)), (Some((3, true)), Some((2, true)), Some((1, true))));
+	assert_eq!(next_tile(&queues, 0), None);
+}
+
+#[test]
+fn costly_regions_get_small_tiles() {
+	// Glass in one corner of an otherwise cheap frame
+	let mut costs = CostMap::new(70, 40);
+	for y in 0..40 {
+		for x in 0..70 {
+			costs.seconds[y * 70 + x] = if (44..52).contains(&x) && (8..16).contains(&y) { 1.0 } else { 0.001 };
+		}
+	}
+	let tiles = balanced_tiles(70, 40, &costs, 2, TILE_SIZE);
+	let mut covered = vec![0; 70 * 40];
+	for tile in tiles.iter() {
+		assert!(tile.x % 2 == 0 && tile.y % 2 == 0);
+		for y in tile.y..tile.y + tile.height {
+			for x in tile.x..tile.x + tile.width {
+				covered[y * 70 + x] += 1;
+			}
+		}
+	}
+	assert!(covered.iter().all(|count| *count == 1));
+	assert!(tiles[0].width <= MIN_TILE_SIZE && tiles[0].x >= 44 && tiles[0].y >= 8);
+	assert!(tiles.contains(&Tile { x: 0, y: 0, width: 32, height: 32 }));
+	assert_eq!(balanced_tiles(40, 20, &costs, 2, TILE_SIZE), self::tiles(40, 20, TILE_SIZE));
+
+	// Laid out by the last frame's timings, the image doesn't change
+	let mut scene = Scene::default_scene();
+	let first = scene.render(40, 20);
+	assert!(scene.tile_costs.read().unwrap().as_ref().is_some_and(|costs| costs.seconds.iter().all(|seconds| *seconds > 0.0)));
+	assert_eq!(scene.render(40, 20), first);
+}
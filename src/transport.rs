@@ -0,0 +1,111 @@
+//
+//	Light Transport
+//
+
+// What the integrators that follow light around the scene share, SPPM's photons and camera rays
+// and the path tracer's paths alike.
+//
+// Brightness matches the direct integrator in all of them: a white surface facing a light of
+// intensity I from distance d comes out I / d^2, 1 being full white
+
+use crate::sampler::sample_cone;
+use crate::structs::{reflect, refract, Material, Scene, Vec3, GLOSSY_MAX_ANGLE};
+
+pub const OFFSET: f64 = 1e-4; // keeps continuing rays off the surface they leave
+
+pub type Rgb = [f64; 3];
+
+// Surface color as the fraction of each channel it reflects
+pub fn albedo(material: &Material) -> Rgb {
+	[material.color.r as f64 / 255.0, material.color.g as f64 / 255.0, material.color.b as f64 / 255.0]
+}
+
+// Normal of the hit turned to face where the ray came from, straight back at it when the hit has none to offer
+pub fn facing(normal: &Vec3, direction: &Vec3) -> Vec3 {
+	if Vec3::dot(normal, normal) < 1e-18 { return direction.normalize().mul(-1.0) }
+	let normal = normal.normalize();
+	if Vec3::dot(&normal, direction) > 0.0 { normal.mul(-1.0) } else { normal }
+}
+
+// A direction sampled around the z axis, turned to be around axis instead
+pub fn around(axis: &Vec3, local: Vec3) -> Vec3 {
+	let (tangent, bitangent) = axis.orthonormal_basis();
+	tangent.mul(local.x).add(&bitangent.mul(local.y)).add(&axis.mul(local.z))
+}
+
+// Where a ray along incident, unit length, carries on from point on a surface of material: mirrored
+// or sent through, picked by choice in [0, 1) in proportion to the Fresnel weights the direct
+// integrator blends by. Mirrors draw gloss to spread over a cone when rough, refraction past the
+// critical angle reflects instead. None for the rest, which scatters diffusely
+pub fn specular_bounce(material: &Material, point: &Vec3, incident: &Vec3, normal: &Vec3, choice: f64, gloss: impl FnOnce() -> (f64, f64)) -> Option<(Vec3, Vec3)> {
+	let outside = facing(normal, incident);
+	let (reflected, transmitted) = material.fresnel_weights(incident, normal);
+	if choice < reflected {
+		let mirrored = reflect(incident, &outside);
+		let (u, v) = gloss();
+		let direction = match material.roughness > 0.0 {
+			true => around(&mirrored, sample_cone(u, v, (material.roughness as f64 * GLOSSY_MAX_ANGLE).cos())),
+			false => mirrored,
+		};
+		Some((point.add(&outside.mul(OFFSET)), direction))
+	} else if choice < reflected + transmitted {
+		Some(match refract(incident, normal, material.ior as f64) {
+			Some(direction) => (point.sub(&outside.mul(OFFSET)), direction),
+			None => (point.add(&outside.mul(OFFSET)), reflect(incident, &outside)),
+		})
+	} else {
+		None
+	}
+}
+
+// Light from the environment along direction for rays leaving the scene, none without one
+pub fn environment(scene: &Scene, direction: &Vec3) -> Rgb {
+	match &scene.environment {
+		Some(environment) => environment.specular(direction, 0.0).map(|channel| channel * environment.intensity),
+		None => [0.0; 3],
+	}
+}
+
+#[test]
+fn light_stops_at_the_first_surface() {
+	use crate::render::Integrator;
+	use crate::structs::{Color, LightSource, Mesh, Rot3, SceneObject, Sphere, Tri};
+	let quad = |size: f64, z: f64, mat: Material| {
+		let corner = |x: f64, y: f64| Vec3 { x: x * size, y: y * size, z };
+		Mesh::new(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, Rot3::new(), vec![
+			Tri { a: corner(-1.0, -1.0), b: corner(1.0, -1.0), c: corner(1.0, 1.0), mat },
+			Tri { a: corner(-1.0, -1.0), b: corner(1.0, 1.0), c: corner(-1.0, 1.0), mat },
+		])
+	};
+	let gray = Material { color: Color { r: 128, g: 128, b: 128 }, reflectivity: 0.0, transparency: 0.0, roughness: 0.0, ior: 1.5 };
+	let black = Material { color: Color { r: 0, g: 0, b: 0 }, ..gray };
+	// A floor under a light with a black ball past it, a black plate between the two to come
+	let objects: Vec<Box<dyn SceneObject>> = vec![
+		Box::new(quad(20.0, 0.0, gray)),
+		Box::new(LightSource::new(Vec3 { x: 0.0, y: 0.0, z: 2.0 }, Rot3::new(), 2.0)),
+		Box::new(Sphere::new(Vec3 { x: 0.0, y: 0.0, z: 3.0 }, 0.3, black)),
+	];
+	let mut scene = Scene::default_scene();
+	scene.objects = objects;
+	scene.render_settings.samples_per_pixel = 16;
+	scene.render_settings.photons_per_pass = 5000;
+
+	// Looking at the floor below the plate from under it, nearer the floor than the plate, and from
+	// beside it, where the ball is nearer than the plate. Whichever is nearer the camera, what a ray
+	// meets first is what stops it
+	let cameras = [Vec3 { x: 0.0, y: -0.01, z: 0.3 }, Vec3 { x: 3.0, y: 0.0, z: 3.2 }];
+	let center = 4 * 8 + 4;
+	for integrator in [Integrator::Sppm, Integrator::Path] {
+		scene.render_settings.integrator = integrator;
+		for pos in cameras {
+			scene.current_camera.pos = pos;
+			scene.current_camera.look_at(&Vec3 { x: 0.0, y: 0.0, z: 0.0 }, &Vec3 { x: 0.0, y: 0.0, z: 1.0 });
+			scene.objects.truncate(3);
+			let lit = scene.render(8, 8)[center];
+			assert!(lit.r > 40, "{:?} from {:?}: {:?}", integrator, pos, lit);
+			scene.objects.push(Box::new(quad(0.5, 1.0, black)));
+			let shadowed = scene.render(8, 8)[center];
+			assert!(shadowed.r < 6, "{:?} from {:?}: {:?}", integrator, pos, shadowed);
+		}
+	}
+}
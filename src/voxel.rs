@@ -0,0 +1,260 @@
+//
+//	Voxels
+//
+
+// Blocky worlds as a grid of cells rather than triangles: rays step from cell to cell (Amanatides
+// and Woo's DDA), so the cost goes with how far a ray travels and not with how many blocks there are
+
+use crate::structs::{Aabb, Color, Material, Ray, RayMask, Rot3, SceneObject, Vec3};
+use core::any::Any;
+use std::fs;
+use std::path::Path;
+use uuid::Uuid;
+
+pub struct VoxelGrid {
+	pub anchor: Vec3, // corner of cell (0, 0, 0)
+	pub rot: Rot3,
+	pub voxel_size: f64,
+	pub dims: [usize; 3],
+	pub cells: Vec<u8>, // x fastest, then y, then z; 0 is empty, anything else is palette[value - 1]
+	pub palette: Vec<Material>,
+	pub mask: RayMask,
+	pub id: String,
+	pub path: String, // where it was loaded from, empty for generated ones
+}
+
+impl VoxelGrid {
+	pub fn new(anchor: Vec3, dims: [usize; 3], voxel_size: f64, palette: Vec<Material>) -> Self {
+		VoxelGrid {
+			anchor,
+			rot: Rot3::new(),
+			voxel_size,
+			dims,
+			cells: vec![0; dims[0] * dims[1] * dims[2]],
+			palette,
+			mask: RayMask::ALL,
+			id: Uuid::new_v4().to_hyphenated().to_string(),
+			path: String::new(),
+		}
+	}
+
+	// From a 3D array indexed [z][y][x], every row the same length and every layer the same size
+	pub fn from_array(anchor: Vec3, cells: &[Vec<Vec<u8>>], voxel_size: f64, palette: Vec<Material>) -> Result<Self, String> {
+		let height = cells.first().map_or(0, |layer| layer.len());
+		let width = cells.first().and_then(|layer| layer.first()).map_or(0, |row| row.len());
+		let mut grid = VoxelGrid::new(anchor, [width, height, cells.len()], voxel_size, palette);
+		for (z, layer) in cells.iter().enumerate() {
+			if layer.len() != height { return Err(format!("layer {} has {} rows, expected {}", z, layer.len(), height)) }
+			for (y, row) in layer.iter().enumerate() {
+				if row.len() != width { return Err(format!("layer {} row {} has {} cells, expected {}", z, y, row.len(), width)) }
+				for (x, value) in row.iter().enumerate() {
+					if *value as usize > grid.palette.len() { return Err(format!("cell {} {} {}: no material {}", x, y, z, value)) }
+					grid.set(x, y, z, *value);
+				}
+			}
+		}
+		Ok(grid)
+	}
+
+	// Text chunks, `#` starting a comment:
+	//
+	//	material 1 255 255 255      index, color and optionally reflectivity, transparency, roughness and ior
+	//	layer                       one per z, bottom first
+	//	1111                        one row per y, one character per x: `.` empty, 1-9 a material
+	//	1..1
+	pub fn parse(text: &str, voxel_size: f64) -> Result<Self, String> {
+		let mut palette: Vec<Material> = Vec::new();
+		let mut layers: Vec<Vec<Vec<u8>>> = Vec::new();
+		for (line_no, line) in text.lines().enumerate() {
+			let line = line.split('#').next().unwrap_or("").trim();
+			let error = |message: &str| format!("line {}: {}", line_no + 1, message);
+			if line.is_empty() { continue }
+			let words: Vec<&str> = line.split_whitespace().collect();
+			match words[0] {
+				"material" => {
+					let numbers = words[1..].iter().map(|word| word.parse::<f64>()).collect::<Result<Vec<f64>, _>>()
+						.map_err(|_| error("expected `material INDEX R G B [REFLECTIVITY] [TRANSPARENCY] [ROUGHNESS] [IOR]`"))?;
+					if numbers.len() < 4 || numbers[0] as usize != palette.len() + 1 {
+						return Err(error("expected `material INDEX R G B`, numbered from 1 in order"));
+					}
+					let channel = |value: f64| value.clamp(0.0, 255.0) as u8;
+					palette.push(Material {
+						color: Color { r: channel(numbers[1]), g: channel(numbers[2]), b: channel(numbers[3]) },
+						reflectivity: numbers.get(4).copied().unwrap_or(0.0) as f32,
+						transparency: numbers.get(5).copied().unwrap_or(0.0) as f32,
+						roughness: numbers.get(6).copied().unwrap_or(0.0) as f32,
+						ior: numbers.get(7).copied().unwrap_or(1.5) as f32,
+					});
+				}
+				"layer" => layers.push(Vec::new()),
+				_ => {
+					let layer = layers.last_mut().ok_or(error("cells before the first `layer`"))?;
+					let row = line.chars().map(|cell| match cell {
+						'.' => Ok(0),
+						'1'..='9' => Ok(cell as u8 - b'0'),
+						_ => Err(error(&format!("unknown cell `{}`", cell))),
+					}).collect::<Result<Vec<u8>, String>>()?;
+					layer.push(row);
+				}
+			}
+		}
+		VoxelGrid::from_array(Vec3 { x: 0.0, y: 0.0, z: 0.0 }, &layers, voxel_size, palette)
+	}
+
+	pub fn load(path: &Path, voxel_size: f64) -> Result<Self, String> {
+		let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+		let mut grid = VoxelGrid::parse(&text, voxel_size).map_err(|e| format!("{}: {}", path.display(), e))?;
+		grid.path = path.to_string_lossy().into_owned();
+		Ok(grid)
+	}
+
+	fn index(&self, x: usize, y: usize, z: usize) -> usize {
+		(z * self.dims[1] + y) * self.dims[0] + x
+	}
+
+	pub fn get(&self, x: usize, y: usize, z: usize) -> u8 {
+		self.cells[self.index(x, y, z)]
+	}
+
+	pub fn set(&mut self, x: usize, y: usize, z: usize, value: u8) {
+		let index = self.index(x, y, z);
+		self.cells[index] = value;
+	}
+
+	// The grid's box in grid space, a unit per cell
+	fn local_bounds(&self) -> Aabb {
+		Aabb { min: Vec3 { x: 0.0, y: 0.0, z: 0.0 }, max: Vec3 { x: self.dims[0] as f64, y: self.dims[1] as f64, z: self.dims[2] as f64 } }
+	}
+
+	fn to_local(&self, point: &Vec3) -> Vec3 {
+		point.sub(&self.anchor).unrotate(&self.rot).div(self.voxel_size)
+	}
+
+	fn to_world(&self, point: &Vec3) -> Vec3 {
+		point.mul(self.voxel_size).rotate(&self.rot).add(&self.anchor)
+	}
+}
+
+impl SceneObject for VoxelGrid {
+	fn get_pos(&self) -> &Vec3 { &self.anchor }
+	fn get_rot(&self) -> &Rot3 { &self.rot }
+	fn ray_hit(&self, ray: &Ray) -> Option<(Vec3, Material, Vec3)> {
+		if self.cells.is_empty() { return None }
+		let origin = self.to_local(&ray.origin);
+		let direction = ray.direction.unrotate(&self.rot);
+		let local_ray = Ray { origin, direction, mask: ray.mask, differential: None };
+		let entry = self.local_bounds().ray_distance(&local_ray)?;
+		// Hits closer than this are the surface a secondary ray is leaving, same as for meshes
+		let min_distance = 0.01 / self.voxel_size;
+
+		let origin = [origin.x, origin.y, origin.z];
+		let direction = [direction.x, direction.y, direction.z];
+		let start = [0, 1, 2].map(|axis| origin[axis] + direction[axis] * entry);
+		let mut cell = [0, 1, 2].map(|axis| (start[axis].floor().max(0.0) as usize).min(self.dims[axis].max(1) - 1));
+		let step = direction.map(|d| if d > 0.0 { 1 } else { -1 });
+		// Distance along the ray to the next cell boundary on each axis, and between boundaries
+		let delta = direction.map(|d| if d != 0.0 { 1.0 / d.abs() } else { f64::INFINITY });
+		let mut next = [0, 1, 2].map(|axis| {
+			if direction[axis] == 0.0 { return f64::INFINITY }
+			let boundary = cell[axis] as f64 + if step[axis] > 0 { 1.0 } else { 0.0 };
+			(boundary - origin[axis]) / direction[axis]
+		});
+		// Axis of the face the ray came in through, the largest entry distance when it starts outside
+		let crossed = [0, 1, 2].map(|axis| if direction[axis] == 0.0 { f64::NEG_INFINITY } else { next[axis] - delta[axis] });
+		let mut face = (0..3).max_by(|a, b| crossed[*a].total_cmp(&crossed[*b])).unwrap_or(0);
+		let mut distance = entry;
+		loop {
+			let value = self.get(cell[0], cell[1], cell[2]);
+			if value != 0 && distance >= min_distance {
+				let point = self.to_world(&local_ray.origin.add(&local_ray.direction.mul(distance)));
+				let mut normal = [0.0; 3];
+				normal[face] = -step[face] as f64;
+				let normal = Vec3 { x: normal[0], y: normal[1], z: normal[2] }.rotate(&self.rot);
+				return Some((point, self.palette[value as usize - 1], normal));
+			}
+			face = if next[0] < next[1] && next[0] < next[2] { 0 } else if next[1] < next[2] { 1 } else { 2 };
+			distance = next[face];
+			next[face] += delta[face];
+			let moved = cell[face] as i64 + step[face];
+			if moved < 0 || moved >= self.dims[face] as i64 { return None }
+			cell[face] = moved as usize;
+		}
+	}
+	fn as_any(&mut self) -> &mut dyn Any { self }
+	fn as_any_immut(&self) -> &dyn Any { self }
+	fn get_id(&self) -> &String { &self.id }
+	fn get_material(&self) -> Option<Material> { self.palette.first().copied() }
+	fn set_material(&mut self, material: Material) {
+		for slot in self.palette.iter_mut() {
+			*slot = material;
+		}
+	}
+	fn bounds(&self) -> Option<Aabb> {
+		if self.cells.is_empty() { return None }
+		let local = self.local_bounds();
+		let mut bounds = Aabb::empty();
+		for corner in 0..8 {
+			let pick = |bit: usize, min: f64, max: f64| if corner & bit == 0 { min } else { max };
+			let point = Vec3 { x: pick(1, local.min.x, local.max.x), y: pick(2, local.min.y, local.max.y), z: pick(4, local.min.z, local.max.z) };
+			bounds.grow(&self.to_world(&point));
+		}
+		Some(bounds)
+	}
+	fn get_mask(&self) -> RayMask { self.mask }
+	fn set_mask(&mut self, mask: RayMask) { self.mask = mask }
+	fn set_transform(&mut self, pos: Vec3, rot: Rot3) { self.anchor = pos; self.rot = rot }
+	fn closest_point(&self, point: &Vec3) -> Option<Vec3> {
+		let local = self.to_local(point);
+		let mut closest: Option<Vec3> = None;
+		for (index, value) in self.cells.iter().enumerate() {
+			if *value == 0 { continue }
+			let (x, y, z) = (index % self.dims[0], index / self.dims[0] % self.dims[1], index / (self.dims[0] * self.dims[1]));
+			let min = Vec3 { x: x as f64, y: y as f64, z: z as f64 };
+			let candidate = Aabb { min, max: min.add(&Vec3 { x: 1.0, y: 1.0, z: 1.0 }) }.closest_point(&local);
+			if closest.is_none_or(|closest| candidate.dist(&local) < closest.dist(&local)) {
+				closest = Some(candidate);
+			}
+		}
+		closest.map(|closest| self.to_world(&closest))
+	}
+}
+
+#[test]
+fn rays_step_through_cells() {
+	let chunk = "
+		material 1 255 255 255
+		material 2 200 40 40 0.5
+		layer   # z = 0, a floor with a hole
+		111
+		1.1
+		111
+		layer   # z = 1, a single block
+		...
+		..2
+		...
+	";
+	let mut grid = VoxelGrid::parse(chunk, 0.5).unwrap();
+	assert_eq!(grid.dims, [3, 3, 2]);
+	assert_eq!((grid.get(1, 1, 0), grid.get(2, 1, 1)), (0, 2));
+	assert!(VoxelGrid::parse("layer\n11\n1", 1.0).is_err());
+	assert!(VoxelGrid::parse("layer\n13", 1.0).is_err());
+
+	let down = |x: f64, y: f64| Ray { origin: Vec3 { x, y, z: 5.0 }, direction: Vec3 { x: 0.0, y: 0.0, z: -1.0 }, mask: RayMask::ALL, differential: None };
+	// Onto the top of the floor, on top of the block, and through the hole
+	let (point, material, normal) = grid.ray_hit(&down(0.25, 0.25)).unwrap();
+	assert!(point.dist(&Vec3 { x: 0.25, y: 0.25, z: 0.5 }) < 1e-9 && normal.z == 1.0 && material.reflectivity == 0.0);
+	let (point, material, _) = grid.ray_hit(&down(1.25, 0.75)).unwrap();
+	assert!((point.z - 1.0).abs() < 1e-9 && material.reflectivity == 0.5);
+	assert!(grid.ray_hit(&down(0.75, 0.75)).is_none());
+
+	// Sideways into the block's face, on a grid turned a quarter about z and moved
+	grid.anchor = Vec3 { x: 10.0, y: 0.0, z: 0.0 };
+	grid.rot = Rot3::from_axis_angle(&Vec3 { x: 0.0, y: 0.0, z: 1.0 }, std::f64::consts::FRAC_PI_2);
+	let block = grid.to_world(&Vec3 { x: 2.5, y: 1.5, z: 1.5 });
+	let ray = Ray::from_to(&block.add(&Vec3 { x: 0.0, y: 3.0, z: 0.0 }), &block);
+	let (point, _, normal) = grid.ray_hit(&ray).unwrap();
+	assert!(point.dist(&block.add(&Vec3 { x: 0.0, y: 0.25, z: 0.0 })) < 1e-9, "{:?}", point);
+	assert!(normal.dist(&Vec3 { x: 0.0, y: 1.0, z: 0.0 }) < 1e-9, "{:?}", normal);
+	let bounds = grid.bounds().unwrap();
+	assert!(bounds.min.dist(&Vec3 { x: 8.5, y: 0.0, z: 0.0 }) < 1e-9 && bounds.max.dist(&Vec3 { x: 10.0, y: 1.5, z: 1.0 }) < 1e-9);
+}